@@ -1,12 +1,32 @@
+use std::path::PathBuf;
+use ui::recording::InputMode;
+
 fn main() {
     env_logger::init();
 
-    let args: Vec<String> = std::env::args().collect();
-    let file_to_open = if args.len() == 2 {
-        Some(args[1].clone())
-    } else {
-        None
-    };
+    let mut file_to_open = None;
+    let mut input_mode = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--record-input" | "--replay-input" => {
+                let Some(path) = args.next().map(PathBuf::from) else {
+                    eprintln!("{arg} needs a file");
+                    std::process::exit(2);
+                };
+                input_mode = Some(if arg == "--record-input" {
+                    InputMode::Record(path)
+                } else {
+                    InputMode::Replay(path)
+                });
+            }
+            _ if arg.starts_with("--") => {
+                eprintln!("Unknown option {arg}");
+                std::process::exit(2);
+            }
+            _ => file_to_open = Some(arg),
+        }
+    }
 
-    ui::run(file_to_open);
+    ui::run(file_to_open, input_mode);
 }
@@ -0,0 +1,54 @@
+use crate::TextEditor;
+use crop::{Rope, RopeSlice};
+use std::ops::Range;
+
+/// Read access to a text by byte offsets and zero based lines. Implemented for
+/// the editor and for a plain rope, so code that only looks at text works on both.
+/// The names differ from Rope's and TextEditor's own methods, so which one is
+/// called never depends on whether the trait is in scope.
+pub trait TextBuffer {
+    /// The text itself.
+    fn rope(&self) -> &Rope;
+
+    /// The length of the text in bytes.
+    fn len_bytes(&self) -> usize {
+        self.rope().byte_len()
+    }
+
+    /// Get the text in the given byte range, e.g. the contents of a VisualLine.
+    fn slice_bytes(&self, range: Range<usize>) -> RopeSlice<'_> {
+        self.rope().byte_slice(range)
+    }
+
+    /// How many lines the text has. Text ending in a newline has an empty last line.
+    fn len_lines(&self) -> usize {
+        self.rope().line_of_byte(self.len_bytes()) + 1
+    }
+
+    /// The zero based line and character column of 'byte_idx'.
+    fn line_and_column(&self, byte_idx: usize) -> (usize, usize) {
+        let rope = self.rope();
+        let line = rope.line_of_byte(byte_idx);
+        let line_start = rope.byte_of_line(line);
+        let column = rope.byte_slice(line_start..byte_idx).chars().count();
+        (line, column)
+    }
+
+    /// The byte offset where the zero based 'line' starts. Lines past the end map
+    /// to the start of the last line.
+    fn start_of_line(&self, line: usize) -> usize {
+        self.rope().byte_of_line(line.min(self.len_lines() - 1))
+    }
+}
+
+impl TextBuffer for Rope {
+    fn rope(&self) -> &Rope {
+        self
+    }
+}
+
+impl TextBuffer for TextEditor {
+    fn rope(&self) -> &Rope {
+        &self.content
+    }
+}
@@ -0,0 +1,179 @@
+use encoding_rs::{EncoderResult, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+use std::io;
+
+/// How the text of a file is stored as bytes. Text is always edited as UTF-8 and
+/// converted when the file is read and saved.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    /// UTF-8 starting with a byte order mark.
+    Utf8Bom,
+    Utf16Le,
+    /// UTF-16 LE starting with a byte order mark.
+    Utf16LeBom,
+    Utf16Be,
+    /// UTF-16 BE starting with a byte order mark.
+    Utf16BeBom,
+    /// One byte per character, for files that aren't valid UTF-8. Every sequence of
+    /// bytes is valid Windows-1252, which makes it the fallback.
+    Windows1252,
+}
+
+/// How many bytes from the start of a file are looked at to tell UTF-16 without a
+/// byte order mark from other encodings.
+const UTF16_SNIFF_LEN: usize = 4096;
+
+impl Encoding {
+    /// Every encoding, in the order "reopen with encoding" goes through them.
+    pub const ALL: [Encoding; 7] = [
+        Encoding::Utf8,
+        Encoding::Utf8Bom,
+        Encoding::Utf16Le,
+        Encoding::Utf16LeBom,
+        Encoding::Utf16Be,
+        Encoding::Utf16BeBom,
+        Encoding::Windows1252,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf8Bom => "UTF-8 with BOM",
+            Encoding::Utf16Le => "UTF-16 LE",
+            Encoding::Utf16LeBom => "UTF-16 LE with BOM",
+            Encoding::Utf16Be => "UTF-16 BE",
+            Encoding::Utf16BeBom => "UTF-16 BE with BOM",
+            Encoding::Windows1252 => "Windows-1252",
+        }
+    }
+
+    /// The encoding after this one in ALL, starting over after the last.
+    pub fn next(self) -> Encoding {
+        let i = Self::ALL.iter().position(|e| *e == self).unwrap();
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+
+    fn codec(self) -> &'static encoding_rs::Encoding {
+        match self {
+            Encoding::Utf8 | Encoding::Utf8Bom => UTF_8,
+            Encoding::Utf16Le | Encoding::Utf16LeBom => UTF_16LE,
+            Encoding::Utf16Be | Encoding::Utf16BeBom => UTF_16BE,
+            Encoding::Windows1252 => WINDOWS_1252,
+        }
+    }
+
+    /// The encoding 'bytes' announce with a byte order mark, or, without one, UTF-16
+    /// if they look like it: mostly ASCII with every other byte zero. None if
+    /// neither tells.
+    pub fn sniff(bytes: &[u8]) -> Option<Encoding> {
+        if let Some((codec, _)) = encoding_rs::Encoding::for_bom(bytes) {
+            return Some(if codec == UTF_8 {
+                Encoding::Utf8Bom
+            } else if codec == UTF_16LE {
+                Encoding::Utf16LeBom
+            } else {
+                Encoding::Utf16BeBom
+            });
+        }
+
+        let start = &bytes[..bytes.len().min(UTF16_SNIFF_LEN) & !1];
+        if start.is_empty() {
+            return None;
+        }
+        let pairs = start.len() / 2;
+        let ascii = |zero: usize, other: usize| {
+            start
+                .chunks(2)
+                .filter(|pair| pair[zero] == 0 && pair[other] != 0)
+                .count()
+        };
+
+        // Three in four characters being ASCII is plenty for source code and logs,
+        // and real UTF-8 or Windows-1252 text never has that many zero bytes.
+        let (le, be) = (ascii(1, 0), ascii(0, 1));
+        let encoding = if le * 4 >= pairs * 3 {
+            Encoding::Utf16Le
+        } else if be * 4 >= pairs * 3 {
+            Encoding::Utf16Be
+        } else {
+            return None;
+        };
+
+        // A cut off last character is fine, anything else that doesn't decode isn't.
+        let (_, malformed) = encoding
+            .codec()
+            .decode_without_bom_handling(&bytes[..bytes.len() & !1]);
+        (!malformed).then_some(encoding)
+    }
+
+    /// Guess the encoding of 'bytes'. A byte order mark or a UTF-16 pattern decides
+    /// it. Otherwise, anything that is valid UTF-8 is taken to be UTF-8, and anything
+    /// else Windows-1252.
+    pub fn detect(bytes: &[u8]) -> Encoding {
+        Self::sniff(bytes).unwrap_or_else(|| {
+            if std::str::from_utf8(bytes).is_ok() {
+                Encoding::Utf8
+            } else {
+                Encoding::Windows1252
+            }
+        })
+    }
+
+    /// The byte order mark files in this encoding start with, if any.
+    pub fn bom(self) -> &'static [u8] {
+        match self {
+            Encoding::Utf8Bom => &[0xEF, 0xBB, 0xBF],
+            Encoding::Utf16LeBom => &[0xFF, 0xFE],
+            Encoding::Utf16BeBom => &[0xFE, 0xFF],
+            _ => &[],
+        }
+    }
+
+    /// The text in 'bytes', without its byte order mark. Bytes that don't decode,
+    /// e.g. half of a UTF-16 pair, become replacement characters.
+    pub fn decode(self, bytes: &[u8]) -> String {
+        let bytes = bytes.strip_prefix(self.bom()).unwrap_or(bytes);
+        let (text, _) = self.codec().decode_without_bom_handling(bytes);
+        text.into_owned()
+    }
+
+    /// 'text' as bytes, with the byte order mark if the encoding has one. Fails if
+    /// the text has a character the encoding can't store.
+    pub fn encode(self, text: &str) -> io::Result<Vec<u8>> {
+        let mut bytes = self.bom().to_vec();
+        self.encode_into(text, &mut bytes)?;
+        Ok(bytes)
+    }
+
+    /// Append 'text' as bytes to 'out', without a byte order mark. Text can be
+    /// encoded a piece at a time, as long as no piece splits a character.
+    pub fn encode_into(self, text: &str, out: &mut Vec<u8>) -> io::Result<()> {
+        match self {
+            Encoding::Utf8 | Encoding::Utf8Bom => out.extend_from_slice(text.as_bytes()),
+            // encoding_rs only decodes UTF-16, its encoders write UTF-8 for it.
+            Encoding::Utf16Le | Encoding::Utf16LeBom => {
+                out.extend(text.encode_utf16().flat_map(u16::to_le_bytes))
+            }
+            Encoding::Utf16Be | Encoding::Utf16BeBom => {
+                out.extend(text.encode_utf16().flat_map(u16::to_be_bytes))
+            }
+            Encoding::Windows1252 => {
+                let mut encoder = self.codec().new_encoder();
+                let max_len = encoder
+                    .max_buffer_length_from_utf8_without_replacement(text.len())
+                    .unwrap_or(text.len());
+                out.reserve(max_len);
+                let (result, _) =
+                    encoder.encode_from_utf8_to_vec_without_replacement(text, out, true);
+                if let EncoderResult::Unmappable(c) = result {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("{c:?} can't be saved as {}", self.name()),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,78 @@
+use crop::Rope;
+use unicode_segmentation::{GraphemeCursor, GraphemeIncomplete};
+
+/// The byte index where the grapheme cluster starting at 'byte_idx' ends.
+pub fn next_boundary(rope: &Rope, byte_idx: usize) -> usize {
+    let mut cursor = GraphemeCursor::new(byte_idx, rope.byte_len(), true);
+    let (mut chunk, mut chunk_start) = chunk_after(rope, byte_idx);
+    loop {
+        match cursor.next_boundary(chunk, chunk_start) {
+            Ok(boundary) => return boundary.unwrap_or(byte_idx),
+            Err(GraphemeIncomplete::NextChunk) => {
+                (chunk, chunk_start) = chunk_after(rope, chunk_start + chunk.len())
+            }
+            Err(incomplete) => provide(rope, &mut cursor, incomplete),
+        }
+    }
+}
+
+/// The byte index where the cluster ending at 'byte_idx' starts.
+pub fn prev_boundary(rope: &Rope, byte_idx: usize) -> usize {
+    let mut cursor = GraphemeCursor::new(byte_idx, rope.byte_len(), true);
+    let (mut chunk, mut chunk_start) = chunk_before(rope, byte_idx);
+    loop {
+        match cursor.prev_boundary(chunk, chunk_start) {
+            Ok(boundary) => return boundary.unwrap_or(byte_idx),
+            Err(GraphemeIncomplete::PrevChunk) => {
+                (chunk, chunk_start) = chunk_before(rope, chunk_start)
+            }
+            Err(incomplete) => provide(rope, &mut cursor, incomplete),
+        }
+    }
+}
+
+/// The start of the cluster 'byte_idx' is in. That is 'byte_idx' itself if it
+/// is already on a boundary.
+pub fn cluster_start(rope: &Rope, byte_idx: usize) -> usize {
+    if byte_idx == 0 || byte_idx >= rope.byte_len() {
+        return byte_idx;
+    }
+
+    let mut cursor = GraphemeCursor::new(byte_idx, rope.byte_len(), true);
+    let (chunk, chunk_start) = chunk_after(rope, byte_idx);
+    loop {
+        match cursor.is_boundary(chunk, chunk_start) {
+            Ok(true) => return byte_idx,
+            Ok(false) => return prev_boundary(rope, byte_idx),
+            Err(incomplete) => provide(rope, &mut cursor, incomplete),
+        }
+    }
+}
+
+/// Give 'cursor' the text before a chunk that it asked for to decide a boundary,
+/// e.g. how many regional indicators came before.
+fn provide(rope: &Rope, cursor: &mut GraphemeCursor, incomplete: GraphemeIncomplete) {
+    let GraphemeIncomplete::PreContext(end) = incomplete else {
+        unreachable!("chunks are always given around the cursor: {incomplete:?}");
+    };
+    let (chunk, chunk_start) = chunk_before(rope, end);
+    cursor.provide_context(chunk, chunk_start);
+}
+
+/// The text from 'byte_idx' to the end of the rope chunk it is in, and where it
+/// starts.
+fn chunk_after(rope: &Rope, byte_idx: usize) -> (&str, usize) {
+    let chunk = rope.byte_slice(byte_idx..).chunks().next().unwrap_or("");
+    (chunk, byte_idx)
+}
+
+/// The text from the start of the rope chunk before 'byte_idx' up to it, and
+/// where it starts.
+fn chunk_before(rope: &Rope, byte_idx: usize) -> (&str, usize) {
+    let chunk = rope
+        .byte_slice(..byte_idx)
+        .chunks()
+        .next_back()
+        .unwrap_or("");
+    (chunk, byte_idx - chunk.len())
+}
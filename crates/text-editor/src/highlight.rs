@@ -0,0 +1,392 @@
+use crop::Rope;
+use std::{
+    cmp::Reverse,
+    ops::Range,
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use tree_sitter::{InputEdit, Parser, Point, Query, QueryCursor, Tree};
+
+/// The languages that can be syntax highlighted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Language {
+    Rust,
+    Json,
+}
+
+impl Language {
+    /// Pick the language from a file's extension.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "rs" => Some(Language::Rust),
+            "json" => Some(Language::Json),
+            _ => None,
+        }
+    }
+
+    fn grammar(self) -> tree_sitter::Language {
+        match self {
+            Language::Rust => tree_sitter_rust::language(),
+            Language::Json => tree_sitter_json::language(),
+        }
+    }
+
+    fn highlights_query(self) -> &'static str {
+        match self {
+            Language::Rust => tree_sitter_rust::HIGHLIGHTS_QUERY,
+            Language::Json => tree_sitter_json::HIGHLIGHTS_QUERY,
+        }
+    }
+}
+
+/// What a highlighted piece of text is. Decides the color it is drawn in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    Keyword,
+    Function,
+    Type,
+    String,
+    Escape,
+    Number,
+    Constant,
+    Comment,
+    Attribute,
+    Property,
+    Variable,
+    Label,
+    Operator,
+    Punctuation,
+}
+
+impl TokenKind {
+    /// Map a tree-sitter capture name like "function.method" to a kind, by its
+    /// first part.
+    fn from_capture_name(name: &str) -> Option<Self> {
+        let kind = match name.split('.').next()? {
+            "keyword" => TokenKind::Keyword,
+            "function" | "constructor" => TokenKind::Function,
+            "type" => TokenKind::Type,
+            "string" => TokenKind::String,
+            "escape" => TokenKind::Escape,
+            "number" => TokenKind::Number,
+            "constant" => TokenKind::Constant,
+            "comment" => TokenKind::Comment,
+            "attribute" => TokenKind::Attribute,
+            "property" => TokenKind::Property,
+            "variable" => TokenKind::Variable,
+            "label" => TokenKind::Label,
+            "operator" => TokenKind::Operator,
+            "punctuation" => TokenKind::Punctuation,
+            _ => return None,
+        };
+        Some(kind)
+    }
+}
+
+/// A highlighted byte range of the text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HighlightSpan {
+    pub range: Range<usize>,
+    pub kind: TokenKind,
+}
+
+/// How bad a diagnostic is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// A problem found in the text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub range: Range<usize>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// Keeps a syntax tree of the text up to date, reparsing only what edits touched,
+/// and answers which parts of it to highlight.
+pub struct SyntaxHighlighter {
+    language: Language,
+    parser: Parser,
+    query: Query,
+
+    /// The kind of each capture in 'query', by capture index.
+    capture_kinds: Vec<Option<TokenKind>>,
+    tree: Option<Tree>,
+
+    /// Set by edits. The tree is reparsed before it is used again.
+    stale: bool,
+
+    /// Changes with every parse. Unique across highlighters, so it also tells
+    /// one text from another.
+    version: u64,
+
+    /// The syntax errors in 'tree', found when it was parsed.
+    diagnostics: Vec<Diagnostic>,
+}
+
+/// The last version given to a parsed tree.
+static TREE_VERSION: AtomicU64 = AtomicU64::new(0);
+
+/// The most characters of the text at an error that its message quotes.
+const SNIPPET_CHARS: usize = 20;
+
+impl SyntaxHighlighter {
+    pub fn new(language: Language) -> Self {
+        let mut parser = Parser::new();
+        parser.set_language(&language.grammar()).unwrap();
+
+        let query = Query::new(&language.grammar(), language.highlights_query()).unwrap();
+        let capture_kinds = query
+            .capture_names()
+            .iter()
+            .map(|name| TokenKind::from_capture_name(name))
+            .collect();
+
+        Self {
+            language,
+            parser,
+            query,
+            capture_kinds,
+            tree: None,
+            stale: true,
+            version: 0,
+            diagnostics: vec![],
+        }
+    }
+
+    pub fn language(&self) -> Language {
+        self.language
+    }
+
+    /// Tell the tree about 'text' about to be inserted into 'rope' at 'byte_idx'.
+    pub(crate) fn insert(&mut self, rope: &Rope, byte_idx: usize, text: &str) {
+        let start = point_of(rope, byte_idx);
+        let lines = text.split('\n').count() - 1;
+        let last_line = text.rsplit('\n').next().unwrap_or_default();
+        let new_end = Point {
+            row: start.row + lines,
+            column: if lines == 0 {
+                start.column + text.len()
+            } else {
+                last_line.len()
+            },
+        };
+
+        self.edit(InputEdit {
+            start_byte: byte_idx,
+            old_end_byte: byte_idx,
+            new_end_byte: byte_idx + text.len(),
+            start_position: start,
+            old_end_position: start,
+            new_end_position: new_end,
+        });
+    }
+
+    /// Tell the tree about 'range' about to be deleted from 'rope'.
+    pub(crate) fn delete(&mut self, rope: &Rope, range: Range<usize>) {
+        let start = point_of(rope, range.start);
+        self.edit(InputEdit {
+            start_byte: range.start,
+            old_end_byte: range.end,
+            new_end_byte: range.start,
+            start_position: start,
+            old_end_position: point_of(rope, range.end),
+            new_end_position: start,
+        });
+    }
+
+    fn edit(&mut self, edit: InputEdit) {
+        if let Some(tree) = &mut self.tree {
+            tree.edit(&edit);
+        }
+        self.stale = true;
+    }
+
+    /// Reparse the text if it changed since the last parse. Parts of the tree that
+    /// edits didn't touch are reused.
+    pub fn update(&mut self, rope: &Rope) {
+        if !self.stale {
+            return;
+        }
+
+        self.tree = self.parser.parse_with(
+            &mut |byte_idx, _| {
+                rope.byte_slice(byte_idx.min(rope.byte_len())..)
+                    .chunks()
+                    .next()
+                    .map_or(&[] as &[u8], str::as_bytes)
+            },
+            self.tree.as_ref(),
+        );
+        self.stale = false;
+        self.version = TREE_VERSION.fetch_add(1, Ordering::Relaxed) + 1;
+        self.diagnostics = self.find_diagnostics(rope);
+    }
+
+    /// Which parse of the text the tree and diagnostics are from. 0 before the
+    /// first.
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// The syntax errors in the tree, in the order they appear. Text the parser
+    /// couldn't make sense of is an error. Tokens it had to assume were there to
+    /// carry on, like a missing semicolon, are warnings.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// Is 'byte_idx' inside a string or a comment, as of the last parse? Grammars
+    /// name those nodes e.g. string_literal, line_comment or char_literal.
+    pub fn in_string_or_comment(&self, byte_idx: usize) -> bool {
+        let Some(tree) = &self.tree else {
+            return false;
+        };
+
+        let mut node = tree
+            .root_node()
+            .descendant_for_byte_range(byte_idx, byte_idx + 1);
+        while let Some(current) = node {
+            let kind = current.kind();
+            if kind.contains("string") || kind.contains("comment") || kind == "char_literal" {
+                return true;
+            }
+            node = current.parent();
+        }
+        false
+    }
+
+    /// The highlighted parts of 'range', ordered and not overlapping. Where tokens
+    /// nest, e.g. an escape inside a string, the innermost one wins.
+    pub fn spans(&self, rope: &Rope, range: Range<usize>) -> Vec<HighlightSpan> {
+        let Some(tree) = &self.tree else {
+            return vec![];
+        };
+
+        let mut cursor = QueryCursor::new();
+        cursor.set_byte_range(range.clone());
+
+        let text = |node: tree_sitter::Node| {
+            rope.byte_slice(node.byte_range())
+                .chunks()
+                .map(str::as_bytes)
+        };
+
+        let mut captured = vec![];
+        for (m, capture_idx) in cursor.captures(&self.query, tree.root_node(), text) {
+            let capture = m.captures[capture_idx];
+            if let Some(kind) = self.capture_kinds[capture.index as usize] {
+                captured.push(HighlightSpan {
+                    range: capture.node.byte_range(),
+                    kind,
+                });
+            }
+        }
+
+        // Outer tokens first so inner ones are painted over them. A node captured
+        // by several patterns takes the first one, like tree-sitter's own highlighter.
+        captured.sort_by_key(|span| (span.range.start, Reverse(span.range.end)));
+        captured.dedup_by(|later, first| later.range == first.range);
+
+        let mut kinds = vec![None; range.len()];
+        for span in &captured {
+            let start = span.range.start.clamp(range.start, range.end) - range.start;
+            let end = span.range.end.clamp(range.start, range.end) - range.start;
+            kinds[start..end].fill(Some(span.kind));
+        }
+
+        let mut spans: Vec<HighlightSpan> = vec![];
+        for (i, kind) in kinds.into_iter().enumerate() {
+            let Some(kind) = kind else {
+                continue;
+            };
+
+            let byte_idx = range.start + i;
+            match spans.last_mut() {
+                Some(last) if last.kind == kind && last.range.end == byte_idx => {
+                    last.range.end += 1
+                }
+                _ => spans.push(HighlightSpan {
+                    range: byte_idx..byte_idx + 1,
+                    kind,
+                }),
+            }
+        }
+
+        spans
+    }
+}
+
+impl SyntaxHighlighter {
+    fn find_diagnostics(&self, rope: &Rope) -> Vec<Diagnostic> {
+        let Some(tree) = &self.tree else {
+            return vec![];
+        };
+
+        let mut diagnostics = vec![];
+        let mut cursor = tree.walk();
+        if !cursor.node().has_error() {
+            return diagnostics;
+        }
+
+        // Only subtrees with errors in them are visited.
+        loop {
+            let node = cursor.node();
+            let mut descend = node.has_error();
+            if node.is_error() {
+                let message = match snippet(rope, node.byte_range()) {
+                    (text, _) if text.is_empty() => "Syntax error".to_string(),
+                    (text, false) => format!("Unexpected `{text}`"),
+                    (text, true) => format!("Unexpected `{text}...`"),
+                };
+                diagnostics.push(Diagnostic {
+                    range: node.byte_range(),
+                    severity: Severity::Error,
+                    message,
+                });
+                descend = false;
+            } else if node.is_missing() {
+                diagnostics.push(Diagnostic {
+                    range: node.byte_range(),
+                    severity: Severity::Warning,
+                    message: format!("Missing `{}`", node.kind()),
+                });
+            }
+
+            if descend && cursor.goto_first_child() {
+                continue;
+            }
+            while !cursor.goto_next_sibling() {
+                if !cursor.goto_parent() {
+                    return diagnostics;
+                }
+            }
+        }
+    }
+}
+
+/// The start of the first line of 'range', trimmed and cut to SNIPPET_CHARS
+/// characters, and whether it was cut. Only what is quoted is read, however big
+/// the range.
+fn snippet(rope: &Rope, range: Range<usize>) -> (String, bool) {
+    let mut chars = rope
+        .byte_slice(range)
+        .chars()
+        .take_while(|&c| c != '\n')
+        .skip_while(|c| c.is_whitespace());
+    let text: String = chars.by_ref().take(SNIPPET_CHARS).collect();
+    let cut = chars.any(|c| !c.is_whitespace());
+    (text.trim_end().to_string(), cut)
+}
+
+/// The row and byte column of 'byte_idx' in 'rope'.
+fn point_of(rope: &Rope, byte_idx: usize) -> Point {
+    let row = rope.line_of_byte(byte_idx);
+    Point {
+        row,
+        column: byte_idx - rope.byte_of_line(row),
+    }
+}
@@ -0,0 +1,647 @@
+use crate::{GlyphMetrics, GlyphRasterizer};
+use crop::Rope;
+use std::ops::Range;
+
+/// A single line of text as it appears on screen, after wrapping.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VisualLine {
+    /// The bytes of the rope shown on this line. Does not include the newline that ended it.
+    pub range: Range<usize>,
+
+    /// How wide the line is in pixels.
+    pub width: f32,
+
+    /// Did this line end at a newline character, rather than being wrapped or
+    /// reaching the end of the rope?
+    pub ends_in_newline: bool,
+}
+
+impl VisualLine {
+    /// The byte index the following visual line starts at.
+    pub fn next_start(&self) -> usize {
+        self.range.end + self.ends_in_newline as usize
+    }
+}
+
+/// Decides where long lines are broken into multiple visual lines.
+pub trait WrapPolicy {
+    /// Lay out the visual line starting at byte 'start'. A line ends if:
+    ///  - A newline character is reached, or
+    ///  - The policy decides the line is full, or
+    ///  - We reach the end of the rope
+    ///
+    /// The line starts at pixel 0 and may extend to pixel 'max_width'.
+    fn layout_line(
+        &self,
+        content: &Rope,
+        start: usize,
+        max_width: f32,
+        font_size: f32,
+        glyph_rasterizer: &mut dyn GlyphRasterizer,
+    ) -> VisualLine;
+
+    /// The start of every visual line in the hard line starting at 'line_start',
+    /// up to but not including 'end'. Going backwards means laying out a hard line
+    /// from its start, so this is how to do it without repeating the work per line.
+    fn line_starts(
+        &self,
+        content: &Rope,
+        line_start: usize,
+        end: usize,
+        max_width: f32,
+        font_size: f32,
+        glyph_rasterizer: &mut dyn GlyphRasterizer,
+    ) -> Vec<usize> {
+        line_starts_by_layout(
+            self,
+            content,
+            line_start,
+            end,
+            max_width,
+            font_size,
+            glyph_rasterizer,
+        )
+    }
+
+    /// Where this policy breaks lines, if the widths of the characters alone decide
+    /// it. A hard line's Advances can then be measured once and kept for laying it
+    /// out again, e.g. while scrolling up through it. None, the default, for
+    /// policies that need more than widths.
+    fn break_at(&self) -> Option<BreakAt> {
+        None
+    }
+}
+
+/// Where a policy that goes by character widths may break a line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakAt {
+    /// Lines only end at newlines.
+    Nowhere,
+    /// At whatever character would cross the right edge.
+    AnyChar,
+    /// After whitespace or a hyphen, falling back to AnyChar for long words.
+    Words,
+}
+
+/// The default line_starts: lay out each visual line of the hard line in turn.
+fn line_starts_by_layout<P: WrapPolicy + ?Sized>(
+    policy: &P,
+    content: &Rope,
+    line_start: usize,
+    end: usize,
+    max_width: f32,
+    font_size: f32,
+    glyph_rasterizer: &mut dyn GlyphRasterizer,
+) -> Vec<usize> {
+    let mut starts = vec![line_start];
+    let mut start = line_start;
+    loop {
+        let line = policy.layout_line(content, start, max_width, font_size, glyph_rasterizer);
+        let next_start = line.next_start();
+        if next_start >= end || next_start == start {
+            return starts;
+        }
+
+        starts.push(next_start);
+        start = next_start;
+    }
+}
+
+/// How many spaces apart tab stops are unless told otherwise.
+pub const DEFAULT_TAB_WIDTH: usize = 4;
+
+/// How far a tab 'x' pixels into its line moves the pen: up to the next multiple
+/// of 'stop'.
+pub fn tab_advance(x: f32, stop: f32) -> f32 {
+    if stop > 0.0 {
+        stop - x % stop
+    } else {
+        0.0
+    }
+}
+
+/// How far 'c' moves the pen when it is 'x' pixels into its line. The advance of
+/// a tab glyph is taken to be the distance between tab stops, as TabStops gives it.
+pub(crate) fn advance(
+    c: char,
+    x: f32,
+    font_size: f32,
+    glyph_rasterizer: &mut dyn GlyphRasterizer,
+) -> f32 {
+    let advance = glyph_rasterizer.get_glyph(c, font_size).advance.0;
+    if c == '\t' {
+        tab_advance(x, advance)
+    } else {
+        advance
+    }
+}
+
+/// Gives tabs the advance of 'tab_width' spaces, the distance between tab stops,
+/// for the wrap policies to lay lines out with. Carriage returns take up no space,
+/// they are only ever drawn as part of a CRLF line ending.
+pub struct TabStops<'a> {
+    glyph_rasterizer: &'a mut dyn GlyphRasterizer,
+    tab_width: usize,
+}
+
+impl<'a> TabStops<'a> {
+    pub fn new(glyph_rasterizer: &'a mut dyn GlyphRasterizer, tab_width: usize) -> Self {
+        Self {
+            glyph_rasterizer,
+            tab_width,
+        }
+    }
+}
+
+impl GlyphRasterizer for TabStops<'_> {
+    fn get_glyph(&mut self, c: char, font_size: f32) -> GlyphMetrics {
+        let advance = match c {
+            '\t' => self.tab_width as f32,
+            '\r' => 0.0,
+            _ => return self.glyph_rasterizer.get_glyph(c, font_size),
+        };
+
+        let space = self.glyph_rasterizer.get_glyph(' ', font_size);
+        GlyphMetrics {
+            advance: (space.advance.0 * advance, space.advance.1),
+            ..space
+        }
+    }
+}
+
+/// Breaks lines at whatever character would cross the right edge.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CharWrap;
+
+impl WrapPolicy for CharWrap {
+    fn layout_line(
+        &self,
+        content: &Rope,
+        start: usize,
+        max_width: f32,
+        font_size: f32,
+        glyph_rasterizer: &mut dyn GlyphRasterizer,
+    ) -> VisualLine {
+        let mut end = start;
+        let mut width = 0.0;
+        for c in content.byte_slice(start..).chars() {
+            if c == '\n' {
+                return VisualLine {
+                    range: start..end,
+                    width,
+                    ends_in_newline: true,
+                };
+            }
+
+            let advance = advance(c, width, font_size, glyph_rasterizer);
+
+            // Always take at least one character so a narrow view still makes progress.
+            if width + advance >= max_width && end > start {
+                return VisualLine {
+                    range: start..end,
+                    width,
+                    ends_in_newline: false,
+                };
+            }
+
+            width += advance;
+            end += c.len_utf8();
+        }
+
+        VisualLine {
+            range: start..end,
+            width,
+            ends_in_newline: false,
+        }
+    }
+
+    fn line_starts(
+        &self,
+        content: &Rope,
+        line_start: usize,
+        end: usize,
+        max_width: f32,
+        font_size: f32,
+        glyph_rasterizer: &mut dyn GlyphRasterizer,
+    ) -> Vec<usize> {
+        Advances::measure(content, line_start, end, font_size, glyph_rasterizer)
+            .line_starts(max_width, BreakAt::AnyChar)
+    }
+
+    fn break_at(&self) -> Option<BreakAt> {
+        Some(BreakAt::AnyChar)
+    }
+}
+
+/// Prefers breaking lines after whitespace or a hyphen. Words too long to fit
+/// on a line by themselves are broken like CharWrap.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WordWrap;
+
+impl WrapPolicy for WordWrap {
+    fn layout_line(
+        &self,
+        content: &Rope,
+        start: usize,
+        max_width: f32,
+        font_size: f32,
+        glyph_rasterizer: &mut dyn GlyphRasterizer,
+    ) -> VisualLine {
+        let mut end = start;
+        let mut width = 0.0;
+
+        // The last place we could break the line, and the line width up to it.
+        let mut break_point: Option<(usize, f32)> = None;
+
+        for c in content.byte_slice(start..).chars() {
+            if c == '\n' {
+                return VisualLine {
+                    range: start..end,
+                    width,
+                    ends_in_newline: true,
+                };
+            }
+
+            let advance = advance(c, width, font_size, glyph_rasterizer);
+
+            if width + advance >= max_width && end > start {
+                let (end, width) = break_point.unwrap_or((end, width));
+                return VisualLine {
+                    range: start..end,
+                    width,
+                    ends_in_newline: false,
+                };
+            }
+
+            width += advance;
+            end += c.len_utf8();
+
+            if is_break_after(c) {
+                break_point = Some((end, width));
+            }
+        }
+
+        VisualLine {
+            range: start..end,
+            width,
+            ends_in_newline: false,
+        }
+    }
+
+    fn line_starts(
+        &self,
+        content: &Rope,
+        line_start: usize,
+        end: usize,
+        max_width: f32,
+        font_size: f32,
+        glyph_rasterizer: &mut dyn GlyphRasterizer,
+    ) -> Vec<usize> {
+        Advances::measure(content, line_start, end, font_size, glyph_rasterizer)
+            .line_starts(max_width, BreakAt::Words)
+    }
+
+    fn break_at(&self) -> Option<BreakAt> {
+        Some(BreakAt::Words)
+    }
+}
+
+/// Can WordWrap break a line after this character?
+fn is_break_after(c: char) -> bool {
+    c.is_whitespace() || c == '-'
+}
+
+/// The running width of a stretch of a hard line, so the break points of lines in
+/// it can be found by binary search instead of scanning every character again.
+/// That keeps pathological lines, like a single token hundreds of thousands of
+/// characters long, at O(n + wraps * log n) to lay out backwards.
+pub struct Advances {
+    /// The byte index of each character, then the byte index after the last one.
+    offsets: Vec<usize>,
+
+    /// The advance of a tab as measured, which changes with the font, its size and
+    /// the tab width. Tells whether the widths are still good for other glyphs.
+    tab_stop: f32,
+
+    /// The width of the characters before each index in 'offsets'. Kept as f64 so
+    /// sums of font unit advances are exact and agree with layout_line.
+    widths: Vec<f64>,
+
+    /// Indices into 'offsets' that come right after a character WordWrap may
+    /// break after, in ascending order.
+    breaks: Vec<usize>,
+
+    /// Indices into 'offsets' of the tabs, in ascending order. How wide a tab is
+    /// depends on where its visual line starts, so they add nothing to 'widths'.
+    tabs: Vec<usize>,
+}
+
+impl Advances {
+    /// Measure from 'line_start' up to 'end' or the end of the hard line, whichever
+    /// comes first.
+    pub fn measure(
+        content: &Rope,
+        line_start: usize,
+        end: usize,
+        font_size: f32,
+        glyph_rasterizer: &mut dyn GlyphRasterizer,
+    ) -> Self {
+        let mut offsets = vec![line_start];
+        let mut widths = vec![0.0];
+        let mut breaks = vec![];
+        let mut tabs = vec![];
+
+        let mut offset = line_start;
+        let mut width = 0.0;
+        for c in content.byte_slice(line_start..end).chars() {
+            if c == '\n' {
+                break;
+            }
+            if c == '\t' {
+                tabs.push(offsets.len() - 1);
+            } else {
+                width += glyph_rasterizer.get_glyph(c, font_size).advance.0 as f64;
+            }
+
+            offset += c.len_utf8();
+            offsets.push(offset);
+            widths.push(width);
+            if is_break_after(c) {
+                breaks.push(offsets.len() - 1);
+            }
+        }
+
+        Self {
+            offsets,
+            tab_stop: glyph_rasterizer.get_glyph('\t', font_size).advance.0,
+            widths,
+            breaks,
+            tabs,
+        }
+    }
+
+    /// Were these measured from 'line_start' with the glyphs 'glyph_rasterizer'
+    /// gives now? Edits to the text aren't noticed, whoever keeps them has to.
+    pub fn measured(
+        &self,
+        line_start: usize,
+        font_size: f32,
+        glyph_rasterizer: &mut dyn GlyphRasterizer,
+    ) -> bool {
+        self.offsets[0] == line_start
+            && self.tab_stop == glyph_rasterizer.get_glyph('\t', font_size).advance.0
+    }
+
+    /// Where each visual line starts, breaking at 'break_at'.
+    pub fn line_starts(&self, max_width: f32, break_at: BreakAt) -> Vec<usize> {
+        if break_at == BreakAt::Nowhere {
+            return vec![self.offsets[0]];
+        }
+
+        let mut starts = vec![self.offsets[0]];
+        let mut start = 0;
+        while let Some(mut end) = self.first_past_edge(start, max_width as f64) {
+            if break_at == BreakAt::Words {
+                let breaks_up_to_end = self.breaks.partition_point(|&b| b <= end);
+                if let Some(&b) = self.breaks[..breaks_up_to_end].last() {
+                    if b > start {
+                        end = b;
+                    }
+                }
+            }
+
+            starts.push(self.offsets[end]);
+            start = end;
+        }
+        starts
+    }
+
+    /// The index of the first character that would reach the right edge of a visual
+    /// line starting at index 'start', or None if the rest of the hard line fits.
+    /// Every line takes at least one character. The running widths between tabs
+    /// are searched, and each tab is measured from where the line starts.
+    fn first_past_edge(&self, start: usize, max_width: f64) -> Option<usize> {
+        let chars = self.offsets.len() - 1;
+
+        // How wide the line is up to index 'from'.
+        let mut x = 0.0;
+        let mut from = start;
+        loop {
+            let tab = self.tabs[self.tabs.partition_point(|&t| t < from)..]
+                .first()
+                .copied()
+                .unwrap_or(chars);
+
+            // Character i ends at widths[i + 1] - base on this line.
+            let first = from.max(start + 1);
+            if first < tab {
+                let base = self.widths[from] - x;
+                let fitting =
+                    self.widths[first + 1..=tab].partition_point(|w| w - base < max_width);
+                if first + fitting < tab {
+                    return Some(first + fitting);
+                }
+            }
+            if tab == chars {
+                return None;
+            }
+
+            x += self.widths[tab] - self.widths[from];
+            let advance = tab_advance(x as f32, self.tab_stop) as f64;
+            if x + advance >= max_width && tab > start {
+                return Some(tab);
+            }
+            x += advance;
+            from = tab + 1;
+        }
+    }
+}
+
+/// Never wraps. Lines only end at newlines, and may be wider than the view.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoWrap;
+
+impl WrapPolicy for NoWrap {
+    fn layout_line(
+        &self,
+        content: &Rope,
+        start: usize,
+        _max_width: f32,
+        font_size: f32,
+        glyph_rasterizer: &mut dyn GlyphRasterizer,
+    ) -> VisualLine {
+        CharWrap.layout_line(content, start, f32::INFINITY, font_size, glyph_rasterizer)
+    }
+
+    fn line_starts(
+        &self,
+        _content: &Rope,
+        line_start: usize,
+        _end: usize,
+        _max_width: f32,
+        _font_size: f32,
+        _glyph_rasterizer: &mut dyn GlyphRasterizer,
+    ) -> Vec<usize> {
+        vec![line_start]
+    }
+
+    fn break_at(&self) -> Option<BreakAt> {
+        Some(BreakAt::Nowhere)
+    }
+}
+
+/// Lay out the visual line that comes right before the one starting at 'end'.
+/// Primarily used for scrolling up. Returns None at the start of the rope.
+///
+/// Wrapping is only well defined going forwards, so this lays out the hard
+/// line containing 'end' from its start and returns the last visual line before 'end'.
+pub fn layout_line_before(
+    policy: &dyn WrapPolicy,
+    content: &Rope,
+    end: usize,
+    max_width: f32,
+    font_size: f32,
+    glyph_rasterizer: &mut dyn GlyphRasterizer,
+) -> Option<VisualLine> {
+    if end == 0 {
+        return None;
+    }
+
+    let line_start = content.byte_of_line(content.line_of_byte(end - 1));
+    let starts = policy.line_starts(
+        content,
+        line_start,
+        end,
+        max_width,
+        font_size,
+        glyph_rasterizer,
+    );
+    let start = *starts.last().unwrap();
+    Some(policy.layout_line(content, start, max_width, font_size, glyph_rasterizer))
+}
+
+/// The start of the visual line 'count' lines before the one starting at 'end', or
+/// 0 if the rope starts sooner. Each hard line on the way is only laid out once.
+pub fn line_start_before(
+    policy: &dyn WrapPolicy,
+    content: &Rope,
+    end: usize,
+    count: usize,
+    max_width: f32,
+    font_size: f32,
+    glyph_rasterizer: &mut dyn GlyphRasterizer,
+) -> usize {
+    start_before(content, end, count, |line_start, end| {
+        policy.line_starts(
+            content,
+            line_start,
+            end,
+            max_width,
+            font_size,
+            glyph_rasterizer,
+        )
+    })
+}
+
+/// line_start_before, with 'line_starts' giving the starts of the visual lines
+/// from 'line_start' up to but not including 'end', like WrapPolicy::line_starts.
+pub(crate) fn start_before(
+    content: &Rope,
+    end: usize,
+    count: usize,
+    mut line_starts: impl FnMut(usize, usize) -> Vec<usize>,
+) -> usize {
+    let mut end = end;
+    let mut remaining = count;
+    while remaining > 0 && end > 0 {
+        let line_start = content.byte_of_line(content.line_of_byte(end - 1));
+        let starts = line_starts(line_start, end);
+        if starts.len() >= remaining {
+            return starts[starts.len() - remaining];
+        }
+
+        remaining -= starts.len();
+        end = line_start;
+    }
+
+    end
+}
+
+/// Lays out visual lines lazily, one per call to next(), so callers that stop
+/// early (e.g. at the bottom of the view) never lay out more than they need.
+pub struct VisualLines<'a, G> {
+    policy: &'a dyn WrapPolicy,
+    content: &'a Rope,
+    max_width: f32,
+    font_size: f32,
+    tab_width: usize,
+    glyph_rasterizer: &'a mut G,
+
+    /// Where the next line starts. None once the end of the rope has been reached.
+    next_start: Option<usize>,
+
+    /// How many more lines may be produced.
+    remaining: usize,
+}
+
+impl<'a, G: GlyphRasterizer> VisualLines<'a, G> {
+    /// Lay out the lines of 'content' from byte 'start', which should be the start
+    /// of a visual line, producing at most 'max_lines' of them.
+    pub fn new(
+        policy: &'a dyn WrapPolicy,
+        content: &'a Rope,
+        start: usize,
+        max_width: f32,
+        font_size: f32,
+        glyph_rasterizer: &'a mut G,
+        max_lines: usize,
+    ) -> Self {
+        Self {
+            policy,
+            content,
+            max_width,
+            font_size,
+            tab_width: DEFAULT_TAB_WIDTH,
+            glyph_rasterizer,
+            next_start: Some(start),
+            remaining: max_lines,
+        }
+    }
+
+    /// Put tab stops 'tab_width' spaces apart instead of DEFAULT_TAB_WIDTH.
+    pub fn tab_width(mut self, tab_width: usize) -> Self {
+        self.tab_width = tab_width;
+        self
+    }
+
+    /// Access the rasterizer the lines are laid out with, e.g. to draw the glyphs
+    /// of the line that was just returned.
+    pub fn glyph_rasterizer(&mut self) -> &mut G {
+        self.glyph_rasterizer
+    }
+}
+
+impl<G: GlyphRasterizer> Iterator for VisualLines<'_, G> {
+    type Item = VisualLine;
+
+    fn next(&mut self) -> Option<VisualLine> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let start = self.next_start?;
+        let line = self.policy.layout_line(
+            self.content,
+            start,
+            self.max_width,
+            self.font_size,
+            &mut TabStops::new(self.glyph_rasterizer, self.tab_width),
+        );
+
+        let is_last = line.range.end == self.content.byte_len() && !line.ends_in_newline;
+        self.next_start = (!is_last).then(|| line.next_start());
+        self.remaining -= 1;
+
+        Some(line)
+    }
+}
@@ -1,4 +1,47 @@
-use crop::{Rope, RopeBuilder, RopeSlice};
+//! A text editing engine: a rope with cursors, selections, undo, search and
+//! syntax highlighting, laid out into wrapped lines for any renderer that can
+//! measure glyphs. Nothing here depends on a windowing or graphics library.
+//!
+//! [`TextEditor`] is the editor itself. [`TextBuffer`] reads text by bytes and
+//! lines, from an editor or a plain [`Rope`]. [`layout`] wraps lines, and its
+//! [`VisualLines`] lays them out one at a time. [`loader`] reads large files on
+//! another thread, [`mapped`] reads files too big to edit without loading them,
+//! and [`Encoding`] converts files that aren't UTF-8. [`LineInput`] edits a single
+//! line, like a search query.
+//!
+//! The rope and regex crates appear in the API, so they are re-exported to be
+//! used at the same versions.
+
+mod buffer;
+pub mod encoding;
+mod grapheme;
+pub mod highlight;
+pub mod layout;
+mod line_input;
+pub mod loader;
+pub mod mapped;
+mod undo;
+
+pub use buffer::TextBuffer;
+pub use crop::{self, Rope, RopeSlice};
+pub use encoding::Encoding;
+pub use layout::{CharWrap, NoWrap, VisualLine, VisualLines, WordWrap, WrapPolicy};
+pub use line_input::LineInput;
+pub use regex::{self, Regex};
+
+use crop::RopeBuilder;
+use highlight::{Diagnostic, HighlightSpan, Language, SyntaxHighlighter};
+use layout::{Advances, TabStops};
+use loader::LoadedFile;
+use std::{
+    cell::{Cell, OnceCell, RefCell},
+    collections::VecDeque,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    ops::Range,
+    path::{Path, PathBuf},
+};
+use undo::{Edit, UndoStack};
 
 /// Contains information needed to lay out a glyph on the screen.
 /// https://freetype.org/freetype2/docs/glyphs/glyphs-3.html
@@ -10,17 +53,239 @@ pub struct GlyphMetrics {
     pub pos: (f32, f32),
 }
 
+/// How a font spaces its lines at one size, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LineMetrics {
+    /// How far the font reaches above the baseline.
+    pub ascent: f32,
+
+    /// How far the font reaches below the baseline, as a positive distance.
+    pub descent: f32,
+
+    /// Space the font asks for between the descent of a line and the ascent of
+    /// the next.
+    pub line_gap: f32,
+
+    /// Multiplies the height the font gives lines, e.g. 1.5 spreads them out.
+    pub spacing: f32,
+}
+
+impl LineMetrics {
+    /// Metrics for when the font can't say: lines 1.2 times the font size apart,
+    /// with a fifth of that below the baseline.
+    pub fn fallback(font_size: f32) -> Self {
+        Self {
+            ascent: font_size,
+            descent: font_size * 0.2,
+            line_gap: 0.0,
+            spacing: 1.0,
+        }
+    }
+
+    pub fn with_spacing(mut self, spacing: f32) -> Self {
+        self.spacing = spacing;
+        self
+    }
+
+    /// The distance from one baseline to the next, never less than a pixel.
+    pub fn line_height(&self) -> f32 {
+        ((self.ascent + self.descent + self.line_gap) * self.spacing).max(1.0)
+    }
+
+    /// How far below the top of a line its baseline is. The space beyond the
+    /// ascent and descent is shared evenly above and below them, so nothing
+    /// reaches into the lines next to it.
+    pub fn baseline(&self) -> f32 {
+        (self.line_height() - self.ascent - self.descent) / 2.0 + self.ascent
+    }
+
+    /// How far above the baseline the cursor starts, and how tall it is. It spans
+    /// the whole line, so it grows with the spacing the way the lines do.
+    pub fn cursor(&self) -> (f32, f32) {
+        (self.baseline(), self.line_height())
+    }
+}
+
 pub trait GlyphRasterizer {
     /// Get the metrics from the given character and font size.
     fn get_glyph(&mut self, c: char, font_size: f32) -> GlyphMetrics;
+
+    /// How the font spaces lines at 'font_size', with a spacing of 1.
+    fn line_metrics(&mut self, font_size: f32) -> LineMetrics {
+        LineMetrics::fallback(font_size)
+    }
+}
+
+/// Brackets searched for when matching the one at the cursor.
+const BRACKETS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
+/// How many characters to look through for a matching bracket before giving up.
+const MAX_BRACKET_SCAN: usize = 100_000;
+
+/// The brackets matching_bracket() found with the cursor at 'cursor' and the
+/// syntax tree at 'syntax_version'.
+#[derive(Debug, Clone, Copy)]
+struct BracketMatch {
+    cursor: usize,
+    syntax_version: u64,
+    found: Option<(usize, usize)>,
+}
+
+/// Opening characters and the characters that close them, for auto-closing.
+const PAIRS: [(char, char); 4] = [('(', ')'), ('[', ']'), ('{', '}'), ('"', '"')];
+
+/// The kinds of characters that make up a word when double clicking.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CharClass {
+    Word,
+    Space,
+    Other,
+}
+
+/// How lines end.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::Crlf => "\r\n",
+        }
+    }
+
+    /// 'text' with every line ending replaced by this one.
+    pub fn convert(self, text: &str) -> String {
+        let mut converted = String::with_capacity(text.len());
+        for line in text.split_inclusive('\n') {
+            match line.strip_suffix('\n') {
+                Some(line) => {
+                    converted.push_str(line.strip_suffix('\r').unwrap_or(line));
+                    converted.push_str(self.as_str());
+                }
+                None => converted.push_str(line),
+            }
+        }
+        converted
+    }
+}
+
+/// Counts of the line endings in a text.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LineEndings {
+    pub lf: usize,
+    pub crlf: usize,
+
+    /// How many line endings the text ends with, e.g. 2 for a text ending in a
+    /// blank line.
+    pub trailing: usize,
+}
+
+impl LineEndings {
+    /// Replace the counts 'before' with 'after', for an edit that changed the
+    /// part of the text they were counted in.
+    fn update(&mut self, before: LineEndings, after: LineEndings) {
+        self.lf = self.lf + after.lf - before.lf;
+        self.crlf = self.crlf + after.crlf - before.crlf;
+    }
+
+    /// Does the text use both kinds of line endings?
+    pub fn is_mixed(&self) -> bool {
+        self.lf > 0 && self.crlf > 0
+    }
+
+    /// The line ending most lines use. Lf if there is a tie.
+    pub fn dominant(&self) -> LineEnding {
+        if self.crlf > self.lf {
+            LineEnding::Crlf
+        } else {
+            LineEnding::Lf
+        }
+    }
+}
+
+/// Count the line endings whose '\n' is in 'range' of 'rope', which is clamped to
+/// the text. A '\r' just before the range still makes the first one a CRLF.
+fn count_line_endings(rope: &Rope, range: Range<usize>) -> LineEndings {
+    let range = range.start..range.end.min(rope.byte_len());
+    let mut counts = LineEndings::default();
+    let mut prev = range.start.checked_sub(1).map(|i| rope.byte(i));
+    // The end may fall inside a character, so the bytes are taken by count.
+    for b in rope.byte_slice(range.start..).bytes().take(range.len()) {
+        if b == b'\n' {
+            if prev == Some(b'\r') {
+                counts.crlf += 1;
+            } else {
+                counts.lf += 1;
+            }
+        }
+        prev = Some(b);
+    }
+    counts
+}
+
+/// How the lines of a text are indented.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Indentation {
+    Tabs,
+    /// Each level is this many spaces.
+    Spaces(usize),
+}
+
+/// How many lines are looked at when guessing the indentation of a text.
+const INDENTATION_SAMPLE_LINES: usize = 10_000;
+
+/// Sizes of a buffer's text and history, for debugging.
+#[derive(Debug, Clone, Copy)]
+pub struct BufferStats {
+    pub bytes: usize,
+    pub lines: usize,
+
+    /// How many chunks the rope stores the text in.
+    pub chunks: usize,
+    pub undo_steps: usize,
+    pub redo_steps: usize,
+
+    /// Roughly how much memory the undo and redo history hold.
+    pub history_bytes: usize,
+}
+
+/// A cursor and the other end of its selection, if it has one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Caret {
+    cursor: usize,
+    anchor: Option<usize>,
+}
+
+impl Caret {
+    fn selection(&self) -> Option<Range<usize>> {
+        let anchor = self.anchor?;
+        (anchor != self.cursor).then(|| anchor.min(self.cursor)..anchor.max(self.cursor))
+    }
+
+    /// The span the caret covers: its selection, or just the cursor.
+    fn span(&self) -> Range<usize> {
+        self.selection().unwrap_or(self.cursor..self.cursor)
+    }
 }
 
 #[derive(Debug)]
 pub enum ScrollAmount {
-    Up { lines: usize },
-    Down { lines: usize },
+    Up {
+        lines: usize,
+    },
+    Down {
+        lines: usize,
+    },
     ToStart,
     ToEnd,
+    /// Scroll up a full window of lines, moving the cursor with the text.
+    PageUp,
+    /// Scroll down a full window of lines, moving the cursor with the text.
+    PageDown,
 }
 
 pub struct TextEditor {
@@ -36,14 +301,72 @@ pub struct TextEditor {
     /// The current font size
     font_size: f32,
 
+    /// How far apart lines are. Set by the renderer from the font it draws with,
+    /// so clicks, scrolling and paging line up with what is drawn.
+    line_metrics: LineMetrics,
+
     /// Window width in pixels
     window_width: f32,
 
     /// Window height in pixels
     window_height: f32,
 
-    /// Is the control key currently pressed?
-    pub ctrl_down: bool,
+    /// The other end of the selection. The selection spans from here to the cursor.
+    selection_anchor: Option<usize>,
+
+    /// Cursors besides the main one, added with add_next_occurrence. Typing, deleting
+    /// and moving happen at every cursor.
+    extra_carets: Vec<Caret>,
+
+    /// Cursor movement extends the selection while this is set, e.g. while shift
+    /// is held down.
+    pub extend_selection: bool,
+
+    /// Edits that can be undone and redone.
+    history: UndoStack,
+
+    /// Decides where long lines wrap.
+    wrap_policy: Box<dyn WrapPolicy>,
+
+    /// How many spaces apart tab stops are.
+    tab_width: usize,
+
+    /// Indent with a tab stop's worth of spaces rather than with a tab.
+    insert_spaces: bool,
+
+    /// The file this text was loaded from and is saved to.
+    path: Option<PathBuf>,
+
+    /// How the file stores the text.
+    encoding: Encoding,
+
+    /// Insert the closing bracket or quote along with the opening one.
+    auto_close: bool,
+
+    /// Colors the text by its syntax. None for languages without a grammar.
+    syntax: Option<SyntaxHighlighter>,
+
+    /// Set once highlighting was turned off, so a new path doesn't turn it back on.
+    syntax_disabled: bool,
+
+    /// The LF and CRLF counts of the text, kept up to date by every edit.
+    /// 'trailing' isn't kept, line_endings() counts it from the end of the text.
+    line_endings: LineEndings,
+
+    /// The widths of the hard line last wrapped going backwards, so scrolling up
+    /// through a long line doesn't measure it again for every step. Cleared by
+    /// edits.
+    advances: RefCell<Option<Advances>>,
+
+    /// The text as one string, for regex searches, which can't search a rope. Made
+    /// by the first search after an edit and cleared by edits.
+    text: OnceCell<String>,
+
+    /// What stats() last counted, until the next edit.
+    stats: Cell<Option<BufferStats>>,
+
+    /// What matching_bracket() last found. Cleared by edits.
+    bracket_match: Cell<Option<BracketMatch>>,
 }
 
 impl TextEditor {
@@ -53,271 +376,2139 @@ impl TextEditor {
         let mut builder = RopeBuilder::new();
         builder.append(content);
 
-        let text = builder.build();
+        Self::with_rope(builder.build(), window_width, window_height, font_size)
+    }
 
+    fn with_rope(content: Rope, window_width: f32, window_height: f32, font_size: f32) -> Self {
         Self {
-            content: text,
+            line_endings: count_line_endings(&content, 0..content.byte_len()),
+            content,
             cursor_position: 0,
             text_start_idx: 0,
             font_size,
+            line_metrics: LineMetrics::fallback(font_size),
             window_width,
             window_height,
-            ctrl_down: false,
+            selection_anchor: None,
+            extra_carets: vec![],
+            extend_selection: false,
+            history: UndoStack::default(),
+            wrap_policy: Box::new(CharWrap),
+            tab_width: layout::DEFAULT_TAB_WIDTH,
+            insert_spaces: true,
+            path: None,
+            encoding: Encoding::Utf8,
+            auto_close: true,
+            syntax: None,
+            syntax_disabled: false,
+            advances: RefCell::new(None),
+            text: OnceCell::new(),
+            stats: Cell::new(None),
+            bracket_match: Cell::new(None),
         }
     }
 
-    pub fn update_window_size(&mut self, new_width: f32, new_height: f32) {
-        self.window_width = new_width;
-        self.window_height = new_height;
+    /// Creates a text editor holding the contents of the file at 'path', in the
+    /// encoding detected from them. Saving writes back to the same file, in the
+    /// same encoding.
+    pub fn from_file(
+        path: impl Into<PathBuf>,
+        window_width: f32,
+        window_height: f32,
+        font_size: f32,
+    ) -> io::Result<Self> {
+        let path = path.into();
+        let bytes = fs::read(&path)?;
+        let encoding = Encoding::detect(&bytes);
+
+        let mut editor = Self::new(
+            &encoding.decode(&bytes),
+            window_width,
+            window_height,
+            font_size,
+        );
+        editor.encoding = encoding;
+        editor.set_path(path);
+        Ok(editor)
     }
 
-    pub fn update_font_size(&mut self, new_font_size: f32) {
-        self.font_size = new_font_size;
+    /// Creates a text editor holding a file read by a FileLoader.
+    pub fn from_loaded(
+        file: LoadedFile,
+        window_width: f32,
+        window_height: f32,
+        font_size: f32,
+    ) -> Self {
+        let mut editor = Self::with_rope(file.rope, window_width, window_height, font_size);
+        editor.encoding = file.encoding;
+        editor.set_path(file.path);
+        editor
     }
 
-    /// Get the current position of the cursor
-    pub fn cursor_position(&self) -> usize {
-        self.cursor_position
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
     }
 
-    /// Get the starting position of the text area that will be rendered
-    pub fn text_start_idx(&self) -> usize {
-        self.text_start_idx
+    /// Save the text in 'encoding' from now on.
+    pub fn set_encoding(&mut self, encoding: Encoding) {
+        self.encoding = encoding;
     }
 
-    /// This function will use the glyph metrics to decide when to wrap characters.
-    /// A line ends if:
-    ///  - A newline character is reached, or
-    ///  - We cannot fit any more characters on the current line, or
-    ///  - We reach the end of the internal character rope
-    pub fn layout_lines(&self, glyph_rasterizer: &mut impl GlyphRasterizer) -> Vec<RopeSlice<'_>> {
-        let mut lines = vec![];
-        let line_height = self.font_size * 1.2;
-        let start_index = self.text_start_idx;
+    /// Read the file again, taking it to be in 'encoding', for when the detected
+    /// one was wrong. Replacing the text is a single undo step.
+    pub fn reopen_with_encoding(&mut self, encoding: Encoding) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no file to read the text from",
+            ));
+        };
 
-        let mut byte_index = start_index;
-        let mut y = 0.0;
-        loop {
-            let (has_trailing_newline, line) = self.layout_line(byte_index, glyph_rasterizer);
-            byte_index += line.byte_len();
-            lines.push(line);
-            y += line_height;
+        let text = encoding.decode(&fs::read(path)?);
+        self.replace_ranges(&[(0..self.len(), text)]);
+        self.encoding = encoding;
+        // The text is what the file holds, read another way.
+        self.history.mark_saved();
+        Ok(())
+    }
 
-            if has_trailing_newline {
-                byte_index += 1;
-            }
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
 
-            if y >= self.window_height {
-                // We are done!
-                break;
-            }
+    /// Set the file the text is saved to. Its extension picks the syntax highlighting.
+    pub fn set_path(&mut self, path: impl Into<PathBuf>) {
+        let path = path.into();
+        let language = Language::from_path(&path);
+        if !self.syntax_disabled && self.syntax.as_ref().map(|s| s.language()) != language {
+            self.syntax = language.map(SyntaxHighlighter::new);
         }
+        self.path = Some(path);
+    }
 
-        lines
+    /// Stop highlighting the text, e.g. because it is too big to parse quickly.
+    pub fn disable_syntax(&mut self) {
+        self.syntax = None;
+        self.syntax_disabled = true;
     }
 
-    /// This function will use the glyph metrics to decide when to wrap characters.
-    /// The line ends if:
-    ///  - A newline character is reached, or
-    ///  - We cannot fit any more characters on the current line, or
-    ///  - We reach the end of the internal character rope
-    ///
-    /// We assume that the start of the line is pixel 0, and it ends at pixel 'self.window_width'
-    ///
-    /// Returns: bool: If there is a trailing newline that needs to be consumed
-    ///          RopeSlice: the content of this line
-    fn layout_line(
-        &self,
-        start_index: usize,
-        glyph_rasterizer: &mut impl GlyphRasterizer,
-    ) -> (bool, RopeSlice<'_>) {
-        let mut byte_index = start_index;
-        let mut x = 0.0;
-        for c in self.content.byte_slice(start_index..).chars() {
-            // We've reached the end of this line, save the offsets
-            if c == '\n' {
-                return (true, self.content.byte_slice(start_index..byte_index));
-            }
+    pub fn syntax_disabled(&self) -> bool {
+        self.syntax_disabled
+    }
+
+    /// Bring the syntax tree up to date with the edits made since the last call.
+    /// Needed before highlight_spans() sees them.
+    pub fn update_syntax(&mut self) {
+        if let Some(syntax) = &mut self.syntax {
+            syntax.update(&self.content);
+        }
+    }
 
-            let glyph_metrics = glyph_rasterizer.get_glyph(c, self.font_size);
+    /// The syntax errors in the text as of the last update_syntax, in order.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        self.syntax
+            .as_ref()
+            .map_or(&[], |syntax| syntax.diagnostics())
+    }
 
-            if x + glyph_metrics.advance.0 >= self.window_width {
-                return (false, self.content.byte_slice(start_index..byte_index));
-            }
+    /// Changes whenever update_syntax reparses the text, so anything built from
+    /// diagnostics() only needs rebuilding when it does. Differs between editors
+    /// with syntax trees. 0 without one.
+    pub fn syntax_version(&self) -> u64 {
+        self.syntax.as_ref().map_or(0, |syntax| syntax.version())
+    }
 
-            x += glyph_metrics.advance.0;
-            byte_index += c.len_utf8();
+    /// The syntax highlighted parts of 'range', ordered by where they start.
+    pub fn highlight_spans(&self, range: Range<usize>) -> Vec<HighlightSpan> {
+        self.syntax
+            .as_ref()
+            .map_or(vec![], |syntax| syntax.spans(&self.content, range))
+    }
+
+    /// Insert into the rope, keeping the syntax tree in step. All insertions go
+    /// through here.
+    fn rope_insert(&mut self, byte_idx: usize, text: &str) {
+        if let Some(syntax) = &mut self.syntax {
+            syntax.insert(&self.content, byte_idx, text);
         }
+        // A '\n' right after the insertion may lose or gain the '\r' before it.
+        let before = count_line_endings(&self.content, byte_idx..byte_idx + 1);
+        self.content.insert(byte_idx, text);
+        self.advances.take();
+        self.text.take();
+        self.stats.take();
+        self.bracket_match.take();
+        let after = count_line_endings(&self.content, byte_idx..byte_idx + text.len() + 1);
+        self.line_endings.update(before, after);
+    }
 
-        // If we haven't returned yet, this is probably the last line
-        (false, self.content.byte_slice(start_index..))
+    /// Delete from the rope, keeping the syntax tree in step. All deletions go
+    /// through here.
+    fn rope_delete(&mut self, range: Range<usize>) {
+        if let Some(syntax) = &mut self.syntax {
+            syntax.delete(&self.content, range.clone());
+        }
+        let before = count_line_endings(&self.content, range.start..range.end + 1);
+        self.content.delete(range.clone());
+        self.advances.take();
+        self.text.take();
+        self.stats.take();
+        self.bracket_match.take();
+        let after = count_line_endings(&self.content, range.start..range.start + 1);
+        self.line_endings.update(before, after);
     }
 
-    // Lays out the line in before the one we are on (from start_index). Primarily used for scrolling up.
-    fn layout_line_rev(
-        &self,
-        start_index: usize,
-        glyph_rasterizer: &mut impl GlyphRasterizer,
-    ) -> (bool, RopeSlice<'_>) {
-        let mut byte_index = start_index;
-        let mut x = self.window_width;
-        for c in self.content.byte_slice(..start_index).chars().rev() {
-            if c == '\n' && byte_index == start_index {
-                byte_index = byte_index.saturating_sub(1);
-                continue;
-            } else if c == '\n' {
-                // We've reached the start of this line, save the offsets
-                return (true, self.content.byte_slice(byte_index..start_index));
+    /// Write the text to its file in its encoding. The text is written to a temporary
+    /// file next to it first and then renamed over it, so a failed save never leaves
+    /// a half written file.
+    pub fn save(&mut self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                "no file to save the text to",
+            ));
+        };
+
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let tmp_path = path.with_file_name(format!(".{file_name}.tmp"));
+
+        let result = (|| {
+            let mut writer = BufWriter::new(File::create(&tmp_path)?);
+            writer.write_all(self.encoding.bom())?;
+            let mut bytes = vec![];
+            for chunk in self.content.chunks() {
+                bytes.clear();
+                self.encoding.encode_into(chunk, &mut bytes)?;
+                writer.write_all(&bytes)?;
             }
 
-            let glyph_metrics = glyph_rasterizer.get_glyph(c, self.font_size);
+            let file = writer.into_inner().map_err(|e| e.into_error())?;
+            file.sync_all()?;
 
-            if x - glyph_metrics.advance.0 <= 0.0 {
-                return (false, self.content.byte_slice(byte_index..start_index));
+            // Keep the permissions of the file we are replacing.
+            if let Ok(metadata) = fs::metadata(path) {
+                fs::set_permissions(&tmp_path, metadata.permissions())?;
             }
 
-            x -= glyph_metrics.advance.0;
-            byte_index -= c.len_utf8();
+            fs::rename(&tmp_path, path)
+        })();
+
+        match result {
+            Ok(()) => self.history.mark_saved(),
+            Err(_) => {
+                let _ = fs::remove_file(&tmp_path);
+            }
         }
 
-        // If we haven't returned yet, this is probably the last line
-        (false, self.content.byte_slice(start_index..))
+        result
     }
 
-    /// Move the cursor one position to the left.
-    pub fn left(&mut self) {
-        let mut curr_pos = self.cursor_position;
-        loop {
-            curr_pos = curr_pos.saturating_sub(1);
+    /// Has the text changed since it was opened or last saved?
+    pub fn is_modified(&self) -> bool {
+        self.history.is_modified()
+    }
 
-            if self.content.is_char_boundary(curr_pos) {
-                break;
-            }
+    pub fn update_window_size(&mut self, new_width: f32, new_height: f32) {
+        self.window_width = new_width;
+        self.window_height = new_height;
+    }
+
+    pub fn font_size(&self) -> f32 {
+        self.font_size
+    }
+
+    /// Change the font size. The line metrics are scaled along with it until the
+    /// renderer sets the font's own.
+    pub fn update_font_size(&mut self, new_font_size: f32) {
+        if new_font_size != self.font_size && self.font_size > 0.0 {
+            let scale = new_font_size / self.font_size;
+            self.line_metrics = LineMetrics {
+                ascent: self.line_metrics.ascent * scale,
+                descent: self.line_metrics.descent * scale,
+                line_gap: self.line_metrics.line_gap * scale,
+                spacing: self.line_metrics.spacing,
+            };
         }
+        self.font_size = new_font_size;
+    }
 
-        self.cursor_position = curr_pos;
+    pub fn line_metrics(&self) -> LineMetrics {
+        self.line_metrics
     }
 
-    /// Move the cursor one position to the right.
-    pub fn right(&mut self) {
-        let mut curr_pos = self.cursor_position;
-        loop {
-            curr_pos += 1;
+    pub fn set_line_metrics(&mut self, line_metrics: LineMetrics) {
+        self.line_metrics = line_metrics;
+    }
 
-            if curr_pos >= self.content.byte_len() {
-                return;
-            }
+    /// Get the current position of the cursor
+    pub fn cursor_position(&self) -> usize {
+        self.cursor_position
+    }
 
-            if self.content.is_char_boundary(curr_pos) {
-                break;
-            }
+    /// Get the selected byte range, ordered from start to end. None if nothing is selected.
+    pub fn selection_range(&self) -> Option<Range<usize>> {
+        self.main_caret().selection()
+    }
+
+    fn main_caret(&self) -> Caret {
+        Caret {
+            cursor: self.cursor_position,
+            anchor: self.selection_anchor,
         }
+    }
 
-        self.cursor_position = curr_pos;
+    /// The positions of the cursors besides the main one.
+    pub fn extra_cursors(&self) -> impl Iterator<Item = usize> + '_ {
+        self.extra_carets.iter().map(|caret| caret.cursor)
     }
 
-    pub fn delete(&mut self) {
-        let len = self.content.byte_len();
-        if len == 0 || self.cursor_position + 1 > self.content.byte_len() {
+    /// The selected byte ranges of every cursor, ordered from start to end.
+    pub fn selection_ranges(&self) -> Vec<Range<usize>> {
+        let mut ranges: Vec<_> = std::iter::once(self.main_caret())
+            .chain(self.extra_carets.iter().copied())
+            .filter_map(|caret| caret.selection())
+            .collect();
+        ranges.sort_by_key(|range| range.start);
+        ranges
+    }
+
+    /// Go back to just the main cursor. Returns false if there were no others.
+    pub fn clear_extra_cursors(&mut self) -> bool {
+        let had_extra = !self.extra_carets.is_empty();
+        self.extra_carets.clear();
+        had_extra
+    }
+
+    /// Select the word at the cursor if nothing is selected. Otherwise add a cursor
+    /// selecting the next occurrence of the selected text, which becomes the main
+    /// cursor. The search wraps around to the start of the text.
+    pub fn add_next_occurrence(&mut self) {
+        let Some(selection) = self.selection_range() else {
+            let word = self.word_range_at(self.cursor_position);
+            if !word.is_empty() {
+                self.selection_anchor = Some(word.start);
+                self.cursor_position = word.end;
+            }
             return;
+        };
+
+        let needle = self.content.byte_slice(selection.clone()).to_string();
+        let taken: Vec<_> = std::iter::once(self.main_caret())
+            .chain(self.extra_carets.iter().copied())
+            .map(|caret| caret.span())
+            .collect();
+
+        // Search on from the main cursor, which is the last one added, and stop at
+        // the first occurrence without a cursor.
+        let after = self.occurrences(selection.end..self.content.byte_len(), &needle);
+        let before = self.occurrences(0..selection.end, &needle);
+        let next = after.chain(before).find(|range| !taken.contains(range));
+
+        if let Some(next) = next {
+            self.extra_carets.push(self.main_caret());
+            self.selection_anchor = Some(next.start);
+            self.cursor_position = next.end;
+            self.history.break_coalescing();
         }
+    }
 
-        // Find char boundry one character back
-        let mut curr_pos = self.cursor_position;
-        loop {
-            curr_pos += 1;
+    /// Add a cursor on the line above the topmost cursor, in the main cursor's column.
+    pub fn add_cursor_above(&mut self) {
+        self.add_cursor_on_next_line(false);
+    }
 
-            if curr_pos >= self.content.byte_len() {
-                return;
-            }
+    /// Add a cursor on the line below the bottommost cursor, in the main cursor's column.
+    pub fn add_cursor_below(&mut self) {
+        self.add_cursor_on_next_line(true);
+    }
 
-            if self.content.is_char_boundary(curr_pos) {
-                break;
+    fn add_cursor_on_next_line(&mut self, below: bool) {
+        let cursors = std::iter::once(self.cursor_position).chain(self.extra_cursors());
+        let edge = if below {
+            cursors.max().unwrap()
+        } else {
+            cursors.min().unwrap()
+        };
+
+        let line = self.content.line_of_byte(edge);
+        let target = match below {
+            true if line + 1 < self.line_count() => line + 1,
+            false if line > 0 => line - 1,
+            _ => return,
+        };
+
+        // The column comes from the main cursor, so short lines in between don't
+        // pull the cursors to the left.
+        let line_start = self.line_start(self.cursor_position);
+        let column = self
+            .content
+            .byte_slice(line_start..self.cursor_position)
+            .chars()
+            .count();
+        let target_start = self.content.byte_of_line(target);
+        let target_end = self.line_end(target_start);
+        let offset: usize = self
+            .content
+            .byte_slice(target_start..target_end)
+            .chars()
+            .take(column)
+            .map(char::len_utf8)
+            .sum();
+
+        self.extra_carets.push(Caret {
+            cursor: grapheme::cluster_start(&self.content, target_start + offset),
+            anchor: None,
+        });
+        self.history.break_coalescing();
+    }
+
+    /// Select the rectangle between the points 'from' and 'to', measured in pixels
+    /// from the top left of the text area, as one selection per visual line. The
+    /// cursor on the line of 'to' becomes the main one.
+    pub fn select_block(
+        &mut self,
+        from: (f32, f32),
+        to: (f32, f32),
+        glyph_rasterizer: &mut impl GlyphRasterizer,
+    ) {
+        let line_height = self.line_metrics.line_height();
+        let row_of = |y: f32| (y / line_height).floor().max(0.0) as usize;
+        let (from_row, to_row) = (row_of(from.1), row_of(to.1));
+
+        let mut carets: Vec<Caret> = vec![];
+        for row in from_row.min(to_row)..=from_row.max(to_row) {
+            let y = (row as f32 + 0.5) * line_height;
+            let caret = Caret {
+                cursor: self.position_at_point(to.0, y, glyph_rasterizer),
+                anchor: Some(self.position_at_point(from.0, y, glyph_rasterizer)),
+            };
+
+            // Rows below the text all land on its last line.
+            if carets.last() != Some(&caret) {
+                carets.push(caret);
             }
         }
 
-        self.content.delete(self.cursor_position..curr_pos)
+        let main = carets.remove(if to_row >= from_row {
+            carets.len() - 1
+        } else {
+            0
+        });
+        self.cursor_position = main.cursor;
+        self.selection_anchor = main.anchor;
+        self.extra_carets = carets;
+        self.history.break_coalescing();
     }
 
-    pub fn backspace(&mut self) {
-        let len = self.content.byte_len();
-        if len == 0 || self.cursor_position == 0 {
-            return;
+    /// Run 'edit' once at every cursor, as one undo step, if there is more than one
+    /// cursor. Returns false without doing anything if there is only the main one.
+    /// Cursors are visited from the end of the text backwards, so an edit only moves
+    /// the cursors already visited, which are shifted by how much the text grew.
+    fn at_each_cursor(&mut self, mut edit: impl FnMut(&mut Self)) -> bool {
+        if self.extra_carets.is_empty() {
+            return false;
         }
 
-        // Find char boundry one character back
-        let mut curr_pos = self.cursor_position;
-        loop {
-            curr_pos = curr_pos.saturating_sub(1);
+        let mut carets: Vec<(bool, Caret)> = std::iter::once((true, self.main_caret()))
+            .chain(self.extra_carets.drain(..).map(|caret| (false, caret)))
+            .collect();
+        carets.sort_by_key(|(_, caret)| std::cmp::Reverse(caret.span().start));
 
-            if self.content.is_char_boundary(curr_pos) {
-                break;
+        self.history
+            .begin_transaction(self.cursor_position, self.text_start_idx);
+        let mut visited: Vec<(bool, Caret)> = vec![];
+        for (main, caret) in carets {
+            self.cursor_position = caret.cursor;
+            self.selection_anchor = caret.anchor;
+
+            let len_before = self.content.byte_len() as isize;
+            edit(self);
+            let grew = self.content.byte_len() as isize - len_before;
+
+            let shift = |idx: usize| idx.saturating_add_signed(grew);
+            for (_, caret) in &mut visited {
+                caret.cursor = shift(caret.cursor);
+                caret.anchor = caret.anchor.map(shift);
             }
+            visited.push((main, self.main_caret()));
         }
+        self.history.end_transaction();
 
-        self.content.delete(curr_pos..self.cursor_position);
-        self.cursor_position = curr_pos;
+        // Cursors that ran into each other become one.
+        let mut extra = vec![];
+        for (main, caret) in visited {
+            if main {
+                self.cursor_position = caret.cursor;
+                self.selection_anchor = caret.anchor;
+            } else {
+                extra.push(caret);
+            }
+        }
+        let main = self.main_caret();
+        extra.sort_by_key(|caret| caret.cursor);
+        extra.dedup_by_key(|caret| caret.cursor);
+        extra.retain(|caret| caret.cursor != main.cursor);
+        self.extra_carets = extra;
+
+        true
     }
 
-    pub fn insert_text(&mut self, text: &str) {
-        self.content.insert(self.cursor_position, text);
+    /// Called before the cursor moves. Starts a selection at the cursor if it is
+    /// being extended, otherwise drops the current selection.
+    fn update_selection_anchor(&mut self) {
+        if self.extend_selection {
+            self.selection_anchor.get_or_insert(self.cursor_position);
+        } else {
+            self.selection_anchor = None;
+        }
+    }
 
-        // Needed to handle emojis correctly, as well as regular ascii
-        let mut bytes_to_advance = 0;
-        for c in text.chars() {
-            bytes_to_advance += c.len_utf8();
+    /// Delete the selected text, if there is any.
+    fn delete_selection(&mut self) {
+        if let Some(range) = self.selection_range() {
+            let cursor_before = self.cursor_position;
+            self.cursor_position = range.start;
+            self.record_delete(range.start, range, cursor_before);
         }
+        self.selection_anchor = None;
+    }
 
-        self.cursor_position += bytes_to_advance;
+    /// Get the starting position of the text area that will be rendered
+    pub fn text_start_idx(&self) -> usize {
+        self.text_start_idx
     }
 
-    pub fn scroll(&mut self, scroll: ScrollAmount, glyph_rasterizer: &mut impl GlyphRasterizer) {
-        match scroll {
-            ScrollAmount::Up { lines } => self.scroll_up(lines, glyph_rasterizer),
-            ScrollAmount::Down { lines } => self.scroll_down(lines, glyph_rasterizer),
-            ScrollAmount::ToStart => self.scroll_to_start(),
-            ScrollAmount::ToEnd => self.scroll_to_end(glyph_rasterizer),
-        }
+    /// Lay out the visual lines that fit in the window, starting from the top of
+    /// the viewport. Lines are laid out lazily and stop at the end of the rope.
+    pub fn visible_lines<'a, G: GlyphRasterizer>(
+        &'a self,
+        glyph_rasterizer: &'a mut G,
+    ) -> VisualLines<'a, G> {
+        self.visible_lines_up_to(glyph_rasterizer, self.visible_line_count())
     }
 
-    fn scroll_to_start(&mut self) {
-        self.text_start_idx = 0;
-        self.cursor_position = 0;
+    /// Like visible_lines, but produces at most 'max_lines' lines.
+    pub fn visible_lines_up_to<'a, G: GlyphRasterizer>(
+        &'a self,
+        glyph_rasterizer: &'a mut G,
+        max_lines: usize,
+    ) -> VisualLines<'a, G> {
+        VisualLines::new(
+            self.wrap_policy.as_ref(),
+            &self.content,
+            self.text_start_idx,
+            self.window_width,
+            self.font_size,
+            glyph_rasterizer,
+            max_lines,
+        )
+        .tab_width(self.tab_width)
     }
 
-    fn scroll_to_end(&mut self, glyph_rasterizer: &mut impl GlyphRasterizer) {
-        let bottom = self.content.byte_len().saturating_sub(1);
+    /// How many visual lines it takes to fill the window. A partially visible line
+    /// at the bottom counts.
+    pub fn visible_line_count(&self) -> usize {
+        let line_height = self.line_metrics.line_height();
+        ((self.window_height / line_height).ceil() as usize).max(1)
+    }
 
-        self.text_start_idx = bottom;
-        self.cursor_position = bottom;
-        self.scroll_up(1, glyph_rasterizer); // so we aren't totally at the bottom and see nothing
+    /// The bytes that can be on screen. Counts hard lines, so with wrapping on it
+    /// reaches further than the window does.
+    pub fn visible_range(&self) -> Range<usize> {
+        let end_line = self.content.line_of_byte(self.text_start_idx) + self.visible_line_count();
+        let end = if end_line < self.line_count() {
+            self.content.byte_of_line(end_line)
+        } else {
+            self.content.byte_len()
+        };
+        self.text_start_idx..end
     }
 
-    /// Scroll the viewport up 'lines' wrapped lines.
-    fn scroll_up(&mut self, lines: usize, glyph_rasterizer: &mut impl GlyphRasterizer) {
-        let mut byte_idx = self.text_start_idx;
+    /// Get the text in the given byte range, e.g. the contents of a VisualLine.
+    pub fn slice(&self, range: Range<usize>) -> RopeSlice<'_> {
+        self.slice_bytes(range)
+    }
+
+    /// The length of the text in bytes.
+    pub fn len(&self) -> usize {
+        self.len_bytes()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.content.is_empty()
+    }
 
-        for _ in 0..lines {
-            let (_, line) = self.layout_line_rev(byte_idx, glyph_rasterizer);
-            byte_idx = byte_idx.saturating_sub(line.byte_len());
+    /// Counting walks the whole rope and history, so it is done once per edit and
+    /// kept until the next one.
+    pub fn stats(&self) -> BufferStats {
+        if let Some(stats) = self.stats.get() {
+            return stats;
         }
 
-        self.text_start_idx = byte_idx;
+        let stats = BufferStats {
+            bytes: self.content.byte_len(),
+            lines: self.line_count(),
+            chunks: self.content.chunks().count(),
+            undo_steps: self.history.undo_len(),
+            redo_steps: self.history.redo_len(),
+            history_bytes: self.history.memory_bytes(),
+        };
+        self.stats.set(Some(stats));
+        stats
     }
 
-    /// Scroll the viewport down 'lines' wrapped lines.
-    fn scroll_down(&mut self, lines: usize, glyph_rasterizer: &mut impl GlyphRasterizer) {
-        let mut byte_idx = self.text_start_idx;
+    /// Turn auto-closing of brackets and quotes on or off. It is on by default.
+    pub fn set_auto_close(&mut self, enabled: bool) {
+        self.auto_close = enabled;
+    }
 
-        for _ in 0..lines {
-            let (has_trailing_newline, line) = self.layout_line(byte_idx, glyph_rasterizer);
-            if has_trailing_newline {
-                byte_idx += 1;
-            }
+    pub fn auto_close(&self) -> bool {
+        self.auto_close
+    }
 
-            byte_idx += line.byte_len();
+    /// Put tab stops 'tab_width' spaces apart. Like set_wrap_policy, the view
+    /// moves to the start of the hard line it was in, as tabs now wrap differently.
+    pub fn set_tab_width(&mut self, tab_width: usize) {
+        let tab_width = tab_width.max(1);
+        if tab_width != self.tab_width {
+            self.tab_width = tab_width;
+            self.text_start_to_hard_line();
         }
+    }
+
+    pub fn tab_width(&self) -> usize {
+        self.tab_width
+    }
+
+    /// Indent with spaces if 'insert_spaces' is set, otherwise with tabs.
+    pub fn set_insert_spaces(&mut self, insert_spaces: bool) {
+        self.insert_spaces = insert_spaces;
+    }
 
-        self.text_start_idx = byte_idx;
+    pub fn insert_spaces(&self) -> bool {
+        self.insert_spaces
+    }
+
+    /// Guess how the text is indented: with tabs if more lines start with a tab
+    /// than with spaces, otherwise with the step in indentation between
+    /// neighbouring lines seen most often. None if there's nothing to go by.
+    pub fn detect_indentation(&self) -> Option<Indentation> {
+        let mut tab_lines = 0;
+        let mut space_lines = 0;
+        // How often the indentation grew by each number of spaces from one line to the next.
+        let mut steps = [0; 9];
+        let mut prev = 0;
+        for line in self.content.lines().take(INDENTATION_SAMPLE_LINES) {
+            if line.chars().all(char::is_whitespace) {
+                continue;
+            }
+
+            if line.chars().next() == Some('\t') {
+                tab_lines += 1;
+                continue;
+            }
+            let spaces = line.chars().take_while(|&c| c == ' ').count();
+            if spaces > 0 {
+                space_lines += 1;
+            }
+            if let Some(step) = steps.get_mut(spaces.saturating_sub(prev)) {
+                *step += 1;
+            }
+            prev = spaces;
+        }
+
+        if tab_lines > space_lines {
+            return Some(Indentation::Tabs);
+        }
+        // Steps of one space are usually alignment, e.g. the stars of a block comment.
+        // Ties go to the narrower width.
+        let width = (2..steps.len()).rev().max_by_key(|&width| steps[width])?;
+        (steps[width] > 0).then_some(Indentation::Spaces(width))
+    }
+
+    /// Indent the way the text already is, if detect_indentation can tell how.
+    pub fn follow_detected_indentation(&mut self) {
+        match self.detect_indentation() {
+            Some(Indentation::Tabs) => self.insert_spaces = false,
+            Some(Indentation::Spaces(width)) => {
+                self.insert_spaces = true;
+                self.set_tab_width(width);
+            }
+            None => {}
+        }
+    }
+
+    /// One level of indentation: a tab, or a tab stop's worth of spaces.
+    fn indent_unit(&self) -> String {
+        if self.insert_spaces {
+            " ".repeat(self.tab_width)
+        } else {
+            "\t".to_string()
+        }
+    }
+
+    /// Indent at each cursor, replacing any selection like typing would.
+    pub fn indent(&mut self) {
+        self.insert_text(&self.indent_unit());
+    }
+
+    /// Use a different policy for breaking long lines. The view moves to the start
+    /// of the hard line it was in, since the old start may fall mid-line now.
+    pub fn set_wrap_policy(&mut self, wrap_policy: Box<dyn WrapPolicy>) {
+        self.wrap_policy = wrap_policy;
+        self.text_start_to_hard_line();
+    }
+
+    /// Move the view to the start of the hard line it starts in, for when lines
+    /// wrap differently than when it was put there.
+    fn text_start_to_hard_line(&mut self) {
+        let line = self.content.line_of_byte(self.text_start_idx);
+        self.text_start_idx = self.content.byte_of_line(line);
+    }
+
+    /// Lay out the visual line starting at 'start_index' using the current wrap policy.
+    fn layout_line(
+        &self,
+        start_index: usize,
+        glyph_rasterizer: &mut impl GlyphRasterizer,
+    ) -> VisualLine {
+        self.wrap_policy.layout_line(
+            &self.content,
+            start_index,
+            self.window_width,
+            self.font_size,
+            &mut TabStops::new(glyph_rasterizer, self.tab_width),
+        )
+    }
+
+    /// Get the byte index the cursor goes to for a click at (x, y), measured in pixels
+    /// from the top left of the text area: before or after the nearest glyph edge.
+    /// Points below the last visible line land on it.
+    pub fn position_at_point(
+        &self,
+        x: f32,
+        y: f32,
+        glyph_rasterizer: &mut impl GlyphRasterizer,
+    ) -> usize {
+        let Some(line) = self.visual_line_at_point(y, glyph_rasterizer) else {
+            return self.text_start_idx;
+        };
+
+        let mut tab_stops = TabStops::new(glyph_rasterizer, self.tab_width);
+        let mut byte_idx = line.range.start;
+        let mut line_x = 0.0;
+        while byte_idx < line.range.end {
+            let end = grapheme::next_boundary(&self.content, byte_idx).min(line.range.end);
+            let cluster = self.content.byte_slice(byte_idx..end);
+
+            // Clicks past the end of the line land before its newline.
+            if cluster
+                .chars()
+                .next()
+                .is_some_and(|c| c == '\n' || c == '\r')
+            {
+                break;
+            }
+
+            // Clicking the right half of a cluster puts the cursor after it.
+            let advance: f32 = cluster
+                .chars()
+                .map(|c| layout::advance(c, line_x, self.font_size, &mut tab_stops))
+                .sum();
+            if x < line_x + advance / 2.0 {
+                break;
+            }
+
+            line_x += advance;
+            byte_idx = end;
+        }
+
+        byte_idx
+    }
+
+    /// Get the visual line shown at height 'y', measured in pixels from the top of
+    /// the text area. Points below the last visible line land on it.
+    pub fn visual_line_at_point(
+        &self,
+        y: f32,
+        glyph_rasterizer: &mut impl GlyphRasterizer,
+    ) -> Option<VisualLine> {
+        let line_height = self.line_metrics.line_height();
+        let row = (y / line_height).floor().max(0.0) as usize;
+
+        self.visible_lines_up_to(glyph_rasterizer, row + 1).last()
+    }
+
+    /// Get the range of the word containing 'byte_idx'. A run of whitespace counts
+    /// as one word, and any other punctuation is a word by itself.
+    pub fn word_range_at(&self, byte_idx: usize) -> Range<usize> {
+        let class = |c: char| {
+            if c.is_alphanumeric() || c == '_' {
+                CharClass::Word
+            } else if c.is_whitespace() && c != '\n' {
+                CharClass::Space
+            } else {
+                CharClass::Other
+            }
+        };
+
+        // At the end of a line or the text, use the word before the cursor.
+        let mut start = byte_idx;
+        let target = match self.content.byte_slice(byte_idx..).chars().next() {
+            Some(c) if c != '\n' => c,
+            _ => match self.content.byte_slice(..byte_idx).chars().next_back() {
+                Some(c) if c != '\n' => {
+                    start -= c.len_utf8();
+                    c
+                }
+                _ => return byte_idx..byte_idx,
+            },
+        };
+
+        let target_class = class(target);
+        if target_class == CharClass::Other {
+            return start..start + target.len_utf8();
+        }
+
+        for c in self.content.byte_slice(..start).chars().rev() {
+            if class(c) != target_class {
+                break;
+            }
+            start -= c.len_utf8();
+        }
+
+        let mut end = start;
+        for c in self.content.byte_slice(start..).chars() {
+            if class(c) != target_class {
+                break;
+            }
+            end += c.len_utf8();
+        }
+
+        start..end
+    }
+
+    /// If the cursor is next to a bracket, get the byte index of that bracket and of
+    /// the bracket matching it, taking nesting into account. The bracket after the
+    /// cursor is preferred over the one before it. Brackets in strings and comments
+    /// are left out, when the text has a syntax tree to tell.
+    pub fn matching_bracket(&self) -> Option<(usize, usize)> {
+        let cursor = self.cursor_position;
+        let syntax_version = self.syntax_version();
+        if let Some(cached) = self.bracket_match.get() {
+            if cached.cursor == cursor && cached.syntax_version == syntax_version {
+                return cached.found;
+            }
+        }
+
+        let after = self
+            .char_after(self.cursor_position)
+            .map(|c| (self.cursor_position, c));
+        let before = self
+            .char_before(self.cursor_position)
+            .map(|c| (self.cursor_position - c.len_utf8(), c));
+
+        let found = [after, before]
+            .into_iter()
+            .flatten()
+            .find_map(|(byte_idx, c)| {
+                let matched = self.find_matching_bracket(byte_idx, c)?;
+                Some((byte_idx, matched))
+            });
+        self.bracket_match.set(Some(BracketMatch {
+            cursor,
+            syntax_version,
+            found,
+        }));
+        found
+    }
+
+    /// Is 'byte_idx' in a string or a comment? Always false without a syntax tree.
+    fn in_string_or_comment(&self, byte_idx: usize) -> bool {
+        self.syntax
+            .as_ref()
+            .is_some_and(|syntax| syntax.in_string_or_comment(byte_idx))
+    }
+
+    /// Find the bracket matching 'bracket', which sits at 'byte_idx'.
+    fn find_matching_bracket(&self, byte_idx: usize, bracket: char) -> Option<usize> {
+        let (open, close) = *BRACKETS
+            .iter()
+            .find(|(open, close)| bracket == *open || bracket == *close)?;
+        if self.in_string_or_comment(byte_idx) {
+            return None;
+        }
+
+        let mut depth = 0usize;
+        if bracket == open {
+            let mut idx = byte_idx;
+            for c in self
+                .content
+                .byte_slice(byte_idx..)
+                .chars()
+                .take(MAX_BRACKET_SCAN)
+            {
+                if (c == open || c == close) && self.in_string_or_comment(idx) {
+                    // Not a bracket of the code.
+                } else if c == open {
+                    depth += 1;
+                } else if c == close {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(idx);
+                    }
+                }
+                idx += c.len_utf8();
+            }
+        } else {
+            let mut idx = byte_idx + close.len_utf8();
+            let before = self.content.byte_slice(..idx);
+            for c in before.chars().rev().take(MAX_BRACKET_SCAN) {
+                idx -= c.len_utf8();
+                if (c == open || c == close) && self.in_string_or_comment(idx) {
+                    // Not a bracket of the code.
+                } else if c == close {
+                    depth += 1;
+                } else if c == open {
+                    depth -= 1;
+                    if depth == 0 {
+                        return Some(idx);
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    /// Find every occurrence of 'pattern' in the text. Returns their byte ranges in
+    /// order. Matches do not overlap.
+    pub fn find(&self, pattern: &str) -> Vec<Range<usize>> {
+        self.occurrences(0..self.content.byte_len(), pattern)
+            .collect()
+    }
+
+    /// The occurrences of 'pattern' in 'range' of the text, in order and not
+    /// overlapping. The rope is searched a chunk at a time as they are asked for.
+    fn occurrences<'a>(
+        &'a self,
+        range: Range<usize>,
+        pattern: &'a str,
+    ) -> impl Iterator<Item = Range<usize>> + 'a {
+        let mut chunks = self.content.byte_slice(range.clone()).chunks();
+        let mut found: VecDeque<Range<usize>> = VecDeque::new();
+        let mut window = String::new();
+        let mut window_start = range.start;
+        let mut searched_to = range.start;
+
+        std::iter::from_fn(move || loop {
+            if let Some(next) = found.pop_front() {
+                return Some(next);
+            }
+            if pattern.is_empty() {
+                return None;
+            }
+
+            // A match can run from one chunk into the next, so each chunk is searched
+            // along with the end of the ones before it that no match was found in.
+            window.push_str(chunks.next()?);
+            let from = searched_to.saturating_sub(window_start);
+            found.extend(window[from..].match_indices(pattern).map(|(i, m)| {
+                let start = window_start + from + i;
+                start..start + m.len()
+            }));
+            searched_to = found.back().map_or(searched_to, |m| m.end);
+
+            // Keep what could still be the start of a match, after the last match.
+            let mut cut = window.len().saturating_sub(pattern.len() - 1);
+            while !window.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            let cut = cut.max(searched_to.saturating_sub(window_start));
+            window.drain(..cut);
+            window_start += cut;
+        })
+    }
+
+    /// Find every match of 'regex' in the text. Returns their byte ranges in order.
+    pub fn find_regex(&self, regex: &Regex) -> Vec<Range<usize>> {
+        regex.find_iter(self.text()).map(|m| m.range()).collect()
+    }
+
+    /// The text as one string, made once per edit.
+    fn text(&self) -> &str {
+        self.text.get_or_init(|| self.content.to_string())
+    }
+
+    /// If the selection is a match of 'regex', replace it with 'replacement', then
+    /// select the next match after the cursor. `$1` style references in 'replacement'
+    /// are expanded from the match's capture groups. Returns false if nothing matches.
+    /// Like Regex::replace_all, an empty match right where a replaced one ended is
+    /// skipped, so patterns that match nothing move along instead of replacing at
+    /// the same spot forever.
+    pub fn replace_next(&mut self, regex: &Regex, replacement: &str) -> bool {
+        self.extra_carets.clear();
+
+        // Selecting an empty match leaves the anchor on the cursor.
+        let selected = self
+            .selection_anchor
+            .map(|anchor| anchor.min(self.cursor_position)..anchor.max(self.cursor_position));
+        let mut replaced = false;
+        if let Some(selected) = selected {
+            let captures = regex
+                .captures_at(self.text(), selected.start)
+                .filter(|captures| captures.get(0).unwrap().range() == selected);
+
+            if let Some(captures) = captures {
+                let mut expanded = String::new();
+                captures.expand(replacement, &mut expanded);
+                if selected.is_empty() {
+                    self.selection_anchor = None;
+                }
+                self.insert_text(&expanded);
+                replaced = true;
+            }
+        }
+
+        let cursor = self.cursor_position;
+        let mut next = regex.find_at(self.text(), cursor);
+        if replaced && next.is_some_and(|m| m.is_empty() && m.start() == cursor) {
+            next = self
+                .char_after(cursor)
+                .and_then(|c| regex.find_at(self.text(), cursor + c.len_utf8()));
+        }
+
+        match next.or_else(|| regex.find(self.text())) {
+            Some(m) => {
+                self.select_range(m.range());
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Replace every match of 'regex' with 'replacement', expanding capture group
+    /// references like replace_next. It is a single undo step. Returns how many
+    /// matches were replaced.
+    pub fn replace_all(&mut self, regex: &Regex, replacement: &str) -> usize {
+        self.extra_carets.clear();
+        let replacements: Vec<(Range<usize>, String)> = regex
+            .captures_iter(self.text())
+            .map(|captures| {
+                let mut replaced = String::new();
+                captures.expand(replacement, &mut replaced);
+                (captures.get(0).unwrap().range(), replaced)
+            })
+            .collect();
+
+        if replacements.is_empty() {
+            return 0;
+        }
+
+        self.history
+            .begin_transaction(self.cursor_position, self.text_start_idx);
+        self.selection_anchor = None;
+
+        // Going backwards keeps the ranges of the earlier matches valid.
+        for (range, replaced) in replacements.iter().rev() {
+            self.cursor_position = range.start;
+            if !range.is_empty() {
+                self.delete_range(range.start, range.end);
+            }
+            if !replaced.is_empty() {
+                self.insert_text(replaced);
+            }
+        }
+
+        self.history.end_transaction();
+        self.clamp_text_start();
+
+        replacements.len()
+    }
+
+    /// Count the line endings of the text. Only the trailing ones are counted here,
+    /// so it is cheap enough to call every frame.
+    pub fn line_endings(&self) -> LineEndings {
+        let mut line_endings = self.line_endings;
+        let mut trailing = self.content.byte_len();
+        while trailing > 0 && self.content.byte(trailing - 1) == b'\n' {
+            line_endings.trailing += 1;
+            trailing -= 1;
+            if trailing > 0 && self.content.byte(trailing - 1) == b'\r' {
+                trailing -= 1;
+            }
+        }
+        line_endings
+    }
+
+    /// Make every line end with 'ending'. It is a single undo step.
+    pub fn normalize_line_endings(&mut self, ending: LineEnding) {
+        let mut replacements = vec![];
+        let mut prev = None;
+        for (i, b) in self.content.bytes().enumerate() {
+            if b == b'\n' {
+                match (ending, prev == Some(b'\r')) {
+                    (LineEnding::Lf, true) => replacements.push((i - 1..i, String::new())),
+                    (LineEnding::Crlf, false) => replacements.push((i..i, "\r".into())),
+                    _ => {}
+                }
+            }
+            prev = Some(b);
+        }
+
+        self.replace_ranges(&replacements);
+    }
+
+    /// Make the text end with exactly one line ending, the one most lines use.
+    /// Empty text is left empty. It is a single undo step.
+    pub fn ensure_trailing_newline(&mut self) {
+        if let Some(replacement) = self.trailing_newline_fix() {
+            self.replace_ranges(&[replacement]);
+        }
+    }
+
+    /// Like ensure_trailing_newline, for fixing the text up as it is saved. The fix
+    /// joins the last undo step rather than being one of its own, so undoing the
+    /// last edit takes it back too.
+    pub fn ensure_trailing_newline_on_save(&mut self) {
+        if let Some(replacement) = self.trailing_newline_fix() {
+            self.history
+                .join_last(self.cursor_position, self.text_start_idx);
+            self.replace_ranges(&[replacement]);
+            self.history.end_transaction();
+        }
+    }
+
+    /// The replacement that leaves the text ending with exactly one line ending, if
+    /// it needs one.
+    fn trailing_newline_fix(&self) -> Option<(Range<usize>, String)> {
+        let line_endings = self.line_endings();
+        if line_endings.trailing == 1 || self.content.byte_len() == 0 {
+            return None;
+        }
+
+        let len = self.content.byte_len();
+        let mut end = len;
+        while end > 0 && matches!(self.content.byte(end - 1), b'\n' | b'\r') {
+            end -= 1;
+        }
+
+        let ending = line_endings.dominant().as_str().to_string();
+        Some((end..len, ending))
+    }
+
+    /// Rewrite the leading whitespace of every line to use only spaces, keeping
+    /// its width at the current tab width, and indent with spaces from now on. It
+    /// is a single undo step.
+    pub fn indentation_to_spaces(&mut self) {
+        self.rewrite_indentation(false);
+    }
+
+    /// Rewrite the leading whitespace of every line to use as many tabs as fit,
+    /// with spaces for what's left over, and indent with tabs from now on. It is
+    /// a single undo step.
+    pub fn indentation_to_tabs(&mut self) {
+        self.rewrite_indentation(true);
+    }
+
+    fn rewrite_indentation(&mut self, tabs: bool) {
+        self.insert_spaces = !tabs;
+
+        let mut replacements = vec![];
+        for line in 0..self.line_count() {
+            let start = self.byte_of_line(line);
+            let mut end = start;
+            let mut width = 0;
+            while end < self.content.byte_len() {
+                match self.content.byte(end) {
+                    b' ' => width += 1,
+                    b'\t' => width += self.tab_width - width % self.tab_width,
+                    _ => break,
+                }
+                end += 1;
+            }
+
+            let indentation = if tabs {
+                "\t".repeat(width / self.tab_width) + &" ".repeat(width % self.tab_width)
+            } else {
+                " ".repeat(width)
+            };
+            if self.content.byte_slice(start..end) != *indentation {
+                replacements.push((start..end, indentation));
+            }
+        }
+
+        self.replace_ranges(&replacements);
+    }
+
+    /// Duplicate the selection right after itself and select the copy. Without a
+    /// selection, the cursor's line is duplicated below itself and the cursor moves
+    /// down onto the copy.
+    pub fn duplicate(&mut self) {
+        self.extra_carets.clear();
+
+        let cursor_before = self.cursor_position;
+        let (byte_idx, text, cursor_after, anchor_after) = match self.selection_range() {
+            Some(range) => {
+                let text = self.content.byte_slice(range.clone()).to_string();
+                let cursor_after = range.end + text.len();
+                (range.end, text, cursor_after, Some(range.end))
+            }
+            None => {
+                let start = self.line_start(cursor_before);
+                let end = self.line_end(cursor_before);
+                let ending = self.line_endings().dominant().as_str();
+                let text = format!("{ending}{}", self.content.byte_slice(start..end));
+                let cursor_after = cursor_before + text.len();
+                (end, text, cursor_after, None)
+            }
+        };
+
+        self.history
+            .begin_transaction(cursor_before, self.text_start_idx);
+        self.rope_insert(byte_idx, &text);
+        self.history.record(
+            Edit::Insert { byte_idx, text },
+            cursor_before,
+            cursor_after,
+            self.text_start_idx,
+        );
+        self.history.end_transaction();
+
+        self.cursor_position = cursor_after;
+        self.selection_anchor = anchor_after;
+    }
+
+    /// Swap the lines under the cursor or selection with the line above them.
+    pub fn move_lines_up(&mut self) {
+        self.move_lines(false);
+    }
+
+    /// Swap the lines under the cursor or selection with the line below them.
+    pub fn move_lines_down(&mut self) {
+        self.move_lines(true);
+    }
+
+    fn move_lines(&mut self, down: bool) {
+        self.extra_carets.clear();
+
+        let span = self.main_caret().span();
+        let first = self.content.line_of_byte(span.start);
+        let mut last = self.content.line_of_byte(span.end);
+        // A selection ending at the start of a line doesn't take that line along.
+        if last > first && self.content.byte_of_line(last) == span.end {
+            last -= 1;
+        }
+
+        let (above, below) = match down {
+            true if last + 1 < self.line_count() => (first, last + 1),
+            false if first > 0 => (first - 1, last),
+            _ => return,
+        };
+
+        // The lines that move up come first in the new text, keeping the line ending
+        // that separated the two groups between them.
+        let split = if down {
+            self.content.byte_of_line(last + 1)
+        } else {
+            self.content.byte_of_line(first)
+        };
+        let start = self.content.byte_of_line(above);
+        let end = self.line_end(self.content.byte_of_line(below));
+        let upper = self.content.byte_slice(start..split).to_string();
+        let lower = self.content.byte_slice(split..end).to_string();
+        let ending = if upper.ends_with("\r\n") {
+            "\r\n"
+        } else {
+            "\n"
+        };
+        let moved = format!("{lower}{ending}{}", &upper[..upper.len() - ending.len()]);
+
+        // The moved lines shift by the length of the lines they swapped with.
+        let shift = |byte_idx: usize| {
+            if down {
+                byte_idx + lower.len() + ending.len()
+            } else {
+                byte_idx - (split - start)
+            }
+        };
+        let cursor_before = self.cursor_position;
+        let cursor_after = shift(cursor_before);
+
+        self.history
+            .begin_transaction(cursor_before, self.text_start_idx);
+        self.rope_delete(start..end);
+        self.history.record(
+            Edit::Delete {
+                byte_idx: start,
+                text: format!("{upper}{lower}"),
+            },
+            cursor_before,
+            cursor_after,
+            self.text_start_idx,
+        );
+        self.rope_insert(start, &moved);
+        self.history.record(
+            Edit::Insert {
+                byte_idx: start,
+                text: moved,
+            },
+            cursor_before,
+            cursor_after,
+            self.text_start_idx,
+        );
+        self.history.end_transaction();
+
+        self.cursor_position = cursor_after;
+        self.selection_anchor = self.selection_anchor.map(shift);
+    }
+
+    /// Replace each range with its text as one undo step, keeping the cursor on the
+    /// same text. The ranges must be ordered and not overlap. Returns how many were
+    /// replaced.
+    pub fn replace_ranges(&mut self, replacements: &[(Range<usize>, String)]) -> usize {
+        self.extra_carets.clear();
+        if replacements.is_empty() {
+            return 0;
+        }
+
+        let cursor_before = self.cursor_position;
+        let mut cursor_after = cursor_before;
+        for (range, text) in replacements {
+            if range.end <= cursor_before {
+                cursor_after = cursor_after + text.len() - range.len();
+            } else if range.start < cursor_before {
+                cursor_after -= cursor_before - range.start;
+            }
+        }
+
+        self.history
+            .begin_transaction(cursor_before, self.text_start_idx);
+        self.selection_anchor = None;
+
+        // Going backwards keeps the earlier ranges valid.
+        for (range, text) in replacements.iter().rev() {
+            if !range.is_empty() {
+                let deleted = self.content.byte_slice(range.clone()).to_string();
+                self.rope_delete(range.clone());
+                self.history.record(
+                    Edit::Delete {
+                        byte_idx: range.start,
+                        text: deleted,
+                    },
+                    cursor_before,
+                    cursor_after,
+                    self.text_start_idx,
+                );
+            }
+            if !text.is_empty() {
+                self.rope_insert(range.start, text);
+                self.history.record(
+                    Edit::Insert {
+                        byte_idx: range.start,
+                        text: text.clone(),
+                    },
+                    cursor_before,
+                    cursor_after,
+                    self.text_start_idx,
+                );
+            }
+        }
+
+        self.history.end_transaction();
+        self.cursor_position = cursor_after;
+        self.clamp_text_start();
+
+        replacements.len()
+    }
+
+    /// Scroll so the cursor is on screen. If it was off screen, its line is
+    /// centered in the window.
+    pub fn scroll_to_cursor(&mut self, glyph_rasterizer: &mut impl GlyphRasterizer) {
+        let line_height = self.line_metrics.line_height();
+        let page = ((self.window_height / line_height).floor() as usize).max(1);
+
+        let on_screen = self.cursor_position >= self.text_start_idx
+            && self
+                .visible_lines_up_to(glyph_rasterizer, page)
+                .any(|line| {
+                    line.range.contains(&self.cursor_position)
+                        || line.range.end == self.cursor_position
+                });
+        if on_screen {
+            return;
+        }
+
+        // Find the visual line holding the cursor, starting from its hard line.
+        let mut start = self.line_start(self.cursor_position);
+        loop {
+            let line = self.layout_line(start, glyph_rasterizer);
+            if line.range.end >= self.cursor_position || line.next_start() == start {
+                break;
+            }
+            start = line.next_start();
+        }
+
+        self.text_start_idx = start;
+        self.scroll_up(page / 2, glyph_rasterizer);
+    }
+
+    /// How many lines the text has. Text ending in a newline has an empty last line.
+    pub fn line_count(&self) -> usize {
+        self.len_lines()
+    }
+
+    /// The byte offset where the zero based 'line' starts. Lines past the end map
+    /// to the start of the last line.
+    pub fn byte_of_line(&self, line: usize) -> usize {
+        self.start_of_line(line)
+    }
+
+    /// Put the cursor at the start of the zero based 'line' and scroll it into view.
+    pub fn go_to_line(&mut self, line: usize, glyph_rasterizer: &mut impl GlyphRasterizer) {
+        self.extra_carets.clear();
+        self.selection_anchor = None;
+        self.cursor_position = self.byte_of_line(line);
+        self.history.break_coalescing();
+        self.scroll_to_cursor(glyph_rasterizer);
+    }
+
+    /// Select 'range', leaving the cursor at its end.
+    pub fn select_range(&mut self, range: Range<usize>) {
+        self.extra_carets.clear();
+        self.selection_anchor = Some(range.start);
+        self.cursor_position = range.end;
+        self.history.break_coalescing();
+    }
+
+    /// Move the cursor to 'byte_idx', e.g. after a mouse click. Extends the
+    /// selection if shift is held.
+    pub fn move_cursor_to(&mut self, byte_idx: usize) {
+        self.extra_carets.clear();
+        self.update_selection_anchor();
+        self.cursor_position = byte_idx;
+        self.history.break_coalescing();
+    }
+
+    /// Move the cursor to 'byte_idx', selecting everything between it and where the
+    /// selection started. Used while dragging the mouse.
+    pub fn select_to(&mut self, byte_idx: usize) {
+        self.extra_carets.clear();
+        self.selection_anchor.get_or_insert(self.cursor_position);
+        self.cursor_position = byte_idx;
+        self.history.break_coalescing();
+    }
+
+    /// Move the cursor one position to the left.
+    pub fn left(&mut self) {
+        if self.at_each_cursor(Self::left) {
+            return;
+        }
+
+        self.update_selection_anchor();
+
+        self.cursor_position = grapheme::prev_boundary(&self.content, self.cursor_position);
+        self.history.break_coalescing();
+    }
+
+    /// Move the cursor one position to the right.
+    pub fn right(&mut self) {
+        if self.at_each_cursor(Self::right) {
+            return;
+        }
+
+        self.update_selection_anchor();
+
+        let next = grapheme::next_boundary(&self.content, self.cursor_position);
+        if next >= self.content.byte_len() {
+            return;
+        }
+
+        self.cursor_position = next;
+        self.history.break_coalescing();
+    }
+
+    /// Move the cursor to the start of its line.
+    pub fn move_to_line_start(&mut self) {
+        if self.at_each_cursor(Self::move_to_line_start) {
+            return;
+        }
+
+        self.update_selection_anchor();
+        self.cursor_position = self.line_start(self.cursor_position);
+        self.history.break_coalescing();
+    }
+
+    /// Move the cursor to the end of its line, before the newline.
+    pub fn move_to_line_end(&mut self) {
+        if self.at_each_cursor(Self::move_to_line_end) {
+            return;
+        }
+
+        self.update_selection_anchor();
+        self.cursor_position = self.line_end(self.cursor_position);
+        self.history.break_coalescing();
+    }
+
+    /// Move the cursor to the end of the next word.
+    pub fn word_forward(&mut self) {
+        if self.at_each_cursor(Self::word_forward) {
+            return;
+        }
+
+        self.update_selection_anchor();
+        let is_word = |c: &char| c.is_alphanumeric() || *c == '_';
+
+        let rest = self.content.byte_slice(self.cursor_position..);
+        let skipped: usize = rest
+            .chars()
+            .take_while(|c| !is_word(c))
+            .map(char::len_utf8)
+            .sum();
+        let word: usize = rest
+            .chars()
+            .skip_while(|c| !is_word(c))
+            .take_while(is_word)
+            .map(char::len_utf8)
+            .sum();
+
+        self.cursor_position += skipped + word;
+        self.history.break_coalescing();
+    }
+
+    /// Move the cursor to the start of the previous word.
+    pub fn word_backward(&mut self) {
+        if self.at_each_cursor(Self::word_backward) {
+            return;
+        }
+
+        self.update_selection_anchor();
+        let is_word = |c: &char| c.is_alphanumeric() || *c == '_';
+
+        let before = self.content.byte_slice(..self.cursor_position);
+        let skipped: usize = before
+            .chars()
+            .rev()
+            .take_while(|c| !is_word(c))
+            .map(char::len_utf8)
+            .sum();
+        let word: usize = before
+            .chars()
+            .rev()
+            .skip_while(|c| !is_word(c))
+            .take_while(is_word)
+            .map(char::len_utf8)
+            .sum();
+
+        self.cursor_position -= skipped + word;
+        self.history.break_coalescing();
+    }
+
+    /// Remove the text from the cursor to the end of the line and return it. At the
+    /// end of a line, the newline is removed instead, joining the next line.
+    pub fn kill_line(&mut self) -> Option<String> {
+        self.selection_anchor = None;
+        let mut end = self.line_end(self.cursor_position);
+        if end == self.cursor_position {
+            end += self.char_after(end)?.len_utf8();
+        }
+
+        let text = self
+            .content
+            .byte_slice(self.cursor_position..end)
+            .to_string();
+        self.delete_range(self.cursor_position, end);
+        self.history.break_coalescing();
+        Some(text)
+    }
+
+    pub fn delete(&mut self) {
+        if self.at_each_cursor(Self::delete) {
+            return;
+        }
+
+        if self.selection_range().is_some() {
+            self.delete_selection();
+            return;
+        }
+
+        if self.cursor_position >= self.content.byte_len() {
+            return;
+        }
+
+        let end = grapheme::next_boundary(&self.content, self.cursor_position);
+        self.delete_range(self.cursor_position, end);
+    }
+
+    pub fn backspace(&mut self) {
+        if self.at_each_cursor(Self::backspace) {
+            return;
+        }
+
+        if self.selection_range().is_some() {
+            self.delete_selection();
+            return;
+        }
+
+        let len = self.content.byte_len();
+        if len == 0 || self.cursor_position == 0 {
+            return;
+        }
+
+        // Deleting the opening half of an empty pair deletes the closing half too.
+        if self.auto_close {
+            if let (Some(open), Some(close)) = (
+                self.char_before(self.cursor_position),
+                self.char_after(self.cursor_position),
+            ) {
+                if PAIRS.contains(&(open, close)) {
+                    let cursor_before = self.cursor_position;
+                    self.cursor_position -= open.len_utf8();
+                    self.record_delete(
+                        self.cursor_position,
+                        self.cursor_position..cursor_before + close.len_utf8(),
+                        cursor_before,
+                    );
+                    return;
+                }
+            }
+        }
+
+        let curr_pos = grapheme::prev_boundary(&self.content, self.cursor_position);
+        let cursor_before = self.cursor_position;
+        self.cursor_position = curr_pos;
+        self.record_delete(curr_pos, self.cursor_position..cursor_before, cursor_before);
+    }
+
+    /// Insert text at the cursor, replacing the selection if there is one.
+    pub fn insert_text(&mut self, text: &str) {
+        if self.at_each_cursor(|editor| editor.insert_text(text)) {
+            return;
+        }
+
+        let replacing = self.selection_range().is_some();
+        if replacing {
+            self.history
+                .begin_transaction(self.cursor_position, self.text_start_idx);
+            self.delete_selection();
+        }
+
+        let cursor_before = self.cursor_position;
+        self.rope_insert(self.cursor_position, text);
+
+        // Needed to handle emojis correctly, as well as regular ascii
+        let mut bytes_to_advance = 0;
+        for c in text.chars() {
+            bytes_to_advance += c.len_utf8();
+        }
+
+        self.cursor_position += bytes_to_advance;
+        self.history.record(
+            Edit::Insert {
+                byte_idx: cursor_before,
+                text: text.to_string(),
+            },
+            cursor_before,
+            self.cursor_position,
+            self.text_start_idx,
+        );
+
+        if replacing {
+            self.history.end_transaction();
+        }
+    }
+
+    /// Insert 'before' and 'after' at the cursor as one edit, leaving the cursor
+    /// between them.
+    fn insert_around_cursor(&mut self, before: &str, after: &str) {
+        self.history
+            .begin_transaction(self.cursor_position, self.text_start_idx);
+        self.delete_selection();
+
+        let cursor_before = self.cursor_position;
+        let text = format!("{before}{after}");
+        self.rope_insert(cursor_before, &text);
+        self.cursor_position += before.len();
+        self.history.record(
+            Edit::Insert {
+                byte_idx: cursor_before,
+                text,
+            },
+            cursor_before,
+            self.cursor_position,
+            self.text_start_idx,
+        );
+
+        self.history.end_transaction();
+    }
+
+    fn char_before(&self, byte_idx: usize) -> Option<char> {
+        self.content.byte_slice(..byte_idx).chars().next_back()
+    }
+
+    fn char_after(&self, byte_idx: usize) -> Option<char> {
+        self.content.byte_slice(byte_idx..).chars().next()
+    }
+
+    /// Can a pair be auto-closed in front of 'next'? Only if it would not end up
+    /// glued to the start of a word.
+    fn can_close_before(next: Option<char>) -> bool {
+        next.is_none_or(|c| c.is_whitespace() || matches!(c, ')' | ']' | '}'))
+    }
+
+    /// Insert an opening bracket at the cursor, along with its closing bracket if
+    /// auto-closing is on.
+    pub fn insert_opening_bracket(&mut self, open: char) {
+        if self.at_each_cursor(|editor| editor.insert_opening_bracket(open)) {
+            return;
+        }
+
+        let close = PAIRS.iter().find(|(o, _)| *o == open).map(|(_, c)| *c);
+
+        match close {
+            Some(close)
+                if self.auto_close
+                    && self.selection_range().is_none()
+                    && Self::can_close_before(self.char_after(self.cursor_position)) =>
+            {
+                self.insert_around_cursor(
+                    open.encode_utf8(&mut [0; 4]),
+                    close.encode_utf8(&mut [0; 4]),
+                )
+            }
+            _ => self.insert_text(open.encode_utf8(&mut [0; 4])),
+        }
+    }
+
+    /// Insert a quote at the cursor. With auto-closing on, typing a quote right
+    /// before the same quote steps over it, and a quote that starts a string gets
+    /// its closing quote.
+    pub fn insert_quote(&mut self, quote: char) {
+        if self.at_each_cursor(|editor| editor.insert_quote(quote)) {
+            return;
+        }
+
+        if self.auto_close && self.selection_range().is_none() {
+            if self.char_after(self.cursor_position) == Some(quote) {
+                self.cursor_position += quote.len_utf8();
+                self.history.break_coalescing();
+                return;
+            }
+
+            let starts_string = self
+                .char_before(self.cursor_position)
+                .is_none_or(|c| !c.is_alphanumeric() && c != quote);
+            if starts_string && Self::can_close_before(self.char_after(self.cursor_position)) {
+                let mut buf = [0; 4];
+                let quote = quote.encode_utf8(&mut buf);
+                self.insert_around_cursor(quote, quote);
+                return;
+            }
+        }
+
+        self.insert_text(quote.encode_utf8(&mut [0; 4]));
+    }
+
+    /// Delete the bytes in 'start..end', leaving the cursor where it is.
+    fn delete_range(&mut self, start: usize, end: usize) {
+        self.record_delete(start, start..end, self.cursor_position);
+    }
+
+    /// Remove 'range' from the rope and record it in the undo history. 'cursor_before'
+    /// is where the cursor was before the edit, the cursor must already be in its
+    /// final position.
+    fn record_delete(
+        &mut self,
+        byte_idx: usize,
+        range: std::ops::Range<usize>,
+        cursor_before: usize,
+    ) {
+        let text = self.content.byte_slice(range.clone()).to_string();
+        self.rope_delete(range);
+        self.history.record(
+            Edit::Delete { byte_idx, text },
+            cursor_before,
+            self.cursor_position,
+            self.text_start_idx,
+        );
+    }
+
+    /// Get the selected text, if there is any.
+    pub fn copy(&self) -> Option<String> {
+        self.selection_range()
+            .map(|range| self.content.byte_slice(range).to_string())
+    }
+
+    /// Remove the selected text and return it, if there is any.
+    pub fn cut(&mut self) -> Option<String> {
+        let text = self.copy()?;
+        self.delete_selection();
+        Some(text)
+    }
+
+    /// Revert the most recent group of edits, and show the text where it was shown
+    /// when they were made.
+    pub fn undo(&mut self) {
+        self.extra_carets.clear();
+        if let Some(tx) = self.history.undo().cloned() {
+            for edit in tx.edits.iter().rev() {
+                match edit {
+                    Edit::Insert { byte_idx, text } => {
+                        self.rope_delete(*byte_idx..byte_idx + text.len())
+                    }
+                    Edit::Delete { byte_idx, text } => self.rope_insert(*byte_idx, text),
+                }
+            }
+            self.cursor_position = tx.cursor_before;
+            self.selection_anchor = None;
+            self.text_start_idx = tx.view_before;
+            self.clamp_text_start();
+        }
+    }
+
+    /// Reapply the most recently undone group of edits, and show the text where it
+    /// was shown when they were made.
+    pub fn redo(&mut self) {
+        self.extra_carets.clear();
+        if let Some(tx) = self.history.redo().cloned() {
+            for edit in &tx.edits {
+                match edit {
+                    Edit::Insert { byte_idx, text } => self.rope_insert(*byte_idx, text),
+                    Edit::Delete { byte_idx, text } => {
+                        self.rope_delete(*byte_idx..byte_idx + text.len())
+                    }
+                }
+            }
+            self.cursor_position = tx.cursor_after;
+            self.selection_anchor = None;
+            self.text_start_idx = tx.view_after;
+            self.clamp_text_start();
+        }
+    }
+
+    /// Put the cursor at 'cursor' and start the view at the line 'text_start' is in,
+    /// e.g. where they were when the file was last open. Both are clamped to the
+    /// text, which may have changed since.
+    pub fn restore_view(&mut self, cursor: usize, text_start: usize) {
+        self.extra_carets.clear();
+        self.selection_anchor = None;
+        let mut cursor = cursor.min(self.content.byte_len());
+        while !self.content.is_char_boundary(cursor) {
+            cursor -= 1;
+        }
+        self.cursor_position = grapheme::cluster_start(&self.content, cursor);
+        self.text_start_idx = self.line_start(text_start.min(self.content.byte_len()));
+        self.history.break_coalescing();
+    }
+
+    /// Keep the start of the viewport inside the text after it shrinks.
+    fn clamp_text_start(&mut self) {
+        let mut start = self.text_start_idx.min(self.content.byte_len());
+        while !self.content.is_char_boundary(start) {
+            start -= 1;
+        }
+        self.text_start_idx = start;
+    }
+
+    /// Insert a newline at the cursor, keeping the indentation of the current line.
+    /// One extra level of indentation is added after an opening bracket.
+    pub fn insert_newline(&mut self) {
+        if self.at_each_cursor(Self::insert_newline) {
+            return;
+        }
+
+        self.history
+            .begin_transaction(self.cursor_position, self.text_start_idx);
+        self.delete_selection();
+
+        let line_start = self.line_start(self.cursor_position);
+        let before_cursor = self
+            .content
+            .byte_slice(line_start..self.cursor_position)
+            .to_string();
+
+        let line_indent: String = before_cursor
+            .chars()
+            .take_while(|c| *c == ' ' || *c == '\t')
+            .collect();
+        let mut indent = line_indent.clone();
+        if before_cursor.trim_end().ends_with(['{', '(', '[']) {
+            indent.push_str(&self.indent_unit());
+        }
+
+        // New lines end the way most lines already do.
+        let ending = self.line_endings().dominant().as_str();
+
+        // Between an empty pair of brackets, the closing bracket goes on its own line
+        // below the cursor.
+        let between_brackets = self.auto_close
+            && matches!(
+                (
+                    self.char_before(self.cursor_position),
+                    self.char_after(self.cursor_position)
+                ),
+                (Some('{'), Some('}')) | (Some('('), Some(')')) | (Some('['), Some(']'))
+            );
+
+        if between_brackets {
+            self.insert_around_cursor(
+                &format!("{ending}{indent}"),
+                &format!("{ending}{line_indent}"),
+            );
+        } else {
+            self.insert_text(&format!("{ending}{indent}"));
+        }
+        self.history.end_transaction();
+    }
+
+    /// Insert a closing bracket at the cursor. If it is the first thing on the line,
+    /// the line is reindented to line up with the line of the matching opening bracket.
+    pub fn insert_closing_bracket(&mut self, close: char) {
+        if self.at_each_cursor(|editor| editor.insert_closing_bracket(close)) {
+            return;
+        }
+
+        // Typing the closing bracket that auto-closing already inserted steps over it.
+        if self.auto_close
+            && self.selection_range().is_none()
+            && self.char_after(self.cursor_position) == Some(close)
+        {
+            self.cursor_position += close.len_utf8();
+            self.history.break_coalescing();
+            return;
+        }
+
+        self.history
+            .begin_transaction(self.cursor_position, self.text_start_idx);
+        self.delete_selection();
+
+        let line_start = self.line_start(self.cursor_position);
+        let only_whitespace = self
+            .content
+            .byte_slice(line_start..self.cursor_position)
+            .chars()
+            .all(|c| c == ' ' || c == '\t');
+
+        if only_whitespace {
+            if let Some(open_idx) = self.find_open_bracket(close, self.cursor_position) {
+                let indent = self.line_indent(open_idx);
+                let cursor_before = self.cursor_position;
+                self.cursor_position = line_start;
+                self.record_delete(line_start, line_start..cursor_before, cursor_before);
+                self.insert_text(&indent);
+            }
+        }
+
+        self.insert_text(close.encode_utf8(&mut [0; 4]));
+        self.history.end_transaction();
+    }
+
+    /// Search backwards from 'before' for the bracket that 'close' would close,
+    /// skipping over any nested pairs.
+    fn find_open_bracket(&self, close: char, before: usize) -> Option<usize> {
+        let open = match close {
+            '}' => '{',
+            ')' => '(',
+            ']' => '[',
+            _ => return None,
+        };
+
+        let mut depth = 0;
+        let mut byte_idx = before;
+        for c in self.content.byte_slice(..before).chars().rev() {
+            byte_idx -= c.len_utf8();
+            if c == close {
+                depth += 1;
+            } else if c == open {
+                if depth == 0 {
+                    return Some(byte_idx);
+                }
+                depth -= 1;
+            }
+        }
+
+        None
+    }
+
+    /// Get the byte index of the start of the line containing 'byte_idx'.
+    fn line_start(&self, byte_idx: usize) -> usize {
+        self.content
+            .byte_of_line(self.content.line_of_byte(byte_idx))
+    }
+
+    /// Get the byte index of the end of the line containing 'byte_idx', before its
+    /// newline.
+    fn line_end(&self, byte_idx: usize) -> usize {
+        let line = self.content.line_of_byte(byte_idx);
+        let line_start = self.content.byte_of_line(line);
+
+        // The empty last line after a trailing newline.
+        if line_start == self.content.byte_len() {
+            return line_start;
+        }
+        line_start + self.content.line(line).byte_len()
+    }
+
+    /// Get the leading whitespace of the line containing 'byte_idx'.
+    fn line_indent(&self, byte_idx: usize) -> String {
+        let line_start = self.line_start(byte_idx);
+        self.content
+            .byte_slice(line_start..)
+            .chars()
+            .take_while(|c| *c == ' ' || *c == '\t')
+            .collect()
+    }
+
+    pub fn scroll(&mut self, scroll: ScrollAmount, glyph_rasterizer: &mut impl GlyphRasterizer) {
+        match scroll {
+            ScrollAmount::Up { lines } => self.scroll_up(lines, glyph_rasterizer),
+            ScrollAmount::Down { lines } => self.scroll_down(lines, glyph_rasterizer),
+            ScrollAmount::ToStart => self.scroll_to_start(),
+            ScrollAmount::ToEnd => self.scroll_to_end(glyph_rasterizer),
+            ScrollAmount::PageUp => self.scroll_page(true, glyph_rasterizer),
+            ScrollAmount::PageDown => self.scroll_page(false, glyph_rasterizer),
+        }
+    }
+
+    /// Scroll by the number of lines that fully fit in the window. The cursor keeps its
+    /// row and column on screen, so it moves by a page along with the text.
+    fn scroll_page(&mut self, up: bool, glyph_rasterizer: &mut impl GlyphRasterizer) {
+        self.update_selection_anchor();
+
+        let line_height = self.line_metrics.line_height();
+        let page = ((self.window_height / line_height).floor() as usize).max(1);
+
+        // Where the cursor is on screen, or the top of the page if it is scrolled out of view.
+        let (row, column) = self
+            .visible_lines(glyph_rasterizer)
+            .enumerate()
+            .find(|(_, line)| {
+                line.range.contains(&self.cursor_position) || line.range.end == self.cursor_position
+            })
+            .map(|(row, line)| {
+                let column = self
+                    .content
+                    .byte_slice(line.range.start..self.cursor_position)
+                    .chars()
+                    .count();
+                (row, column)
+            })
+            .unwrap_or((0, 0));
+
+        if up {
+            self.scroll_up(page, glyph_rasterizer);
+        } else {
+            self.scroll_down(page, glyph_rasterizer);
+        }
+
+        if let Some(line) = self.visible_lines(glyph_rasterizer).take(row + 1).last() {
+            self.cursor_position = line.range.start
+                + self
+                    .content
+                    .byte_slice(line.range.clone())
+                    .chars()
+                    .take(column)
+                    .map(char::len_utf8)
+                    .sum::<usize>();
+        }
+        self.history.break_coalescing();
+    }
+
+    fn scroll_to_start(&mut self) {
+        self.update_selection_anchor();
+
+        self.text_start_idx = 0;
+        self.cursor_position = 0;
+    }
+
+    fn scroll_to_end(&mut self, glyph_rasterizer: &mut impl GlyphRasterizer) {
+        self.update_selection_anchor();
+
+        let bottom = self.content.byte_len().saturating_sub(1);
+
+        self.text_start_idx = self.last_page_start(glyph_rasterizer);
+        self.cursor_position = bottom;
+    }
+
+    /// Scroll the viewport up 'lines' wrapped lines.
+    fn scroll_up(&mut self, lines: usize, glyph_rasterizer: &mut impl GlyphRasterizer) {
+        let glyph_rasterizer = &mut TabStops::new(glyph_rasterizer, self.tab_width);
+        self.text_start_idx =
+            layout::start_before(&self.content, self.text_start_idx, lines, |start, end| {
+                self.line_starts(start, end, glyph_rasterizer)
+            });
+    }
+
+    /// The start of every visual line in the hard line starting at 'line_start', up
+    /// to but not including 'end'. Policies that go by character widths reuse the
+    /// widths of the hard line measured last.
+    fn line_starts(
+        &self,
+        line_start: usize,
+        end: usize,
+        glyph_rasterizer: &mut dyn GlyphRasterizer,
+    ) -> Vec<usize> {
+        let policy = self.wrap_policy.as_ref();
+        let mut advances = self.advances.borrow_mut();
+        if let Some(break_at) = policy.break_at() {
+            let measured = advances
+                .as_ref()
+                .is_some_and(|a| a.measured(line_start, self.font_size, glyph_rasterizer));
+            if !measured {
+                *advances = Some(Advances::measure(
+                    &self.content,
+                    line_start,
+                    self.content.byte_len(),
+                    self.font_size,
+                    glyph_rasterizer,
+                ));
+            }
+            let advances = advances.as_ref().unwrap();
+            let mut starts = advances.line_starts(self.window_width, break_at);
+            starts.retain(|&start| start < end);
+            return starts;
+        }
+
+        policy.line_starts(
+            &self.content,
+            line_start,
+            end,
+            self.window_width,
+            self.font_size,
+            glyph_rasterizer,
+        )
+    }
+
+    /// Scroll the viewport down 'lines' wrapped lines, stopping at the last page:
+    /// the last line of the text stays at the bottom of the window.
+    fn scroll_down(&mut self, lines: usize, glyph_rasterizer: &mut impl GlyphRasterizer) {
+        let line_height = self.line_metrics.line_height();
+        let page = ((self.window_height / line_height).floor() as usize).max(1);
+
+        // A page has to fit below wherever the view ends up.
+        let starts: Vec<usize> = self
+            .visible_lines_up_to(glyph_rasterizer, lines.saturating_add(page))
+            .map(|line| line.range.start)
+            .collect();
+        let scrolled = lines.min(starts.len().saturating_sub(page));
+        self.text_start_idx = starts[scrolled];
+    }
+
+    /// Where the view starts when its bottom line is the last line of the text.
+    fn last_page_start(&self, glyph_rasterizer: &mut impl GlyphRasterizer) -> usize {
+        let line_height = self.line_metrics.line_height();
+        let page = ((self.window_height / line_height).floor() as usize).max(1);
+
+        // Text ending in a newline has an empty last line, starting at the end.
+        let len = self.content.byte_len();
+        let above = if len == 0 || self.content.byte(len - 1) == b'\n' {
+            page - 1
+        } else {
+            page
+        };
+        let glyph_rasterizer = &mut TabStops::new(glyph_rasterizer, self.tab_width);
+        layout::start_before(&self.content, len, above, |start, end| {
+            self.line_starts(start, end, glyph_rasterizer)
+        })
     }
 }
@@ -0,0 +1,131 @@
+use crate::grapheme;
+use crop::Rope;
+
+/// A single line of text being typed, like a search query or a line number. The
+/// cursor moves and deletes by grapheme cluster like it does in a [`TextEditor`],
+/// but there is no selection, no undo, and the text never holds a line break.
+///
+/// [`TextEditor`]: crate::TextEditor
+#[derive(Debug, Clone, Default)]
+pub struct LineInput {
+    text: Rope,
+
+    /// A byte index into the text, always on a cluster boundary.
+    cursor: usize,
+
+    /// Characters that may be typed. None accepts any but line breaks.
+    accepts: Option<fn(char) -> bool>,
+
+    /// The most characters the text can hold.
+    max_chars: Option<usize>,
+}
+
+impl LineInput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only let characters 'accepts' returns true for be typed, e.g. digits.
+    pub fn accepting(mut self, accepts: fn(char) -> bool) -> Self {
+        self.accepts = Some(accepts);
+        self
+    }
+
+    /// Refuse typing that would make the text longer than 'max_chars' characters.
+    pub fn max_chars(mut self, max_chars: usize) -> Self {
+        self.max_chars = Some(max_chars);
+        self
+    }
+
+    pub fn text(&self) -> String {
+        self.text.to_string()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.text.byte_len() == 0
+    }
+
+    /// The byte index of the cursor in the text.
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Replace the text with 'text' and put the cursor at its end. Returns false,
+    /// leaving the text as it was, if 'text' could not have been typed.
+    pub fn set_text(&mut self, text: &str) -> bool {
+        if !self.allows(text, 0) {
+            return false;
+        }
+
+        self.text = Rope::from(text);
+        self.cursor = text.len();
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.text = Rope::new();
+        self.cursor = 0;
+    }
+
+    /// Type 'text' at the cursor. Returns false, leaving the text as it was, if it
+    /// holds a character that isn't accepted or would make the text too long.
+    pub fn insert(&mut self, text: &str) -> bool {
+        if text.is_empty() || !self.allows(text, self.text.chars().count()) {
+            return false;
+        }
+
+        self.text.insert(self.cursor, text);
+        self.cursor += text.len();
+        true
+    }
+
+    /// Delete the cluster before the cursor. Returns false if there was none.
+    pub fn backspace(&mut self) -> bool {
+        let start = grapheme::prev_boundary(&self.text, self.cursor);
+        if start == self.cursor {
+            return false;
+        }
+
+        self.text.delete(start..self.cursor);
+        self.cursor = start;
+        true
+    }
+
+    /// Delete the cluster after the cursor. Returns false if there was none.
+    pub fn delete(&mut self) -> bool {
+        let end = grapheme::next_boundary(&self.text, self.cursor);
+        if end == self.cursor {
+            return false;
+        }
+
+        self.text.delete(self.cursor..end);
+        true
+    }
+
+    pub fn move_left(&mut self) {
+        self.cursor = grapheme::prev_boundary(&self.text, self.cursor);
+    }
+
+    pub fn move_right(&mut self) {
+        self.cursor = grapheme::next_boundary(&self.text, self.cursor);
+    }
+
+    pub fn move_to_start(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn move_to_end(&mut self) {
+        self.cursor = self.text.byte_len();
+    }
+
+    /// Could 'text' be added to a text of 'chars' characters?
+    fn allows(&self, text: &str, chars: usize) -> bool {
+        let accepted = text
+            .chars()
+            .all(|c| c != '\n' && c != '\r' && self.accepts.is_none_or(|accepts| accepts(c)));
+        let fits = self
+            .max_chars
+            .is_none_or(|max| chars + text.chars().count() <= max);
+        accepted && fits
+    }
+}
@@ -0,0 +1,143 @@
+use crate::Encoding;
+use crop::{Rope, RopeBuilder};
+use std::{
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, TryRecvError},
+        Arc,
+    },
+    thread,
+};
+
+/// How many bytes are read and added to the rope at a time.
+const CHUNK_SIZE: usize = 1 << 20;
+
+/// A file read by a FileLoader.
+pub struct LoadedFile {
+    pub path: PathBuf,
+    pub rope: Rope,
+    pub encoding: Encoding,
+}
+
+/// Reads a file into a rope on another thread, a chunk at a time, so opening a
+/// large file doesn't hold up the caller. Poll it until the file is ready.
+pub struct FileLoader {
+    path: PathBuf,
+    len: u64,
+
+    /// How many bytes the thread has read so far.
+    read: Arc<AtomicU64>,
+    result: Receiver<io::Result<LoadedFile>>,
+}
+
+impl FileLoader {
+    pub fn start(path: &Path) -> io::Result<Self> {
+        let len = fs::metadata(path)?.len();
+        let read = Arc::new(AtomicU64::new(0));
+        let (sender, result) = mpsc::channel();
+
+        let thread_path = path.to_path_buf();
+        let thread_read = read.clone();
+        thread::spawn(move || {
+            let loaded = load(&thread_path, &thread_read).map(|(rope, encoding)| LoadedFile {
+                path: thread_path,
+                rope,
+                encoding,
+            });
+            // Nobody is waiting for the file if the loader was dropped.
+            let _ = sender.send(loaded);
+        });
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            len,
+            read,
+            result,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// How much of the file has been read, from 0 to 1.
+    pub fn progress(&self) -> f32 {
+        if self.len == 0 {
+            return 1.0;
+        }
+        (self.read.load(Ordering::Relaxed) as f64 / self.len as f64).min(1.0) as f32
+    }
+
+    /// The file, once it has been read. None while it is still being read.
+    pub fn poll(&mut self) -> Option<io::Result<LoadedFile>> {
+        match self.result.try_recv() {
+            Ok(loaded) => Some(loaded),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => {
+                Some(Err(io::Error::other("the thread reading the file stopped")))
+            }
+        }
+    }
+}
+
+/// Read the file at 'path' into a rope, counting the bytes read in 'read'. UTF-8
+/// is added to the rope as it is read. A file in any other encoding is read again
+/// whole, and decoded at once.
+fn load(path: &Path, read: &AtomicU64) -> io::Result<(Rope, Encoding)> {
+    let mut file = File::open(path)?;
+    let mut builder = RopeBuilder::new();
+    let mut chunk = vec![0; CHUNK_SIZE];
+
+    // Read bytes that weren't added yet, e.g. the start of a character cut in two
+    // by the end of a chunk.
+    let mut pending = vec![];
+    let mut utf8 = true;
+    let mut first = true;
+    loop {
+        let n = match file.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        read.fetch_add(n as u64, Ordering::Relaxed);
+        pending.extend_from_slice(&chunk[..n]);
+
+        // Byte order marks and UTF-16 without one can be valid UTF-8, but aren't.
+        if first && Encoding::sniff(&pending).is_some() {
+            utf8 = false;
+            break;
+        }
+        first = false;
+        match std::str::from_utf8(&pending) {
+            Ok(text) => {
+                builder.append(text);
+                pending.clear();
+            }
+            // Only the end is cut off, the rest waits for the next chunk.
+            Err(e) if e.error_len().is_none() => {
+                let valid = e.valid_up_to();
+                builder.append(std::str::from_utf8(&pending[..valid]).unwrap());
+                pending.drain(..valid);
+            }
+            Err(_) => {
+                utf8 = false;
+                break;
+            }
+        }
+    }
+
+    if utf8 && pending.is_empty() {
+        return Ok((builder.build(), Encoding::Utf8));
+    }
+
+    let bytes = fs::read(path)?;
+    read.store(bytes.len() as u64, Ordering::Relaxed);
+    let encoding = Encoding::detect(&bytes);
+    let mut builder = RopeBuilder::new();
+    builder.append(encoding.decode(&bytes));
+    Ok((builder.build(), encoding))
+}
@@ -0,0 +1,98 @@
+use memmap2::Mmap;
+use std::{borrow::Cow, fs::File, io, path::Path};
+
+/// Every this many lines, where the line starts is remembered.
+const LINE_INDEX_STRIDE: usize = 1024;
+
+/// A file too big to edit, mapped into memory and read in place. Only the pages
+/// of the lines looked at are loaded, so it can be larger than the memory there is.
+pub struct MappedFile {
+    map: Mmap,
+
+    /// The byte offset of every LINE_INDEX_STRIDE-th line. Lines in between are
+    /// found by scanning forward from the one before them.
+    line_index: Vec<usize>,
+    line_count: usize,
+}
+
+impl MappedFile {
+    /// Map the file at 'path' and index its lines, which reads it through once.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = File::open(path)?;
+        // Safety: another process could change the file while it is mapped. Changed
+        // bytes are harmless, they are only read as bytes and invalid UTF-8 is
+        // replaced. Truncating it makes reading past the new end crash the editor,
+        // which is the price of not reading the whole file into memory.
+        let map = unsafe { Mmap::map(&file)? };
+
+        let mut line_index = vec![0];
+        let mut line_count = 1;
+        for newline in memchr::memchr_iter(b'\n', &map) {
+            if line_count % LINE_INDEX_STRIDE == 0 {
+                line_index.push(newline + 1);
+            }
+            line_count += 1;
+        }
+
+        Ok(Self {
+            map,
+            line_index,
+            line_count,
+        })
+    }
+
+    /// The size of the file in bytes.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// How many lines there are. A file ending in a newline has an empty last line.
+    pub fn line_count(&self) -> usize {
+        self.line_count
+    }
+
+    /// The lines from 'first' on, without their line endings, and cut after
+    /// 'max_bytes' so long lines are only read as far as they are shown. Bytes
+    /// that aren't UTF-8 show up as replacement characters.
+    pub fn lines(&self, first: usize, max_bytes: usize) -> impl Iterator<Item = Cow<'_, str>> {
+        let mut start = self.line_start(first);
+        (first..self.line_count).map(move |_| {
+            let end = self.line_end(start);
+            let line = &self.map[start..end];
+            start = end + 1;
+
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+            if line.len() <= max_bytes {
+                return String::from_utf8_lossy(line);
+            }
+            // Don't cut a character in half, or it would show up as replaced.
+            let mut cut = max_bytes;
+            while cut > 0 && line[cut] & 0xC0 == 0x80 {
+                cut -= 1;
+            }
+            String::from_utf8_lossy(&line[..cut])
+        })
+    }
+
+    /// Where 'line' starts, or the end of the file if there's no such line.
+    fn line_start(&self, line: usize) -> usize {
+        if line >= self.line_count {
+            return self.map.len();
+        }
+
+        let mut start = self.line_index[line / LINE_INDEX_STRIDE];
+        for _ in 0..line % LINE_INDEX_STRIDE {
+            start = self.line_end(start) + 1;
+        }
+        start
+    }
+
+    /// Where the line starting at 'start' ends: at its newline, or the end of the file.
+    fn line_end(&self, start: usize) -> usize {
+        memchr::memchr(b'\n', &self.map[start..]).map_or(self.map.len(), |i| start + i)
+    }
+}
@@ -0,0 +1,221 @@
+/// A single change made to the text rope.
+#[derive(Debug, Clone)]
+pub enum Edit {
+    Insert { byte_idx: usize, text: String },
+    Delete { byte_idx: usize, text: String },
+}
+
+impl Edit {
+    /// Can 'next' be folded into the same undo step as this edit? This is true
+    /// for a run of single characters typed or deleted at one spot.
+    fn continues_with(&self, next: &Edit) -> bool {
+        let is_single_char = |text: &str| text.chars().count() == 1 && text != "\n";
+
+        match (self, next) {
+            (
+                Edit::Insert { byte_idx, text },
+                Edit::Insert {
+                    byte_idx: next_idx,
+                    text: next_text,
+                },
+            ) => {
+                is_single_char(text)
+                    && is_single_char(next_text)
+                    && byte_idx + text.len() == *next_idx
+            }
+            (
+                Edit::Delete { byte_idx, text },
+                Edit::Delete {
+                    byte_idx: next_idx,
+                    text: next_text,
+                },
+            ) => {
+                // Either a run of backspaces, or a run of deletes in place.
+                is_single_char(text)
+                    && is_single_char(next_text)
+                    && (next_idx + next_text.len() == *byte_idx || next_idx == byte_idx)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A group of edits that are undone and redone together.
+#[derive(Debug, Clone)]
+pub struct Transaction {
+    pub edits: Vec<Edit>,
+    pub cursor_before: usize,
+    pub cursor_after: usize,
+
+    /// Where the view started, as a byte index, when the first and the last edit
+    /// were made. Undoing and redoing show the text as it was seen then.
+    pub view_before: usize,
+    pub view_after: usize,
+
+    /// Changes every time edits are added, so each state of the text the history
+    /// can get back to has its own version.
+    version: u64,
+}
+
+/// Records edits made to a TextEditor so they can be undone and redone.
+#[derive(Debug, Default)]
+pub struct UndoStack {
+    undo: Vec<Transaction>,
+    redo: Vec<Transaction>,
+
+    /// How many begin_transaction calls are still waiting on end_transaction.
+    open_transactions: usize,
+
+    /// Set when the next edit must not be merged into the previous step.
+    break_next: bool,
+
+    /// The last version handed to a step, and the version the text had when it was
+    /// last saved.
+    versions: u64,
+    saved: u64,
+}
+
+impl UndoStack {
+    /// Record an edit, made while the view started at 'view'. It joins the previous
+    /// undo step if a transaction is open or it continues the previous edit (e.g.
+    /// typing a word), otherwise it starts a new step.
+    pub fn record(&mut self, edit: Edit, cursor_before: usize, cursor_after: usize, view: usize) {
+        self.redo.clear();
+
+        if let Some(last) = self.undo.last_mut() {
+            let merge = if self.open_transactions > 0 {
+                true
+            } else {
+                !self.break_next
+                    && last
+                        .edits
+                        .last()
+                        .is_some_and(|prev| prev.continues_with(&edit))
+            };
+
+            if merge {
+                self.versions += 1;
+                last.edits.push(edit);
+                last.cursor_after = cursor_after;
+                last.view_after = view;
+                last.version = self.versions;
+                self.break_next = false;
+                return;
+            }
+        }
+
+        self.versions += 1;
+        self.undo.push(Transaction {
+            edits: vec![edit],
+            cursor_before,
+            cursor_after,
+            view_before: view,
+            view_after: view,
+            version: self.versions,
+        });
+        self.break_next = false;
+    }
+
+    /// Every edit recorded until the matching end_transaction becomes one undo step.
+    pub fn begin_transaction(&mut self, cursor: usize, view: usize) {
+        if self.open_transactions == 0 {
+            self.versions += 1;
+            self.undo.push(Transaction {
+                edits: vec![],
+                cursor_before: cursor,
+                cursor_after: cursor,
+                view_before: view,
+                view_after: view,
+                version: self.versions,
+            });
+        }
+        self.open_transactions += 1;
+    }
+
+    /// Like begin_transaction, but the edits join the most recent undo step instead
+    /// of starting one, so undoing that step takes them back too. Starts a new step
+    /// if there is nothing to join, or if steps were undone since.
+    pub fn join_last(&mut self, cursor: usize, view: usize) {
+        if self.open_transactions == 0 && (self.undo.is_empty() || !self.redo.is_empty()) {
+            self.begin_transaction(cursor, view);
+        } else {
+            self.open_transactions += 1;
+        }
+    }
+
+    pub fn end_transaction(&mut self) {
+        self.open_transactions = self.open_transactions.saturating_sub(1);
+        if self.open_transactions == 0 {
+            if self.undo.last().is_some_and(|tx| tx.edits.is_empty()) {
+                self.undo.pop();
+            }
+            self.break_next = true;
+        }
+    }
+
+    /// Stop the next edit from merging into the current undo step.
+    pub fn break_coalescing(&mut self) {
+        self.break_next = true;
+    }
+
+    /// Which state the text is in: the version of the last step that can be undone,
+    /// or 0 before the first.
+    fn version(&self) -> u64 {
+        self.undo.last().map_or(0, |tx| tx.version)
+    }
+
+    /// Remember the text as it is now as saved. The next edit starts a new step, so
+    /// undoing it gets back to the saved text.
+    pub fn mark_saved(&mut self) {
+        self.saved = self.version();
+        self.break_next = true;
+    }
+
+    /// Was the text edited since it was last saved? Undoing back to the saved text
+    /// makes it unmodified again.
+    pub fn is_modified(&self) -> bool {
+        self.version() != self.saved
+    }
+
+    /// How many steps can be undone.
+    pub fn undo_len(&self) -> usize {
+        self.undo.len()
+    }
+
+    /// How many steps can be redone.
+    pub fn redo_len(&self) -> usize {
+        self.redo.len()
+    }
+
+    /// Roughly how much memory the history holds: the bytes of text in every edit,
+    /// plus the edits themselves.
+    pub fn memory_bytes(&self) -> usize {
+        self.undo
+            .iter()
+            .chain(&self.redo)
+            .flat_map(|tx| &tx.edits)
+            .map(|edit| match edit {
+                Edit::Insert { text, .. } | Edit::Delete { text, .. } => {
+                    text.capacity() + std::mem::size_of::<Edit>()
+                }
+            })
+            .sum()
+    }
+
+    /// Move the most recent step onto the redo stack and return it so it can be reverted.
+    pub fn undo(&mut self) -> Option<&Transaction> {
+        let tx = self.undo.pop()?;
+        self.redo.push(tx);
+        self.break_next = true;
+        self.redo.last()
+    }
+
+    /// Move the most recently undone step back onto the undo stack and return it so
+    /// it can be reapplied.
+    pub fn redo(&mut self) -> Option<&Transaction> {
+        let tx = self.redo.pop()?;
+        self.undo.push(tx);
+        self.break_next = true;
+        self.undo.last()
+    }
+}
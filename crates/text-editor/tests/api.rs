@@ -0,0 +1,402 @@
+use text_editor::{
+    GlyphMetrics, GlyphRasterizer, LineMetrics, Regex, Rope, TextBuffer, TextEditor, VisualLines,
+    WordWrap,
+};
+
+/// Every character is 10 pixels wide.
+struct Rasterizer;
+
+impl GlyphRasterizer for Rasterizer {
+    fn get_glyph(&mut self, _c: char, _font_size: f32) -> GlyphMetrics {
+        GlyphMetrics {
+            advance: (10.0, 0.0),
+            size: (10.0, 16.0),
+            pos: (0.0, 16.0),
+        }
+    }
+}
+
+/// An empty directory of its own for a test to write files in.
+fn temp_dir(name: &str) -> std::path::PathBuf {
+    let nanos = std::time::UNIX_EPOCH.elapsed().unwrap().as_nanos();
+    let dir = std::env::temp_dir().join(format!(
+        "text-editor-test-{name}-{}-{nanos}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn text(buffer: &impl TextBuffer) -> String {
+    buffer.slice_bytes(0..buffer.len_bytes()).to_string()
+}
+
+fn lines_of(buffer: &impl TextBuffer) -> Vec<(usize, (usize, usize))> {
+    (0..buffer.len_lines() + 1)
+        .map(|line| {
+            let start = buffer.start_of_line(line);
+            (start, buffer.line_and_column(start))
+        })
+        .collect()
+}
+
+#[test]
+fn ropes_and_editors_read_the_same() {
+    let content = "fn main() {\n    é\n}\n";
+    let rope = Rope::from(content);
+    let editor = TextEditor::new(content, 800.0, 600.0, 16.0);
+
+    assert_eq!(text(&rope), text(&editor));
+    assert_eq!(rope.len_lines(), 4);
+    assert_eq!(editor.line_count(), 4);
+    assert_eq!(lines_of(&rope), lines_of(&editor));
+
+    // Columns count characters, and lines past the end clamp to the last one,
+    // where Rope's own byte_of_line would panic.
+    assert_eq!(
+        editor.line_and_column(content.find('}').unwrap() - 1),
+        (1, 5)
+    );
+    assert_eq!(rope.start_of_line(100), content.len());
+}
+
+#[test]
+fn visual_lines_wrap_a_plain_rope() {
+    let rope = Rope::from("one two three\nfour");
+    let lines: Vec<_> = VisualLines::new(&WordWrap, &rope, 0, 90.0, 16.0, &mut Rasterizer, 10)
+        .map(|line| rope.byte_slice(line.range).to_string())
+        .collect();
+    assert_eq!(lines, ["one two ", "three", "four"]);
+
+    let first_two = VisualLines::new(&WordWrap, &rope, 0, 90.0, 16.0, &mut Rasterizer, 2).count();
+    assert_eq!(first_two, 2);
+}
+
+#[test]
+fn edits_undo_and_redo() {
+    let mut editor = TextEditor::new("hello\n", 800.0, 600.0, 16.0);
+    editor.move_cursor_to(5);
+    editor.insert_text(", world");
+    assert_eq!(text(&editor), "hello, world\n");
+    assert_eq!(editor.stats().undo_steps, 1);
+
+    // The stats are counted again after every change.
+    editor.undo();
+    assert_eq!(text(&editor), "hello\n");
+    assert_eq!(editor.cursor_position(), 5);
+    let stats = editor.stats();
+    assert_eq!((stats.bytes, stats.undo_steps, stats.redo_steps), (6, 0, 1));
+
+    editor.redo();
+    assert_eq!(text(&editor), "hello, world\n");
+}
+
+#[test]
+fn search_and_replace_with_the_reexported_regex() {
+    let mut editor = TextEditor::new("let a = 1;\nlet b = 22;\n", 800.0, 600.0, 16.0);
+    let numbers = Regex::new(r"\d+").unwrap();
+
+    let found: Vec<_> = editor
+        .find_regex(&numbers)
+        .into_iter()
+        .map(|range| editor.slice(range).to_string())
+        .collect();
+    assert_eq!(found, ["1", "22"]);
+
+    // Plain text is found across the chunks of a long rope, never overlapping.
+    let long = "aab é ".repeat(2000);
+    let long_editor = TextEditor::new(&long, 800.0, 600.0, 16.0);
+    for pattern in ["aa", "b é", "é aa", " "] {
+        let expected: Vec<_> = long
+            .match_indices(pattern)
+            .map(|(start, m)| start..start + m.len())
+            .collect();
+        assert_eq!(long_editor.find(pattern), expected, "{pattern:?}");
+    }
+
+    assert_eq!(editor.replace_all(&numbers, "n"), 2);
+    assert_eq!(text(&editor), "let a = n;\nlet b = n;\n");
+
+    // Empty matches are replaced one after another instead of at the same spot.
+    let mut editor = TextEditor::new("ab", 800.0, 600.0, 16.0);
+    let nothing = Regex::new("x*").unwrap();
+    for _ in 0..3 {
+        assert!(editor.replace_next(&nothing, "-"));
+    }
+    assert_eq!(text(&editor), "-a-b");
+    assert_eq!(editor.cursor_position(), 4);
+}
+
+#[test]
+fn replacing_ranges_is_one_undo_step() {
+    let mut editor = TextEditor::new("one two one two\n", 800.0, 600.0, 16.0);
+
+    let replacements = [(0..3, "1".to_string()), (12..15, "2".to_string())];
+    assert_eq!(editor.replace_ranges(&replacements), 2);
+    assert_eq!(text(&editor), "1 two one 2\n");
+
+    editor.undo();
+    assert_eq!(text(&editor), "one two one two\n");
+    assert_eq!(editor.replace_ranges(&[]), 0);
+}
+
+#[test]
+fn mapped_files_read_lines_from_anywhere_without_their_endings() {
+    let dir = temp_dir("mapped");
+    let path = dir.join("file.txt");
+    let text: String = (0..3000).map(|i| format!("line {i}\r\n")).collect();
+    std::fs::write(&path, text).unwrap();
+
+    let file = text_editor::mapped::MappedFile::open(&path).unwrap();
+    assert_eq!(file.line_count(), 3001);
+    let lines: Vec<_> = file.lines(2047, usize::MAX).take(3).collect();
+    assert_eq!(lines, ["line 2047", "line 2048", "line 2049"]);
+    assert_eq!(file.lines(3000, usize::MAX).collect::<Vec<_>>(), [""]);
+    assert_eq!(file.lines(3001, usize::MAX).count(), 0);
+    assert_eq!(file.lines(2047, 6).next().unwrap(), "line 2");
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn files_are_decoded_and_saved_in_their_encoding() {
+    use text_editor::Encoding;
+
+    let dir = temp_dir("encoding");
+    let path = dir.join("file.txt");
+    let cp1252 = b"caf\xe9 \x80\n";
+    std::fs::write(&path, cp1252).unwrap();
+
+    let mut editor = TextEditor::from_file(&path, 800.0, 600.0, 16.0).unwrap();
+    assert_eq!(editor.encoding(), Encoding::Windows1252);
+    assert_eq!(editor.rope().to_string(), "café €\n");
+    editor.save().unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), cp1252);
+
+    // Characters Windows-1252 has no byte for can't be saved, and the file is left
+    // alone.
+    editor.insert_text("ā");
+    assert!(editor.save().is_err());
+    assert_eq!(std::fs::read(&path).unwrap(), cp1252);
+
+    let utf16: Vec<u8> = [0xFF, 0xFE, b'h', 0, b'i', 0].into();
+    std::fs::write(&path, &utf16).unwrap();
+    let mut editor = TextEditor::from_file(&path, 800.0, 600.0, 16.0).unwrap();
+    assert_eq!(editor.encoding(), Encoding::Utf16LeBom);
+    assert_eq!(editor.rope().to_string(), "hi");
+    editor.save().unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), utf16);
+
+    // Without a byte order mark, UTF-16 is told apart by its zero bytes.
+    let utf16be: Vec<u8> = "fn é()\n"
+        .encode_utf16()
+        .flat_map(u16::to_be_bytes)
+        .collect();
+    std::fs::write(&path, &utf16be).unwrap();
+    let mut editor = TextEditor::from_file(&path, 800.0, 600.0, 16.0).unwrap();
+    assert_eq!(editor.encoding(), Encoding::Utf16Be);
+    assert_eq!(editor.rope().to_string(), "fn é()\n");
+    editor.save().unwrap();
+    assert_eq!(std::fs::read(&path).unwrap(), utf16be);
+
+    // Reopening as another encoding reads the file again, as one undo step.
+    editor.reopen_with_encoding(Encoding::Windows1252).unwrap();
+    assert_eq!(editor.rope().to_string(), "\0f\0n\0 \0é\0(\0)\0\n");
+    editor.undo();
+    assert_eq!(editor.rope().to_string(), "fn é()\n");
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn edits_since_the_last_save_leave_the_text_modified() {
+    let dir = temp_dir("modified");
+    let path = dir.join("file.txt");
+    std::fs::write(&path, "ab").unwrap();
+    let mut editor = TextEditor::from_file(&path, 800.0, 600.0, 16.0).unwrap();
+    assert!(!editor.is_modified());
+
+    editor.move_cursor_to(2);
+    editor.insert_text("c");
+    assert!(editor.is_modified());
+    editor.save().unwrap();
+    assert!(!editor.is_modified());
+
+    // Typing after a save starts a new undo step, so undoing it gets back to the
+    // saved text.
+    editor.insert_text("d");
+    assert!(editor.is_modified());
+    editor.undo();
+    assert!(!editor.is_modified());
+    editor.undo();
+    assert!(editor.is_modified());
+    editor.redo();
+    assert!(!editor.is_modified());
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn undo_and_redo_show_the_text_where_it_was_edited() {
+    let text: String = (0..200).map(|i| format!("line {i}\n")).collect();
+    let mut editor = TextEditor::new(&text, 800.0, 600.0, 16.0);
+    editor.insert_text("x");
+
+    editor.scroll(text_editor::ScrollAmount::ToEnd, &mut Rasterizer);
+    assert_ne!(editor.text_start_idx(), 0);
+    editor.undo();
+    assert_eq!(editor.text_start_idx(), 0);
+
+    editor.scroll(text_editor::ScrollAmount::ToEnd, &mut Rasterizer);
+    editor.redo();
+    assert_eq!(editor.text_start_idx(), 0);
+    assert_eq!(editor.cursor_position(), 1);
+}
+
+#[test]
+fn scrolling_down_stops_with_the_last_line_at_the_bottom() {
+    use text_editor::ScrollAmount;
+
+    let text: String = (0..200).map(|i| format!("line {i}\n")).collect();
+    let mut editor = TextEditor::new(&text, 800.0, 600.0, 16.0);
+    editor.scroll(ScrollAmount::Down { lines: 1000 }, &mut Rasterizer);
+    let last_page = editor.text_start_idx();
+    assert_ne!(last_page, 0);
+
+    // The empty line after the last newline is the last one on screen.
+    let page = (600.0 / editor.line_metrics().line_height()).floor() as usize;
+    let lines: Vec<_> = editor.visible_lines(&mut Rasterizer).collect();
+    assert_eq!(lines.len(), page);
+    assert_eq!(lines[page - 1].range, text.len()..text.len());
+
+    editor.scroll(ScrollAmount::Down { lines: 1 }, &mut Rasterizer);
+    assert_eq!(editor.text_start_idx(), last_page);
+    editor.scroll(ScrollAmount::ToStart, &mut Rasterizer);
+    editor.scroll(ScrollAmount::ToEnd, &mut Rasterizer);
+    assert_eq!(editor.text_start_idx(), last_page);
+
+    // Text shorter than the window doesn't scroll at all.
+    let mut editor = TextEditor::new("short\ntext", 800.0, 600.0, 16.0);
+    editor.scroll(ScrollAmount::Down { lines: 1 }, &mut Rasterizer);
+    assert_eq!(editor.text_start_idx(), 0);
+}
+
+#[test]
+fn loaders_read_files_on_another_thread() {
+    use text_editor::{loader::FileLoader, Encoding};
+
+    let load = |path: &std::path::Path| {
+        let mut loader = FileLoader::start(path).unwrap();
+        loop {
+            if let Some(loaded) = loader.poll() {
+                assert_eq!(loader.progress(), 1.0);
+                return loaded.unwrap();
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+    };
+
+    // Two byte characters starting at an odd offset, so one is cut by every chunk.
+    let dir = temp_dir("loader");
+    let path = dir.join("file.txt");
+    let text = format!("x{}", "é".repeat(1 << 20));
+    std::fs::write(&path, &text).unwrap();
+    let loaded = load(&path);
+    assert_eq!(loaded.encoding, Encoding::Utf8);
+    assert_eq!(loaded.rope.to_string(), text);
+
+    std::fs::write(&path, b"\xef\xbb\xbfbom").unwrap();
+    let editor = TextEditor::from_loaded(load(&path), 800.0, 600.0, 16.0);
+    assert_eq!(editor.encoding(), Encoding::Utf8Bom);
+    assert_eq!(editor.rope().to_string(), "bom");
+    assert_eq!(editor.path(), Some(path.as_path()));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn line_inputs_edit_by_cluster_and_refuse_what_they_dont_accept() {
+    use text_editor::LineInput;
+
+    let mut input = LineInput::new();
+    assert!(input.insert("ae\u{301}"));
+    assert!(!input.insert("\n"));
+    input.move_left();
+    assert_eq!(input.cursor(), 1);
+    assert!(input.delete());
+    assert_eq!(input.text(), "a");
+
+    let mut digits = LineInput::new()
+        .accepting(|c| c.is_ascii_digit())
+        .max_chars(3);
+    assert!(digits.insert("12"));
+    assert!(!digits.insert("x"));
+    assert!(!digits.insert("34"));
+    assert!(digits.insert("3"));
+    assert!(!digits.set_text("1a"));
+    assert_eq!(digits.text(), "123");
+
+    digits.move_to_start();
+    assert!(!digits.backspace());
+    digits.move_to_end();
+    assert!(digits.backspace());
+    assert_eq!((digits.text().as_str(), digits.cursor()), ("12", 2));
+}
+
+#[test]
+fn lines_are_spaced_by_the_font_metrics_the_renderer_sets() {
+    let metrics = LineMetrics {
+        ascent: 12.0,
+        descent: 4.0,
+        line_gap: 4.0,
+        spacing: 1.5,
+    };
+    assert_eq!(metrics.line_height(), 30.0);
+    // The extra 14 pixels are split evenly above and below the glyphs.
+    assert_eq!(metrics.baseline(), 19.0);
+
+    let mut editor = TextEditor::new("a\nb\nc\nd\n", 800.0, 60.0, 16.0);
+    assert_eq!(editor.visible_line_count(), 4);
+    editor.set_line_metrics(metrics);
+    assert_eq!(editor.visible_line_count(), 2);
+
+    let line = editor.visual_line_at_point(45.0, &mut Rasterizer).unwrap();
+    assert_eq!(editor.slice(line.range).to_string(), "b");
+
+    // A bigger font spreads the lines out in proportion.
+    editor.update_font_size(32.0);
+    assert_eq!(editor.line_metrics().line_height(), 60.0);
+}
+
+#[test]
+fn brackets_in_strings_and_comments_are_not_matched() {
+    let mut editor = TextEditor::new("f(\")\", ')', /* ) */ x)\n", 800.0, 600.0, 16.0);
+    editor.move_cursor_to(1);
+    assert_eq!(editor.matching_bracket(), Some((1, 3)));
+
+    // Once the text is known to be Rust, the brackets in the string, the character
+    // and the comment are skipped.
+    editor.set_path("f.rs");
+    editor.update_syntax();
+    assert_eq!(editor.matching_bracket(), Some((1, 21)));
+    editor.move_cursor_to(3);
+    assert_eq!(editor.matching_bracket(), None);
+}
+
+#[test]
+fn diagnostics_are_found_once_per_parse_and_quote_little() {
+    let mut editor = TextEditor::new("fn f() {}\n", 800.0, 600.0, 16.0);
+    editor.set_path("f.rs");
+    editor.update_syntax();
+    assert!(editor.diagnostics().is_empty());
+    let version = editor.syntax_version();
+    editor.update_syntax();
+    assert_eq!(editor.syntax_version(), version);
+
+    editor.move_cursor_to(0);
+    editor.insert_text(&format!("$ {}\n", "long ".repeat(1000)));
+    editor.update_syntax();
+    assert_ne!(editor.syntax_version(), version);
+    let messages: Vec<_> = editor.diagnostics().iter().map(|d| &d.message).collect();
+    assert_eq!(messages, ["Unexpected `$ long long long lon...`"]);
+}
@@ -0,0 +1,132 @@
+use text_editor::{
+    layout::{CharWrap, WordWrap, WrapPolicy},
+    GlyphMetrics, GlyphRasterizer, ScrollAmount, TextEditor,
+};
+
+/// Narrow i's so line breaks don't all land on the same columns.
+struct Rasterizer;
+
+impl GlyphRasterizer for Rasterizer {
+    fn get_glyph(&mut self, c: char, _font_size: f32) -> GlyphMetrics {
+        let advance = if c == 'i' { 4.0 } else { 10.0 };
+        GlyphMetrics {
+            advance: (advance, 0.0),
+            size: (advance, 16.0),
+            pos: (0.0, 16.0),
+        }
+    }
+}
+
+/// Where every visual line starts, found by scrolling down one line at a time.
+fn starts_going_down(editor: &mut TextEditor) -> Vec<usize> {
+    let mut starts = vec![editor.text_start_idx()];
+    loop {
+        editor.scroll(ScrollAmount::Down { lines: 1 }, &mut Rasterizer);
+        if editor.text_start_idx() == *starts.last().unwrap() {
+            return starts;
+        }
+        starts.push(editor.text_start_idx());
+    }
+}
+
+fn scrolling_up_matches_scrolling_down(policy: impl WrapPolicy + 'static) {
+    let token = "xi".repeat(250_000);
+    let text = format!("a few short words\n{token} and-then some more words");
+    let mut editor = TextEditor::new(&text, 800.0, 600.0, 16.0);
+    editor.set_wrap_policy(Box::new(policy));
+
+    let starts = starts_going_down(&mut editor);
+    assert!(starts.len() > 1000);
+
+    let last = starts.len() - 1;
+    for lines in [1, 2, 37, last - 1, last] {
+        while editor.text_start_idx() != starts[last] {
+            editor.scroll(ScrollAmount::Down { lines: last }, &mut Rasterizer);
+        }
+        editor.scroll(ScrollAmount::Up { lines }, &mut Rasterizer);
+        assert_eq!(
+            editor.text_start_idx(),
+            starts[last - lines],
+            "{lines} lines up"
+        );
+    }
+}
+
+#[test]
+fn long_tokens_scroll_up_to_the_same_lines_with_char_wrap() {
+    scrolling_up_matches_scrolling_down(CharWrap);
+}
+
+#[test]
+fn long_tokens_scroll_up_to_the_same_lines_with_word_wrap() {
+    scrolling_up_matches_scrolling_down(WordWrap);
+}
+
+#[test]
+fn tabs_advance_to_the_next_tab_stop() {
+    let mut editor = TextEditor::new("a\tb\n\tc", 800.0, 600.0, 16.0);
+    let widths = |editor: &TextEditor| -> Vec<f32> {
+        editor
+            .visible_lines(&mut Rasterizer)
+            .map(|line| line.width)
+            .collect()
+    };
+
+    // Stops are every four spaces, 40 pixels.
+    assert_eq!(widths(&editor), [50.0, 50.0]);
+    editor.set_tab_width(8);
+    assert_eq!(widths(&editor), [90.0, 90.0]);
+
+    // Clicking the right half of the tab puts the cursor after it.
+    assert_eq!(editor.position_at_point(30.0, 1.0, &mut Rasterizer), 1);
+    assert_eq!(editor.position_at_point(60.0, 1.0, &mut Rasterizer), 2);
+}
+
+#[test]
+fn lines_with_tabs_scroll_up_to_the_same_lines() {
+    for policy in [
+        Box::new(CharWrap) as Box<dyn WrapPolicy>,
+        Box::new(WordWrap),
+    ] {
+        let text = "\tsome words\tand tabs".repeat(500);
+        let mut editor = TextEditor::new(&text, 800.0, 600.0, 16.0);
+        editor.set_wrap_policy(policy);
+
+        let starts = starts_going_down(&mut editor);
+        assert!(starts.len() > 10);
+        for expected in starts.iter().rev().skip(1) {
+            editor.scroll(ScrollAmount::Up { lines: 1 }, &mut Rasterizer);
+            assert_eq!(editor.text_start_idx(), *expected);
+        }
+    }
+}
+
+#[test]
+fn scrolling_up_after_an_edit_wraps_the_edited_line() {
+    let text = format!("top\n{}", "xi ".repeat(2000));
+    let mut editor = TextEditor::new(&text, 800.0, 600.0, 16.0);
+    editor.set_wrap_policy(Box::new(WordWrap));
+
+    editor.scroll(ScrollAmount::ToEnd, &mut Rasterizer);
+    editor.scroll(ScrollAmount::Up { lines: 3 }, &mut Rasterizer);
+
+    // Widen the start of the long line, so every line after it wraps elsewhere.
+    editor.move_cursor_to(4);
+    editor.insert_text("wide ");
+    let mut fresh = TextEditor::new(
+        &editor.slice(0..editor.len()).to_string(),
+        800.0,
+        600.0,
+        16.0,
+    );
+    fresh.set_wrap_policy(Box::new(WordWrap));
+    let starts = starts_going_down(&mut fresh);
+
+    while editor.text_start_idx() != *starts.last().unwrap() {
+        editor.scroll(ScrollAmount::Down { lines: 1000 }, &mut Rasterizer);
+    }
+    for expected in starts.iter().rev().skip(1) {
+        editor.scroll(ScrollAmount::Up { lines: 1 }, &mut Rasterizer);
+        assert_eq!(editor.text_start_idx(), *expected);
+    }
+}
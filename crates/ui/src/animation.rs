@@ -0,0 +1,129 @@
+use std::time::{Duration, Instant};
+
+/// Maps linear progress in [0, 1] to eased progress in [0, 1].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Easing {
+    Linear,
+    EaseOutCubic,
+    EaseInOutQuad,
+}
+
+impl Easing {
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseOutCubic => 1.0 - (1.0 - t).powi(3),
+            Easing::EaseInOutQuad => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(2) / 2.0
+                }
+            }
+        }
+    }
+}
+
+/// The time source every animation is driven by. It is advanced once per frame
+/// from the frame timestamp and stops advancing while paused, so animations
+/// freeze in place when the window loses focus instead of skipping ahead.
+#[derive(Debug)]
+pub struct AnimationClock {
+    /// Time accumulated while the clock was running.
+    elapsed: Duration,
+    /// Timestamp of the previous tick.
+    last_tick: Instant,
+    paused: bool,
+}
+
+impl Default for AnimationClock {
+    fn default() -> Self {
+        Self {
+            elapsed: Duration::ZERO,
+            last_tick: Instant::now(),
+            paused: false,
+        }
+    }
+}
+
+impl AnimationClock {
+    /// Advance the clock to the given frame timestamp.
+    pub fn tick(&mut self, frame_time: Instant) {
+        if !self.paused {
+            self.elapsed += frame_time.saturating_duration_since(self.last_tick);
+        }
+        self.last_tick = frame_time;
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// The current animation time.
+    pub fn now(&self) -> Duration {
+        self.elapsed
+    }
+}
+
+/// A single f32 property that eases from one value to another over time.
+#[derive(Debug, Clone, Copy)]
+pub struct Tween {
+    from: f32,
+    to: f32,
+    start: Duration,
+    duration: Duration,
+    easing: Easing,
+}
+
+impl Tween {
+    /// Creates a tween that is already settled at 'value'.
+    pub fn new(value: f32) -> Self {
+        Self {
+            from: value,
+            to: value,
+            start: Duration::ZERO,
+            duration: Duration::ZERO,
+            easing: Easing::Linear,
+        }
+    }
+
+    /// Start animating towards 'to', beginning from wherever the tween currently is.
+    pub fn retarget(&mut self, to: f32, now: Duration, duration: Duration, easing: Easing) {
+        if to == self.to {
+            return;
+        }
+
+        self.from = self.value(now);
+        self.to = to;
+        self.start = now;
+        self.duration = duration;
+        self.easing = easing;
+    }
+
+    /// Jump straight to 'value' without animating.
+    pub fn snap(&mut self, value: f32) {
+        *self = Self::new(value);
+    }
+
+    pub fn target(&self) -> f32 {
+        self.to
+    }
+
+    pub fn value(&self, now: Duration) -> f32 {
+        if self.is_finished(now) {
+            return self.to;
+        }
+
+        let t = (now - self.start).as_secs_f32() / self.duration.as_secs_f32();
+        self.from + (self.to - self.from) * self.easing.apply(t)
+    }
+
+    pub fn is_finished(&self, now: Duration) -> bool {
+        now.saturating_sub(self.start) >= self.duration
+    }
+}
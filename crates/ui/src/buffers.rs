@@ -0,0 +1,138 @@
+use crate::layout::RemoteCaret;
+use std::path::Path;
+use text_editor::TextEditor;
+
+/// The files open in a text node. Only the active one is shown and edited.
+pub struct Buffers {
+    buffers: Vec<Buffer>,
+    active: usize,
+}
+
+/// An open file, and other people's cursors in it.
+struct Buffer {
+    editor: TextEditor,
+    remote_carets: Vec<RemoteCaret>,
+}
+
+impl Buffer {
+    fn new(editor: TextEditor) -> Self {
+        Self {
+            editor,
+            remote_carets: vec![],
+        }
+    }
+}
+
+impl Buffers {
+    pub fn new(editor: TextEditor) -> Self {
+        Self {
+            buffers: vec![Buffer::new(editor)],
+            active: 0,
+        }
+    }
+
+    pub fn active(&self) -> &TextEditor {
+        &self.buffers[self.active].editor
+    }
+
+    pub fn active_mut(&mut self) -> &mut TextEditor {
+        &mut self.buffers[self.active].editor
+    }
+
+    /// Other people's cursors in the active buffer.
+    pub fn active_remote_carets(&self) -> &[RemoteCaret] {
+        &self.buffers[self.active].remote_carets
+    }
+
+    /// Show 'carets' in the buffer at 'idx', replacing the ones it had. Returns
+    /// false if there is no such buffer.
+    pub fn set_remote_carets(&mut self, idx: usize, carets: Vec<RemoteCaret>) -> bool {
+        match self.buffers.get_mut(idx) {
+            Some(buffer) => {
+                buffer.remote_carets = carets;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn active_index(&self) -> usize {
+        self.active
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffers.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &TextEditor> {
+        self.buffers.iter().map(|buffer| &buffer.editor)
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut TextEditor> {
+        self.buffers.iter_mut().map(|buffer| &mut buffer.editor)
+    }
+
+    /// Add a buffer right after the active one and switch to it.
+    pub fn open(&mut self, editor: TextEditor) {
+        self.buffers.insert(self.active + 1, Buffer::new(editor));
+        self.switch_to(self.active + 1);
+    }
+
+    /// Add a buffer right after the active one, staying on the active one.
+    pub fn open_behind(&mut self, editor: TextEditor) {
+        self.buffers.insert(self.active + 1, Buffer::new(editor));
+    }
+
+    /// Switch to the buffer showing 'path'. Returns false if it isn't open.
+    pub fn switch_to_path(&mut self, path: &Path) -> bool {
+        match self.position_of_path(path) {
+            Some(idx) => {
+                self.switch_to(idx);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The index of the buffer showing 'path', if it is open.
+    pub fn position_of_path(&self, path: &Path) -> Option<usize> {
+        // The same file can be reached through different relative paths.
+        let path = path.canonicalize().ok()?;
+        self.iter()
+            .position(|e| e.path().and_then(|p| p.canonicalize().ok()).as_ref() == Some(&path))
+    }
+
+    pub fn get_mut(&mut self, idx: usize) -> Option<&mut TextEditor> {
+        self.buffers.get_mut(idx).map(|buffer| &mut buffer.editor)
+    }
+
+    /// Switch to the next buffer, wrapping around at the end.
+    pub fn next(&mut self) {
+        self.switch_to((self.active + 1) % self.buffers.len());
+    }
+
+    /// Switch to the previous buffer, wrapping around at the start.
+    pub fn prev(&mut self) {
+        self.switch_to((self.active + self.buffers.len() - 1) % self.buffers.len());
+    }
+
+    /// Close the active buffer and switch to the one before it. The last buffer
+    /// is replaced by 'empty' instead, so there is always one to show.
+    pub fn close_active(&mut self, empty: TextEditor) {
+        if self.buffers.len() == 1 {
+            self.buffers[0] = Buffer::new(empty);
+        } else {
+            self.buffers.remove(self.active);
+            self.active = self.active.saturating_sub(1);
+        }
+    }
+
+    /// Switch to the buffer at 'idx'.
+    pub fn switch_to(&mut self, idx: usize) {
+        self.active = idx;
+    }
+}
@@ -0,0 +1,78 @@
+use copypasta::{ClipboardContext, ClipboardProvider};
+
+/// Where copied text goes. Uses the system clipboard when there is one, opening
+/// it the first time it's needed. Without one, e.g. when running headless, text
+/// can still be copied and pasted within the editor.
+pub struct Clipboard {
+    system: System,
+
+    /// The last text copied. Pasted when the system clipboard can't be read.
+    contents: String,
+}
+
+enum System {
+    Untried,
+    Ready(Box<ClipboardContext>),
+    Unavailable,
+}
+
+impl Default for Clipboard {
+    fn default() -> Self {
+        Self {
+            system: System::Untried,
+            contents: String::new(),
+        }
+    }
+}
+
+impl Clipboard {
+    /// A clipboard that never uses the system one.
+    pub fn internal() -> Self {
+        Self {
+            system: System::Unavailable,
+            contents: String::new(),
+        }
+    }
+
+    /// Has the system clipboard failed to open? Copies only stay within the editor then.
+    pub fn is_internal(&self) -> bool {
+        matches!(self.system, System::Unavailable)
+    }
+
+    fn system(&mut self) -> Option<&mut ClipboardContext> {
+        if let System::Untried = self.system {
+            self.system = match ClipboardContext::new() {
+                Ok(context) => System::Ready(Box::new(context)),
+                Err(e) => {
+                    log::warn!("System clipboard unavailable, copying within the editor only: {e}");
+                    System::Unavailable
+                }
+            };
+        }
+
+        match &mut self.system {
+            System::Ready(context) => Some(context),
+            _ => None,
+        }
+    }
+
+    pub fn set_contents(&mut self, text: String) {
+        if let Some(system) = self.system() {
+            if let Err(e) = system.set_contents(text.clone()) {
+                log::error!("Failed to copy to the system clipboard: {e}");
+            }
+        }
+        self.contents = text;
+    }
+
+    pub fn get_contents(&mut self) -> String {
+        match self.system().map(|system| system.get_contents()) {
+            Some(Ok(text)) => text,
+            Some(Err(e)) => {
+                log::error!("Failed to paste from the system clipboard: {e}");
+                self.contents.clone()
+            }
+            None => self.contents.clone(),
+        }
+    }
+}
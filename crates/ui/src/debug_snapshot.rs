@@ -0,0 +1,50 @@
+use std::{
+    io::{self, Cursor, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use zip::{write::SimpleFileOptions, CompressionMethod, DateTime, ZipWriter};
+
+/// Files to attach to a bug report: what the editor looked like and what state it
+/// was in. Written out together as a zip archive.
+#[derive(Debug, Default)]
+pub struct DebugSnapshot {
+    files: Vec<(String, Vec<u8>)>,
+}
+
+impl DebugSnapshot {
+    pub fn add(&mut self, name: &str, contents: impl Into<Vec<u8>>) {
+        self.files.push((name.to_string(), contents.into()));
+    }
+
+    /// Where a snapshot taken now is saved: a file named after the time in the
+    /// current directory.
+    pub fn default_path() -> PathBuf {
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let name = format!("editor-snapshot-{secs}.zip");
+        std::env::current_dir()
+            .map(|dir| dir.join(&name))
+            .unwrap_or_else(|_| PathBuf::from(name))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        std::fs::write(path, self.to_zip()?)
+    }
+
+    /// The files as a zip archive, each one deflated.
+    pub fn to_zip(&self) -> io::Result<Vec<u8>> {
+        // Files get the earliest time zip has, 1980-01-01. Snapshots carry their
+        // time in their name instead.
+        let options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .last_modified_time(DateTime::default());
+        let mut zip = ZipWriter::new(Cursor::new(vec![]));
+        for (name, contents) in &self.files {
+            zip.start_file(name.as_str(), options)?;
+            zip.write_all(contents)?;
+        }
+        Ok(zip.finish()?.into_inner())
+    }
+}
@@ -0,0 +1,55 @@
+use std::{
+    future::Future,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+/// A native file dialog whose answer is waited for on another thread, so the
+/// window keeps drawing and taking input while it is open. Poll it for the answer.
+pub struct FileDialog {
+    answer: Receiver<Option<PathBuf>>,
+}
+
+impl FileDialog {
+    /// Ask for a file to open, starting in 'dir'.
+    pub fn open(dir: Option<&Path>) -> Self {
+        let mut dialog = rfd::AsyncFileDialog::new();
+        if let Some(dir) = dir {
+            dialog = dialog.set_directory(dir);
+        }
+        // The dialog itself is made on the calling thread, some platforms need
+        // that to be the event loop's. Only the waiting happens elsewhere.
+        Self::wait_for(dialog.pick_file())
+    }
+
+    /// Ask where to save a file, starting in 'dir' with 'file_name' filled in.
+    pub fn save(dir: Option<&Path>, file_name: &str) -> Self {
+        let mut dialog = rfd::AsyncFileDialog::new().set_file_name(file_name);
+        if let Some(dir) = dir {
+            dialog = dialog.set_directory(dir);
+        }
+        Self::wait_for(dialog.save_file())
+    }
+
+    fn wait_for(answer: impl Future<Output = Option<rfd::FileHandle>> + Send + 'static) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let path = pollster::block_on(answer).map(|file| file.path().to_path_buf());
+            // Nobody wants the answer anymore if the dialog was dropped.
+            let _ = sender.send(path);
+        });
+
+        Self { answer: receiver }
+    }
+
+    /// None while the dialog is open. Then the path picked, or Some(None) if it
+    /// was cancelled.
+    pub fn poll(&self) -> Option<Option<PathBuf>> {
+        match self.answer.try_recv() {
+            Ok(path) => Some(path),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(None),
+        }
+    }
+}
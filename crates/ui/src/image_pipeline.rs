@@ -1,91 +1,264 @@
 use crate::{
     camera_uniform::CameraUniform,
     layout::{BoundingBox, Color, Drawables},
+    pipeline::{Instance, InstancedPipeline, Mesh, VertexLayout},
     quad_pipeline::QuadInstance,
-    texture_atlas::{AllocationInfo, TextureAtlas},
+    resources::Icon,
+    texture_atlas::{AllocationInfo, FontGlyph, GlyphAtlas, TextureAtlas},
 };
-use std::{borrow::Cow, cell::RefCell, rc::Rc};
-use text_editor::TextEditor;
-use wgpu::util::DeviceExt;
+use std::ops::Range;
+use text_editor::{layout::tab_advance, LineMetrics, TextEditor};
+
+/// Byte ranges of the text that get a colored background, e.g. the selection.
+pub struct Highlight<'a> {
+    /// Sorted by start, and not overlapping each other.
+    pub ranges: &'a [Range<usize>],
+    pub color: Color,
+}
+
+impl Highlight<'_> {
+    fn contains(&self, byte_idx: usize) -> bool {
+        let i = self.ranges.partition_point(|range| range.end <= byte_idx);
+        self.ranges
+            .get(i)
+            .is_some_and(|range| range.contains(&byte_idx))
+    }
+}
+
+/// Glyphs in a byte range drawn in their own color, e.g. a syntax highlighted keyword.
+pub struct ColoredRange {
+    pub range: Range<usize>,
+    pub color: Color,
+}
 
+/// Where a line of text sits: the left end of its baseline.
+pub type Baseline = (f32, f32);
+
+/// Lays out the visible text of 'editor' inside 'area', at the editor's font size.
+/// Returns the glyph and highlight drawables, and the baseline a cursor at each of
+/// 'marks' should be drawn at, or None for those not on screen. A mark where a
+/// wrapped line breaks goes to the start of the next line. Later highlights are
+/// drawn on top of earlier ones. Glyphs in 'colors', which must be sorted and not
+/// overlap, are drawn in their color instead of 'font_color'.
 pub fn layout_text(
     area: BoundingBox,
-    atlas: &mut TextureAtlas,
-    font_size: f32,
+    atlas: &mut impl GlyphAtlas,
     font_color: &Color,
-    draw_cursor: bool,
+    highlights: &[Highlight],
+    colors: &[ColoredRange],
     editor: &TextEditor,
-) -> Vec<Drawables> {
+    marks: &[usize],
+) -> (Vec<Drawables>, Vec<Option<Baseline>>) {
     let mut drawables = vec![];
+    let mut colors = colors.iter().peekable();
+    let font_size = editor.font_size();
 
-    let line_height = font_size * 1.2;
+    // The editor spaces lines the same way when it scrolls or maps clicks to text.
+    let line_metrics = editor.line_metrics();
+    let line_height = line_metrics.line_height();
     let mut baseline = area.top_left();
-    baseline.1 += line_height;
+    baseline.1 += line_metrics.baseline();
+
+    let tab_stop =
+        atlas.glyph(' ', font_size).unwrap().metrics.advance.0 * editor.tab_width() as f32;
+
+    // Indices into 'marks' in byte order, to find the marks at a glyph quickly.
+    let mut in_order: Vec<usize> = (0..marks.len()).collect();
+    in_order.sort_by_key(|&i| marks[i]);
+    let mut baselines = vec![None; marks.len()];
+    let mut place = |byte_idx: usize, baseline: Baseline, at_glyph: bool| {
+        let first = in_order.partition_point(|&i| marks[i] < byte_idx);
+        for &i in in_order[first..]
+            .iter()
+            .take_while(|&&i| marks[i] == byte_idx)
+        {
+            if at_glyph || baselines[i].is_none() {
+                baselines[i] = Some(baseline);
+            }
+        }
+    };
 
-    let mut drew_cursor = false;
-    let mut curr_byte_index = editor.text_start_idx();
-    let layout = editor.layout_lines(atlas);
+    let mut lines = editor.visible_lines(atlas);
+    while let Some(line) = lines.next() {
+        // Stop once we leave our box
+        if baseline.1 > area.max.1 {
+            break;
+        }
 
-    for line in layout {
-        for c in line.chars() {
-            let glyph = atlas.map_get_or_insert_glyph(c, font_size).unwrap();
-            let metrics = glyph.metrics;
+        let atlas = lines.glyph_rasterizer();
 
-            // Move to next line
-            if c == '\n' {
-                baseline.1 += line_height;
-                baseline.0 = area.min.0;
-                continue;
+        let mut curr_byte_index = line.range.start;
+        for c in editor.slice(line.range.clone()).chars() {
+            // The rest of this line is past the right edge. Only happens when wrapping is off.
+            if baseline.0 >= area.max.0 {
+                break;
             }
 
-            // Return early if we leave our box
-            if !area.inside(baseline) {
-                return drawables;
+            let glyph = atlas.glyph(c, font_size).unwrap();
+            let metrics = glyph.metrics;
+            let advance = match c {
+                '\t' => tab_advance(baseline.0 - area.min.0, tab_stop),
+                '\r' => 0.0,
+                _ => metrics.advance.0,
+            };
+
+            place(curr_byte_index, baseline, true);
+
+            for highlight in highlights {
+                if highlight.contains(curr_byte_index) {
+                    drawables.push(Drawables::Rect(QuadInstance::filled(
+                        [baseline.0, baseline.1 - line_metrics.baseline()],
+                        [advance, line_height],
+                        &highlight.color,
+                    )));
+                }
             }
 
-            // TODO: blinking cursor gets out of sync with where we are typing
-            // :) :(
-            if curr_byte_index == editor.cursor_position() && draw_cursor {
-                drew_cursor = true;
-                let cursor_height = (font_size * 0.85).floor();
-                let cursor_width = (font_size / 10.0).floor();
-                drawables.push(Drawables::Rect(QuadInstance {
-                    position: [baseline.0, baseline.1 - cursor_height],
-                    size: [cursor_width, cursor_height],
-                    color: [1.0, 1.0, 1.0, 1.0],
-                }));
-            }
+            // Glyphs come in byte order, so colored ranges behind us are done with.
+            while colors.next_if(|c| c.range.end <= curr_byte_index).is_some() {}
+            let color = colors
+                .peek()
+                .filter(|c| c.range.start <= curr_byte_index)
+                .map_or(font_color, |c| &c.color);
 
-            drawables.push(Drawables::TexturedRect(ImageInstance::add_instance(
-                atlas,
-                glyph.allocation_info,
-                [baseline.0 + metrics.pos.0, baseline.1 - metrics.pos.1],
-                [metrics.size.0, metrics.size.1],
-                font_color.to_f32_arr(),
-            )));
+            // Tabs and the carriage return of a CRLF line ending leave a gap, if anything.
+            if c != '\t' && c != '\r' {
+                drawables.push(layout_glyph(atlas, &glyph, baseline, color));
+            }
 
-            baseline.0 += metrics.advance.0;
+            baseline.0 += advance;
             baseline.1 += metrics.advance.1;
             curr_byte_index += c.len_utf8();
         }
 
+        // A mark can also sit after the last glyph of a line
+        place(line.range.end, baseline, false);
+
         // Move to next line
         baseline.1 += line_height;
         baseline.0 = area.min.0;
-        continue;
     }
 
-    if !drew_cursor && draw_cursor {
-        let cursor_height = (font_size * 0.85).floor();
-        let cursor_width = (font_size / 8.5).floor();
-        drawables.push(Drawables::Rect(QuadInstance {
-            position: [baseline.0, baseline.1 - cursor_height],
-            size: [cursor_width, cursor_height],
-            color: [1.0, 1.0, 1.0, 1.0],
-        }));
+    (drawables, baselines)
+}
+
+/// Make room for 'width' pixels of text at 'at', in drawables and marks laid out by
+/// layout_text: everything after it on its line moves right. What moves past
+/// 'max_x' is dropped instead of wrapping to the next line.
+pub fn open_gap(
+    drawables: &mut Vec<Drawables>,
+    marks: &mut [Option<Baseline>],
+    at: Baseline,
+    width: f32,
+    line_metrics: &LineMetrics,
+    max_x: f32,
+) {
+    let top = at.1 - line_metrics.baseline();
+    let on_line = |y: f32| y >= top && y < top + line_metrics.line_height();
+
+    // Glyphs can reach a little past their advance, so their middle tells best
+    // which side of the gap they are on.
+    drawables.retain_mut(|drawable| {
+        let (position, size) = match drawable {
+            Drawables::Rect(quad) => (&mut quad.position, quad.size),
+            Drawables::TexturedRect(image) => (&mut image.position, image.size),
+        };
+        if on_line(position[1] + size[1] / 2.0) && position[0] + size[0] / 2.0 >= at.0 {
+            position[0] += width;
+        }
+        position[0] < max_x
+    });
+    for (x, y) in marks.iter_mut().flatten() {
+        if *y == at.1 && *x >= at.0 {
+            *x += width;
+        }
+    }
+}
+
+/// Lays out a single line of 'text' with its baseline starting at 'baseline'. Stops
+/// before any glyph that would cross 'max_x'. Returns the drawables and the text width.
+pub fn layout_label(
+    baseline: (f32, f32),
+    max_x: f32,
+    atlas: &mut impl GlyphAtlas,
+    font_size: f32,
+    font_color: &Color,
+    text: &str,
+) -> (Vec<Drawables>, f32) {
+    let (glyphs, width) = label_glyphs(atlas, font_size, text, max_x - baseline.0);
+    let drawables = glyphs
+        .iter()
+        .map(|(_, x, glyph)| layout_glyph(atlas, glyph, (baseline.0 + x, baseline.1), font_color))
+        .collect();
+
+    (drawables, width)
+}
+
+/// How wide 'text' is when laid out as a label, without laying it out.
+pub fn label_width(atlas: &mut impl GlyphAtlas, font_size: f32, text: &str) -> f32 {
+    label_glyphs(atlas, font_size, text, f32::INFINITY).1
+}
+
+/// The glyphs of 'text' on a single line, each with the byte index of its
+/// character and how far from the start of the line it is, and the width of the
+/// line. Stops before any glyph that would end past 'max_width'. Characters no font
+/// has are left out.
+pub fn label_glyphs(
+    atlas: &mut impl GlyphAtlas,
+    font_size: f32,
+    text: &str,
+    max_width: f32,
+) -> (Vec<(usize, f32, FontGlyph)>, f32) {
+    let mut glyphs = vec![];
+    let mut x = 0.0;
+    for (i, c) in text.char_indices() {
+        let Some(glyph) = atlas.glyph(c, font_size) else {
+            continue;
+        };
+        if x + glyph.metrics.advance.0 > max_width {
+            break;
+        }
+        glyphs.push((i, x, glyph));
+        x += glyph.metrics.advance.0;
     }
 
-    drawables
+    (glyphs, x)
+}
+
+/// 'glyph' with its origin on 'baseline'.
+pub fn layout_glyph(
+    atlas: &impl GlyphAtlas,
+    glyph: &FontGlyph,
+    baseline: (f32, f32),
+    color: &Color,
+) -> Drawables {
+    let metrics = glyph.metrics;
+    Drawables::TexturedRect(ImageInstance::add_instance(
+        atlas,
+        glyph.allocation_info,
+        [baseline.0 + metrics.pos.0, baseline.1 - metrics.pos.1],
+        [metrics.size.0, metrics.size.1],
+        color.to_f32_arr(),
+    ))
+}
+
+/// Lay out 'icon' as a 'size' pixel square with its top left corner at 'pos'.
+pub fn layout_icon(
+    pos: (f32, f32),
+    size: f32,
+    atlas: &mut impl GlyphAtlas,
+    color: &Color,
+    icon: Icon,
+) -> Option<Drawables> {
+    let size = size.round();
+    let allocation_info = atlas.icon(icon, size as u32)?;
+    Some(Drawables::TexturedRect(ImageInstance::add_instance(
+        atlas,
+        allocation_info,
+        [pos.0.round(), pos.1.round()],
+        [size, size],
+        color.to_f32_arr(),
+    )))
 }
 
 /// The projection matrix used in the shaders.
@@ -132,18 +305,8 @@ impl ImageInstance {
         9 => Float32x4,
     ];
 
-    const MAX: usize = 65536;
-
-    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
-        wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<ImageInstance>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Instance,
-            attributes: &Self::ATTRIBS,
-        }
-    }
-
     pub fn add_instance(
-        atlas: &TextureAtlas,
+        atlas: &impl GlyphAtlas,
         allocation_info: AllocationInfo,
         position: [f32; 2],
         size: [f32; 2],
@@ -167,6 +330,24 @@ impl ImageInstance {
     }
 }
 
+impl VertexLayout for ImageInstance {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<ImageInstance>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Instance,
+            attributes: &Self::ATTRIBS,
+        }
+    }
+}
+
+impl Instance for ImageInstance {
+    type Mesh = ImageVertex;
+
+    const SHADER: &'static str = include_str!("image.wgsl");
+    const BLEND: Option<wgpu::BlendState> = Some(wgpu::BlendState::ALPHA_BLENDING);
+    const INITIAL_CAPACITY: usize = 65536;
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct ImageVertex {
@@ -175,22 +356,26 @@ pub struct ImageVertex {
 }
 
 impl ImageVertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![
+        0 => Float32x2,
+        1 => Float32x2,
+    ];
+}
+
+impl Mesh for ImageVertex {
     #[rustfmt::skip]
-    pub const VERTICES: &'static [ImageVertex] = &[
+    const VERTICES: &'static [ImageVertex] = &[
         ImageVertex { position: [0.0, 1.0], tex_coords: [0.0, 1.0] },
         ImageVertex { position: [0.0, 0.0], tex_coords: [0.0, 0.0] },
         ImageVertex { position: [1.0, 1.0], tex_coords: [1.0, 1.0] },
         ImageVertex { position: [1.0, 0.0], tex_coords: [1.0, 0.0] },
     ];
 
-    pub const INDICES: &'static [u16] = &[0, 1, 2, 2, 3, 1];
-
-    const ATTRIBS: [wgpu::VertexAttribute; 2] = wgpu::vertex_attr_array![
-        0 => Float32x2,
-        1 => Float32x2,
-    ];
+    const INDICES: &'static [u16] = &[0, 1, 2, 2, 3, 1];
+}
 
-    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+impl VertexLayout for ImageVertex {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
         wgpu::VertexBufferLayout {
             array_stride: std::mem::size_of::<ImageVertex>() as wgpu::BufferAddress,
             step_mode: wgpu::VertexStepMode::Vertex,
@@ -199,21 +384,20 @@ impl ImageVertex {
     }
 }
 
+/// Draws glyphs and icons from the texture atlas.
 pub struct ImagePipeline {
-    pipeline: wgpu::RenderPipeline,
+    instanced: InstancedPipeline<ImageInstance>,
 
+    atlas_bind_group_layout: wgpu::BindGroupLayout,
     atlas_bind_group: wgpu::BindGroup,
-
-    vertex_buffer: wgpu::Buffer,
-    index_buffer: wgpu::Buffer,
-    instance_buffer: wgpu::Buffer,
-    instances: Vec<ImageInstance>,
+    /// The atlas generation 'atlas_bind_group' was made for.
+    atlas_generation: u64,
 }
 
 impl ImagePipeline {
     pub fn new(
         device: &wgpu::Device,
-        camera_uniform: Rc<RefCell<CameraUniform>>,
+        camera_uniform: &CameraUniform,
         atlas: &TextureAtlas,
     ) -> Self {
         let atlas_bind_group_layout =
@@ -239,9 +423,43 @@ impl ImagePipeline {
                 label: Some("atlas texture_bind_group_layout"),
             });
 
+        let atlas_bind_group = Self::atlas_bind_group(device, &atlas_bind_group_layout, atlas);
+        let instanced = InstancedPipeline::new(device, camera_uniform, &[&atlas_bind_group_layout]);
+
+        Self {
+            instanced,
+
+            atlas_bind_group_layout,
+            atlas_bind_group,
+            atlas_generation: atlas.generation(),
+        }
+    }
+
+    /// Rebuild the pipeline with the shader in 'source'. Keeps the old one if it
+    /// doesn't compile.
+    #[cfg(debug_assertions)]
+    pub fn reload_shader(
+        &mut self,
+        device: &wgpu::Device,
+        camera_uniform: &CameraUniform,
+        source: &str,
+    ) -> Result<(), String> {
+        self.instanced.reload_shader(
+            device,
+            camera_uniform,
+            &[&self.atlas_bind_group_layout],
+            source,
+        )
+    }
+
+    fn atlas_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        atlas: &TextureAtlas,
+    ) -> wgpu::BindGroup {
         let atlas_texture = atlas.texture();
-        let atlas_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &atlas_bind_group_layout,
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
             entries: &[
                 wgpu::BindGroupEntry {
                     binding: 0,
@@ -253,104 +471,31 @@ impl ImagePipeline {
                 },
             ],
             label: Some("atlas texture_bind_group"),
-        });
-
-        let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Instance Buffer"),
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            size: (std::mem::size_of::<ImageInstance>() * ImageInstance::MAX) as u64,
-            mapped_at_creation: false,
-        });
-
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(ImageVertex::VERTICES),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
-
-        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Index Buffer"),
-            contents: bytemuck::cast_slice(ImageVertex::INDICES),
-            usage: wgpu::BufferUsages::INDEX,
-        });
-
-        let instances = vec![];
-
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: None,
-            bind_group_layouts: &[
-                camera_uniform.borrow().bind_group_layout(),
-                &atlas_bind_group_layout,
-            ],
-            push_constant_ranges: &[],
-        });
-
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: None,
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("image.wgsl"))),
-        });
-
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: None,
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[ImageVertex::desc(), ImageInstance::desc()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                    format: wgpu::TextureFormat::Bgra8UnormSrgb,
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState::default(),
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState::default(),
-            multiview: None,
-        });
-
-        Self {
-            pipeline,
-
-            atlas_bind_group,
-
-            vertex_buffer,
-            instance_buffer,
-            index_buffer,
+        })
+    }
 
-            instances,
+    /// Bind the atlas texture again if it was replaced since the last call, e.g.
+    /// because the atlas grew.
+    pub fn update_atlas(&mut self, device: &wgpu::Device, atlas: &TextureAtlas) {
+        if atlas.generation() == self.atlas_generation {
+            return;
         }
+
+        self.atlas_bind_group =
+            Self::atlas_bind_group(device, &self.atlas_bind_group_layout, atlas);
+        self.atlas_generation = atlas.generation();
     }
 
     pub fn instances(&mut self) -> &mut Vec<ImageInstance> {
-        &mut self.instances
+        self.instanced.instances()
     }
 
-    pub fn update(&mut self, queue: &wgpu::Queue) {
-        queue.write_buffer(
-            &self.instance_buffer,
-            0,
-            bytemuck::cast_slice(&self.instances),
-        );
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.instanced.update(device, queue);
     }
 
     pub fn draw<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>, camera_uniform: &'a CameraUniform) {
-        rpass.set_pipeline(&self.pipeline);
-
-        rpass.set_bind_group(camera_uniform.index(), camera_uniform.bind_group(), &[]);
-        rpass.set_bind_group(1, &self.atlas_bind_group, &[]);
-
-        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        rpass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-        rpass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-        rpass.draw_indexed(
-            0..ImageVertex::INDICES.len() as u32,
-            0,
-            0..self.instances.len() as u32,
-        );
+        self.instanced
+            .draw(rpass, camera_uniform, &[&self.atlas_bind_group]);
     }
 }
@@ -0,0 +1,83 @@
+use serde::{Deserialize, Serialize};
+use winit::{
+    event::{ElementState, KeyEvent},
+    keyboard::{Key, ModifiersState, NamedKey},
+};
+
+/// A key being pressed or released, with only what the scene looks at. Built from
+/// winit's KeyEvent, which can't be created outside of winit, so that input can
+/// also be scripted, e.g. to drive a scene without a window.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyInput {
+    pub logical_key: Key,
+    pub state: ElementState,
+}
+
+impl KeyInput {
+    pub fn pressed(key: Key) -> Self {
+        Self {
+            logical_key: key,
+            state: ElementState::Pressed,
+        }
+    }
+
+    pub fn released(key: Key) -> Self {
+        Self {
+            logical_key: key,
+            state: ElementState::Released,
+        }
+    }
+
+    /// Is the key one of the modifiers Modifiers follows?
+    pub fn is_modifier(&self) -> bool {
+        matches!(
+            self.logical_key,
+            Key::Named(NamedKey::Control | NamedKey::Shift | NamedKey::Alt | NamedKey::Super)
+        )
+    }
+}
+
+impl From<&KeyEvent> for KeyInput {
+    fn from(event: &KeyEvent) -> Self {
+        Self {
+            logical_key: event.logical_key.clone(),
+            state: event.state,
+        }
+    }
+}
+
+/// The modifier keys held down. Follows winit's ModifiersChanged events, which
+/// also see keys pressed while the window was not focused.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    pub super_key: bool,
+}
+
+impl Modifiers {
+    /// Follow a modifier key in 'event' going down or up, for input that doesn't
+    /// come with ModifiersChanged events, e.g. when it is scripted.
+    pub fn track(&mut self, event: &KeyInput) {
+        let held = event.state == ElementState::Pressed;
+        match event.logical_key {
+            Key::Named(NamedKey::Control) => self.ctrl = held,
+            Key::Named(NamedKey::Shift) => self.shift = held,
+            Key::Named(NamedKey::Alt) => self.alt = held,
+            Key::Named(NamedKey::Super) => self.super_key = held,
+            _ => {}
+        }
+    }
+}
+
+impl From<ModifiersState> for Modifiers {
+    fn from(state: ModifiersState) -> Self {
+        Self {
+            ctrl: state.control_key(),
+            shift: state.shift_key(),
+            alt: state.alt_key(),
+            super_key: state.super_key(),
+        }
+    }
+}
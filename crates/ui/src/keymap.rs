@@ -0,0 +1,449 @@
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    fmt, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use winit::keyboard::{Key, NamedKey};
+
+use crate::input::Modifiers;
+
+/// Something a key chord can be bound to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Command {
+    MoveLeft,
+    MoveRight,
+    LineStart,
+    LineEnd,
+    WordForward,
+    WordBackward,
+    ScrollToStart,
+    ScrollToEnd,
+    PageUp,
+    PageDown,
+    Newline,
+    Indent,
+    Backspace,
+    Delete,
+    KillLine,
+    Copy,
+    Cut,
+    Paste,
+    Undo,
+    Redo,
+    Save,
+    OpenFile,
+    ReopenWithEncoding,
+    CloseBuffer,
+    NextBuffer,
+    PrevBuffer,
+    Find,
+    Replace,
+    NextMatch,
+    PrevMatch,
+    GotoLine,
+    UnicodeEntry,
+    ToggleAutoClose,
+    ToggleInsertSpaces,
+    IndentationToSpaces,
+    IndentationToTabs,
+    NormalizeLineEndings,
+    ConvertLineEndings,
+    FixTrailingNewline,
+    BufferInfo,
+    AddNextOccurrence,
+    AddCursorAbove,
+    AddCursorBelow,
+    MoveLinesUp,
+    MoveLinesDown,
+    Duplicate,
+    IncreaseFontSize,
+    DecreaseFontSize,
+    SplitHorizontal,
+    SplitVertical,
+    ClosePane,
+    FocusNextPane,
+    FocusPrevPane,
+    ToggleZenMode,
+    FindInFiles,
+    Problems,
+    Help,
+    DebugSnapshot,
+
+    /// Removes a default binding.
+    Unbound,
+}
+
+impl Command {
+    /// The group the command is listed under in the help.
+    pub fn category(self) -> &'static str {
+        match self {
+            Command::MoveLeft
+            | Command::MoveRight
+            | Command::LineStart
+            | Command::LineEnd
+            | Command::WordForward
+            | Command::WordBackward
+            | Command::ScrollToStart
+            | Command::ScrollToEnd
+            | Command::PageUp
+            | Command::PageDown
+            | Command::GotoLine => "Cursor",
+            Command::Newline
+            | Command::Indent
+            | Command::Backspace
+            | Command::Delete
+            | Command::KillLine
+            | Command::Undo
+            | Command::Redo
+            | Command::UnicodeEntry
+            | Command::ToggleAutoClose
+            | Command::ToggleInsertSpaces
+            | Command::IndentationToSpaces
+            | Command::IndentationToTabs
+            | Command::NormalizeLineEndings
+            | Command::ConvertLineEndings
+            | Command::FixTrailingNewline
+            | Command::MoveLinesUp
+            | Command::MoveLinesDown
+            | Command::Duplicate => "Editing",
+            Command::Copy | Command::Cut | Command::Paste => "Clipboard",
+            Command::AddNextOccurrence | Command::AddCursorAbove | Command::AddCursorBelow => {
+                "Multiple cursors"
+            }
+            Command::Find
+            | Command::Replace
+            | Command::NextMatch
+            | Command::PrevMatch
+            | Command::FindInFiles => "Search",
+            Command::Save
+            | Command::OpenFile
+            | Command::ReopenWithEncoding
+            | Command::CloseBuffer
+            | Command::NextBuffer
+            | Command::PrevBuffer => "Files",
+            Command::SplitHorizontal
+            | Command::SplitVertical
+            | Command::ClosePane
+            | Command::FocusNextPane
+            | Command::FocusPrevPane => "Panes",
+            Command::BufferInfo
+            | Command::IncreaseFontSize
+            | Command::DecreaseFontSize
+            | Command::ToggleZenMode
+            | Command::Problems
+            | Command::Help
+            | Command::DebugSnapshot
+            | Command::Unbound => "View",
+        }
+    }
+
+    /// The command's name in words, e.g. "Move lines up".
+    pub fn name(self) -> String {
+        let mut name = String::new();
+        for c in format!("{self:?}").chars() {
+            if c.is_uppercase() && !name.is_empty() {
+                name.push(' ');
+                name.extend(c.to_lowercase());
+            } else {
+                name.push(c);
+            }
+        }
+        name
+    }
+}
+
+/// A key together with the modifiers held down with it. Written in keymap files
+/// like "ctrl+shift+f", "alt+b" or "pagedown".
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Chord {
+    pub ctrl: bool,
+    pub shift: bool,
+    pub alt: bool,
+    pub super_key: bool,
+    pub key: Key,
+}
+
+impl Chord {
+    pub fn new(modifiers: Modifiers, key: &Key) -> Self {
+        // Shift changes the character a key types, so letters are compared lowercase.
+        let key = match key {
+            Key::Character(c) => Key::Character(c.to_lowercase().into()),
+            key => key.clone(),
+        };
+        Self {
+            ctrl: modifiers.ctrl,
+            shift: modifiers.shift,
+            alt: modifiers.alt,
+            super_key: modifiers.super_key,
+            key,
+        }
+    }
+}
+
+impl FromStr for Chord {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // A trailing '+' is the plus key itself, as in "ctrl++".
+        let (modifiers, key) = match s.strip_suffix("++") {
+            Some(modifiers) => (modifiers, "+"),
+            None if s == "+" => ("", "+"),
+            None => s.rsplit_once('+').unwrap_or(("", s)),
+        };
+
+        let mut held = Modifiers::default();
+        for modifier in modifiers.split('+').filter(|m| !m.is_empty()) {
+            match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => held.ctrl = true,
+                "shift" => held.shift = true,
+                "alt" | "meta" => held.alt = true,
+                "super" | "cmd" | "win" => held.super_key = true,
+                _ => return Err(format!("unknown modifier '{modifier}' in '{s}'")),
+            }
+        }
+
+        let key = match named_key(key) {
+            Some(named) => Key::Named(named),
+            None if key.chars().count() == 1 => Key::Character(key.into()),
+            None => return Err(format!("unknown key '{key}' in '{s}'")),
+        };
+
+        Ok(Chord::new(held, &key))
+    }
+}
+
+impl fmt::Display for Chord {
+    /// Writes the chord the way keymap files do, e.g. "ctrl+shift+f".
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (held, name) in [
+            (self.ctrl, "ctrl+"),
+            (self.shift, "shift+"),
+            (self.alt, "alt+"),
+            (self.super_key, "super+"),
+        ] {
+            if held {
+                f.write_str(name)?;
+            }
+        }
+
+        match &self.key {
+            Key::Named(named) => match NAMED_KEYS.iter().find(|(_, key)| key == named) {
+                Some((name, _)) => f.write_str(name),
+                None => write!(f, "{named:?}"),
+            },
+            Key::Character(c) => f.write_str(c),
+            key => write!(f, "{key:?}"),
+        }
+    }
+}
+
+/// The names of keys that don't type a character.
+const NAMED_KEYS: &[(&str, NamedKey)] = &[
+    ("left", NamedKey::ArrowLeft),
+    ("right", NamedKey::ArrowRight),
+    ("up", NamedKey::ArrowUp),
+    ("down", NamedKey::ArrowDown),
+    ("home", NamedKey::Home),
+    ("end", NamedKey::End),
+    ("pageup", NamedKey::PageUp),
+    ("pagedown", NamedKey::PageDown),
+    ("enter", NamedKey::Enter),
+    ("tab", NamedKey::Tab),
+    ("space", NamedKey::Space),
+    ("backspace", NamedKey::Backspace),
+    ("delete", NamedKey::Delete),
+    ("insert", NamedKey::Insert),
+    ("f1", NamedKey::F1),
+    ("f2", NamedKey::F2),
+    ("f3", NamedKey::F3),
+    ("f4", NamedKey::F4),
+    ("f5", NamedKey::F5),
+    ("f6", NamedKey::F6),
+    ("f7", NamedKey::F7),
+    ("f8", NamedKey::F8),
+    ("f9", NamedKey::F9),
+    ("f10", NamedKey::F10),
+    ("f11", NamedKey::F11),
+    ("f12", NamedKey::F12),
+];
+
+fn named_key(name: &str) -> Option<NamedKey> {
+    NAMED_KEYS
+        .iter()
+        .find(|(key_name, _)| key_name.eq_ignore_ascii_case(name))
+        .map(|(_, key)| *key)
+}
+
+const DEFAULT_BINDINGS: &[(&str, Command)] = &[
+    ("left", Command::MoveLeft),
+    ("right", Command::MoveRight),
+    ("home", Command::ScrollToStart),
+    ("end", Command::ScrollToEnd),
+    ("pageup", Command::PageUp),
+    ("pagedown", Command::PageDown),
+    ("enter", Command::Newline),
+    ("tab", Command::Indent),
+    ("backspace", Command::Backspace),
+    ("delete", Command::Delete),
+    ("ctrl+c", Command::Copy),
+    ("ctrl+x", Command::Cut),
+    ("ctrl+v", Command::Paste),
+    ("ctrl+z", Command::Undo),
+    ("ctrl+y", Command::Redo),
+    ("ctrl+shift+z", Command::Redo),
+    ("ctrl+s", Command::Save),
+    ("ctrl+o", Command::OpenFile),
+    ("ctrl+alt+e", Command::ReopenWithEncoding),
+    ("ctrl+w", Command::CloseBuffer),
+    ("ctrl+tab", Command::NextBuffer),
+    ("ctrl+shift+tab", Command::PrevBuffer),
+    ("ctrl+f", Command::Find),
+    ("ctrl+h", Command::Replace),
+    ("f3", Command::NextMatch),
+    ("shift+f3", Command::PrevMatch),
+    ("ctrl+g", Command::GotoLine),
+    ("ctrl+shift+u", Command::UnicodeEntry),
+    ("ctrl+shift+p", Command::ToggleAutoClose),
+    ("ctrl+shift+t", Command::ToggleInsertSpaces),
+    ("ctrl+alt+s", Command::IndentationToSpaces),
+    ("ctrl+alt+i", Command::IndentationToTabs),
+    ("ctrl+shift+l", Command::NormalizeLineEndings),
+    ("ctrl+alt+r", Command::ConvertLineEndings),
+    ("ctrl+shift+n", Command::FixTrailingNewline),
+    ("ctrl+shift+i", Command::BufferInfo),
+    ("ctrl+d", Command::AddNextOccurrence),
+    ("ctrl+alt+up", Command::AddCursorAbove),
+    ("ctrl+alt+down", Command::AddCursorBelow),
+    ("alt+up", Command::MoveLinesUp),
+    ("alt+down", Command::MoveLinesDown),
+    ("ctrl+shift+d", Command::Duplicate),
+    ("ctrl+=", Command::IncreaseFontSize),
+    ("ctrl+-", Command::DecreaseFontSize),
+    ("ctrl+\\", Command::SplitHorizontal),
+    ("ctrl+|", Command::SplitVertical),
+    ("ctrl+shift+w", Command::ClosePane),
+    ("f6", Command::FocusNextPane),
+    ("shift+f6", Command::FocusPrevPane),
+    ("f11", Command::ToggleZenMode),
+    ("ctrl+shift+f", Command::FindInFiles),
+    ("ctrl+shift+m", Command::Problems),
+    ("f1", Command::Help),
+    ("ctrl+alt+d", Command::DebugSnapshot),
+];
+
+/// Emacs style bindings, applied on top of the defaults.
+const EMACS_BINDINGS: &[(&str, Command)] = &[
+    ("ctrl+a", Command::LineStart),
+    ("ctrl+e", Command::LineEnd),
+    ("ctrl+f", Command::MoveRight),
+    ("ctrl+b", Command::MoveLeft),
+    ("alt+f", Command::WordForward),
+    ("alt+b", Command::WordBackward),
+    ("ctrl+d", Command::Delete),
+    ("ctrl+k", Command::KillLine),
+    ("ctrl+y", Command::Paste),
+    ("ctrl+w", Command::Cut),
+    ("alt+w", Command::Copy),
+    ("ctrl+/", Command::Undo),
+    ("ctrl+s", Command::Find),
+];
+
+/// A set of bindings to start from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Preset {
+    #[default]
+    Default,
+    Emacs,
+}
+
+/// What a keymap file holds: the preset to start from and the chords to bind on
+/// top of it.
+#[derive(Deserialize)]
+struct KeymapFile {
+    #[serde(default)]
+    preset: Preset,
+
+    #[serde(flatten)]
+    bindings: HashMap<String, Command>,
+}
+
+/// Maps key chords to commands. Loaded from a TOML file of chords and the commands
+/// they run, e.g. `"ctrl+shift+v" = "paste"`, which are applied on top of a preset
+/// picked with e.g. `preset = "emacs"`.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    bindings: HashMap<Chord, Command>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::preset(Preset::Default)
+    }
+}
+
+impl Keymap {
+    pub fn preset(preset: Preset) -> Self {
+        let extra = match preset {
+            Preset::Default => &[][..],
+            Preset::Emacs => EMACS_BINDINGS,
+        };
+
+        let bindings = DEFAULT_BINDINGS
+            .iter()
+            .chain(extra)
+            .map(|(chord, command)| (chord.parse().unwrap(), *command))
+            .collect();
+        Self { bindings }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        let invalid = |e| io::Error::new(io::ErrorKind::InvalidData, e);
+
+        let file: KeymapFile =
+            toml::from_str(&contents).map_err(|e: toml::de::Error| invalid(e.to_string()))?;
+
+        let mut keymap = Self::preset(file.preset);
+        for (chord, command) in file.bindings {
+            keymap.bind(chord.parse().map_err(invalid)?, command);
+        }
+        Ok(keymap)
+    }
+
+    /// Where the user's keymap lives: keymap.toml in the editor's config directory.
+    pub fn default_path() -> Option<PathBuf> {
+        Some(crate::config_dir()?.join("keymap.toml"))
+    }
+
+    /// Every chord that runs a command, in no particular order.
+    pub fn bindings(&self) -> impl Iterator<Item = (&Chord, Command)> {
+        self.bindings
+            .iter()
+            .map(|(chord, command)| (chord, *command))
+            .filter(|(_, command)| *command != Command::Unbound)
+    }
+
+    pub fn bind(&mut self, chord: Chord, command: Command) {
+        self.bindings.insert(chord, command);
+    }
+
+    /// The command bound to 'chord'. A chord with shift that isn't bound itself
+    /// runs the command of the same chord without shift, so e.g. shift+left still
+    /// moves the cursor, extending the selection.
+    pub fn lookup(&self, chord: &Chord) -> Option<Command> {
+        let command = match self.bindings.get(chord) {
+            Some(command) => *command,
+            None if chord.shift => *self.bindings.get(&Chord {
+                shift: false,
+                ..chord.clone()
+            })?,
+            None => return None,
+        };
+
+        (command != Command::Unbound).then_some(command)
+    }
+}
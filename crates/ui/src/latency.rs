@@ -0,0 +1,212 @@
+use crate::{
+    layout::{BoundingBox, Drawables},
+    quad_pipeline::QuadInstance,
+    theme::Theme,
+};
+use serde::Deserialize;
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+/// How typing latency is reported, set with latency_trace in the settings file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LatencyTrace {
+    #[default]
+    Off,
+
+    /// Log percentiles of each stage every REPORT_EVERY key presses.
+    Log,
+
+    /// Log, and also draw a sparkline of the latest key presses.
+    Overlay,
+}
+
+/// How many key presses are collected before their percentiles are logged.
+const REPORT_EVERY: usize = 100;
+
+/// How many of the latest key presses the sparkline shows.
+const SPARKLINE_LEN: usize = 120;
+
+/// The sparkline's scale goes at least this high, about two frames at 60Hz.
+const SPARKLINE_MIN_SCALE: Duration = Duration::from_millis(33);
+
+/// A key press on its way to the screen, with when it passed each stage so far.
+#[derive(Debug)]
+struct Trace {
+    received: Instant,
+    mutated: Option<Instant>,
+    laid_out: Option<Instant>,
+}
+
+/// How long each stage took for one key press. Each stage starts where the last
+/// one ended, the first one when the key event arrived.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencySample {
+    /// Handling the key and changing the buffer.
+    pub mutate: Duration,
+
+    /// Waiting for the next frame and laying it out.
+    pub layout: Duration,
+
+    /// Recording, submitting and presenting the frame.
+    pub present: Duration,
+}
+
+impl LatencySample {
+    pub fn total(&self) -> Duration {
+        self.mutate + self.layout + self.present
+    }
+}
+
+/// Times key presses from the event arriving to the frame showing them being
+/// presented, to get hard numbers on input latency.
+pub struct LatencyTracer {
+    mode: LatencyTrace,
+
+    /// Key presses that haven't made it to the screen yet.
+    pending: Vec<Trace>,
+
+    /// The latest samples, for the sparkline.
+    recent: VecDeque<LatencySample>,
+
+    /// Samples since percentiles were last logged.
+    unreported: Vec<LatencySample>,
+}
+
+impl LatencyTracer {
+    pub fn new(mode: LatencyTrace) -> Self {
+        Self {
+            mode,
+            pending: vec![],
+            recent: VecDeque::new(),
+            unreported: vec![],
+        }
+    }
+
+    fn enabled(&self) -> bool {
+        self.mode != LatencyTrace::Off
+    }
+
+    pub fn key_received(&mut self, now: Instant) {
+        if self.enabled() {
+            self.pending.push(Trace {
+                received: now,
+                mutated: None,
+                laid_out: None,
+            });
+        }
+    }
+
+    /// The key presses received so far have been applied to the buffer.
+    pub fn mutated(&mut self, now: Instant) {
+        for trace in self.pending.iter_mut().filter(|t| t.mutated.is_none()) {
+            trace.mutated = Some(now);
+        }
+    }
+
+    /// A frame including every applied key press has been laid out.
+    pub fn laid_out(&mut self, now: Instant) {
+        for trace in &mut self.pending {
+            if trace.mutated.is_some() && trace.laid_out.is_none() {
+                trace.laid_out = Some(now);
+            }
+        }
+    }
+
+    /// The laid out frame has been presented. Finishes the traces it showed.
+    pub fn presented(&mut self, now: Instant) {
+        let pending = std::mem::take(&mut self.pending);
+        for trace in pending {
+            let (Some(mutated), Some(laid_out)) = (trace.mutated, trace.laid_out) else {
+                self.pending.push(trace);
+                continue;
+            };
+
+            let sample = LatencySample {
+                mutate: mutated - trace.received,
+                layout: laid_out - mutated,
+                present: now - laid_out,
+            };
+            if self.recent.len() == SPARKLINE_LEN {
+                self.recent.pop_front();
+            }
+            self.recent.push_back(sample);
+            self.unreported.push(sample);
+        }
+
+        if self.unreported.len() >= REPORT_EVERY {
+            log::info!("{}", self.report());
+            self.unreported.clear();
+        }
+    }
+
+    /// The latest samples, oldest first.
+    pub fn recent(&self) -> impl Iterator<Item = &LatencySample> {
+        self.recent.iter()
+    }
+
+    /// The 50th, 90th and 99th percentile of each stage of the unreported samples.
+    pub fn report(&self) -> String {
+        let stage = |name: &str, duration: fn(&LatencySample) -> Duration| {
+            let mut durations: Vec<_> = self.unreported.iter().map(duration).collect();
+            durations.sort();
+            // Nearest rank: the smallest duration at least p% of samples are under.
+            let [p50, p90, p99] = [50, 90, 99].map(|p| {
+                let rank = (durations.len() * p).div_ceil(100);
+                durations
+                    .get(rank.saturating_sub(1))
+                    .map_or(0.0, |d| d.as_secs_f64() * 1000.0)
+            });
+            format!("{name} {p50:.2}/{p90:.2}/{p99:.2}")
+        };
+
+        format!(
+            "Typing latency over {} keys, p50/p90/p99 in ms: {}, {}, {}, {}",
+            self.unreported.len(),
+            stage("mutate", |s| s.mutate),
+            stage("layout", |s| s.layout),
+            stage("present", |s| s.present),
+            stage("total", LatencySample::total),
+        )
+    }
+
+    /// A bar per recent key press in the bottom right corner of 'view_size', as tall
+    /// as its total latency. Nothing unless the mode is Overlay.
+    pub fn sparkline(&self, view_size: BoundingBox, theme: &Theme) -> Vec<Drawables> {
+        if self.mode != LatencyTrace::Overlay {
+            return vec![];
+        }
+
+        let bar_width = 2.0;
+        let width = bar_width * SPARKLINE_LEN as f32;
+        let height = 48.0;
+        let x = view_size.max.0 - width;
+        let y = view_size.max.1 - height;
+
+        let mut drawables = vec![Drawables::Rect(QuadInstance::filled(
+            [x, y],
+            [width, height],
+            &theme.popup_background,
+        ))];
+
+        let scale = self
+            .recent
+            .iter()
+            .map(LatencySample::total)
+            .max()
+            .unwrap_or_default()
+            .max(SPARKLINE_MIN_SCALE);
+        for (i, sample) in self.recent.iter().enumerate() {
+            let bar_height = height * sample.total().as_secs_f32() / scale.as_secs_f32();
+            drawables.push(Drawables::Rect(QuadInstance::filled(
+                [x + bar_width * i as f32, view_size.max.1 - bar_height],
+                [bar_width, bar_height],
+                &theme.foreground,
+            )));
+        }
+
+        drawables
+    }
+}
@@ -1,22 +1,43 @@
 use crate::{
-    image_pipeline::{self, ImageInstance},
+    animation::{AnimationClock, Easing, Tween},
+    buffers::Buffers,
+    clipboard::Clipboard,
+    file_dialog::FileDialog,
+    image_pipeline::{self, Baseline, ColoredRange, Highlight, ImageInstance},
+    input::{KeyInput, Modifiers},
+    keymap::{Chord, Command, Keymap},
+    project_search::{ProjectSearch, SearchHit},
     quad_pipeline::QuadInstance,
-    texture_atlas::{AllocationInfo, TextureAtlas},
+    resources::Icon,
+    session::{Session, SessionBuffer},
+    settings::{FileSize, Settings, Wrap},
+    text_input::{InputAction, TextInput},
+    texture_atlas::{AllocationInfo, GlyphAtlas},
+    theme::Theme,
+    virtual_list::VirtualList,
 };
-use copypasta::{ClipboardContext, ClipboardProvider};
+use regex::Regex;
 use std::{
     cell::RefCell,
+    collections::{HashSet, VecDeque},
+    ops::Range,
+    path::{Path, PathBuf},
     rc::Rc,
     time::{Duration, Instant},
 };
-use text_editor::{ScrollAmount, TextEditor};
+use text_editor::{
+    highlight::{Diagnostic, Severity},
+    layout::{VisualLine, WrapPolicy},
+    loader::FileLoader,
+    mapped::MappedFile,
+    Encoding, LineEnding, LineInput, ScrollAmount, TextBuffer, TextEditor,
+};
 use winit::{
-    event::{ElementState, KeyEvent, MouseScrollDelta},
+    event::{ElementState, MouseButton, MouseScrollDelta},
     keyboard::{Key, NamedKey},
-    window::Window,
 };
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct UiNodeId(usize);
 
 #[derive(Debug)]
@@ -38,6 +59,27 @@ impl Color {
         Self { r, g, b, a }
     }
 
+    /// The color 't' of the way from 'self' to 'other'.
+    pub fn lerp(&self, other: Color, t: f32) -> Color {
+        let t = t.clamp(0.0, 1.0);
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+        Color::new(
+            mix(self.r, other.r),
+            mix(self.g, other.g),
+            mix(self.b, other.b),
+            mix(self.a, other.a),
+        )
+    }
+
+    pub fn to_wgpu(&self) -> wgpu::Color {
+        wgpu::Color {
+            r: self.r as f64 / 255.0,
+            g: self.g as f64 / 255.0,
+            b: self.b as f64 / 255.0,
+            a: self.a as f64 / 255.0,
+        }
+    }
+
     pub fn to_f32_arr(&self) -> [f32; 4] {
         [
             self.r as f32 / 255.0,
@@ -55,11 +97,11 @@ pub struct Rectangle {
 
 impl Rectangle {
     fn layout(&self, view_size: BoundingBox, drawables: &mut Vec<Drawables>) {
-        drawables.push(Drawables::Rect(QuadInstance {
-            position: [view_size.min.0, view_size.min.1],
-            size: [view_size.width(), view_size.height()],
-            color: self.color.to_f32_arr(),
-        }));
+        drawables.push(Drawables::Rect(QuadInstance::filled(
+            [view_size.min.0, view_size.min.1],
+            [view_size.width(), view_size.height()],
+            &self.color,
+        )));
     }
 }
 
@@ -72,7 +114,7 @@ pub struct TexturedRectangle {
 impl TexturedRectangle {
     fn layout(
         &self,
-        atlas: &mut TextureAtlas,
+        atlas: &mut impl GlyphAtlas,
         view_size: BoundingBox,
         drawables: &mut Vec<Drawables>,
     ) {
@@ -86,6 +128,37 @@ impl TexturedRectangle {
     }
 }
 
+/// An icon, rasterized at the size it is shown so it stays crisp. It is as big as
+/// fits and centered in its space.
+#[derive(Debug)]
+pub struct IconView {
+    icon: Icon,
+
+    /// None draws it in the theme's widget foreground color.
+    tint: Option<Color>,
+}
+
+impl IconView {
+    fn layout(
+        &self,
+        scene: &Scene,
+        atlas: &mut impl GlyphAtlas,
+        view_size: BoundingBox,
+        drawables: &mut Vec<Drawables>,
+    ) {
+        let size = view_size.width().min(view_size.height());
+        let (cx, cy) = view_size.center();
+        let tint = self.tint.unwrap_or(scene.theme.widget_foreground);
+        drawables.extend(image_pipeline::layout_icon(
+            (cx - size / 2.0, cy - size / 2.0),
+            size,
+            atlas,
+            &tint,
+            self.icon,
+        ));
+    }
+}
+
 #[derive(Debug)]
 pub struct FixedSizedBox {
     width: f32,
@@ -98,17 +171,15 @@ impl FixedSizedBox {
     fn layout(
         &self,
         scene: &Scene,
-        atlas: &mut TextureAtlas,
+        atlas: &mut impl GlyphAtlas,
         view_size: BoundingBox,
-        queue: &wgpu::Queue,
-        window: &Window,
         drawables: &mut Vec<Drawables>,
     ) {
-        drawables.push(Drawables::Rect(QuadInstance {
-            position: [view_size.min.0, view_size.min.1],
-            size: [view_size.width(), view_size.height()],
-            color: self.background_color.to_f32_arr(),
-        }));
+        drawables.push(Drawables::Rect(QuadInstance::filled(
+            [view_size.min.0, view_size.min.1],
+            [view_size.width(), view_size.height()],
+            &self.background_color,
+        )));
 
         // The ceneter of the space we have
         let bbox_center = view_size.center();
@@ -122,18 +193,43 @@ impl FixedSizedBox {
             bbox_center.1 + half_height,
         );
 
+        // The outline goes just outside the box's edges, where the child does not
+        // draw over it.
+        if scene.settings.debug_outlines {
+            drawables.push(Drawables::Rect(QuadInstance::outline(
+                [fixed_size_bbox.min.0 - 1.0, fixed_size_bbox.min.1 - 1.0],
+                [
+                    fixed_size_bbox.width() + 2.0,
+                    fixed_size_bbox.height() + 2.0,
+                ],
+                &scene.theme.widget_foreground,
+                1.0,
+            )));
+        }
+
         let child = scene.node(self.child);
-        child.layout(scene, atlas, fixed_size_bbox, queue, window, drawables);
+        child.layout(scene, atlas, fixed_size_bbox, drawables);
     }
 }
 
 pub struct Text {
-    /// Contains all of the text within this text editor.
-    editor: TextEditor,
+    /// The open files. The active one is shown and edited.
+    buffers: Buffers,
 
     font_size: f32,
-    text_color: Color,
-    background_color: Color,
+
+    /// How long lines are broken, in every buffer of this node.
+    wrap: Wrap,
+
+    /// Tab stops and indentation for buffers opened in this node.
+    tab_width: usize,
+    insert_spaces: bool,
+
+    /// Lines are this many times as far apart as the font asks for.
+    line_spacing: f32,
+
+    /// Buffers longer than this many bytes are opened without highlighting.
+    large_file_bytes: u64,
 
     /// The last time something was entered in the text editor. Used to see if
     /// we should keep the cursor visible or allow it to blink.
@@ -142,30 +238,178 @@ pub struct Text {
     /// The last time the cursor blinked. Used to alternate drawing the cursor
     /// and create the blinking effect.
     last_cursor_blink: Instant,
+
+    /// Whether the cursor blinks at all. When false it is always drawn solid.
+    cursor_blink: bool,
+
+    /// Where the cursor is drawn on screen. Eased towards the real cursor
+    /// position so it glides instead of jumping. None until the cursor is first
+    /// laid out, so it doesn't glide in from the corner.
+    cursor_tween: Option<(Tween, Tween)>,
+
+    /// How many visual lines the wheel scrolled the view, eased so the lines go
+    /// by a few at a time. 'scrolled_lines' of them have been scrolled so far.
+    scroll_tween: Tween,
+    scrolled_lines: i32,
+
+    /// Where this node was last laid out. Used to map mouse positions to text.
+    bounds: Option<BoundingBox>,
+
+    /// The last search made in this node. Kept after the search box closes so
+    /// F3 can keep jumping between matches.
+    search: Option<Search>,
+
+    /// The hex digits typed so far after Ctrl+Shift+U, while entering a character
+    /// by its codepoint.
+    unicode_entry: Option<String>,
+
+    /// The line number typed so far after Ctrl+G.
+    goto_line: Option<TextInput>,
+
+    /// Text being composed at the cursor, e.g. the accent of a dead key or the
+    /// candidates of an input method. Drawn underlined in the line, and only
+    /// inserted into the buffer once it is committed.
+    preedit: Option<String>,
+
+    /// Where the cursor and the text being composed at it were last drawn. Input
+    /// methods show their candidates next to it.
+    cursor_area: Option<BoundingBox>,
+
+    /// Is the overlay with the sizes of the buffer and its caches shown?
+    buffer_info: bool,
+
+    /// Large files being read on other threads, the zero based line to show in
+    /// each once it is open, and when it was asked for.
+    loading: Vec<(FileLoader, Option<usize>, Instant)>,
+
+    /// A file that finished loading after the user went on working, so it was
+    /// opened behind the active buffer instead of taking its place. Its name is
+    /// shown in the status bar as long as the buffer at the index next to it stays
+    /// active.
+    opened_behind: Option<(String, usize)>,
+
+    /// The command, closing the active buffer or the pane or reopening the file,
+    /// that was refused because of unsaved changes. Running it again right after
+    /// discards them.
+    closing_unsaved: Option<Command>,
+
+    /// What the last command did or why it failed, e.g. a save, shown until the
+    /// next key. True if it is a warning.
+    message: Option<(String, bool)>,
+}
+
+/// How 'wrap' breaks the lines of a buffer. Large buffers are always broken at
+/// any character, which is cheaper than looking for the spaces between words.
+fn wrap_policy(wrap: Wrap, large: bool) -> Box<dyn WrapPolicy> {
+    match wrap {
+        Wrap::Word if large => Wrap::Char.policy(),
+        wrap => wrap.policy(),
+    }
+}
+
+/// Someone else's cursor, e.g. a collaborator's. Drawn in its own color with a tag
+/// naming it above.
+#[derive(Debug, Clone)]
+pub struct RemoteCaret {
+    pub name: String,
+    pub color: Color,
+
+    /// Byte indices in the text. Clamped to its length when drawn.
+    pub cursor: usize,
+    pub selection: Option<Range<usize>>,
+}
+
+#[derive(Debug)]
+struct Search {
+    query: TextInput,
+
+    /// Is the query a regex rather than plain text?
+    regex: bool,
+
+    /// What matches are replaced with. Only Some while replacing.
+    replacement: Option<TextInput>,
+
+    /// Are keys going to the replacement rather than the query?
+    editing_replacement: bool,
+
+    /// Where the query occurs in the text.
+    matches: Vec<Range<usize>>,
+
+    /// Is the search box showing? Keys go to the query while it is.
+    open: bool,
 }
 
+impl Default for Search {
+    fn default() -> Self {
+        Self {
+            query: TextInput::default().with_placeholder("Type to search…"),
+            regex: false,
+            replacement: None,
+            editing_replacement: false,
+            matches: vec![],
+            open: false,
+        }
+    }
+}
+
+impl Search {
+    /// The query as a regex. None if it is empty, or is not a valid regex.
+    fn pattern(&self) -> Option<Regex> {
+        let query = self.query.text();
+        if query.is_empty() {
+            return None;
+        }
+
+        if self.regex {
+            Regex::new(&query).ok()
+        } else {
+            Regex::new(&regex::escape(&query)).ok()
+        }
+    }
+}
+
+/// How long the cursor takes to glide to a new position.
+const CURSOR_ANIMATION: Duration = Duration::from_millis(80);
+
+/// How long the lines of a wheel scroll take to go by.
+const SCROLL_ANIMATION: Duration = Duration::from_millis(100);
+
+/// How long fading from one theme to another takes.
+const THEME_FADE: Duration = Duration::from_millis(250);
+
 impl Text {
     fn layout(
         &mut self,
-        atlas: &mut TextureAtlas,
+        atlas: &mut impl GlyphAtlas,
         view_size: BoundingBox,
+        now: Duration,
+        theme: &Theme,
+        scene_stats: Option<SceneStats>,
         drawables: &mut Vec<Drawables>,
     ) {
-        self.editor
-            .update_window_size(view_size.width(), view_size.height());
+        self.bounds = Some(view_size);
+        let font_size = self.font_size;
+        let line_metrics = atlas
+            .line_metrics(font_size)
+            .with_spacing(self.line_spacing);
+        let editor = self.editor_mut();
+        editor.update_window_size(view_size.width(), view_size.height());
+        editor.update_font_size(font_size);
+        editor.set_line_metrics(line_metrics);
+        editor.update_syntax();
 
         // background color
-        drawables.push(Drawables::Rect(QuadInstance {
-            position: [view_size.min.0, view_size.min.1],
-            size: [view_size.width(), view_size.height()],
-            color: self.background_color.to_f32_arr(),
-        }));
+        drawables.push(Drawables::Rect(QuadInstance::filled(
+            [view_size.min.0, view_size.min.1],
+            [view_size.width(), view_size.height()],
+            &theme.background,
+        )));
 
         // Default cursor blink rate is 530ms. TIL
         // Only blink cursor if there was no action in the last second
-        let draw_cursor = if Instant::now().duration_since(self.last_action)
-            > Duration::from_millis(1060)
-        {
+        let draw_cursor = if !self.cursor_blink {
+            true
+        } else if Instant::now().duration_since(self.last_action) > Duration::from_millis(1060) {
             if Instant::now().duration_since(self.last_cursor_blink) > Duration::from_millis(530) {
                 if Instant::now().duration_since(self.last_cursor_blink)
                     > Duration::from_millis(1060)
@@ -180,271 +424,4598 @@ impl Text {
             true
         };
 
-        drawables.extend(image_pipeline::layout_text(
+        let selections = self.editor().selection_ranges();
+        let brackets: Vec<_> = match self.editor().matching_bracket() {
+            Some((a, b)) => vec![a.min(b)..a.min(b) + 1, a.max(b)..a.max(b) + 1],
+            None => vec![],
+        };
+
+        let mut highlights = vec![Highlight {
+            ranges: &brackets,
+            color: theme.bracket_match,
+        }];
+        if let Some(search) = self.search.as_ref().filter(|search| search.open) {
+            highlights.push(Highlight {
+                ranges: &search.matches,
+                color: theme.search_match,
+            });
+        }
+        highlights.push(Highlight {
+            ranges: &selections,
+            color: theme.selection,
+        });
+
+        let len = self.editor().len();
+        let remote_carets = self.buffers.active_remote_carets();
+        let remote_selections: Vec<_> = remote_carets
+            .iter()
+            .map(|caret| {
+                let range = caret.selection.clone().unwrap_or_default();
+                range.start.min(len)..range.end.min(len)
+            })
+            .collect();
+        for (caret, range) in remote_carets.iter().zip(&remote_selections) {
+            highlights.push(Highlight {
+                ranges: std::slice::from_ref(range),
+                color: Color {
+                    a: 80,
+                    ..caret.color
+                },
+            });
+        }
+
+        let syntax_colors: Vec<_> = self
+            .editor()
+            .highlight_spans(self.editor().visible_range())
+            .into_iter()
+            .filter_map(|span| {
+                theme.syntax.color(span.kind).map(|color| ColoredRange {
+                    range: span.range,
+                    color,
+                })
+            })
+            .collect();
+
+        // The cursor, the extra cursors, then the remote carets.
+        let mut marks = vec![self.editor().cursor_position()];
+        marks.extend(self.editor().extra_cursors());
+        let extra_cursors = marks.len() - 1;
+        marks.extend(remote_carets.iter().map(|caret| caret.cursor.min(len)));
+
+        let (mut glyphs, mut baselines) = image_pipeline::layout_text(
             view_size,
             atlas,
-            self.font_size,
-            &self.text_color,
-            draw_cursor,
-            &self.editor,
-        ));
-    }
+            &theme.foreground,
+            &highlights,
+            &syntax_colors,
+            self.editor(),
+            &marks,
+        );
 
-    pub fn backspace(&mut self) {
-        self.editor.backspace();
-    }
+        // Text being composed goes in at the cursor, pushing the rest of its line
+        // right, and the cursor to its end.
+        let preedit_start = baselines[0];
+        let mut preedit_width = 0.0;
+        if let (Some(preedit), Some(start)) = (&self.preedit, preedit_start) {
+            preedit_width = image_pipeline::label_width(atlas, self.font_size, preedit);
+            image_pipeline::open_gap(
+                &mut glyphs,
+                &mut baselines,
+                start,
+                preedit_width,
+                &line_metrics,
+                view_size.max.0,
+            );
+        }
+        drawables.extend(glyphs);
+        let cursor = baselines[0];
+        self.cursor_area = preedit_start.map(|(x, y)| {
+            let top = y - line_metrics.baseline();
+            BoundingBox::new(x, top, x + preedit_width, top + line_metrics.line_height())
+        });
+        let (extra_cursors, remote_carets) = baselines[1..].split_at(extra_cursors);
 
-    pub fn delete(&mut self) {
-        self.editor.delete();
-    }
+        if let Some((x, y)) = cursor {
+            let (tween_x, tween_y) = self
+                .cursor_tween
+                .get_or_insert_with(|| (Tween::new(x), Tween::new(y)));
+            tween_x.retarget(x, now, CURSOR_ANIMATION, Easing::EaseOutCubic);
+            tween_y.retarget(y, now, CURSOR_ANIMATION, Easing::EaseOutCubic);
 
-    pub fn add_char(&mut self, c: &str) {
-        self.last_action = Instant::now();
-        self.editor.insert_text(c);
-    }
+            if draw_cursor {
+                let baseline = (tween_x.value(now), tween_y.value(now));
+                drawables.push(self.cursor_rect(baseline, theme));
+            }
+        }
 
-    pub fn increase_font_size(&mut self) {
-        self.font_size += 4.0;
-        self.editor.update_font_size(self.font_size);
-    }
+        // Extra cursors jump instead of gliding, there is no tween to drive them.
+        if draw_cursor {
+            for &baseline in extra_cursors.iter().flatten() {
+                drawables.push(self.cursor_rect(baseline, theme));
+            }
+        }
 
-    pub fn decrease_font_size(&mut self) {
-        if self.font_size - 4.0 >= 1.0 {
-            self.font_size -= 4.0;
-            self.editor.update_font_size(self.font_size);
+        if let (Some(digits), Some(baseline)) = (&self.unicode_entry, cursor) {
+            // Show the codepoint being typed at the cursor, where the character will go.
+            let (glyphs, width) = image_pipeline::layout_label(
+                baseline,
+                view_size.max.0,
+                atlas,
+                self.font_size,
+                &theme.foreground,
+                &format!("u{digits}"),
+            );
+            let line_metrics = self.editor().line_metrics();
+            drawables.push(Drawables::Rect(QuadInstance::filled(
+                [baseline.0, baseline.1 - line_metrics.baseline()],
+                [width, line_metrics.line_height()],
+                &theme.popup_background,
+            )));
+            drawables.extend(glyphs);
         }
-    }
 
-    /// Scrolls the text viewport 'scroll_lines' at a time.
-    pub fn scroll_delta(
-        &mut self,
-        delta: MouseScrollDelta,
-        lines: usize,
-        glyph_rasterizer: &mut impl text_editor::GlyphRasterizer,
-    ) {
-        let scroll_amount = match delta {
-            MouseScrollDelta::LineDelta(_, y) => {
-                if y > 0.0 {
-                    ScrollAmount::Up { lines }
-                } else {
-                    ScrollAmount::Down { lines }
-                }
-            }
-            MouseScrollDelta::PixelDelta(_) => todo!(),
-        };
+        if let (Some(preedit), Some(baseline)) = (&self.preedit, preedit_start) {
+            let (glyphs, width) = image_pipeline::layout_label(
+                baseline,
+                view_size.max.0,
+                atlas,
+                self.font_size,
+                &theme.foreground,
+                preedit,
+            );
+            drawables.extend(glyphs);
 
-        self.editor.scroll(scroll_amount, glyph_rasterizer);
+            let thickness = (self.font_size / 16.0).ceil();
+            drawables.push(Drawables::Rect(QuadInstance::filled(
+                [baseline.0, baseline.1 + thickness],
+                [width, thickness],
+                &theme.foreground,
+            )));
+        }
+
+        self.layout_remote_carets(atlas, view_size, theme, remote_carets, drawables);
+        self.layout_diagnostic_marks(atlas, view_size, theme, drawables);
+        self.layout_search(atlas, view_size, theme, drawables);
+        self.layout_goto_line(atlas, view_size, theme, drawables);
+        self.layout_buffer_info(atlas, view_size, theme, scene_stats, drawables);
     }
 
-    pub fn scroll(
-        &mut self,
-        amount: ScrollAmount,
-        glyph_rasterizer: &mut impl text_editor::GlyphRasterizer,
-    ) {
-        self.editor.scroll(amount, glyph_rasterizer);
+    fn cursor_rect(&self, baseline: (f32, f32), theme: &Theme) -> Drawables {
+        let (cursor_top, cursor_height) = self.editor().line_metrics().cursor();
+        let cursor_width = (self.font_size / 10.0).floor();
+        Drawables::Rect(QuadInstance::filled(
+            [baseline.0, baseline.1 - cursor_top],
+            [cursor_width, cursor_height],
+            &theme.cursor,
+        ))
     }
-}
 
-#[derive(Debug)]
-pub struct Hbox {
-    elements: Vec<UiNodeId>,
-}
+    /// Show other people's cursors in the buffer at 'buffer', replacing the ones it
+    /// had. Their selections are drawn with the rest of the text.
+    pub fn set_remote_carets(&mut self, buffer: usize, carets: Vec<RemoteCaret>) -> bool {
+        self.buffers.set_remote_carets(buffer, carets)
+    }
 
-impl Hbox {
-    fn layout(
+    /// Each remote caret of the active buffer in its color, with its name on a tag
+    /// above it, at the baselines layout_text found for them. The tag goes below the
+    /// line instead if there is no room above.
+    fn layout_remote_carets(
         &self,
-        scene: &Scene,
-        atlas: &mut TextureAtlas,
-        parent_size: BoundingBox,
-        queue: &wgpu::Queue,
-        window: &Window,
+        atlas: &mut impl GlyphAtlas,
+        view_size: BoundingBox,
+        theme: &Theme,
+        baselines: &[Option<Baseline>],
         drawables: &mut Vec<Drawables>,
     ) {
-        for (i, id) in self.elements.iter().enumerate() {
-            let child_index = i;
-            let child_width = parent_size.width() / self.elements.len() as f32;
-            let x0 = parent_size.min.0 + child_width * child_index as f32;
+        let line_metrics = self.editor().line_metrics();
+        let (cursor_top, cursor_height) = line_metrics.cursor();
+        let cursor_width = (self.font_size / 10.0).floor();
+        let tag_font_size = (self.font_size * 0.75).floor();
+        let tag_height = (tag_font_size * 1.4).floor();
+        let padding = (tag_font_size / 3.0).floor();
+        let remote_carets = self.buffers.active_remote_carets();
+        for (caret, baseline) in remote_carets.iter().zip(baselines) {
+            let Some((x, y)) = *baseline else {
+                continue;
+            };
 
-            let view_size =
-                BoundingBox::new(x0, parent_size.min.1, x0 + child_width, parent_size.max.1);
+            drawables.push(Drawables::Rect(QuadInstance::filled(
+                [x, y - cursor_top],
+                [cursor_width, cursor_height],
+                &caret.color,
+            )));
 
-            scene
-                .node(*id)
-                .layout(scene, atlas, view_size, queue, window, drawables);
+            // Above the line the caret is on, or below it if there is no room.
+            let line_top = y - line_metrics.baseline();
+            let mut tag_top = line_top - tag_height;
+            if tag_top < view_size.min.1 {
+                tag_top = line_top + line_metrics.line_height();
+            }
+            let (label, width) = image_pipeline::layout_label(
+                (
+                    x + padding,
+                    tag_top + (tag_height + tag_font_size * 0.7) / 2.0,
+                ),
+                view_size.max.0,
+                atlas,
+                tag_font_size,
+                &theme.background,
+                &caret.name,
+            );
+            drawables.push(Drawables::Rect(QuadInstance::filled(
+                [x, tag_top],
+                [width + padding * 2.0, tag_height],
+                &caret.color,
+            )));
+            drawables.extend(label);
         }
     }
-}
-
-#[derive(Debug)]
-pub struct Vbox {
-    elements: Vec<UiNodeId>,
-}
 
-impl Vbox {
-    fn layout(
+    /// An icon at the right edge of each visible line with a problem on it, for the
+    /// worst one there.
+    fn layout_diagnostic_marks(
         &self,
-        scene: &Scene,
-        atlas: &mut TextureAtlas,
-        parent_size: BoundingBox,
-        queue: &wgpu::Queue,
-        window: &Window,
+        atlas: &mut impl GlyphAtlas,
+        view_size: BoundingBox,
+        theme: &Theme,
         drawables: &mut Vec<Drawables>,
     ) {
-        for (i, id) in self.elements.iter().enumerate() {
-            let child_index = self.elements.len() - i - 1;
-            let child_height = parent_size.height() / self.elements.len() as f32;
-            let y0 = parent_size.min.1 + child_height * child_index as f32;
+        let diagnostics = self.editor().diagnostics();
+        if diagnostics.is_empty() {
+            return;
+        }
 
-            let view_size =
-                BoundingBox::new(parent_size.min.0, y0, parent_size.max.0, y0 + child_height);
+        let worst_on = |line: &VisualLine| {
+            diagnostics
+                .iter()
+                .filter(|d| {
+                    (line.range.start..line.next_start()).contains(&d.range.start)
+                        || d.range.start == line.range.end
+                })
+                .map(|d| d.severity)
+                .max()
+        };
+        let marks: Vec<_> = self
+            .editor()
+            .visible_lines(atlas)
+            .enumerate()
+            .filter_map(|(i, line)| worst_on(&line).map(|severity| (i, severity)))
+            .collect();
+
+        let line_height = self.editor().line_metrics().line_height();
+        let icon_size = (self.font_size * 0.8).floor();
+        for (i, severity) in marks {
+            let top = view_size.min.1 + line_height * i as f32;
+            if top >= view_size.max.1 {
+                break;
+            }
 
-            scene
-                .node(*id)
-                .layout(scene, atlas, view_size, queue, window, drawables);
+            let (icon, color) = match severity {
+                Severity::Error => (Icon::Error, &theme.error),
+                Severity::Warning => (Icon::Warning, &theme.warning),
+            };
+            drawables.extend(image_pipeline::layout_icon(
+                (
+                    view_size.max.0 - icon_size - 2.0,
+                    top + (line_height - icon_size) / 2.0,
+                ),
+                icon_size,
+                atlas,
+                color,
+                icon,
+            ));
         }
     }
-}
-
-pub enum Ui {
-    TexturedRectangle(TexturedRectangle),
-    FixedSizedBox(FixedSizedBox),
-    Rectangle(Rectangle),
-    Text(RefCell<Text>),
-    Hbox(Hbox),
-    Vbox(Vbox),
-    Spacer,
-}
 
-impl Ui {
-    fn layout(
+    /// Draw the sizes of the buffer, its history, the glyph atlas and the scene in
+    /// the bottom right corner of the node.
+    fn layout_buffer_info(
         &self,
-        scene: &Scene,
-        atlas: &mut TextureAtlas,
+        atlas: &mut impl GlyphAtlas,
         view_size: BoundingBox,
-        queue: &wgpu::Queue,
-        window: &Window,
+        theme: &Theme,
+        scene_stats: Option<SceneStats>,
         drawables: &mut Vec<Drawables>,
     ) {
-        match self {
-            Ui::TexturedRectangle(tr) => tr.layout(atlas, view_size, drawables),
-            Ui::FixedSizedBox(fsb) => fsb.layout(scene, atlas, view_size, queue, window, drawables),
-            Ui::Rectangle(r) => r.layout(view_size, drawables),
-            Ui::Text(td) => td.borrow_mut().layout(atlas, view_size, drawables),
-            Ui::Hbox(h) => h.layout(scene, atlas, view_size, queue, window, drawables),
-            Ui::Vbox(v) => v.layout(scene, atlas, view_size, queue, window, drawables),
-            Ui::Spacer => {}
+        if !self.buffer_info {
+            return;
         }
-    }
-}
-
-pub struct Scene {
-    nodes: RefCell<Vec<Rc<Ui>>>,
-    node_root: UiNodeId,
-    cursor_pos: (f32, f32),
-    focused: Option<UiNodeId>,
-
-    /// Handle to the system clipboard for copy/paste
-    clipboard_context: ClipboardContext,
-}
 
-impl Default for Scene {
-    fn default() -> Self {
-        Self {
-            nodes: RefCell::new(vec![]),
-            node_root: UiNodeId(0),
-            cursor_pos: (0.0, 0.0),
-            focused: None,
-            clipboard_context: ClipboardContext::new().unwrap(),
-        }
-    }
-}
+        let buffer = self.editor().stats();
+        let glyphs = atlas.stats();
+        let lines = [
+            format!("Text: {} bytes, {} lines", buffer.bytes, buffer.lines),
+            format!("Rope: {} chunks", buffer.chunks),
+            format!(
+                "History: {} undo, {} redo, {} KiB",
+                buffer.undo_steps,
+                buffer.redo_steps,
+                buffer.history_bytes.div_ceil(1024)
+            ),
+            format!("Buffers: {}", self.buffers.len()),
+            format!(
+                "Atlas: {0}x{0}, {1} glyphs, {2} evicted",
+                glyphs.size, glyphs.glyphs, glyphs.evictions
+            ),
+        ]
+        .into_iter()
+        .chain(scene_stats.into_iter().flat_map(|scene| {
+            [
+                format!("Nodes: {} live, {} removed", scene.nodes, scene.removed),
+                format!("Texts: {}, {} buffers", scene.texts, scene.buffers),
+            ]
+        }))
+        .collect::<Vec<_>>();
 
-impl Scene {
-    pub fn set_focus(&mut self, node: UiNodeId) {
-        self.focused = Some(node);
-    }
+        let font_size = (self.font_size * 0.9).floor();
+        let padding = font_size / 2.0;
+        let line_metrics = atlas.line_metrics(font_size);
+        let line_height = line_metrics.line_height();
+        let box_width = (font_size * 20.0).min(view_size.width());
+        let box_height = line_height * lines.len() as f32 + padding * 2.0;
+        let x = view_size.max.0 - box_width;
+        let y = view_size.max.1 - box_height;
 
-    pub fn set_root(&mut self, root: UiNodeId) {
-        self.node_root = root;
-    }
+        drawables.push(Drawables::Rect(QuadInstance::filled(
+            [x, y],
+            [box_width, box_height],
+            &theme.popup_background,
+        )));
 
-    pub fn scroll(
-        &self,
-        delta: MouseScrollDelta,
-        glyph_rasterizer: &mut impl text_editor::GlyphRasterizer,
-    ) {
-        if let Some(focused) = self.focused {
-            if let Ui::Text(td) = self.node(focused).as_ref() {
-                td.borrow_mut().scroll_delta(delta, 3, glyph_rasterizer);
-            }
+        for (i, line) in lines.iter().enumerate() {
+            let (glyphs, _) = image_pipeline::layout_label(
+                (
+                    x + padding,
+                    y + padding + line_metrics.baseline() + line_height * i as f32,
+                ),
+                x + box_width - padding,
+                atlas,
+                font_size,
+                &theme.foreground,
+                line,
+            );
+            drawables.extend(glyphs);
         }
     }
 
-    pub fn send_keystroke(
+    fn layout_goto_line(
         &mut self,
-        event: &KeyEvent,
-        glyph_rasterizer: &mut impl text_editor::GlyphRasterizer,
+        atlas: &mut impl GlyphAtlas,
+        view_size: BoundingBox,
+        theme: &Theme,
+        drawables: &mut Vec<Drawables>,
     ) {
-        if let Some(focused) = self.focused {
-            if let Ui::Text(td) = self.node(focused).as_ref() {
-                let mut td = td.borrow_mut();
-                match event.state {
-                    ElementState::Pressed => match &event.logical_key {
-                        Key::Named(n) => match n {
-                            NamedKey::ArrowLeft => td.editor.left(),
-                            NamedKey::ArrowRight => td.editor.right(),
-                            NamedKey::Control => td.editor.ctrl_down = true,
-                            NamedKey::Enter => td.add_char("\n"),
-                            NamedKey::Tab => td.add_char("    "), // TODO: handle tabs more correctly
-                            NamedKey::Space => td.add_char(" "),
-                            NamedKey::End => td.scroll(ScrollAmount::ToEnd, glyph_rasterizer),
-                            NamedKey::Home => td.scroll(ScrollAmount::ToStart, glyph_rasterizer),
-                            NamedKey::Backspace => td.backspace(),
-                            NamedKey::Delete => td.delete(),
-                            _ => {}
-                        },
-                        Key::Character(c) => match c.as_str() {
-                            c if c.eq_ignore_ascii_case("v") && td.editor.ctrl_down => {
-                                let clipboard_contents =
-                                    self.clipboard_context.get_contents().unwrap();
-                                td.editor.insert_text(&clipboard_contents);
-                            }
-                            c if c == "-" && td.editor.ctrl_down => td.decrease_font_size(),
-                            c if c == "=" && td.editor.ctrl_down => td.increase_font_size(),
-                            _ => td.add_char(c),
-                        },
-                        _ => {}
-                    },
-                    ElementState::Released => match &event.logical_key {
-                        Key::Named(n) => match n {
-                            NamedKey::Control => td.editor.ctrl_down = false,
-                            _ => {}
-                        },
-                        _ => {}
-                    },
-                }
-            }
-        }
-    }
-
-    pub fn layout(
-        &self,
-        atlas: &mut TextureAtlas,
-        view_size: (f32, f32),
-        queue: &wgpu::Queue,
-        window: &Window,
-    ) -> Vec<Drawables> {
-        let parent_size = BoundingBox {
-            min: (0.0, 0.0),
-            max: (view_size.0, view_size.1),
+        let label = format!("Go to line (1-{}): ", self.editor().line_count());
+        let font_size = (self.font_size * 0.9).floor();
+        let Some(input) = &mut self.goto_line else {
+            return;
         };
 
-        let mut drawables = vec![];
+        let padding = font_size / 2.0;
+        let line_metrics = atlas.line_metrics(font_size);
+        let box_width = (font_size * 20.0).min(view_size.width());
+        let box_height = line_metrics.line_height() + padding * 2.0;
+        let x = view_size.center().0 - box_width / 2.0;
+        let top = view_size.min.1 + padding;
 
-        self.node(self.node_root)
-            .layout(self, atlas, parent_size, queue, window, &mut drawables);
+        drawables.push(Drawables::Rect(QuadInstance::filled(
+            [x, view_size.min.1],
+            [box_width, box_height],
+            &theme.popup_background,
+        )));
 
-        drawables
+        let (glyphs, width) = image_pipeline::layout_label(
+            (x + padding, top + line_metrics.baseline()),
+            x + box_width - padding,
+            atlas,
+            font_size,
+            &theme.foreground,
+            &label,
+        );
+        drawables.extend(glyphs);
+
+        let field = BoundingBox::new(
+            x + padding + width,
+            top,
+            x + box_width - padding,
+            top + line_metrics.line_height(),
+        );
+        input.layout(atlas, field, font_size, theme, true, drawables);
+    }
+
+    /// Draw the search box in the top right corner of the node.
+    fn layout_search(
+        &mut self,
+        atlas: &mut impl GlyphAtlas,
+        view_size: BoundingBox,
+        theme: &Theme,
+        drawables: &mut Vec<Drawables>,
+    ) {
+        let font_size = (self.font_size * 0.9).floor();
+        let selection = self.editor().selection_range();
+        let Some(search) = self.search.as_mut().filter(|search| search.open) else {
+            return;
+        };
+
+        let current = selection
+            .and_then(|selection| search.matches.iter().position(|m| *m == selection))
+            .map_or(0, |i| i + 1);
+        let status = if search.regex && !search.query.is_empty() && search.pattern().is_none() {
+            String::from("invalid regex")
+        } else {
+            format!("{}/{}", current, search.matches.len())
+        };
+
+        let line_metrics = atlas.line_metrics(font_size);
+        let line_height = line_metrics.line_height();
+        let padding = font_size / 2.0;
+        let box_width = (view_size.width() / 3.0).max(font_size * 12.0);
+        let rows = 1 + search.replacement.is_some() as usize;
+        let box_height = line_height * rows as f32 + padding * 2.0;
+        let x = (view_size.max.0 - box_width).max(view_size.min.0);
+        let right = view_size.max.0 - padding;
+
+        drawables.push(Drawables::Rect(QuadInstance::filled(
+            [x, view_size.min.1],
+            [box_width, box_height],
+            &theme.popup_background,
+        )));
+
+        // The match count goes at the right end of the query's row.
+        let status_width = image_pipeline::label_width(atlas, font_size, &status);
+        let status_x = (right - status_width).max(x + padding);
+        let (glyphs, _) = image_pipeline::layout_label(
+            (
+                status_x,
+                view_size.min.1 + padding + line_metrics.baseline(),
+            ),
+            right,
+            atlas,
+            font_size,
+            &theme.foreground,
+            &status,
+        );
+        drawables.extend(glyphs);
+
+        let editing_replacement = search.editing_replacement;
+        let find = if search.regex {
+            "Find regex: "
+        } else {
+            "Find: "
+        };
+        let fields = std::iter::once((
+            find,
+            &mut search.query,
+            !editing_replacement,
+            status_x - padding,
+        ))
+        .chain(
+            search
+                .replacement
+                .as_mut()
+                .map(|replacement| ("Replace: ", replacement, editing_replacement, right)),
+        );
+        for (i, (label, input, focused, max_x)) in fields.enumerate() {
+            let top = view_size.min.1 + padding + line_height * i as f32;
+            let (glyphs, width) = image_pipeline::layout_label(
+                (x + padding, top + line_metrics.baseline()),
+                max_x,
+                atlas,
+                font_size,
+                &theme.foreground,
+                label,
+            );
+            drawables.extend(glyphs);
+
+            let field = BoundingBox::new(x + padding + width, top, max_x, top + line_height);
+            input.layout(atlas, field, font_size, theme, focused, drawables);
+        }
+    }
+
+    /// Start searching, and also replacing if 'replace'. A selection on a single line
+    /// becomes the new query.
+    pub fn open_search(&mut self, replace: bool) {
+        let selected = self
+            .editor()
+            .selection_range()
+            .map(|range| self.editor().slice(range).to_string())
+            .filter(|text| !text.is_empty() && !text.contains('\n'));
+
+        let search = self.search.get_or_insert_with(Search::default);
+        search.open = true;
+        search.editing_replacement = false;
+        if let Some(selected) = selected {
+            search.query.line_mut().set_text(&selected);
+        }
+        if replace {
+            search.replacement.get_or_insert_with(|| {
+                TextInput::default().with_placeholder("Type a replacement…")
+            });
+        } else {
+            search.replacement = None;
+        }
+        self.update_matches();
+    }
+
+    /// Stop searching. Returns false if there was no search open.
+    pub fn close_search(&mut self) -> bool {
+        match &mut self.search {
+            Some(search) if search.open => {
+                search.open = false;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn is_searching(&self) -> bool {
+        self.search.as_ref().is_some_and(|search| search.open)
+    }
+
+    fn update_matches(&mut self) {
+        if let Some(search) = &self.search {
+            // Plain text is searched in the rope, without making a string of it.
+            let matches = if search.regex {
+                search
+                    .pattern()
+                    .map_or(vec![], |pattern| self.editor().find_regex(&pattern))
+            } else {
+                self.editor().find(&search.query.text())
+            };
+            self.search.as_mut().unwrap().matches = matches;
+        }
+    }
+
+    /// Switch between treating the query as plain text and as a regex.
+    pub fn toggle_search_regex(&mut self) {
+        if let Some(search) = &mut self.search {
+            search.regex = !search.regex;
+        }
+        self.update_matches();
+    }
+
+    /// Replace the selected match and select the next one, or replace every match
+    /// if 'all'.
+    pub fn replace(&mut self, all: bool, glyph_rasterizer: &mut impl text_editor::GlyphRasterizer) {
+        let Some(search) = &self.search else {
+            return;
+        };
+        let (Some(pattern), Some(replacement)) = (
+            search.pattern(),
+            search.replacement.as_ref().map(TextInput::text),
+        ) else {
+            return;
+        };
+
+        let editor = self.editor_mut();
+        if all {
+            editor.replace_all(&pattern, &replacement);
+        } else {
+            editor.replace_next(&pattern, &replacement);
+        }
+        editor.scroll_to_cursor(glyph_rasterizer);
+        self.update_matches();
+    }
+
+    /// Where an incremental search starts from: the start of the current selection,
+    /// so a growing query keeps matching at the same spot.
+    fn match_search_start(&self) -> usize {
+        self.editor()
+            .selection_range()
+            .map_or(self.editor().cursor_position(), |range| range.start)
+    }
+
+    /// Select the next match after the cursor, or the previous one before the
+    /// selection if 'reverse'. Wraps around at either end of the text.
+    pub fn jump_to_match(
+        &mut self,
+        reverse: bool,
+        glyph_rasterizer: &mut impl text_editor::GlyphRasterizer,
+    ) {
+        // The text may have changed since the search was closed.
+        if !self.is_searching() {
+            self.update_matches();
+        }
+
+        let from = if reverse {
+            self.match_search_start()
+        } else {
+            self.editor().cursor_position()
+        };
+        self.jump_to_match_from(from, reverse, glyph_rasterizer);
+    }
+
+    fn jump_to_match_from(
+        &mut self,
+        from: usize,
+        reverse: bool,
+        glyph_rasterizer: &mut impl text_editor::GlyphRasterizer,
+    ) {
+        let Some(search) = &self.search else {
+            return;
+        };
+
+        let found = if reverse {
+            search
+                .matches
+                .iter()
+                .rev()
+                .find(|m| m.start < from)
+                .or(search.matches.last())
+        } else {
+            search
+                .matches
+                .iter()
+                .find(|m| m.start >= from)
+                .or(search.matches.first())
+        };
+
+        if let Some(found) = found.cloned() {
+            let editor = self.editor_mut();
+            editor.select_range(found);
+            editor.scroll_to_cursor(glyph_rasterizer);
+        }
+    }
+
+    /// Send a key pressed while the search box is open to the field being edited.
+    /// Returns false if the key should be handled as usual instead. Any other key
+    /// closes the search.
+    fn search_keystroke(
+        &mut self,
+        event: &KeyInput,
+        modifiers: Modifiers,
+        glyph_rasterizer: &mut impl text_editor::GlyphRasterizer,
+    ) -> bool {
+        let Some(search) = &mut self.search else {
+            return false;
+        };
+
+        match &event.logical_key {
+            Key::Named(NamedKey::F3) => {
+                self.jump_to_match(modifiers.shift, glyph_rasterizer);
+                return true;
+            }
+            Key::Named(NamedKey::Tab) if !modifiers.ctrl => {
+                search.editing_replacement =
+                    search.replacement.is_some() && !search.editing_replacement;
+                return true;
+            }
+            Key::Character(c) if modifiers.ctrl && c.eq_ignore_ascii_case("r") => {
+                self.toggle_search_regex();
+                return true;
+            }
+            _ => {}
+        }
+
+        let editing_replacement = search.editing_replacement;
+        let input = match &mut search.replacement {
+            Some(replacement) if editing_replacement => replacement,
+            _ => &mut search.query,
+        };
+
+        match input.keystroke(event, modifiers) {
+            InputAction::Submitted if editing_replacement => {
+                self.replace(modifiers.ctrl, glyph_rasterizer)
+            }
+            InputAction::Submitted => self.jump_to_match(modifiers.shift, glyph_rasterizer),
+            // Changing the query jumps to the first match at or after the current one.
+            InputAction::Edited if !editing_replacement => {
+                self.update_matches();
+                self.jump_to_match_from(self.match_search_start(), false, glyph_rasterizer);
+            }
+            InputAction::Edited | InputAction::Handled => {}
+            InputAction::Cancelled => {
+                self.close_search();
+            }
+            InputAction::Ignored => {
+                if !matches!(
+                    event.logical_key,
+                    Key::Named(NamedKey::Shift | NamedKey::Control)
+                ) {
+                    self.close_search();
+                }
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Start entering a character by its hex codepoint.
+    pub fn start_unicode_entry(&mut self) {
+        self.unicode_entry = Some(String::new());
+    }
+
+    /// Stop entering a codepoint without inserting anything. Returns false if
+    /// there was no entry in progress.
+    pub fn cancel_unicode_entry(&mut self) -> bool {
+        self.unicode_entry.take().is_some()
+    }
+
+    /// Send a key pressed while entering a codepoint. Hex digits are collected and
+    /// Enter or Space inserts the character. Returns false if the key should be
+    /// handled as usual instead. Any other key cancels the entry.
+    fn unicode_keystroke(&mut self, event: &KeyInput) -> bool {
+        let Some(digits) = &mut self.unicode_entry else {
+            return false;
+        };
+
+        match &event.logical_key {
+            Key::Character(c) if c.chars().all(|c| c.is_ascii_hexdigit()) => {
+                if digits.len() + c.len() <= 6 {
+                    digits.push_str(c);
+                }
+            }
+            Key::Named(NamedKey::Backspace) => {
+                digits.pop();
+            }
+            Key::Named(NamedKey::Enter | NamedKey::Space) => {
+                let c = u32::from_str_radix(digits, 16)
+                    .ok()
+                    .and_then(char::from_u32);
+                self.unicode_entry = None;
+                if let Some(c) = c {
+                    self.add_char(c.encode_utf8(&mut [0; 4]));
+                }
+            }
+            Key::Named(NamedKey::Shift | NamedKey::Control) => return false,
+            _ => {
+                self.unicode_entry = None;
+                return false;
+            }
+        }
+
+        true
+    }
+
+    pub fn toggle_buffer_info(&mut self) {
+        self.buffer_info = !self.buffer_info;
+    }
+
+    /// Hide the buffer info overlay. Returns false if it wasn't shown.
+    pub fn close_buffer_info(&mut self) -> bool {
+        std::mem::take(&mut self.buffer_info)
+    }
+
+    pub fn start_goto_line(&mut self) {
+        let digits = LineInput::new()
+            .accepting(|c| c.is_ascii_digit())
+            .max_chars(9);
+        self.goto_line = Some(TextInput::new(digits));
+    }
+
+    /// Close the goto line box without moving. Returns false if it wasn't open.
+    pub fn cancel_goto_line(&mut self) -> bool {
+        self.goto_line.take().is_some()
+    }
+
+    /// Send a key pressed while the goto line box is open. Only digits can be typed,
+    /// and Enter moves the cursor to the line. Returns false if the key should be
+    /// handled as usual instead.
+    fn goto_line_keystroke(
+        &mut self,
+        event: &KeyInput,
+        modifiers: Modifiers,
+        glyph_rasterizer: &mut impl text_editor::GlyphRasterizer,
+    ) -> bool {
+        let Some(input) = &mut self.goto_line else {
+            return false;
+        };
+
+        match input.keystroke(event, modifiers) {
+            InputAction::Submitted => {
+                // Lines are numbered from 1 on screen.
+                let line = input.text().parse::<usize>().ok();
+                self.goto_line = None;
+                if let Some(line) = line {
+                    self.editor_mut()
+                        .go_to_line(line.saturating_sub(1), glyph_rasterizer);
+                }
+            }
+            InputAction::Cancelled => self.goto_line = None,
+            InputAction::Ignored
+                if matches!(
+                    event.logical_key,
+                    Key::Named(NamedKey::Shift | NamedKey::Control)
+                ) =>
+            {
+                return false
+            }
+            _ => {}
+        }
+
+        true
+    }
+
+    /// Called whenever the user does something with this node (typing, moving the
+    /// cursor, selecting, scrolling). Keeps the cursor solid and restarts its blink.
+    pub fn report_activity(&mut self) {
+        self.last_action = Instant::now();
+        self.last_cursor_blink = Instant::now();
+    }
+
+    pub fn set_cursor_blink(&mut self, enabled: bool) {
+        self.cursor_blink = enabled;
+    }
+
+    pub fn editor(&self) -> &TextEditor {
+        self.buffers.active()
+    }
+
+    /// Is nothing open here, just a single untitled buffer with no text in it?
+    fn is_blank(&self) -> bool {
+        self.buffers.len() == 1
+            && self.editor().path().is_none()
+            && self.editor().is_empty()
+            && self.preedit.is_none()
+            && self.unicode_entry.is_none()
+    }
+
+    pub fn editor_mut(&mut self) -> &mut TextEditor {
+        self.buffers.active_mut()
+    }
+
+    pub fn buffers(&self) -> &Buffers {
+        &self.buffers
+    }
+
+    pub fn next_buffer(&mut self) {
+        self.buffers.next();
+    }
+
+    pub fn prev_buffer(&mut self) {
+        self.buffers.prev();
+    }
+
+    /// Close the active buffer. Closing the last one leaves an empty buffer behind.
+    /// A buffer with unsaved changes is only closed when asked twice in a row, the
+    /// first time just warns about them. Returns false if it wasn't closed.
+    pub fn close_buffer(&mut self) -> bool {
+        if self.editor().is_modified() && !self.confirm_discard(Command::CloseBuffer) {
+            return false;
+        }
+
+        let (width, height) = self
+            .bounds
+            .map_or((1360.0, 720.0), |b| (b.width(), b.height()));
+        let mut empty = TextEditor::new("", width, height, self.font_size);
+        self.configure(&mut empty);
+        self.buffers.close_active(empty);
+        true
+    }
+
+    /// Read the active buffer's file again in the encoding after its current one,
+    /// and say which it was. Unsaved changes are only thrown away when asked twice.
+    fn reopen_with_next_encoding(&mut self) {
+        if self.editor().is_modified() && !self.confirm_discard(Command::ReopenWithEncoding) {
+            return;
+        }
+
+        let encoding = self.editor().encoding().next();
+        self.message = Some(match self.editor_mut().reopen_with_encoding(encoding) {
+            Ok(()) => (format!("Reopened as {}", encoding.name()), false),
+            Err(e) => {
+                log::error!("Failed to reopen file as {}: {e}", encoding.name());
+                (format!("Couldn't reopen as {}: {e}", encoding.name()), true)
+            }
+        });
+    }
+
+    /// Does any buffer here have changes that weren't saved?
+    fn has_unsaved_changes(&self) -> bool {
+        self.buffers.iter().any(TextEditor::is_modified)
+    }
+
+    /// May 'command' close unsaved changes? The first time it only warns about them,
+    /// running it again right after goes ahead.
+    fn confirm_discard(&mut self, command: Command) -> bool {
+        if self.closing_unsaved.take() == Some(command) {
+            return true;
+        }
+        self.closing_unsaved = Some(command);
+        false
+    }
+
+    /// Get the byte index of the text under the window position 'pos'. Positions
+    /// outside the node are clamped to its edges.
+    fn position_at(
+        &self,
+        pos: (f32, f32),
+        glyph_rasterizer: &mut impl text_editor::GlyphRasterizer,
+    ) -> Option<usize> {
+        let bounds = self.bounds?;
+        let x = pos.0.clamp(bounds.min.0, bounds.max.0) - bounds.min.0;
+        let y = pos.1.clamp(bounds.min.1, bounds.max.1 - 1.0) - bounds.min.1;
+
+        Some(self.editor().position_at_point(x, y, glyph_rasterizer))
+    }
+
+    /// Select the rectangle between the window positions 'from' and 'to', one
+    /// selection per line. Positions outside the node are clamped to its edges.
+    fn select_block(
+        &mut self,
+        from: (f32, f32),
+        to: (f32, f32),
+        glyph_rasterizer: &mut impl text_editor::GlyphRasterizer,
+    ) {
+        let Some(bounds) = self.bounds else {
+            return;
+        };
+        let local = |pos: (f32, f32)| {
+            (
+                pos.0.clamp(bounds.min.0, bounds.max.0) - bounds.min.0,
+                pos.1.clamp(bounds.min.1, bounds.max.1 - 1.0) - bounds.min.1,
+            )
+        };
+
+        self.editor_mut()
+            .select_block(local(from), local(to), glyph_rasterizer);
+    }
+
+    /// Get the visual line under the window position 'pos'.
+    fn line_at(
+        &self,
+        pos: (f32, f32),
+        glyph_rasterizer: &mut impl text_editor::GlyphRasterizer,
+    ) -> Option<VisualLine> {
+        let bounds = self.bounds?;
+        let y = pos.1.clamp(bounds.min.1, bounds.max.1 - 1.0) - bounds.min.1;
+
+        self.editor().visual_line_at_point(y, glyph_rasterizer)
+    }
+
+    pub fn backspace(&mut self) {
+        self.editor_mut().backspace();
+    }
+
+    pub fn delete(&mut self) {
+        self.editor_mut().delete();
+    }
+
+    /// Show 'text' being composed at the cursor, replacing what was there. Empty
+    /// text cancels the composition.
+    pub fn set_preedit(&mut self, text: &str) {
+        self.preedit = (!text.is_empty()).then(|| text.to_string());
+    }
+
+    /// Insert the text a composition ended with, in place of what was shown while
+    /// composing.
+    pub fn commit_preedit(&mut self, text: &str) {
+        self.preedit = None;
+        if !text.is_empty() {
+            self.add_char(text);
+        }
+    }
+
+    pub fn add_char(&mut self, c: &str) {
+        self.editor_mut().insert_text(c);
+    }
+
+    pub fn add_newline(&mut self) {
+        self.editor_mut().insert_newline();
+    }
+
+    pub fn add_opening_bracket(&mut self, open: char) {
+        self.editor_mut().insert_opening_bracket(open);
+    }
+
+    pub fn add_quote(&mut self, quote: char) {
+        self.editor_mut().insert_quote(quote);
+    }
+
+    pub fn add_closing_bracket(&mut self, close: char) {
+        self.editor_mut().insert_closing_bracket(close);
+    }
+
+    /// Show 'path', switching to its buffer if it is already open. Large files are
+    /// read on another thread and shown once they are loaded. Returns false if the
+    /// file couldn't be read.
+    pub fn open_path(&mut self, path: &Path) -> bool {
+        if self.buffers.switch_to_path(path)
+            || self
+                .loading
+                .iter()
+                .any(|(loader, ..)| loader.path() == path)
+        {
+            return true;
+        }
+
+        let large = std::fs::metadata(path).is_ok_and(|m| m.len() > self.large_file_bytes);
+        if large {
+            return match FileLoader::start(path) {
+                Ok(loader) => {
+                    self.loading.push((loader, None, Instant::now()));
+                    true
+                }
+                Err(e) => {
+                    log::error!("Failed to open {}: {e}", path.display());
+                    false
+                }
+            };
+        }
+
+        let (width, height) = self.editor_size();
+        match TextEditor::from_file(path, width, height, self.font_size) {
+            Ok(mut editor) => {
+                self.configure(&mut editor);
+                self.buffers.open(editor);
+                true
+            }
+            Err(e) => {
+                log::error!("Failed to open {}: {e}", path.display());
+                false
+            }
+        }
+    }
+
+    /// Open 'buffers' and put their cursors and views back, then show 'active'.
+    /// The empty buffer the node starts out with is closed if anything opened.
+    /// Files read on another thread open with the cursor at the start.
+    fn restore_session(&mut self, buffers: &[SessionBuffer], active: Option<&Path>) {
+        let blank = self.is_blank();
+        for buffer in buffers {
+            if !self.open_path(&buffer.path)
+                || self
+                    .loading
+                    .iter()
+                    .any(|(loader, ..)| loader.path() == buffer.path)
+            {
+                continue;
+            }
+            self.editor_mut().restore_view(buffer.cursor, buffer.scroll);
+        }
+
+        if blank && self.buffers.len() > 1 {
+            self.buffers.switch_to(0);
+            self.close_buffer();
+        }
+        if let Some(active) = active {
+            self.buffers.switch_to_path(active);
+        }
+    }
+
+    /// Show 'path' with the zero based 'line' in view, once it is open.
+    pub fn open_path_at_line(
+        &mut self,
+        path: &Path,
+        line: usize,
+        glyph_rasterizer: &mut impl text_editor::GlyphRasterizer,
+    ) -> bool {
+        if !self.open_path(path) {
+            return false;
+        }
+        match self
+            .loading
+            .iter_mut()
+            .find(|(loader, ..)| loader.path() == path)
+        {
+            Some((_, pending_line, _)) => *pending_line = Some(line),
+            None => self.editor_mut().go_to_line(line, glyph_rasterizer),
+        }
+        true
+    }
+
+    /// Open the files that finished loading. A file is only shown right away if
+    /// nothing was done here since it was asked for, otherwise it is opened behind
+    /// the active buffer so it doesn't take over what the user is typing in.
+    pub fn poll_loading(&mut self, glyph_rasterizer: &mut impl text_editor::GlyphRasterizer) {
+        if self
+            .opened_behind
+            .as_ref()
+            .is_some_and(|(_, active)| *active != self.buffers.active_index())
+        {
+            self.opened_behind = None;
+        }
+
+        let (width, height) = self.editor_size();
+        let mut i = 0;
+        while i < self.loading.len() {
+            let Some(result) = self.loading[i].0.poll() else {
+                i += 1;
+                continue;
+            };
+            let (loader, line, requested) = self.loading.remove(i);
+            match result {
+                Ok(file) => {
+                    let mut editor = TextEditor::from_loaded(file, width, height, self.font_size);
+                    self.configure(&mut editor);
+                    if let Some(line) = line {
+                        editor.go_to_line(line, glyph_rasterizer);
+                    }
+
+                    if self.last_action > requested {
+                        let name = loader.path().file_name().unwrap_or_default();
+                        let name = name.to_string_lossy().into_owned();
+                        self.opened_behind = Some((name, self.buffers.active_index()));
+                        self.buffers.open_behind(editor);
+                    } else {
+                        self.buffers.open(editor);
+                        self.report_activity();
+                    }
+                }
+                Err(e) => log::error!("Failed to open {}: {e}", loader.path().display()),
+            }
+        }
+    }
+
+    /// What is still being loaded, e.g. "Loading big.log 40%", or what was opened
+    /// behind the active buffer.
+    fn loading_status(&self) -> Option<String> {
+        let Some((loader, ..)) = self.loading.first() else {
+            let (name, _) = self.opened_behind.as_ref()?;
+            return Some(format!("Opened {name} in the next buffer"));
+        };
+        let name = loader.path().file_name().unwrap_or_default();
+        Some(format!(
+            "Loading {} {}%",
+            name.to_string_lossy(),
+            (loader.progress() * 100.0) as u32
+        ))
+    }
+
+    /// The size new buffers are laid out for.
+    fn editor_size(&self) -> (f32, f32) {
+        self.bounds
+            .map_or((1360.0, 720.0), |b| (b.width(), b.height()))
+    }
+
+    /// Set up a new buffer the way the others in this node are. Large buffers are
+    /// opened without highlighting and with simpler wrapping.
+    fn configure(&self, editor: &mut TextEditor) {
+        let large = editor.len() as u64 > self.large_file_bytes;
+        editor.set_wrap_policy(wrap_policy(self.wrap, large));
+        editor.set_tab_width(self.tab_width);
+        editor.set_insert_spaces(self.insert_spaces);
+        editor.follow_detected_indentation();
+        if large {
+            editor.disable_syntax();
+        }
+    }
+
+    /// Use 'tab_width' and 'insert_spaces' in every buffer whose own indentation
+    /// can't be detected, replacing what was toggled in any of them.
+    pub fn set_indentation(&mut self, tab_width: usize, insert_spaces: bool) {
+        self.tab_width = tab_width;
+        self.insert_spaces = insert_spaces;
+        for editor in self.buffers.iter_mut() {
+            editor.set_tab_width(tab_width);
+            editor.set_insert_spaces(insert_spaces);
+            editor.follow_detected_indentation();
+        }
+    }
+
+    /// Open buffers longer than 'bytes' without highlighting from now on.
+    pub fn set_large_file_bytes(&mut self, bytes: u64) {
+        self.large_file_bytes = bytes;
+    }
+
+    /// Break long lines in every buffer according to 'wrap'.
+    pub fn set_wrap(&mut self, wrap: Wrap) {
+        self.wrap = wrap;
+        for editor in self.buffers.iter_mut() {
+            let large = editor.len() as u64 > self.large_file_bytes;
+            editor.set_wrap_policy(wrap_policy(wrap, large));
+        }
+    }
+
+    /// Space lines 'spacing' times as far apart as the font asks for.
+    pub fn set_line_spacing(&mut self, spacing: f32) {
+        self.line_spacing = spacing;
+    }
+
+    pub fn increase_font_size(&mut self) {
+        self.font_size += 4.0;
+        self.buffers.active_mut().update_font_size(self.font_size);
+    }
+
+    pub fn decrease_font_size(&mut self) {
+        if self.font_size - 4.0 >= 1.0 {
+            self.font_size -= 4.0;
+            self.buffers.active_mut().update_font_size(self.font_size);
+        }
+    }
+
+    /// Scrolls the text viewport 'scroll_lines' at a time.
+    /// Scroll 'lines' lines in the direction of 'delta'. They are eased from 'now'
+    /// on by advance_scroll, or scrolled at once without a time to start from.
+    pub fn scroll_delta(
+        &mut self,
+        delta: MouseScrollDelta,
+        lines: usize,
+        now: Option<Duration>,
+        glyph_rasterizer: &mut impl text_editor::GlyphRasterizer,
+    ) {
+        let lines = match delta {
+            MouseScrollDelta::LineDelta(_, y) if y > 0.0 => -(lines as i32),
+            MouseScrollDelta::LineDelta(_, _) => lines as i32,
+            MouseScrollDelta::PixelDelta(_) => todo!(),
+        };
+
+        let Some(now) = now else {
+            self.scroll_lines(lines, glyph_rasterizer);
+            return;
+        };
+        let target = self.scroll_tween.target() + lines as f32;
+        self.scroll_tween
+            .retarget(target, now, SCROLL_ANIMATION, Easing::EaseOutCubic);
+    }
+
+    /// Scroll the lines an eased wheel scroll has reached by 'now'.
+    fn advance_scroll(
+        &mut self,
+        now: Duration,
+        glyph_rasterizer: &mut impl text_editor::GlyphRasterizer,
+    ) {
+        let reached = self.scroll_tween.value(now).round() as i32;
+        self.scroll_lines(reached - self.scrolled_lines, glyph_rasterizer);
+        self.scrolled_lines = reached;
+
+        if self.scroll_tween.is_finished(now) {
+            self.scroll_tween.snap(0.0);
+            self.scrolled_lines = 0;
+        }
+    }
+
+    /// Scroll down 'lines' visual lines, or up if it is negative.
+    fn scroll_lines(
+        &mut self,
+        lines: i32,
+        glyph_rasterizer: &mut impl text_editor::GlyphRasterizer,
+    ) {
+        let amount = match lines {
+            0 => return,
+            ..0 => ScrollAmount::Up {
+                lines: lines.unsigned_abs() as usize,
+            },
+            _ => ScrollAmount::Down {
+                lines: lines as usize,
+            },
+        };
+        self.editor_mut().scroll(amount, glyph_rasterizer);
+    }
+
+    pub fn scroll(
+        &mut self,
+        amount: ScrollAmount,
+        glyph_rasterizer: &mut impl text_editor::GlyphRasterizer,
+    ) {
+        self.editor_mut().scroll(amount, glyph_rasterizer);
+    }
+}
+
+/// A row of tabs naming the buffers open in a text node, drawn above it, and a
+/// status bar about the active buffer drawn below it.
+#[derive(Debug)]
+pub struct TabBar {
+    /// The text node whose buffers are listed. It fills the space between the bars.
+    child: UiNodeId,
+    font_size: f32,
+
+    /// Where each tab was last drawn, for finding the one under the pointer.
+    tabs: RefCell<Vec<BoundingBox>>,
+
+    /// Where the status bar item showing the indentation was last drawn, and the
+    /// choices of the menu it opens, if that's open.
+    indentation_item: RefCell<Option<BoundingBox>>,
+    indentation_choices: RefCell<Vec<(BoundingBox, IndentationChoice)>>,
+}
+
+/// Indent with spaces or tabs, and the tab width.
+type IndentationChoice = (bool, usize);
+
+/// What the indentation menu in the status bar offers.
+const INDENTATION_CHOICES: [IndentationChoice; 6] = [
+    (true, 2),
+    (true, 4),
+    (true, 8),
+    (false, 2),
+    (false, 4),
+    (false, 8),
+];
+
+fn indentation_label((insert_spaces, tab_width): IndentationChoice) -> String {
+    if insert_spaces {
+        format!("Spaces: {tab_width}")
+    } else {
+        format!("Tab width: {tab_width}")
+    }
+}
+
+/// How many lines of a buffer its tab preview shows.
+const TAB_PREVIEW_LINES: usize = 6;
+
+/// The tab under the pointer and when the pointer got there.
+#[derive(Debug, Clone, Copy)]
+struct TabHover {
+    /// The text node of the tab bar, which identifies the bar.
+    text: UiNodeId,
+    tab: usize,
+    since: Duration,
+}
+
+impl TabBar {
+    fn layout(
+        &self,
+        scene: &Scene,
+        atlas: &mut impl GlyphAtlas,
+        view_size: BoundingBox,
+        drawables: &mut Vec<Drawables>,
+    ) {
+        let bar_height = (self.font_size * 1.8).floor();
+        let padding = self.font_size;
+
+        drawables.push(Drawables::Rect(QuadInstance::filled(
+            [view_size.min.0, view_size.min.1],
+            [view_size.width(), bar_height],
+            &scene.theme.widget_background,
+        )));
+
+        let child = scene.node(self.child);
+        if let Ui::Text(td) = child.as_ref() {
+            let td = td.borrow();
+            let buffers = td.buffers();
+
+            let baseline_y = view_size.min.1 + (bar_height + self.font_size * 0.7) / 2.0;
+            let mut x = view_size.min.0;
+            let mut tabs = self.tabs.borrow_mut();
+            tabs.clear();
+            for (i, editor) in buffers.iter().enumerate() {
+                if x >= view_size.max.0 {
+                    break;
+                }
+
+                let name = editor
+                    .path()
+                    .and_then(|p| p.file_name())
+                    .map_or("untitled".into(), |n| n.to_string_lossy());
+
+                let (label, width) = image_pipeline::layout_label(
+                    (x + padding, baseline_y),
+                    view_size.max.0,
+                    atlas,
+                    self.font_size,
+                    &scene.theme.widget_foreground,
+                    &name,
+                );
+
+                if i == buffers.active_index() {
+                    drawables.push(Drawables::Rect(QuadInstance::filled(
+                        [x, view_size.min.1],
+                        [width + padding * 2.0, bar_height],
+                        &scene.theme.widget_active,
+                    )));
+                }
+                drawables.extend(label);
+
+                tabs.push(BoundingBox::new(
+                    x,
+                    view_size.min.1,
+                    x + width + padding * 2.0,
+                    view_size.min.1 + bar_height,
+                ));
+                x += width + padding * 2.0;
+            }
+        }
+
+        let status_y = view_size.max.1 - bar_height;
+        if let Ui::Text(td) = child.as_ref() {
+            let status_size =
+                BoundingBox::new(view_size.min.0, status_y, view_size.max.0, view_size.max.1);
+            self.layout_status(scene, &td.borrow(), atlas, status_size, drawables);
+        }
+
+        let child_size = BoundingBox::new(
+            view_size.min.0,
+            view_size.min.1 + bar_height,
+            view_size.max.0,
+            status_y,
+        );
+        child.layout(scene, atlas, child_size, drawables);
+        self.layout_preview(scene, atlas, view_size, drawables);
+        self.layout_indentation_menu(scene, atlas, drawables);
+    }
+
+    /// The tab under 'pos', if any.
+    fn tab_at(&self, pos: (f32, f32)) -> Option<usize> {
+        self.tabs.borrow().iter().position(|tab| tab.inside(pos))
+    }
+
+    fn on_indentation_item(&self, pos: (f32, f32)) -> bool {
+        self.indentation_item
+            .borrow()
+            .is_some_and(|item| item.inside(pos))
+    }
+
+    /// The indentation menu choice under 'pos', if the menu is open.
+    fn indentation_choice_at(&self, pos: (f32, f32)) -> Option<IndentationChoice> {
+        self.indentation_choices
+            .borrow()
+            .iter()
+            .find(|(bounds, _)| bounds.inside(pos))
+            .map(|(_, choice)| *choice)
+    }
+
+    /// The choices of indentation for the active buffer, listed above the status bar
+    /// item showing it. The one in use is highlighted.
+    fn layout_indentation_menu(
+        &self,
+        scene: &Scene,
+        atlas: &mut impl GlyphAtlas,
+        drawables: &mut Vec<Drawables>,
+    ) {
+        let mut choices = self.indentation_choices.borrow_mut();
+        choices.clear();
+        let (Some(item), true) = (
+            *self.indentation_item.borrow(),
+            scene.indentation_menu == Some(self.child),
+        ) else {
+            return;
+        };
+        let child = scene.node(self.child);
+        let Ui::Text(td) = child.as_ref() else {
+            return;
+        };
+        let current = {
+            let td = td.borrow();
+            (td.editor().insert_spaces(), td.editor().tab_width())
+        };
+
+        let padding = self.font_size / 2.0;
+        let row_height = (self.font_size * 1.5).floor();
+        let width = self.font_size * 8.0;
+        let top = item.min.1 - row_height * INDENTATION_CHOICES.len() as f32;
+        drawables.push(Drawables::Rect(QuadInstance::filled(
+            [item.min.0, top],
+            [width, item.min.1 - top],
+            &scene.theme.popup_background,
+        )));
+
+        for (i, choice) in INDENTATION_CHOICES.into_iter().enumerate() {
+            let y = top + row_height * i as f32;
+            let bounds = BoundingBox::new(item.min.0, y, item.min.0 + width, y + row_height);
+            if choice == current {
+                drawables.push(Drawables::Rect(QuadInstance::filled(
+                    [bounds.min.0, bounds.min.1],
+                    [bounds.width(), bounds.height()],
+                    &scene.theme.widget_active,
+                )));
+            }
+
+            let baseline_y = y + (row_height + self.font_size * 0.7) / 2.0;
+            let (label, _) = image_pipeline::layout_label(
+                (bounds.min.0 + padding, baseline_y),
+                bounds.max.0,
+                atlas,
+                self.font_size,
+                &scene.theme.foreground,
+                &indentation_label(choice),
+            );
+            drawables.extend(label);
+            choices.push((bounds, choice));
+        }
+    }
+
+    /// A tooltip under the hovered tab with the full path and first visible lines
+    /// of its buffer, once the pointer has rested there for the configured delay.
+    fn layout_preview(
+        &self,
+        scene: &Scene,
+        atlas: &mut impl GlyphAtlas,
+        view_size: BoundingBox,
+        drawables: &mut Vec<Drawables>,
+    ) {
+        let Some(hover) = scene.tab_hover.filter(|h| h.text == self.child) else {
+            return;
+        };
+        let delay = Duration::from_millis(scene.settings.tab_preview_delay_ms);
+        if scene.animation_clock.now().saturating_sub(hover.since) < delay {
+            return;
+        }
+
+        let child = scene.node(self.child);
+        let Ui::Text(td) = child.as_ref() else {
+            return;
+        };
+        let td = td.borrow();
+        let (Some(editor), Some(tab)) = (
+            td.buffers().iter().nth(hover.tab),
+            self.tabs.borrow().get(hover.tab).copied(),
+        ) else {
+            return;
+        };
+
+        let title = editor
+            .path()
+            .map_or("untitled".into(), |p| p.to_string_lossy());
+        let start = editor.text_start_idx();
+        let text = editor.slice(start..editor.len());
+        let lines = text.lines().take(TAB_PREVIEW_LINES);
+
+        let font_size = (self.font_size * 0.9).floor();
+        let padding = font_size / 2.0;
+        let line_metrics = atlas.line_metrics(font_size);
+        let line_height = line_metrics.line_height();
+        let box_width = (font_size * 30.0).min(view_size.width());
+        let box_height = line_height * (TAB_PREVIEW_LINES + 1) as f32 + padding * 2.0;
+        let x = tab.min.0.min(view_size.max.0 - box_width);
+        let y = tab.max.1;
+
+        drawables.push(Drawables::Rect(QuadInstance::filled(
+            [x, y],
+            [box_width, box_height],
+            &scene.theme.popup_background,
+        )));
+
+        let rows = std::iter::once((title.to_string(), &scene.theme.widget_foreground))
+            .chain(lines.map(|line| (line.to_string(), &scene.theme.foreground)));
+        for (i, (row, color)) in rows.enumerate() {
+            let (glyphs, _) = image_pipeline::layout_label(
+                (
+                    x + padding,
+                    y + padding + line_metrics.baseline() + line_height * i as f32,
+                ),
+                x + box_width - padding,
+                atlas,
+                font_size,
+                color,
+                &row,
+            );
+            drawables.extend(glyphs);
+        }
+    }
+
+    /// The status bar: the active buffer's line endings, indentation and encoding
+    /// if that isn't UTF-8, whether it ends the way the settings say it should and
+    /// whether it is too large to highlight. Then files still loading, and whether
+    /// the system clipboard is unavailable.
+    fn layout_status(
+        &self,
+        scene: &Scene,
+        td: &Text,
+        atlas: &mut impl GlyphAtlas,
+        view_size: BoundingBox,
+        drawables: &mut Vec<Drawables>,
+    ) {
+        let editor = td.editor();
+        drawables.push(Drawables::Rect(QuadInstance::filled(
+            [view_size.min.0, view_size.min.1],
+            [view_size.width(), view_size.height()],
+            &scene.theme.widget_background,
+        )));
+
+        // Each item is its text and whether it is a warning.
+        let line_endings = editor.line_endings();
+        let mut items = vec![];
+        if line_endings.is_mixed() {
+            let label = format!(
+                "Mixed line endings ({} LF, {} CRLF)",
+                line_endings.lf, line_endings.crlf
+            );
+            items.push((label, true));
+        } else if line_endings.crlf > 0 {
+            items.push(("CRLF".to_string(), false));
+        } else {
+            items.push(("LF".to_string(), false));
+        }
+
+        let indentation_index = items.len();
+        items.push((
+            indentation_label((editor.insert_spaces(), editor.tab_width())),
+            false,
+        ));
+
+        if editor.encoding() != Encoding::Utf8 {
+            items.push((editor.encoding().name().to_string(), false));
+        }
+
+        let mismatch = line_endings.trailing != 1 && !editor.is_empty();
+        if scene.settings.ensure_trailing_newline && mismatch {
+            let label = match line_endings.trailing {
+                0 => "No newline at end of file".to_string(),
+                n => format!("{n} newlines at end of file"),
+            };
+            items.push((label, true));
+        }
+
+        if editor.syntax_disabled() {
+            items.push(("Large file, highlighting is off".to_string(), true));
+        }
+        if let Some(loading) = td.loading_status() {
+            items.push((loading, false));
+        }
+        if let Some(message) = &td.message {
+            items.push(message.clone());
+        }
+        let discarding = match td.closing_unsaved {
+            Some(Command::ReopenWithEncoding) => Some("reopen"),
+            Some(_) => Some("close"),
+            None => scene.quitting_unsaved.then_some("close"),
+        };
+        if let Some(discarding) = discarding {
+            items.push((
+                format!("Unsaved changes, {discarding} again to discard them"),
+                true,
+            ));
+        }
+
+        if scene.clipboard.is_internal() {
+            items.push(("Copying within the editor only".to_string(), true));
+        }
+        if let Some(notice) = &scene.notice {
+            items.push((notice.clone(), true));
+        }
+
+        let padding = self.font_size;
+        let icon_size = self.font_size;
+        let baseline_y = view_size.min.1 + (view_size.height() + self.font_size * 0.7) / 2.0;
+        let mut x = view_size.min.0 + padding;
+        for (i, (item, warning)) in items.into_iter().enumerate() {
+            if warning {
+                let icon_y = view_size.min.1 + (view_size.height() - icon_size) / 2.0;
+                drawables.extend(image_pipeline::layout_icon(
+                    (x, icon_y),
+                    icon_size,
+                    atlas,
+                    &scene.theme.widget_foreground,
+                    Icon::Warning,
+                ));
+                x += icon_size + padding / 2.0;
+            }
+
+            let (label, width) = image_pipeline::layout_label(
+                (x, baseline_y),
+                view_size.max.0,
+                atlas,
+                self.font_size,
+                &scene.theme.widget_foreground,
+                &item,
+            );
+            drawables.extend(label);
+            if i == indentation_index {
+                *self.indentation_item.borrow_mut() = Some(BoundingBox::new(
+                    x - padding,
+                    view_size.min.1,
+                    x + width + padding,
+                    view_size.max.1,
+                ));
+            }
+            x += width + padding * 2.0;
+        }
+    }
+}
+
+/// The results of searching every file in the project, drawn as a panel below the
+/// editors. Picking a result opens its file at the matching line. With a
+/// replacement the results become a preview of it, grouped by file, where each
+/// match can be checked or unchecked before replacing.
+pub struct FindInFiles {
+    /// The directory that is searched.
+    root: PathBuf,
+    query: TextInput,
+
+    /// What the matches are replaced with. The results are a preview while it is
+    /// Some.
+    replacement: Option<TextInput>,
+
+    /// Are keys typed into the replacement rather than the query?
+    editing_replacement: bool,
+
+    /// The running or finished search, and the query it was started with.
+    search: Option<(String, ProjectSearch)>,
+
+    /// One row per search hit in the order they were found, or in the preview a
+    /// row for each file followed by a row for each of its matches.
+    results: VirtualList,
+
+    /// What each row of 'results' is.
+    rows: Vec<ResultRow>,
+
+    /// The matches left out of the replacement, as indices of the hit and of the
+    /// match on its line. Everything found is checked to begin with.
+    unchecked: HashSet<(usize, usize)>,
+
+    /// What the last replacement did, shown until the next search.
+    replaced: Option<String>,
+
+    open: bool,
+
+    /// The text node that was focused before the panel, results are opened there.
+    return_focus: Option<UiNodeId>,
+
+    bounds: Option<BoundingBox>,
+    font_size: f32,
+}
+
+/// A row of the find in files results.
+#[derive(Debug, Clone, Copy)]
+enum ResultRow {
+    /// A search hit, by index.
+    Hit(usize),
+    /// A file in the preview, and the range of hits in it.
+    File(usize, usize),
+    /// A match in the preview: the index of its hit and which match on the line.
+    Match(usize, usize),
+}
+
+/// A match to replace: the zero based line, the line as it was when searched, and
+/// the byte range of the match in it.
+struct LineMatch {
+    line: usize,
+    text: String,
+    range: Range<usize>,
+}
+
+impl FindInFiles {
+    fn layout(
+        &mut self,
+        atlas: &mut impl GlyphAtlas,
+        view_size: BoundingBox,
+        theme: &Theme,
+        drawables: &mut Vec<Drawables>,
+    ) {
+        self.bounds = Some(view_size);
+
+        drawables.push(Drawables::Rect(QuadInstance::filled(
+            [view_size.min.0, view_size.min.1],
+            [view_size.width(), view_size.height()],
+            &theme.widget_background,
+        )));
+
+        let row_height = (self.font_size * 1.5).floor();
+        let padding = self.font_size / 2.0;
+
+        let status = match (&self.search, &self.replaced) {
+            (_, Some(replaced)) => format!("({replaced})"),
+            (Some((_, search)), _) if !search.is_done() => {
+                format!("({} results, searching...)", search.hits().len())
+            }
+            (Some((_, search)), _) => format!("({} results)", search.hits().len()),
+            (None, None) => String::new(),
+        };
+        let replace_status = self.replacement.as_ref().map(|_| {
+            let (checked, total) = self.checked_count();
+            format!("({checked} of {total} matches checked, ctrl+Enter replaces them)")
+        });
+
+        // Each header row is a label, the field, and its status at the right end.
+        let editing_replacement = self.editing_replacement;
+        let fields = std::iter::once((
+            "Find in files: ",
+            &mut self.query,
+            !editing_replacement,
+            status,
+        ))
+        .chain(self.replacement.as_mut().zip(replace_status).map(
+            |(replacement, status)| ("Replace with: ", replacement, editing_replacement, status),
+        ));
+        let right = view_size.max.0 - padding;
+        let mut header_rows = 0;
+        for (i, (label, input, focused, status)) in fields.enumerate() {
+            let top = view_size.min.1 + row_height * i as f32;
+            let baseline = top + (row_height + self.font_size * 0.7) / 2.0;
+            let (glyphs, width) = image_pipeline::layout_label(
+                (view_size.min.0 + padding, baseline),
+                right,
+                atlas,
+                self.font_size,
+                &theme.widget_foreground,
+                label,
+            );
+            drawables.extend(glyphs);
+
+            let field_x = view_size.min.0 + padding + width;
+            let status_width = image_pipeline::label_width(atlas, self.font_size, &status);
+            let status_x = (right - status_width).max(field_x);
+            let (glyphs, _) = image_pipeline::layout_label(
+                (status_x, baseline),
+                right,
+                atlas,
+                self.font_size,
+                &theme.widget_foreground,
+                &status,
+            );
+            drawables.extend(glyphs);
+
+            let field_top = baseline - atlas.line_metrics(self.font_size).baseline();
+            let field = BoundingBox::new(field_x, field_top, status_x - padding, top + row_height);
+            input.layout(atlas, field, self.font_size, theme, focused, drawables);
+            header_rows += 1;
+        }
+
+        let list_size = BoundingBox::new(
+            view_size.min.0,
+            (view_size.min.1 + row_height * header_rows as f32).min(view_size.max.1),
+            view_size.max.0,
+            view_size.max.1,
+        );
+        self.results.layout(atlas, list_size, theme, drawables);
+    }
+
+    /// Collect new hits from the running search and add rows for them.
+    fn poll(&mut self) {
+        let Some((_, search)) = &mut self.search else {
+            return;
+        };
+        search.poll();
+        if search.hits().len() == self.hit_count() {
+            return;
+        }
+
+        // The last file in the preview may have more hits now, so only its rows are
+        // made again.
+        let last_file = self
+            .rows
+            .iter()
+            .rposition(|row| matches!(row, ResultRow::File(..)));
+        let (kept, start) = match last_file.map(|row| (row, self.rows[row])) {
+            Some((row, ResultRow::File(start, _))) => (row, start),
+            _ => (self.rows.len(), self.hit_count()),
+        };
+
+        let (rows, labels) = self.rows_from(start);
+        self.rows.truncate(kept);
+        self.rows.extend(rows);
+        self.results.truncate(kept);
+        self.results.extend(labels);
+    }
+
+    /// How many hits the rows are for.
+    fn hit_count(&self) -> usize {
+        match self.rows.last() {
+            Some(ResultRow::Hit(hit) | ResultRow::Match(hit, _)) => hit + 1,
+            Some(ResultRow::File(_, end)) => *end,
+            None => 0,
+        }
+    }
+
+    /// Lay out every row again, as a list of hits or as the replacement preview.
+    fn update_rows(&mut self) {
+        let (rows, labels) = self.rows_from(0);
+        self.rows = rows;
+        self.results.update_items(labels);
+    }
+
+    /// The rows for the hits from 'start' on, and their labels. In the preview,
+    /// 'start' has to be the first hit in its file.
+    fn rows_from(&self, start: usize) -> (Vec<ResultRow>, Vec<String>) {
+        let hits = self.search.as_ref().map_or(&[][..], |(_, s)| s.hits());
+        let mut rows = vec![];
+        let mut labels = vec![];
+
+        let Some(replacement) = self.replacement.as_ref().map(TextInput::text) else {
+            for (i, hit) in hits.iter().enumerate().skip(start) {
+                let path = hit.path.strip_prefix(&self.root).unwrap_or(&hit.path);
+                labels.push(format!(
+                    "{}:{}: {}",
+                    path.display(),
+                    hit.line + 1,
+                    hit.text.trim_start().replace('\t', "    ")
+                ));
+                rows.push(ResultRow::Hit(i));
+            }
+            return (rows, labels);
+        };
+
+        // A file's hits arrive one after another, so each run of hits with the same
+        // path is a file.
+        let mut start = start;
+        while start < hits.len() {
+            let path = &hits[start].path;
+            let end = start + hits[start..].iter().take_while(|h| h.path == *path).count();
+
+            let matches: Vec<_> = (start..end)
+                .flat_map(|hit| (0..hits[hit].matches.len()).map(move |m| (hit, m)))
+                .collect();
+            let checked = matches
+                .iter()
+                .filter(|m| !self.unchecked.contains(m))
+                .count();
+            let shown_path = path.strip_prefix(&self.root).unwrap_or(path);
+            let plural = if matches.len() == 1 { "" } else { "es" };
+            labels.push(format!(
+                "{} {} ({} match{plural})",
+                checkbox(checked, matches.len()),
+                shown_path.display(),
+                matches.len()
+            ));
+            rows.push(ResultRow::File(start, end));
+
+            for (hit, m) in matches {
+                let text = &hits[hit].text;
+                let range = hits[hit].matches[m].clone();
+                let checked = !self.unchecked.contains(&(hit, m));
+                let preview = if checked {
+                    format!(
+                        "{}{replacement}{}",
+                        &text[..range.start],
+                        &text[range.end..]
+                    )
+                } else {
+                    text.clone()
+                };
+                labels.push(format!(
+                    "    {} {}: {}",
+                    checkbox(checked as usize, 1),
+                    hits[hit].line + 1,
+                    preview.trim_start().replace('\t', "    ")
+                ));
+                rows.push(ResultRow::Match(hit, m));
+            }
+
+            start = end;
+        }
+
+        (rows, labels)
+    }
+
+    /// Show or hide the replacement, which turns the results into a preview of it.
+    fn toggle_replacement(&mut self) {
+        if self.replacement.take().is_none() {
+            self.replacement = Some(TextInput::default());
+        }
+        self.editing_replacement = self.replacement.is_some();
+        self.update_rows();
+    }
+
+    /// Check or uncheck the selected match in the preview. On a file, every match
+    /// in it is unchecked if all were checked, and checked otherwise.
+    fn toggle_selected(&mut self) {
+        let Some(hits) = self.search.as_ref().map(|(_, s)| s.hits()) else {
+            return;
+        };
+        let Some(row) = self.results.selected().map(|i| self.rows[i]) else {
+            return;
+        };
+
+        match row {
+            ResultRow::Hit(_) => return,
+            ResultRow::File(start, end) => {
+                let matches: Vec<_> = (start..end)
+                    .flat_map(|hit| (0..hits[hit].matches.len()).map(move |m| (hit, m)))
+                    .collect();
+                if matches.iter().all(|m| !self.unchecked.contains(m)) {
+                    self.unchecked.extend(matches);
+                } else {
+                    for m in matches {
+                        self.unchecked.remove(&m);
+                    }
+                }
+            }
+            ResultRow::Match(hit, m) => {
+                if !self.unchecked.remove(&(hit, m)) {
+                    self.unchecked.insert((hit, m));
+                }
+            }
+        }
+        self.update_rows();
+    }
+
+    /// How many matches are checked, and how many were found.
+    fn checked_count(&self) -> (usize, usize) {
+        let hits = self.search.as_ref().map_or(&[][..], |(_, s)| s.hits());
+        let total = hits.iter().map(|hit| hit.matches.len()).sum::<usize>();
+        (total - self.unchecked.len(), total)
+    }
+
+    /// The checked matches, grouped by file in the order they were found.
+    fn checked_matches(&self) -> Vec<(PathBuf, Vec<LineMatch>)> {
+        let hits = self.search.as_ref().map_or(&[][..], |(_, s)| s.hits());
+        let mut files: Vec<(PathBuf, Vec<LineMatch>)> = vec![];
+        for (i, hit) in hits.iter().enumerate() {
+            let matches = hit
+                .matches
+                .iter()
+                .enumerate()
+                .filter(|(m, _)| !self.unchecked.contains(&(i, *m)))
+                .map(|(_, range)| LineMatch {
+                    line: hit.line,
+                    text: hit.text.clone(),
+                    range: range.clone(),
+                });
+
+            match files.last_mut() {
+                Some((path, file)) if *path == hit.path => file.extend(matches),
+                _ => files.push((hit.path.clone(), matches.collect())),
+            }
+        }
+
+        files.retain(|(_, matches)| !matches.is_empty());
+        files
+    }
+
+    /// Search for the query, unless the results shown are already for it.
+    /// Returns false if nothing was started.
+    fn start_search(&mut self) -> bool {
+        let query = self.query.text();
+        if query.is_empty() || self.search.as_ref().is_some_and(|(q, _)| *q == query) {
+            return false;
+        }
+
+        let pattern = Regex::new(&regex::escape(&query)).unwrap();
+        self.search = Some((query, ProjectSearch::start(&self.root, pattern)));
+        self.unchecked.clear();
+        self.replaced = None;
+        self.rows.clear();
+        self.results.clear();
+        true
+    }
+
+    /// Drop the results after replacing, leaving 'summary' in their place.
+    fn finish_replacing(&mut self, summary: String) {
+        self.search = None;
+        self.unchecked.clear();
+        self.replaced = Some(summary);
+        self.rows.clear();
+        self.results.clear();
+    }
+
+    fn selected_hit(&self) -> Option<&SearchHit> {
+        let (_, search) = self.search.as_ref()?;
+        let hit = match self.rows.get(self.results.selected()?)? {
+            ResultRow::Hit(hit) | ResultRow::File(hit, _) | ResultRow::Match(hit, _) => *hit,
+        };
+        search.hits().get(hit)
+    }
+}
+
+/// A checkbox for a row with 'checked' of 'total' matches checked.
+fn checkbox(checked: usize, total: usize) -> &'static str {
+    if checked == total {
+        "[x]"
+    } else if checked == 0 {
+        "[ ]"
+    } else {
+        "[-]"
+    }
+}
+
+/// Every problem in the open buffers, grouped by file, drawn as a panel below the
+/// editors. Picking one jumps to it.
+pub struct Problems {
+    /// A row for each file with problems, followed by a row for each of its problems.
+    list: VirtualList,
+
+    /// Where each row of 'list' jumps to. A file's row jumps to its first problem.
+    targets: Vec<ProblemTarget>,
+
+    /// How many errors and warnings there are in total.
+    errors: usize,
+    warnings: usize,
+
+    /// Every buffer the problems were collected from: its text node, index, syntax
+    /// version and path. They are only collected again when one of these changes.
+    sources: Vec<(UiNodeId, usize, u64, Option<PathBuf>)>,
+
+    open: bool,
+
+    /// The text node that was focused before the panel.
+    return_focus: Option<UiNodeId>,
+
+    bounds: Option<BoundingBox>,
+    font_size: f32,
+}
+
+/// The problems in one buffer, and where it is.
+struct FileProblems {
+    text: UiNodeId,
+    buffer: usize,
+    name: String,
+
+    /// Each diagnostic with the zero based line and column it starts at.
+    diagnostics: Vec<(Diagnostic, (usize, usize))>,
+}
+
+/// A problem's place: the text node, the index of the buffer in it, and the byte.
+#[derive(Debug, Clone, Copy)]
+struct ProblemTarget {
+    text: UiNodeId,
+    buffer: usize,
+    byte_idx: usize,
+}
+
+impl Problems {
+    fn layout(
+        &mut self,
+        atlas: &mut impl GlyphAtlas,
+        view_size: BoundingBox,
+        theme: &Theme,
+        drawables: &mut Vec<Drawables>,
+    ) {
+        self.bounds = Some(view_size);
+
+        drawables.push(Drawables::Rect(QuadInstance::filled(
+            [view_size.min.0, view_size.min.1],
+            [view_size.width(), view_size.height()],
+            &theme.widget_background,
+        )));
+
+        let header_height = (self.font_size * 1.5).floor();
+        let padding = self.font_size / 2.0;
+        let baseline_y = view_size.min.1 + (header_height + self.font_size * 0.7) / 2.0;
+        let icon_size = self.font_size;
+        let icon_y = view_size.min.1 + (header_height - icon_size) / 2.0;
+
+        let (title, width) = image_pipeline::layout_label(
+            (view_size.min.0 + padding, baseline_y),
+            view_size.max.0 - padding,
+            atlas,
+            self.font_size,
+            &theme.widget_foreground,
+            "Problems",
+        );
+        drawables.extend(title);
+
+        // The totals, each next to its icon and in its color.
+        let mut x = view_size.min.0 + padding * 3.0 + width;
+        let counts = [
+            (Icon::Error, &theme.error, self.errors, "error"),
+            (Icon::Warning, &theme.warning, self.warnings, "warning"),
+        ];
+        for (icon, color, count, noun) in counts {
+            drawables.extend(image_pipeline::layout_icon(
+                (x, icon_y),
+                icon_size,
+                atlas,
+                color,
+                icon,
+            ));
+            x += icon_size + padding / 2.0;
+
+            let plural = if count == 1 { "" } else { "s" };
+            let (label, width) = image_pipeline::layout_label(
+                (x, baseline_y),
+                view_size.max.0 - padding,
+                atlas,
+                self.font_size,
+                color,
+                &format!("{count} {noun}{plural}"),
+            );
+            drawables.extend(label);
+            x += width + padding * 2.0;
+        }
+
+        let list_size = BoundingBox::new(
+            view_size.min.0,
+            view_size.min.1 + header_height,
+            view_size.max.0,
+            view_size.max.1,
+        );
+        self.list.layout(atlas, list_size, theme, drawables);
+    }
+
+    /// Show the problems of 'files'. The selection is kept unless the rows changed.
+    fn set_problems(&mut self, files: Vec<FileProblems>) {
+        let mut rows = vec![];
+        let mut targets = vec![];
+        self.errors = 0;
+        self.warnings = 0;
+
+        for file in files {
+            let Some((first, _)) = file.diagnostics.first() else {
+                continue;
+            };
+            rows.push(format!("{} ({})", file.name, file.diagnostics.len()));
+            targets.push(ProblemTarget {
+                text: file.text,
+                buffer: file.buffer,
+                byte_idx: first.range.start,
+            });
+
+            for (diagnostic, (line, column)) in file.diagnostics {
+                let severity = match diagnostic.severity {
+                    Severity::Error => {
+                        self.errors += 1;
+                        "error"
+                    }
+                    Severity::Warning => {
+                        self.warnings += 1;
+                        "warning"
+                    }
+                };
+                rows.push(format!(
+                    "    {}:{} {severity}: {}",
+                    line + 1,
+                    column + 1,
+                    diagnostic.message
+                ));
+                targets.push(ProblemTarget {
+                    text: file.text,
+                    buffer: file.buffer,
+                    byte_idx: diagnostic.range.start,
+                });
+            }
+        }
+
+        self.targets = targets;
+        if self.list.items().ne(&rows) {
+            self.list.set_items(rows);
+        }
+    }
+
+    fn selected_target(&self) -> Option<ProblemTarget> {
+        self.targets.get(self.list.selected()?).copied()
+    }
+}
+
+/// Every bound key, drawn over the editors. Typing filters the list.
+pub struct Help {
+    /// A row for each command with its category and the chords that run it.
+    list: VirtualList,
+    open: bool,
+
+    /// The text node that was focused before the help.
+    return_focus: Option<UiNodeId>,
+
+    font_size: f32,
+}
+
+impl Help {
+    fn layout(
+        &mut self,
+        atlas: &mut impl GlyphAtlas,
+        view_size: BoundingBox,
+        theme: &Theme,
+        drawables: &mut Vec<Drawables>,
+    ) {
+        drawables.push(Drawables::Rect(QuadInstance::filled(
+            [view_size.min.0, view_size.min.1],
+            [view_size.width(), view_size.height()],
+            &theme.widget_background,
+        )));
+
+        let header_height = (self.font_size * 1.5).floor();
+        let padding = self.font_size / 2.0;
+        let (glyphs, _) = image_pipeline::layout_label(
+            (
+                view_size.min.0 + padding,
+                view_size.min.1 + (header_height + self.font_size * 0.7) / 2.0,
+            ),
+            view_size.max.0 - padding,
+            atlas,
+            self.font_size,
+            &theme.widget_foreground,
+            &format!("Keyboard shortcuts: {}|", self.list.filter()),
+        );
+        drawables.extend(glyphs);
+
+        let list_size = BoundingBox::new(
+            view_size.min.0,
+            view_size.min.1 + header_height,
+            view_size.max.0,
+            view_size.max.1,
+        );
+        self.list.layout(atlas, list_size, theme, drawables);
+    }
+
+    /// List what 'keymap' binds, grouped by category. Chords running the same
+    /// command share its row.
+    fn set_keymap(&mut self, keymap: &Keymap) {
+        let mut chords: Vec<(Command, Vec<String>)> = vec![];
+        for (chord, command) in keymap.bindings() {
+            match chords.iter_mut().find(|(c, _)| *c == command) {
+                Some((_, names)) => names.push(chord.to_string()),
+                None => chords.push((command, vec![chord.to_string()])),
+            }
+        }
+        chords.sort_by_key(|(command, _)| (command.category(), command.name()));
+
+        let rows = chords
+            .into_iter()
+            .map(|(command, mut names)| {
+                names.sort();
+                format!(
+                    "{:<18}{:<24}{}",
+                    command.category(),
+                    command.name(),
+                    names.join(", ")
+                )
+            })
+            .collect();
+        self.list.set_filter("");
+        self.list.set_items(rows);
+    }
+}
+
+/// A file too large to edit, shown read-only over the editors. Only the lines on
+/// screen are read from it.
+pub struct FileViewer {
+    file: MappedFile,
+    path: PathBuf,
+
+    /// The line shown at the top.
+    first_line: usize,
+
+    /// How many lines fit on screen, as of the last layout. Used for paging.
+    page_lines: usize,
+    open: bool,
+
+    /// The text node that was focused before the viewer.
+    return_focus: Option<UiNodeId>,
+
+    font_size: f32,
+}
+
+impl FileViewer {
+    fn layout(
+        &mut self,
+        atlas: &mut impl GlyphAtlas,
+        view_size: BoundingBox,
+        theme: &Theme,
+        drawables: &mut Vec<Drawables>,
+    ) {
+        drawables.push(Drawables::Rect(QuadInstance::filled(
+            [view_size.min.0, view_size.min.1],
+            [view_size.width(), view_size.height()],
+            &theme.widget_background,
+        )));
+
+        let line_height = (self.font_size * 1.5).floor();
+        let padding = self.font_size / 2.0;
+        let baseline_offset = (line_height + self.font_size * 0.7) / 2.0;
+        let (glyphs, _) = image_pipeline::layout_label(
+            (view_size.min.0 + padding, view_size.min.1 + baseline_offset),
+            view_size.max.0 - padding,
+            atlas,
+            self.font_size,
+            &theme.widget_foreground,
+            &format!(
+                "{} ({} MB, too large to edit, read-only)",
+                self.path.display(),
+                self.file.len() / (1024 * 1024)
+            ),
+        );
+        drawables.extend(glyphs);
+
+        let text_top = view_size.min.1 + line_height;
+        self.page_lines = (((view_size.max.1 - text_top) / line_height).floor() as usize).max(1);
+        self.scroll_to(self.first_line);
+
+        // Only read as much of each line as fits. A character takes at most four
+        // bytes, and none is narrower than a space in the editor's monospaced font.
+        let number_width = self.file.line_count().to_string().len();
+        let space = image_pipeline::label_width(atlas, self.font_size, " ").max(1.0);
+        let columns = ((view_size.width() - padding * 2.0) / space)
+            .ceil()
+            .max(0.0) as usize;
+        let max_bytes = columns.saturating_sub(number_width + 2) * 4;
+        let lines = self
+            .file
+            .lines(self.first_line, max_bytes)
+            .take(self.page_lines);
+        for (i, line) in lines.enumerate() {
+            let (glyphs, _) = image_pipeline::layout_label(
+                (
+                    view_size.min.0 + padding,
+                    text_top + line_height * i as f32 + baseline_offset,
+                ),
+                view_size.max.0 - padding,
+                atlas,
+                self.font_size,
+                &theme.foreground,
+                &format!("{:>number_width$}  {line}", self.first_line + i + 1),
+            );
+            drawables.extend(glyphs);
+        }
+    }
+
+    /// Show 'line' at the top, or as close to it as the end of the file allows.
+    fn scroll_to(&mut self, line: usize) {
+        let last_page = self.file.line_count().saturating_sub(self.page_lines);
+        self.first_line = line.min(last_page);
+    }
+
+    /// Handle the keys that scroll: arrows, Page Up/Down, and Home/End. Returns
+    /// false for any other key.
+    fn keystroke(&mut self, event: &KeyInput) -> bool {
+        if event.state != ElementState::Pressed {
+            return false;
+        }
+
+        let line = match &event.logical_key {
+            Key::Named(NamedKey::ArrowUp) => self.first_line.saturating_sub(1),
+            Key::Named(NamedKey::ArrowDown) => self.first_line + 1,
+            Key::Named(NamedKey::PageUp) => self.first_line.saturating_sub(self.page_lines),
+            Key::Named(NamedKey::PageDown) => self.first_line + self.page_lines,
+            Key::Named(NamedKey::Home) => 0,
+            Key::Named(NamedKey::End) => usize::MAX,
+            _ => return false,
+        };
+        self.scroll_to(line);
+
+        true
+    }
+}
+
+#[derive(Debug)]
+pub struct Hbox {
+    elements: RefCell<Vec<UiNodeId>>,
+}
+
+impl Hbox {
+    fn layout(
+        &self,
+        scene: &Scene,
+        atlas: &mut impl GlyphAtlas,
+        parent_size: BoundingBox,
+        drawables: &mut Vec<Drawables>,
+    ) {
+        let elements = self.elements.borrow().clone();
+        for (i, id) in elements.iter().enumerate() {
+            let child_index = i;
+            let child_width = parent_size.width() / elements.len() as f32;
+            let x0 = parent_size.min.0 + child_width * child_index as f32;
+
+            let view_size =
+                BoundingBox::new(x0, parent_size.min.1, x0 + child_width, parent_size.max.1);
+
+            scene.node(*id).layout(scene, atlas, view_size, drawables);
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Vbox {
+    elements: RefCell<Vec<UiNodeId>>,
+}
+
+impl Vbox {
+    fn layout(
+        &self,
+        scene: &Scene,
+        atlas: &mut impl GlyphAtlas,
+        parent_size: BoundingBox,
+        drawables: &mut Vec<Drawables>,
+    ) {
+        let elements = self.elements.borrow().clone();
+        for (i, id) in elements.iter().enumerate() {
+            let child_index = elements.len() - i - 1;
+            let child_height = parent_size.height() / elements.len() as f32;
+            let y0 = parent_size.min.1 + child_height * child_index as f32;
+
+            let view_size =
+                BoundingBox::new(parent_size.min.0, y0, parent_size.max.0, y0 + child_height);
+
+            scene.node(*id).layout(scene, atlas, view_size, drawables);
+        }
+    }
+}
+
+pub enum Ui {
+    TexturedRectangle(TexturedRectangle),
+    Icon(IconView),
+    FixedSizedBox(FixedSizedBox),
+    Rectangle(Rectangle),
+    Text(Box<RefCell<Text>>),
+    TabBar(TabBar),
+    FindInFiles(Box<RefCell<FindInFiles>>),
+    Problems(Box<RefCell<Problems>>),
+    Help(Box<RefCell<Help>>),
+    FileViewer(Box<RefCell<FileViewer>>),
+    VirtualList(Box<RefCell<VirtualList>>),
+    Hbox(Hbox),
+    Vbox(Vbox),
+    Spacer,
+}
+
+impl Ui {
+    fn layout(
+        &self,
+        scene: &Scene,
+        atlas: &mut impl GlyphAtlas,
+        view_size: BoundingBox,
+        drawables: &mut Vec<Drawables>,
+    ) {
+        match self {
+            Ui::TexturedRectangle(tr) => tr.layout(atlas, view_size, drawables),
+            Ui::Icon(i) => i.layout(scene, atlas, view_size, drawables),
+            Ui::FixedSizedBox(fsb) => fsb.layout(scene, atlas, view_size, drawables),
+            Ui::Rectangle(r) => r.layout(view_size, drawables),
+            Ui::Text(td) => {
+                let scene_stats = td.borrow().buffer_info.then(|| scene.stats());
+                td.borrow_mut().layout(
+                    atlas,
+                    view_size,
+                    scene.animation_clock.now(),
+                    &scene.theme,
+                    scene_stats,
+                    drawables,
+                )
+            }
+            Ui::TabBar(t) => t.layout(scene, atlas, view_size, drawables),
+            Ui::FindInFiles(f) => f
+                .borrow_mut()
+                .layout(atlas, view_size, &scene.theme, drawables),
+            Ui::Problems(p) => p
+                .borrow_mut()
+                .layout(atlas, view_size, &scene.theme, drawables),
+            Ui::Help(h) => h
+                .borrow_mut()
+                .layout(atlas, view_size, &scene.theme, drawables),
+            Ui::FileViewer(v) => v
+                .borrow_mut()
+                .layout(atlas, view_size, &scene.theme, drawables),
+            Ui::VirtualList(l) => l
+                .borrow_mut()
+                .layout(atlas, view_size, &scene.theme, drawables),
+            Ui::Hbox(h) => h.layout(scene, atlas, view_size, drawables),
+            Ui::Vbox(v) => v.layout(scene, atlas, view_size, drawables),
+            Ui::Spacer => {}
+        }
+    }
+}
+
+/// How many of the latest commands a debug snapshot lists.
+const RECENT_COMMANDS: usize = 50;
+
+/// What the scene holds, to check that closing things really frees them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SceneStats {
+    /// Nodes that are alive.
+    pub nodes: usize,
+
+    /// Nodes that were removed and dropped.
+    pub removed: usize,
+
+    /// Text nodes, and the buffers open in them.
+    pub texts: usize,
+    pub buffers: usize,
+}
+
+/// Which way a pane is split. Horizontal puts the new pane to the right of the
+/// old one, vertical puts it below.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SplitDirection {
+    Horizontal,
+    Vertical,
+}
+
+/// What the file dialog was opened for.
+#[derive(Debug, Clone, Copy)]
+enum DialogPurpose {
+    /// Opening the file picked.
+    Open,
+    /// Saving the buffer at this index, which has no file yet, to the file picked.
+    Save(usize),
+}
+
+pub struct Scene {
+    /// Every node, indexed by UiNodeId. Removed nodes leave an empty slot behind so
+    /// ids are never reused, and an id held after its node is gone finds nothing.
+    nodes: RefCell<Vec<Option<Rc<Ui>>>>,
+    node_root: UiNodeId,
+    cursor_pos: (f32, f32),
+    focused: Option<UiNodeId>,
+
+    /// Is the left mouse button held down after clicking into a text node?
+    dragging: bool,
+
+    /// Where a drag with Alt held started. It selects a rectangle instead of a range.
+    block_drag: Option<(f32, f32)>,
+
+    /// The tab the pointer is resting on, for showing its preview.
+    tab_hover: Option<TabHover>,
+
+    /// The text node whose status bar has its indentation menu open.
+    indentation_menu: Option<UiNodeId>,
+
+    /// Zen mode shows only the focused text, in a centered column.
+    zen_mode: bool,
+
+    /// The find in files panel, created the first time it is opened.
+    find_in_files: Option<UiNodeId>,
+
+    /// The directory find in files searches, the working directory to begin with.
+    project_root: PathBuf,
+
+    /// The problems panel, created the first time it is opened.
+    problems: Option<UiNodeId>,
+
+    /// The help, created the first time it is opened.
+    help: Option<UiNodeId>,
+
+    /// The viewer for files too large to edit, created the first time one is opened.
+    file_viewer: Option<UiNodeId>,
+
+    /// How wide the text column is in zen mode, at most.
+    zen_max_width: f32,
+
+    /// Drives every animation in the scene.
+    animation_clock: AnimationClock,
+
+    /// Does the window have the focus, and is it hidden? Animations pause unless
+    /// it is focused and visible.
+    window_focused: bool,
+    window_occluded: bool,
+
+    /// The colors everything is drawn in.
+    theme: Theme,
+
+    /// The theme being faded from and to, and when the fade started.
+    theme_fade: Option<(Theme, Theme, Duration)>,
+
+    /// Which keys run which commands.
+    keymap: Keymap,
+
+    /// The modifier keys held down.
+    modifiers: Modifiers,
+
+    /// Options the user set in their settings file.
+    settings: Settings,
+
+    /// Where copied text goes.
+    clipboard: Clipboard,
+
+    /// A problem to point out in the status bar until it is cleared, e.g. a shader
+    /// that failed to reload.
+    notice: Option<String>,
+
+    /// The open file dialog, the text node that asked for it, and what for.
+    file_dialog: Option<(UiNodeId, DialogPurpose, FileDialog)>,
+
+    /// Was quitting held back to warn about unsaved changes? Quitting again right
+    /// after goes ahead.
+    quitting_unsaved: bool,
+
+    /// The latest commands run, oldest first, for debug snapshots.
+    recent_commands: VecDeque<Command>,
+
+    /// Has a debug snapshot been asked for since the window last checked?
+    snapshot_requested: bool,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self {
+            nodes: RefCell::new(vec![]),
+            node_root: UiNodeId(0),
+            cursor_pos: (0.0, 0.0),
+            focused: None,
+            dragging: false,
+            block_drag: None,
+            tab_hover: None,
+            indentation_menu: None,
+            zen_mode: false,
+            find_in_files: None,
+            project_root: std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")),
+            problems: None,
+            help: None,
+            file_viewer: None,
+            zen_max_width: 900.0,
+            animation_clock: AnimationClock::default(),
+            window_focused: true,
+            window_occluded: false,
+            theme: Theme::default(),
+            theme_fade: None,
+            keymap: Keymap::default(),
+            modifiers: Modifiers::default(),
+            settings: Settings::default(),
+            clipboard: Clipboard::default(),
+            notice: None,
+            file_dialog: None,
+            quitting_unsaved: false,
+            recent_commands: VecDeque::new(),
+            snapshot_requested: false,
+        }
+    }
+}
+
+impl Scene {
+    pub fn set_focus(&mut self, node: UiNodeId) {
+        self.focused = Some(node);
+    }
+
+    /// Move the focus to another text node.
+    fn move_focus(&mut self, node: UiNodeId) {
+        self.focused = Some(node);
+    }
+
+    /// Split the focused pane in two. The new pane gets an empty buffer and the focus.
+    pub fn split(&mut self, direction: SplitDirection) {
+        let Some(focused) = self.focused else {
+            return;
+        };
+
+        let font_size = match self.node(focused).as_ref() {
+            Ui::Text(td) => td.borrow().font_size,
+            _ => return,
+        };
+
+        // A pane is the text node along with the tab bar wrapping it, if it has one.
+        let pane = self
+            .find_node(|node| matches!(node, Ui::TabBar(t) if t.child == focused))
+            .unwrap_or(focused);
+
+        let new_text = self.text_details(String::new(), None, font_size);
+        let new_pane = match self.node(pane).as_ref() {
+            Ui::TabBar(t) => self.tab_bar(new_text, t.font_size),
+            _ => new_text,
+        };
+
+        let parent = self.find_node(|node| match node {
+            Ui::Hbox(Hbox { elements }) | Ui::Vbox(Vbox { elements }) => {
+                elements.borrow().contains(&pane)
+            }
+            _ => false,
+        });
+
+        // Vbox lists its children bottom to top, so a pane below comes before it.
+        let split_box = |scene: &Scene| match direction {
+            SplitDirection::Horizontal => scene.hbox(vec![pane, new_pane]),
+            SplitDirection::Vertical => scene.vbox(vec![new_pane, pane]),
+        };
+
+        let Some(parent) = parent.map(|id| self.node(id)) else {
+            self.node_root = split_box(self);
+            self.move_focus(new_text);
+            return;
+        };
+
+        let (Ui::Hbox(Hbox { elements }) | Ui::Vbox(Vbox { elements })) = parent.as_ref() else {
+            unreachable!()
+        };
+        let idx = elements.borrow().iter().position(|id| *id == pane).unwrap();
+
+        match (parent.as_ref(), direction) {
+            // Add the new pane next to the old one rather than nesting another box.
+            (Ui::Hbox(_), SplitDirection::Horizontal) => {
+                elements.borrow_mut().insert(idx + 1, new_pane)
+            }
+            (Ui::Vbox(_), SplitDirection::Vertical) => elements.borrow_mut().insert(idx, new_pane),
+            _ => {
+                let split = split_box(self);
+                elements.borrow_mut()[idx] = split;
+            }
+        }
+
+        self.move_focus(new_text);
+    }
+
+    /// Close the focused pane and drop its buffers. The focus goes to the next pane
+    /// in reading order, or the previous one if it was the last. The last pane
+    /// can't be closed. Like close_buffer, a pane with unsaved changes in any of
+    /// its buffers is only closed when asked twice in a row.
+    pub fn close_pane(&mut self) {
+        let Some(focused) = self.focused else {
+            return;
+        };
+        if !matches!(self.node(focused).as_ref(), Ui::Text(_)) {
+            return;
+        }
+
+        let mut panes = vec![];
+        self.collect_text_nodes(self.node_root, &mut panes);
+        let Some(idx) = panes.iter().position(|id| *id == focused) else {
+            return;
+        };
+        if panes.len() == 1 {
+            return;
+        }
+        if let Ui::Text(td) = self.node(focused).as_ref() {
+            let mut td = td.borrow_mut();
+            if td.has_unsaved_changes() && !td.confirm_discard(Command::ClosePane) {
+                return;
+            }
+        }
+
+        let pane = self
+            .find_node(|node| matches!(node, Ui::TabBar(t) if t.child == focused))
+            .unwrap_or(focused);
+        let Some(parent) = self.find_node(|node| match node {
+            Ui::Hbox(Hbox { elements }) | Ui::Vbox(Vbox { elements }) => {
+                elements.borrow().contains(&pane)
+            }
+            _ => false,
+        }) else {
+            return;
+        };
+
+        let parent_node = self.node(parent);
+        let (Ui::Hbox(Hbox { elements }) | Ui::Vbox(Vbox { elements })) = parent_node.as_ref()
+        else {
+            unreachable!()
+        };
+
+        // The pane after it gets the focus, or the one before if it was the last.
+        let next = panes.get(idx + 1).unwrap_or(&panes[idx - 1]);
+        self.move_focus(*next);
+
+        elements.borrow_mut().retain(|id| *id != pane);
+        self.remove_node(pane);
+
+        // A box left with a single pane is replaced by that pane.
+        let remaining = elements.borrow().clone();
+        if let [only] = remaining[..] {
+            elements.borrow_mut().clear();
+            match self.find_node(|node| match node {
+                Ui::Hbox(Hbox { elements }) | Ui::Vbox(Vbox { elements }) => {
+                    elements.borrow().contains(&parent)
+                }
+                _ => false,
+            }) {
+                Some(grandparent) => {
+                    if let Ui::Hbox(Hbox { elements }) | Ui::Vbox(Vbox { elements }) =
+                        self.node(grandparent).as_ref()
+                    {
+                        for id in elements.borrow_mut().iter_mut() {
+                            if *id == parent {
+                                *id = only;
+                            }
+                        }
+                    }
+                }
+                None => self.node_root = only,
+            }
+            self.remove_node(parent);
+        }
+    }
+
+    /// Remove a node and everything under it. Dropping a node drops what it owns:
+    /// a text its buffers, a panel its list and running search, a box its children.
+    /// Ids of it held elsewhere are cleared so nothing finds a removed node.
+    fn remove_node(&mut self, id: UiNodeId) {
+        let Some(node) = self.nodes.borrow_mut().get_mut(id.0).and_then(Option::take) else {
+            return;
+        };
+
+        let children = match node.as_ref() {
+            Ui::TabBar(t) => vec![t.child],
+            Ui::FixedSizedBox(fsb) => vec![fsb.child],
+            Ui::Hbox(Hbox { elements }) | Ui::Vbox(Vbox { elements }) => elements.take(),
+            _ => vec![],
+        };
+        for child in children {
+            self.remove_node(child);
+        }
+
+        if self.focused == Some(id) {
+            self.focused = None;
+        }
+        if self.tab_hover.is_some_and(|h| h.text == id) {
+            self.tab_hover = None;
+        }
+        if self.indentation_menu == Some(id) {
+            self.indentation_menu = None;
+        }
+        for panel in [
+            self.find_in_files,
+            self.problems,
+            self.help,
+            self.file_viewer,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            match self.node(panel).as_ref() {
+                Ui::FindInFiles(f) if f.borrow().return_focus == Some(id) => {
+                    f.borrow_mut().return_focus = None
+                }
+                Ui::Problems(p) if p.borrow().return_focus == Some(id) => {
+                    p.borrow_mut().return_focus = None
+                }
+                Ui::Help(h) if h.borrow().return_focus == Some(id) => {
+                    h.borrow_mut().return_focus = None
+                }
+                Ui::FileViewer(v) if v.borrow().return_focus == Some(id) => {
+                    v.borrow_mut().return_focus = None
+                }
+                _ => {}
+            }
+        }
+
+        // Only the scene should own nodes. Anyone else holding on keeps its buffers alive.
+        if Rc::strong_count(&node) > 1 {
+            log::warn!("Removed node {} is still referenced elsewhere", id.0);
+        }
+    }
+
+    /// Count the nodes, texts and buffers in the scene.
+    pub fn stats(&self) -> SceneStats {
+        let nodes = self.nodes.borrow();
+        let mut stats = SceneStats {
+            nodes: 0,
+            removed: 0,
+            texts: 0,
+            buffers: 0,
+        };
+        for node in nodes.iter() {
+            match node.as_deref() {
+                Some(Ui::Text(td)) => {
+                    stats.nodes += 1;
+                    stats.texts += 1;
+                    stats.buffers += td.borrow().buffers.len();
+                }
+                Some(_) => stats.nodes += 1,
+                None => stats.removed += 1,
+            }
+        }
+        stats
+    }
+
+    /// Move the focus to the next pane, in reading order. Goes backwards if 'reverse'.
+    pub fn focus_next_pane(&mut self, reverse: bool) {
+        let Some(focused) = self.focused else {
+            return;
+        };
+
+        let mut panes = vec![];
+        self.collect_text_nodes(self.node_root, &mut panes);
+        let Some(idx) = panes.iter().position(|id| *id == focused) else {
+            return;
+        };
+
+        let next = if reverse {
+            (idx + panes.len() - 1) % panes.len()
+        } else {
+            (idx + 1) % panes.len()
+        };
+        self.move_focus(panes[next]);
+    }
+
+    /// Collect the text nodes under 'id', left to right and top to bottom.
+    fn collect_text_nodes(&self, id: UiNodeId, out: &mut Vec<UiNodeId>) {
+        match self.node(id).as_ref() {
+            Ui::Text(_) => out.push(id),
+            Ui::TabBar(t) => self.collect_text_nodes(t.child, out),
+            Ui::FixedSizedBox(fsb) => self.collect_text_nodes(fsb.child, out),
+            Ui::Hbox(h) => {
+                for child in h.elements.borrow().iter() {
+                    self.collect_text_nodes(*child, out);
+                }
+            }
+            Ui::Vbox(v) => {
+                for child in v.elements.borrow().iter().rev() {
+                    self.collect_text_nodes(*child, out);
+                }
+            }
+            Ui::TexturedRectangle(_)
+            | Ui::Icon(_)
+            | Ui::Rectangle(_)
+            | Ui::FindInFiles(_)
+            | Ui::Problems(_)
+            | Ui::Help(_)
+            | Ui::FileViewer(_)
+            | Ui::VirtualList(_)
+            | Ui::Spacer => {}
+        }
+    }
+
+    fn find_node(&self, pred: impl Fn(&Ui) -> bool) -> Option<UiNodeId> {
+        self.nodes
+            .borrow()
+            .iter()
+            .position(|node| node.as_deref().is_some_and(&pred))
+            .map(UiNodeId)
+    }
+
+    /// Handle the keys that work on panes rather than the focused text. Returns
+    /// true if the key was one of them.
+    fn pane_keystroke(&mut self, event: &KeyInput) -> bool {
+        if event.state != ElementState::Pressed {
+            return false;
+        }
+
+        let Some(focused) = self.focused else {
+            return false;
+        };
+        let chord = match self.node(focused).as_ref() {
+            Ui::Text(_) => self.chord(event),
+            _ => return false,
+        };
+
+        let command = self.keymap.lookup(&chord);
+        match command {
+            Some(Command::FocusNextPane) => self.focus_next_pane(false),
+            Some(Command::FocusPrevPane) => self.focus_next_pane(true),
+            Some(Command::ToggleZenMode) => self.toggle_zen_mode(),
+            Some(Command::SplitHorizontal) => self.split(SplitDirection::Horizontal),
+            Some(Command::SplitVertical) => self.split(SplitDirection::Vertical),
+            Some(Command::ClosePane) => self.close_pane(),
+            Some(Command::FindInFiles) => self.open_find_in_files(),
+            Some(Command::Problems) => self.toggle_problems(),
+            Some(Command::Help) => self.toggle_help(),
+            Some(Command::DebugSnapshot) => self.snapshot_requested = true,
+            _ => return false,
+        }
+
+        self.record_command(command.unwrap());
+        true
+    }
+
+    /// Remember that 'command' ran, keeping only the latest RECENT_COMMANDS.
+    fn record_command(&mut self, command: Command) {
+        if self.recent_commands.len() == RECENT_COMMANDS {
+            self.recent_commands.pop_front();
+        }
+        self.recent_commands.push_back(command);
+    }
+
+    /// Was a debug snapshot asked for since the last call?
+    pub fn take_snapshot_request(&mut self) -> bool {
+        std::mem::take(&mut self.snapshot_requested)
+    }
+
+    /// What a bug report needs to know about the open buffers and the scene. Only
+    /// sizes and counts, never text, and of file names only the extension.
+    pub fn debug_report(&self) -> String {
+        let mut texts = vec![];
+        self.collect_text_nodes(self.node_root, &mut texts);
+
+        let mut report = String::from("Buffers\n");
+        for (pane, text) in texts.into_iter().enumerate() {
+            let node = self.node(text);
+            let Ui::Text(td) = node.as_ref() else {
+                continue;
+            };
+            let td = td.borrow();
+            for (i, editor) in td.buffers.iter().enumerate() {
+                let stats = editor.stats();
+                let endings = editor.line_endings();
+                let kind = match editor.path() {
+                    Some(path) => match path.extension() {
+                        Some(ext) => format!(".{} file", ext.to_string_lossy()),
+                        None => "file".to_string(),
+                    },
+                    None => "untitled".to_string(),
+                };
+                let active = if i == td.buffers.active_index() {
+                    ", active"
+                } else {
+                    ""
+                };
+                report.push_str(&format!(
+                    "  pane {} buffer {}{active}: {kind}, {}, {} bytes, {} lines, {} chunks, \
+                     {} LF, {} CRLF, {} trailing newlines, {} undo, {} redo, {} history bytes\n",
+                    pane + 1,
+                    i + 1,
+                    editor.encoding().name(),
+                    stats.bytes,
+                    stats.lines,
+                    stats.chunks,
+                    endings.lf,
+                    endings.crlf,
+                    endings.trailing,
+                    stats.undo_steps,
+                    stats.redo_steps,
+                    stats.history_bytes,
+                ));
+            }
+        }
+
+        let stats = self.stats();
+        report.push_str(&format!(
+            "\nScene\n  {} nodes, {} removed, {} texts, {} buffers, zen mode {}\n",
+            stats.nodes,
+            stats.removed,
+            stats.texts,
+            stats.buffers,
+            if self.zen_mode { "on" } else { "off" },
+        ));
+
+        report.push_str("\nRecent commands, oldest first\n");
+        for command in &self.recent_commands {
+            report.push_str(&format!("  {}\n", command.name()));
+        }
+        report
+    }
+
+    /// The settings and every key binding, as the snapshot's config.
+    pub fn config_report(&self) -> String {
+        let mut bindings: Vec<_> = self
+            .keymap
+            .bindings()
+            .map(|(chord, command)| format!("{chord} = {}", command.name()))
+            .collect();
+        bindings.sort();
+        format!("{:#?}\n\nKeymap\n{}\n", self.settings, bindings.join("\n"))
+    }
+
+    pub fn set_root(&mut self, root: UiNodeId) {
+        self.node_root = root;
+    }
+
+    /// Open or save to the file picked in the file dialog, once it has been
+    /// answered.
+    fn poll_file_dialog(&mut self) {
+        let Some((text, purpose, dialog)) = &self.file_dialog else {
+            return;
+        };
+        let Some(answer) = dialog.poll() else {
+            return;
+        };
+        let (text, purpose) = (*text, *purpose);
+        self.file_dialog = None;
+
+        let Some(path) = answer else {
+            return;
+        };
+        let node = self.get_node(text);
+        let Some(Ui::Text(td)) = node.as_deref() else {
+            return;
+        };
+        match purpose {
+            DialogPurpose::Open if self.too_large_to_edit(&path) => {
+                self.open_viewer(&path, 0);
+            }
+            DialogPurpose::Open => {
+                td.borrow_mut().open_path(&path);
+            }
+            DialogPurpose::Save(buffer) => {
+                let mut td = td.borrow_mut();
+                if let Some(editor) = td.buffers.get_mut(buffer) {
+                    editor.set_path(path);
+                    self.save_buffer(&mut td, buffer);
+                }
+            }
+        }
+    }
+
+    /// Save the buffer at 'idx' of 'td' to its file. Why it failed, if it did, is
+    /// shown in the status bar.
+    fn save_buffer(&self, td: &mut Text, idx: usize) {
+        let Some(editor) = td.buffers.get_mut(idx) else {
+            return;
+        };
+        if self.settings.ensure_trailing_newline {
+            editor.ensure_trailing_newline_on_save();
+        }
+        td.message = match editor.save() {
+            Ok(()) => None,
+            Err(e) => {
+                let name = editor.path().and_then(Path::file_name).unwrap_or_default();
+                log::error!("Failed to save {}: {e}", name.to_string_lossy());
+                Some((
+                    format!("Couldn't save {}: {e}", name.to_string_lossy()),
+                    true,
+                ))
+            }
+        };
+    }
+
+    /// Turn cursor blinking on or off for every text node. With blinking off the
+    /// cursor is always drawn solid.
+    pub fn set_cursor_blink(&self, enabled: bool) {
+        for node in self.nodes.borrow().iter().flatten() {
+            if let Ui::Text(td) = node.as_ref() {
+                td.borrow_mut().set_cursor_blink(enabled);
+            }
+        }
+    }
+
+    /// Advance all animations to the given frame timestamp, and keep scrolling
+    /// the focused text if a drag selection has left its bounds.
+    pub fn tick(
+        &mut self,
+        frame_time: Instant,
+        glyph_rasterizer: &mut impl text_editor::GlyphRasterizer,
+    ) {
+        self.animation_clock.tick(frame_time);
+
+        if let Some((from, to, start)) = &self.theme_fade {
+            let elapsed = self.animation_clock.now().saturating_sub(*start);
+            let t = elapsed.as_secs_f32() / THEME_FADE.as_secs_f32();
+            self.theme = from.lerp(to, Easing::EaseInOutQuad.apply(t));
+            if t >= 1.0 {
+                self.theme_fade = None;
+            }
+        }
+
+        if let Some(Ui::FindInFiles(f)) = self.find_in_files.map(|id| self.node(id)).as_deref() {
+            f.borrow_mut().poll();
+        }
+        if let Some(Ui::Help(h)) = self.help.map(|id| self.node(id)).as_deref() {
+            h.borrow_mut().list.poll();
+        }
+        self.refresh_problems();
+        self.poll_file_dialog();
+        let now = self.animation_clock.now();
+        for node in self.nodes.borrow().iter() {
+            if let Some(Ui::Text(td)) = node.as_deref() {
+                let mut td = td.borrow_mut();
+                td.poll_loading(glyph_rasterizer);
+                td.advance_scroll(now, glyph_rasterizer);
+            }
+        }
+
+        if !self.dragging {
+            return;
+        }
+
+        if let Some(focused) = self.focused {
+            if let Ui::Text(td) = self.node(focused).as_ref() {
+                let mut td = td.borrow_mut();
+                let Some(bounds) = td.bounds else {
+                    return;
+                };
+
+                let amount = if self.cursor_pos.1 < bounds.min.1 {
+                    ScrollAmount::Up { lines: 1 }
+                } else if self.cursor_pos.1 > bounds.max.1 {
+                    ScrollAmount::Down { lines: 1 }
+                } else {
+                    return;
+                };
+
+                td.scroll(amount, glyph_rasterizer);
+
+                if let Some(pos) = td.position_at(self.cursor_pos, glyph_rasterizer) {
+                    td.editor_mut().select_to(pos);
+                }
+                td.report_activity();
+            }
+        }
+    }
+
+    /// Show 'text' being composed by an input method at the cursor of the focused
+    /// text. Empty text cancels the composition.
+    pub fn set_preedit(&self, text: &str) {
+        if let Some(Ui::Text(td)) = self.keyboard_target().map(|id| self.node(id)).as_deref() {
+            let mut td = td.borrow_mut();
+            td.report_activity();
+            td.set_preedit(text);
+        }
+    }
+
+    /// Where the focused text's cursor, and any text being composed at it, was last
+    /// drawn.
+    pub fn ime_cursor_area(&self) -> Option<BoundingBox> {
+        match self.node(self.keyboard_target()?).as_ref() {
+            Ui::Text(td) => td.borrow().cursor_area,
+            _ => None,
+        }
+    }
+
+    /// Insert text an input method committed at the cursor of the focused text.
+    pub fn commit_text(&self, text: &str) {
+        if let Some(Ui::Text(td)) = self.keyboard_target().map(|id| self.node(id)).as_deref() {
+            let mut td = td.borrow_mut();
+            td.report_activity();
+            td.commit_preedit(text);
+        }
+    }
+
+    /// The modifier keys held down now, as the window system reports them.
+    pub fn set_modifiers(&mut self, modifiers: Modifiers) {
+        self.modifiers = modifiers;
+    }
+
+    /// Animations are paused while the window is not focused.
+    pub fn set_window_focused(&mut self, focused: bool) {
+        self.window_focused = focused;
+        self.animation_clock
+            .set_paused(!self.window_focused || self.window_occluded);
+    }
+
+    /// Animations are paused while the window can't be seen either.
+    pub fn set_window_occluded(&mut self, occluded: bool) {
+        self.window_occluded = occluded;
+        self.animation_clock
+            .set_paused(!self.window_focused || self.window_occluded);
+    }
+
+    pub fn scroll(
+        &self,
+        delta: MouseScrollDelta,
+        glyph_rasterizer: &mut impl text_editor::GlyphRasterizer,
+    ) {
+        if let Some(focused) = self.focused {
+            match self.node(focused).as_ref() {
+                Ui::Text(td) => {
+                    // A paused clock would hold the scroll back until the window is
+                    // focused again.
+                    let now =
+                        (!self.animation_clock.is_paused()).then(|| self.animation_clock.now());
+                    let mut td = td.borrow_mut();
+                    td.report_activity();
+                    td.scroll_delta(delta, 3, now, glyph_rasterizer);
+                }
+                Ui::FileViewer(v) => {
+                    let mut v = v.borrow_mut();
+                    let line = match delta {
+                        MouseScrollDelta::LineDelta(_, y) if y > 0.0 => {
+                            v.first_line.saturating_sub(3)
+                        }
+                        _ => v.first_line + 3,
+                    };
+                    v.scroll_to(line);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    pub fn send_keystroke(
+        &mut self,
+        event: &KeyInput,
+        glyph_rasterizer: &mut impl text_editor::GlyphRasterizer,
+    ) {
+        self.modifiers.track(event);
+        // Quitting with unsaved changes is only confirmed by quitting again right away.
+        if event.state == ElementState::Pressed && !event.is_modifier() {
+            self.quitting_unsaved = false;
+        }
+
+        if self.pane_keystroke(event)
+            || self.find_in_files_keystroke(event, glyph_rasterizer)
+            || self.problems_keystroke(event, glyph_rasterizer)
+            || self.help_keystroke(event)
+            || self.file_viewer_keystroke(event)
+            || self.virtual_list_keystroke(event)
+        {
+            return;
+        }
+
+        if let Some(focused) = self.keyboard_target() {
+            if let Ui::Text(td) = self.node(focused).as_ref() {
+                let mut td = td.borrow_mut();
+                td.editor_mut().extend_selection = self.modifiers.shift;
+                if event.state == ElementState::Pressed {
+                    td.report_activity();
+
+                    // A dead key's accent shows until the next key, which types the
+                    // accented character itself.
+                    match &event.logical_key {
+                        Key::Dead(accent) => {
+                            td.set_preedit(&accent.map(String::from).unwrap_or_default());
+                            return;
+                        }
+                        _ if event.is_modifier() => {}
+                        _ => {
+                            td.set_preedit("");
+                            // Closing unsaved changes is only confirmed by closing
+                            // again right away.
+                            if self.keymap.lookup(&self.chord(event)) != td.closing_unsaved {
+                                td.closing_unsaved = None;
+                            }
+                            td.message = None;
+                        }
+                    }
+
+                    if td.unicode_keystroke(event)
+                        || td.goto_line_keystroke(event, self.modifiers, glyph_rasterizer)
+                    {
+                        return;
+                    }
+
+                    if td.is_searching()
+                        && td.search_keystroke(event, self.modifiers, glyph_rasterizer)
+                    {
+                        return;
+                    }
+                }
+
+                if event.state == ElementState::Pressed {
+                    let chord = self.chord(event);
+                    if let Some(command) = self.keymap.lookup(&chord) {
+                        self.record_command(command);
+                        self.run_text_command(&mut td, command, glyph_rasterizer);
+                        return;
+                    }
+                }
+
+                match event.state {
+                    ElementState::Pressed => match &event.logical_key {
+                        Key::Named(NamedKey::Space) => td.add_char(" "),
+                        Key::Character(c) => match c.as_str() {
+                            "{" | "(" | "[" => td.add_opening_bracket(c.chars().next().unwrap()),
+                            "}" | ")" | "]" => td.add_closing_bracket(c.chars().next().unwrap()),
+                            "\"" => td.add_quote('"'),
+                            _ => td.add_char(c),
+                        },
+                        _ => {}
+                    },
+                    ElementState::Released => {}
+                }
+            }
+        }
+    }
+
+    /// The chord 'event' makes with the modifiers held down.
+    fn chord(&self, event: &KeyInput) -> Chord {
+        Chord::new(self.modifiers, &event.logical_key)
+    }
+
+    /// Run a command bound to a key on the focused text. Commands on panes are run
+    /// by pane_keystroke instead.
+    fn run_text_command(
+        &mut self,
+        td: &mut Text,
+        command: Command,
+        glyph_rasterizer: &mut impl text_editor::GlyphRasterizer,
+    ) {
+        match command {
+            Command::MoveLeft => td.editor_mut().left(),
+            Command::MoveRight => td.editor_mut().right(),
+            Command::LineStart => td.editor_mut().move_to_line_start(),
+            Command::LineEnd => td.editor_mut().move_to_line_end(),
+            Command::WordForward => td.editor_mut().word_forward(),
+            Command::WordBackward => td.editor_mut().word_backward(),
+            Command::ScrollToStart => td.scroll(ScrollAmount::ToStart, glyph_rasterizer),
+            Command::ScrollToEnd => td.scroll(ScrollAmount::ToEnd, glyph_rasterizer),
+            Command::PageUp => td.scroll(ScrollAmount::PageUp, glyph_rasterizer),
+            Command::PageDown => td.scroll(ScrollAmount::PageDown, glyph_rasterizer),
+            Command::Newline => td.add_newline(),
+            Command::Indent => td.editor_mut().indent(),
+            Command::Backspace => td.backspace(),
+            Command::Delete => td.delete(),
+            Command::Copy | Command::Cut | Command::KillLine => {
+                let text = match command {
+                    Command::Copy => td.editor().copy(),
+                    Command::Cut => td.editor_mut().cut(),
+                    _ => td.editor_mut().kill_line(),
+                };
+                if let Some(text) = text {
+                    self.clipboard.set_contents(text);
+                }
+            }
+            Command::Paste => {
+                let mut clipboard_contents = self.clipboard.get_contents();
+                if self.settings.normalize_line_endings_on_paste {
+                    let ending = td.editor().line_endings().dominant();
+                    clipboard_contents = ending.convert(&clipboard_contents);
+                }
+                td.editor_mut().insert_text(&clipboard_contents);
+            }
+            // The view may have been too small to show the cursor from where it was.
+            Command::Undo => {
+                td.editor_mut().undo();
+                td.editor_mut().scroll_to_cursor(glyph_rasterizer);
+            }
+            Command::Redo => {
+                td.editor_mut().redo();
+                td.editor_mut().scroll_to_cursor(glyph_rasterizer);
+            }
+            // Text without a file is saved once the user picks one.
+            Command::Save if td.editor().path().is_none() => {
+                if let (None, Some(text)) = (&self.file_dialog, self.keyboard_target()) {
+                    let dir = self.project_root.as_path();
+                    let purpose = DialogPurpose::Save(td.buffers.active_index());
+                    self.file_dialog =
+                        Some((text, purpose, FileDialog::save(Some(dir), "untitled")));
+                }
+            }
+            Command::Save => {
+                let active = td.buffers.active_index();
+                self.save_buffer(td, active);
+            }
+            // The file picked is opened by tick(), in the text the dialog was
+            // opened from.
+            Command::OpenFile => {
+                if let (None, Some(text)) = (&self.file_dialog, self.keyboard_target()) {
+                    let dir = td.editor().path().and_then(|p| p.parent());
+                    self.file_dialog = Some((text, DialogPurpose::Open, FileDialog::open(dir)));
+                }
+            }
+            Command::CloseBuffer => {
+                td.close_buffer();
+            }
+            Command::NextBuffer => td.next_buffer(),
+            Command::PrevBuffer => td.prev_buffer(),
+            Command::Find => td.open_search(false),
+            Command::Replace => td.open_search(true),
+            Command::NextMatch => td.jump_to_match(false, glyph_rasterizer),
+            Command::PrevMatch => td.jump_to_match(true, glyph_rasterizer),
+            Command::GotoLine => td.start_goto_line(),
+            Command::UnicodeEntry => td.start_unicode_entry(),
+            Command::ToggleAutoClose => {
+                let editor = td.editor_mut();
+                editor.set_auto_close(!editor.auto_close());
+            }
+            Command::ToggleInsertSpaces => {
+                let editor = td.editor_mut();
+                editor.set_insert_spaces(!editor.insert_spaces());
+            }
+            Command::IndentationToSpaces => td.editor_mut().indentation_to_spaces(),
+            Command::IndentationToTabs => td.editor_mut().indentation_to_tabs(),
+            Command::NormalizeLineEndings => {
+                let editor = td.editor_mut();
+                editor.normalize_line_endings(editor.line_endings().dominant());
+            }
+            Command::ConvertLineEndings => {
+                let editor = td.editor_mut();
+                let ending = match editor.line_endings().dominant() {
+                    LineEnding::Lf => LineEnding::Crlf,
+                    LineEnding::Crlf => LineEnding::Lf,
+                };
+                editor.normalize_line_endings(ending);
+            }
+            Command::ReopenWithEncoding => td.reopen_with_next_encoding(),
+            Command::FixTrailingNewline => td.editor_mut().ensure_trailing_newline(),
+            Command::BufferInfo => td.toggle_buffer_info(),
+            Command::AddNextOccurrence => td.editor_mut().add_next_occurrence(),
+            Command::AddCursorAbove => td.editor_mut().add_cursor_above(),
+            Command::AddCursorBelow => td.editor_mut().add_cursor_below(),
+            Command::MoveLinesUp => td.editor_mut().move_lines_up(),
+            Command::MoveLinesDown => td.editor_mut().move_lines_down(),
+            Command::Duplicate => td.editor_mut().duplicate(),
+            Command::IncreaseFontSize => td.increase_font_size(),
+            Command::DecreaseFontSize => td.decrease_font_size(),
+            Command::SplitHorizontal
+            | Command::SplitVertical
+            | Command::ClosePane
+            | Command::FocusNextPane
+            | Command::FocusPrevPane
+            | Command::ToggleZenMode
+            | Command::FindInFiles
+            | Command::Problems
+            | Command::Help
+            | Command::DebugSnapshot
+            | Command::Unbound => {}
+        }
+    }
+
+    pub fn layout(&self, atlas: &mut impl GlyphAtlas, view_size: (f32, f32)) -> Vec<Drawables> {
+        let parent_size = BoundingBox {
+            min: (0.0, 0.0),
+            max: (view_size.0, view_size.1),
+        };
+
+        let mut drawables = vec![];
+
+        // Nodes that are not laid out this frame must not be hit by the mouse.
+        for node in self.nodes.borrow().iter().flatten() {
+            match node.as_ref() {
+                Ui::Text(td) => td.borrow_mut().bounds = None,
+                Ui::FindInFiles(f) => f.borrow_mut().bounds = None,
+                Ui::Problems(p) => p.borrow_mut().bounds = None,
+                _ => {}
+            }
+        }
+
+        // The find in files panel takes the bottom third of the window when open.
+        let mut view_size = view_size;
+        let mut parent_size = parent_size;
+        if let Some(panel) = self.open_find_in_files_panel() {
+            let panel_top = (view_size.1 * 2.0 / 3.0).floor();
+            let panel_size = BoundingBox::new(0.0, panel_top, view_size.0, view_size.1);
+            panel.layout(self, atlas, panel_size, &mut drawables);
+
+            view_size.1 = panel_top;
+            parent_size.max.1 = panel_top;
+        }
+
+        // So does the problems panel, of what is left.
+        if let Some(panel) = self.open_problems_panel() {
+            let panel_top = (view_size.1 * 2.0 / 3.0).floor();
+            let panel_size = BoundingBox::new(0.0, panel_top, view_size.0, view_size.1);
+            panel.layout(self, atlas, panel_size, &mut drawables);
+
+            view_size.1 = panel_top;
+            parent_size.max.1 = panel_top;
+        }
+
+        let help = self.open_help();
+        let file_viewer = self.open_file_viewer();
+
+        let zen_text = self
+            .focused
+            .filter(|_| self.zen_mode)
+            .map(|id| self.node(id));
+
+        match zen_text {
+            Some(text) => {
+                drawables.push(Drawables::Rect(QuadInstance::filled(
+                    [0.0, 0.0],
+                    [view_size.0, view_size.1],
+                    &self.theme.background,
+                )));
+
+                let width = self.zen_max_width.min(view_size.0);
+                let x0 = (view_size.0 - width) / 2.0;
+                let column = BoundingBox::new(x0, 0.0, x0 + width, view_size.1);
+                text.layout(self, atlas, column, &mut drawables);
+            }
+            None => {
+                self.node(self.node_root)
+                    .layout(self, atlas, parent_size, &mut drawables);
+                self.layout_focus_ring(&mut drawables);
+            }
+        }
+        self.layout_empty_states(atlas, &mut drawables);
+
+        // The help goes over everything, leaving a margin of the editors showing.
+        if let Some(help) = help {
+            let margin = 40.0;
+            let width = (view_size.0 - margin * 2.0).min(900.0);
+            let x0 = ((view_size.0 - width) / 2.0).floor();
+            let help_size = BoundingBox::new(x0, margin, x0 + width, view_size.1 - margin);
+            help.layout(self, atlas, help_size, &mut drawables);
+        }
+
+        // So does the file viewer, the whole width of the window.
+        if let Some(file_viewer) = file_viewer {
+            let margin = 40.0;
+            let viewer_size =
+                BoundingBox::new(margin, margin, view_size.0 - margin, view_size.1 - margin);
+            file_viewer.layout(self, atlas, viewer_size, &mut drawables);
+        }
+
+        drawables
+    }
+
+    /// Draw what can be done next in the middle of each pane with nothing open.
+    fn layout_empty_states(&self, atlas: &mut impl GlyphAtlas, drawables: &mut Vec<Drawables>) {
+        let mut panes = vec![];
+        match self.focused.filter(|_| self.zen_mode) {
+            Some(focused) => panes.push(focused),
+            None => self.collect_text_nodes(self.node_root, &mut panes),
+        }
+
+        for id in panes {
+            let node = self.node(id);
+            let Ui::Text(td) = node.as_ref() else {
+                continue;
+            };
+            let td = td.borrow();
+            let Some(bounds) = td.bounds.filter(|_| td.is_blank()) else {
+                continue;
+            };
+
+            let lines: Vec<_> = ["No file open".to_string(), String::new()]
+                .into_iter()
+                .chain(self.quick_action_hints())
+                .collect();
+
+            let font_size = (td.font_size * 0.9).floor();
+            let line_height = font_size * 1.5;
+            let top = bounds.center().1 - line_height * lines.len() as f32 / 2.0;
+
+            // Lay the lines out from the left edge, then move them all over so the
+            // widest one is centered.
+            let mut glyphs = vec![];
+            let mut widest: f32 = 0.0;
+            for (i, line) in lines.iter().enumerate() {
+                let (line_glyphs, width) = image_pipeline::layout_label(
+                    (bounds.min.0, top + font_size + line_height * i as f32),
+                    bounds.max.0,
+                    atlas,
+                    font_size,
+                    &self.theme.placeholder,
+                    line,
+                );
+                glyphs.extend(line_glyphs);
+                widest = widest.max(width);
+            }
+
+            let offset = ((bounds.width() - widest) / 2.0).floor().max(0.0);
+            for glyph in &mut glyphs {
+                if let Drawables::TexturedRect(image) = glyph {
+                    image.position[0] += offset;
+                }
+            }
+            drawables.extend(glyphs);
+        }
+    }
+
+    /// A line for each of a few commands to start with, and the key that runs it.
+    fn quick_action_hints(&self) -> Vec<String> {
+        [
+            Command::OpenFile,
+            Command::FindInFiles,
+            Command::SplitHorizontal,
+            Command::Help,
+        ]
+        .into_iter()
+        .filter_map(|command| {
+            let chord = self
+                .keymap
+                .bindings()
+                .filter(|(_, bound)| *bound == command)
+                .map(|(chord, _)| chord.to_string())
+                .min()?;
+            Some(format!("{chord:<16}{}", command.name()))
+        })
+        .collect()
+    }
+
+    /// Outline the focused pane, when there is more than one to tell apart.
+    fn layout_focus_ring(&self, drawables: &mut Vec<Drawables>) {
+        let Some(focused) = self.focused else {
+            return;
+        };
+
+        let mut panes = vec![];
+        self.collect_text_nodes(self.node_root, &mut panes);
+        if panes.len() < 2 {
+            return;
+        }
+
+        let Some(bounds) = (match self.node(focused).as_ref() {
+            Ui::Text(td) => td.borrow().bounds,
+            _ => None,
+        }) else {
+            return;
+        };
+
+        drawables.push(Drawables::Rect(QuadInstance::outline(
+            [bounds.min.0, bounds.min.1],
+            [bounds.width(), bounds.height()],
+            &self.theme.cursor,
+            1.0,
+        )));
+    }
+
+    pub fn keymap(&self) -> &Keymap {
+        &self.keymap
+    }
+
+    /// Use 'clipboard' for copy and paste from now on.
+    pub fn set_clipboard(&mut self, clipboard: Clipboard) {
+        self.clipboard = clipboard;
+    }
+
+    /// Draw other people's cursors in the buffer at 'buffer' of 'text', replacing
+    /// the ones it had. Returns false if there is no such buffer.
+    pub fn set_remote_carets(
+        &self,
+        text: UiNodeId,
+        buffer: usize,
+        carets: Vec<RemoteCaret>,
+    ) -> bool {
+        match self.node(text).as_ref() {
+            Ui::Text(td) => td.borrow_mut().set_remote_carets(buffer, carets),
+            _ => false,
+        }
+    }
+
+    /// May the editor quit? With unsaved changes in any buffer, the first time only
+    /// warns about them, asking again right after goes ahead.
+    pub fn confirm_quit(&mut self) -> bool {
+        let unsaved = self
+            .nodes
+            .borrow()
+            .iter()
+            .flatten()
+            .any(|node| match node.as_ref() {
+                Ui::Text(td) => td.borrow().has_unsaved_changes(),
+                _ => false,
+            });
+        if !unsaved || self.quitting_unsaved {
+            return true;
+        }
+        self.quitting_unsaved = true;
+        false
+    }
+
+    /// Show 'notice' as a warning in the status bar, or remove the one shown.
+    pub fn set_notice(&mut self, notice: Option<String>) {
+        self.notice = notice;
+    }
+
+    pub fn set_keymap(&mut self, keymap: Keymap) {
+        self.keymap = keymap;
+    }
+
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    pub fn set_settings(&mut self, settings: Settings) {
+        for node in self.nodes.borrow().iter() {
+            if let Some(Ui::Text(td)) = node.as_deref() {
+                let mut td = td.borrow_mut();
+                td.set_wrap(settings.wrap);
+                td.set_indentation(settings.tab_width, settings.insert_spaces);
+                td.set_line_spacing(settings.line_height);
+                td.set_large_file_bytes(settings.large_file_bytes());
+                td.set_cursor_blink(settings.cursor_blink);
+            }
+        }
+        self.settings = settings;
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    /// Change the colors everything is drawn in, starting with the next frame.
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+        self.theme_fade = None;
+    }
+
+    /// Change the colors everything is drawn in, fading over from the current ones.
+    pub fn fade_to_theme(&mut self, theme: Theme) {
+        let from = self.theme.clone();
+        self.theme_fade = Some((from, theme, self.animation_clock.now()));
+    }
+
+    /// Turn zen mode on or off. It hides everything but the focused text, which is
+    /// shown in a centered column. Turning it off brings back the previous layout.
+    pub fn toggle_zen_mode(&mut self) {
+        self.zen_mode = !self.zen_mode;
+    }
+
+    /// Set how wide the text column is in zen mode.
+    pub fn set_zen_max_width(&mut self, max_width: f32) {
+        self.zen_max_width = max_width;
+    }
+
+    /// Set the directory find in files searches. Results already shown stay.
+    pub fn set_project_root(&mut self, root: impl Into<PathBuf>) {
+        self.project_root = root.into();
+        if let Some(Ui::FindInFiles(f)) = self.find_in_files.map(|id| self.node(id)).as_deref() {
+            f.borrow_mut().root = self.project_root.clone();
+        }
+    }
+
+    /// Open the find in files panel and focus it. Results are opened in the text
+    /// that was focused before.
+    pub fn open_find_in_files(&mut self) {
+        let panel = match self.find_in_files {
+            Some(panel) => panel,
+            None => {
+                let obj = FindInFiles {
+                    root: self.project_root.clone(),
+                    query: TextInput::default(),
+                    replacement: None,
+                    editing_replacement: false,
+                    search: None,
+                    results: VirtualList::new(14.0),
+                    rows: vec![],
+                    unchecked: HashSet::new(),
+                    replaced: None,
+                    open: false,
+                    return_focus: None,
+                    bounds: None,
+                    font_size: 14.0,
+                };
+                let idx = self.nodes.borrow().len();
+                self.nodes
+                    .borrow_mut()
+                    .push(Some(Rc::new(Ui::FindInFiles(Box::new(RefCell::new(obj))))));
+                self.find_in_files = Some(UiNodeId(idx));
+                UiNodeId(idx)
+            }
+        };
+
+        if let Ui::FindInFiles(f) = self.node(panel).as_ref() {
+            let mut f = f.borrow_mut();
+            f.open = true;
+            if self.focused != Some(panel) {
+                f.return_focus = self.focused;
+            }
+        }
+        self.focused = Some(panel);
+    }
+
+    /// Close the find in files panel, giving the focus back to the text it took
+    /// it from. Returns false if the panel wasn't open.
+    pub fn close_find_in_files(&mut self) -> bool {
+        let Some(panel) = self.open_find_in_files_panel() else {
+            return false;
+        };
+        let Ui::FindInFiles(f) = panel.as_ref() else {
+            return false;
+        };
+
+        let mut f = f.borrow_mut();
+        f.open = false;
+        if self.focused == self.find_in_files {
+            self.focused = f.return_focus;
+        }
+        true
+    }
+
+    fn open_find_in_files_panel(&self) -> Option<Rc<Ui>> {
+        let panel = self.node(self.find_in_files?);
+        match panel.as_ref() {
+            Ui::FindInFiles(f) if f.borrow().open => Some(panel),
+            _ => None,
+        }
+    }
+
+    /// The text node keys go to. While the find in files panel is focused that is
+    /// the text it was opened from, which still needs to see modifier keys.
+    fn keyboard_target(&self) -> Option<UiNodeId> {
+        let focused = self.focused?;
+        match self.node(focused).as_ref() {
+            Ui::FindInFiles(f) => f.borrow().return_focus,
+            Ui::Problems(p) => p.borrow().return_focus,
+            Ui::Help(h) => h.borrow().return_focus,
+            Ui::FileViewer(v) => v.borrow().return_focus,
+            _ => Some(focused),
+        }
+    }
+
+    /// Handle a key while the find in files panel is focused. Returns true if the
+    /// panel used it.
+    fn find_in_files_keystroke(
+        &mut self,
+        event: &KeyInput,
+        glyph_rasterizer: &mut impl text_editor::GlyphRasterizer,
+    ) -> bool {
+        if event.state != ElementState::Pressed || self.focused != self.find_in_files {
+            return false;
+        }
+        let Some(panel) = self.open_find_in_files_panel() else {
+            return false;
+        };
+        let Ui::FindInFiles(f) = panel.as_ref() else {
+            return false;
+        };
+
+        let chord = self.chord(event);
+        let ctrl = self.modifiers.ctrl;
+        let mut f = f.borrow_mut();
+        match &event.logical_key {
+            Key::Named(NamedKey::Control | NamedKey::Shift) => return false,
+            _ if self.keymap.lookup(&chord) == Some(Command::Replace) => f.toggle_replacement(),
+            Key::Named(NamedKey::Enter) if ctrl && f.replacement.is_some() => {
+                // Replace what the search found, unless the query changed since.
+                let started = f.start_search();
+                drop(f);
+                if !started {
+                    self.replace_in_files();
+                }
+            }
+            Key::Named(NamedKey::Enter) => {
+                // Enter searches for a new query, or opens the selected result.
+                let started = f.start_search();
+                if !started {
+                    drop(f);
+                    self.open_selected_hit(glyph_rasterizer);
+                }
+            }
+            Key::Named(NamedKey::Tab) => {
+                f.editing_replacement = f.replacement.is_some() && !f.editing_replacement;
+            }
+            Key::Named(NamedKey::Space) if ctrl => f.toggle_selected(),
+            _ if f.results.keystroke(event) => {}
+            _ => {
+                // The rest edit whichever field is focused. The preview shows each
+                // change to the replacement.
+                let editing_replacement = f.editing_replacement;
+                let input = match &mut f.replacement {
+                    Some(replacement) if editing_replacement => replacement,
+                    _ => &mut f.query,
+                };
+                let edited = input.keystroke(event, self.modifiers) == InputAction::Edited;
+                if edited && editing_replacement {
+                    f.update_rows();
+                }
+            }
+        }
+
+        true
+    }
+
+    /// Replace the checked matches in the find in files preview. Each file is
+    /// changed in its buffer as a single undo step, after opening it in the text
+    /// the panel came from if it isn't open anywhere. Nothing is saved.
+    fn replace_in_files(&mut self) {
+        let Some(panel) = self.open_find_in_files_panel() else {
+            return;
+        };
+        let Ui::FindInFiles(f) = panel.as_ref() else {
+            return;
+        };
+        let (files, replacement, return_focus) = {
+            let f = f.borrow();
+            let Some(replacement) = f.replacement.as_ref().map(TextInput::text) else {
+                return;
+            };
+            (f.checked_matches(), replacement, f.return_focus)
+        };
+
+        let mut texts = vec![];
+        self.collect_text_nodes(self.node_root, &mut texts);
+        let target = return_focus.or_else(|| texts.first().copied());
+
+        let mut replaced = 0;
+        let mut changed_files = 0;
+        let mut skipped = 0;
+        for (path, matches) in files {
+            let count = self.replace_in_file(&texts, target, &path, &matches, &replacement);
+            if count > 0 {
+                changed_files += 1;
+            }
+            replaced += count;
+            skipped += matches.len() - count;
+        }
+
+        let matches_plural = if replaced == 1 { "" } else { "es" };
+        let files_plural = if changed_files == 1 { "" } else { "s" };
+        let mut summary = format!(
+            "replaced {replaced} match{matches_plural} in {changed_files} file{files_plural}"
+        );
+        if skipped > 0 {
+            summary += &format!(", skipped {skipped} that changed since the search");
+        }
+        f.borrow_mut().finish_replacing(summary);
+    }
+
+    /// Replace 'matches' in the buffer showing 'path', opening it in 'target' if
+    /// no text has it open. Matches on lines that changed since they were found
+    /// are left alone. Returns how many were replaced.
+    fn replace_in_file(
+        &self,
+        texts: &[UiNodeId],
+        target: Option<UiNodeId>,
+        path: &Path,
+        matches: &[LineMatch],
+        replacement: &str,
+    ) -> usize {
+        if self.too_large_to_edit(path) {
+            return 0;
+        }
+
+        let open = texts.iter().find_map(|&id| {
+            let node = self.node(id);
+            let Ui::Text(td) = node.as_ref() else {
+                return None;
+            };
+            let buffer = td.borrow().buffers.position_of_path(path)?;
+            Some((id, buffer))
+        });
+        let (text, buffer) = match (open, target) {
+            (Some(open), _) => open,
+            (None, Some(target)) => {
+                let node = self.node(target);
+                let Ui::Text(td) = node.as_ref() else {
+                    return 0;
+                };
+                let mut td = td.borrow_mut();
+                td.open_path(path);
+
+                // Large files load in the background and can't be changed yet.
+                match td.buffers.position_of_path(path) {
+                    Some(buffer) => (target, buffer),
+                    None => return 0,
+                }
+            }
+            (None, None) => return 0,
+        };
+
+        let node = self.node(text);
+        let Ui::Text(td) = node.as_ref() else {
+            return 0;
+        };
+        let mut td = td.borrow_mut();
+        let Some(editor) = td.buffers.get_mut(buffer) else {
+            return 0;
+        };
+
+        let replacements: Vec<_> = matches
+            .iter()
+            .filter(|m| m.line < editor.line_count() && editor.rope().line(m.line) == m.text)
+            .map(|m| {
+                let start = editor.byte_of_line(m.line);
+                (
+                    start + m.range.start..start + m.range.end,
+                    replacement.to_string(),
+                )
+            })
+            .collect();
+        editor.replace_ranges(&replacements)
+    }
+
+    /// Open the file of the selected find in files result at its line.
+    fn open_selected_hit(&mut self, glyph_rasterizer: &mut impl text_editor::GlyphRasterizer) {
+        let Some(panel) = self.find_in_files.map(|id| self.node(id)) else {
+            return;
+        };
+        let Ui::FindInFiles(f) = panel.as_ref() else {
+            return;
+        };
+        let (hit, return_focus) = {
+            let f = f.borrow();
+            (f.selected_hit().cloned(), f.return_focus)
+        };
+        let Some(hit) = hit else {
+            return;
+        };
+
+        // Fall back to the first pane if the text the panel came from is gone.
+        let target = return_focus.or_else(|| {
+            let mut texts = vec![];
+            self.collect_text_nodes(self.node_root, &mut texts);
+            texts.first().copied()
+        });
+        let Some(target) = target else {
+            return;
+        };
+
+        if self.too_large_to_edit(&hit.path) {
+            self.open_viewer(&hit.path, hit.line);
+            return;
+        }
+
+        if let Ui::Text(td) = self.node(target).as_ref() {
+            let mut td = td.borrow_mut();
+            if !td.open_path_at_line(&hit.path, hit.line, glyph_rasterizer) {
+                return;
+            }
+            td.report_activity();
+        }
+        self.move_focus(target);
+    }
+
+    /// Open the problems panel and focus it, or close it if it is open.
+    pub fn toggle_problems(&mut self) {
+        if self.close_problems() {
+            return;
+        }
+
+        let panel = match self.problems {
+            Some(panel) => panel,
+            None => {
+                let obj = Problems {
+                    list: VirtualList::new(14.0),
+                    targets: vec![],
+                    errors: 0,
+                    warnings: 0,
+                    sources: vec![],
+                    open: false,
+                    return_focus: None,
+                    bounds: None,
+                    font_size: 14.0,
+                };
+                let idx = self.nodes.borrow().len();
+                self.nodes
+                    .borrow_mut()
+                    .push(Some(Rc::new(Ui::Problems(Box::new(RefCell::new(obj))))));
+                self.problems = Some(UiNodeId(idx));
+                UiNodeId(idx)
+            }
+        };
+
+        if let Ui::Problems(p) = self.node(panel).as_ref() {
+            let mut p = p.borrow_mut();
+            p.open = true;
+            p.return_focus = self.focused;
+        }
+        self.focused = Some(panel);
+        self.refresh_problems();
+    }
+
+    /// Close the problems panel, giving the focus back to the text it took it
+    /// from. Returns false if the panel wasn't open.
+    pub fn close_problems(&mut self) -> bool {
+        let Some(panel) = self.open_problems_panel() else {
+            return false;
+        };
+        let Ui::Problems(p) = panel.as_ref() else {
+            return false;
+        };
+
+        let mut p = p.borrow_mut();
+        p.open = false;
+        if self.focused == self.problems {
+            self.focused = p.return_focus;
+        }
+        true
+    }
+
+    fn open_problems_panel(&self) -> Option<Rc<Ui>> {
+        let panel = self.node(self.problems?);
+        match panel.as_ref() {
+            Ui::Problems(p) if p.borrow().open => Some(panel),
+            _ => None,
+        }
+    }
+
+    /// Collect the diagnostics of every buffer in every pane into the problems
+    /// panel, if it is open and any buffer was reparsed, opened or closed since.
+    fn refresh_problems(&self) {
+        let Some(panel) = self.open_problems_panel() else {
+            return;
+        };
+        let Ui::Problems(p) = panel.as_ref() else {
+            return;
+        };
+
+        let mut texts = vec![];
+        self.collect_text_nodes(self.node_root, &mut texts);
+
+        let mut sources = vec![];
+        for &text in &texts {
+            let node = self.node(text);
+            let Ui::Text(td) = node.as_ref() else {
+                continue;
+            };
+            let mut td = td.borrow_mut();
+            for (buffer, editor) in td.buffers.iter_mut().enumerate() {
+                editor.update_syntax();
+                let path = editor.path().map(Path::to_path_buf);
+                sources.push((text, buffer, editor.syntax_version(), path));
+            }
+        }
+        if p.borrow().sources == sources {
+            return;
+        }
+
+        let mut files = vec![];
+        for text in texts {
+            let node = self.node(text);
+            let Ui::Text(td) = node.as_ref() else {
+                continue;
+            };
+            for (buffer, editor) in td.borrow().buffers.iter().enumerate() {
+                let diagnostics: Vec<_> = editor
+                    .diagnostics()
+                    .iter()
+                    .map(|d| (d.clone(), editor.line_and_column(d.range.start)))
+                    .collect();
+                let name = editor
+                    .path()
+                    .map_or("untitled".to_string(), |p| p.display().to_string());
+                files.push(FileProblems {
+                    text,
+                    buffer,
+                    name,
+                    diagnostics,
+                });
+            }
+        }
+
+        let mut p = p.borrow_mut();
+        p.set_problems(files);
+        p.sources = sources;
+    }
+
+    /// Handle a key while the problems panel is focused. Returns true if the panel
+    /// used it.
+    fn problems_keystroke(
+        &mut self,
+        event: &KeyInput,
+        glyph_rasterizer: &mut impl text_editor::GlyphRasterizer,
+    ) -> bool {
+        if event.state != ElementState::Pressed || self.focused != self.problems {
+            return false;
+        }
+        let Some(panel) = self.open_problems_panel() else {
+            return false;
+        };
+        let Ui::Problems(p) = panel.as_ref() else {
+            return false;
+        };
+
+        let chord = self.chord(event);
+
+        match &event.logical_key {
+            Key::Named(NamedKey::Control | NamedKey::Shift | NamedKey::Alt) => return false,
+            Key::Named(NamedKey::Enter) => self.open_selected_problem(glyph_rasterizer),
+            _ if self.keymap.lookup(&chord) == Some(Command::Problems) => {
+                self.close_problems();
+            }
+            _ => {
+                p.borrow_mut().list.keystroke(event);
+            }
+        }
+
+        true
+    }
+
+    /// Open the help and focus it, or close it if it is open. It lists the
+    /// keymap as it is when opened.
+    pub fn toggle_help(&mut self) {
+        if self.close_help() {
+            return;
+        }
+
+        let panel = match self.help {
+            Some(panel) => panel,
+            None => {
+                let obj = Help {
+                    list: VirtualList::new(14.0),
+                    open: false,
+                    return_focus: None,
+                    font_size: 14.0,
+                };
+                let idx = self.nodes.borrow().len();
+                self.nodes
+                    .borrow_mut()
+                    .push(Some(Rc::new(Ui::Help(Box::new(RefCell::new(obj))))));
+                self.help = Some(UiNodeId(idx));
+                UiNodeId(idx)
+            }
+        };
+
+        if let Ui::Help(h) = self.node(panel).as_ref() {
+            let mut h = h.borrow_mut();
+            h.open = true;
+            h.return_focus = self.focused;
+            h.set_keymap(&self.keymap);
+        }
+        self.focused = Some(panel);
+    }
+
+    /// Close the help, giving the focus back to the text it took it from.
+    /// Returns false if the help wasn't open.
+    pub fn close_help(&mut self) -> bool {
+        let Some(panel) = self.open_help() else {
+            return false;
+        };
+        let Ui::Help(h) = panel.as_ref() else {
+            return false;
+        };
+
+        let mut h = h.borrow_mut();
+        h.open = false;
+        if self.focused == self.help {
+            self.focused = h.return_focus;
+        }
+        true
+    }
+
+    fn open_help(&self) -> Option<Rc<Ui>> {
+        let panel = self.node(self.help?);
+        match panel.as_ref() {
+            Ui::Help(h) if h.borrow().open => Some(panel),
+            _ => None,
+        }
+    }
+
+    /// Show 'path' read-only in the file viewer from 'line' on, and focus it.
+    /// Returns false if the file couldn't be mapped.
+    pub fn open_viewer(&mut self, path: &Path, line: usize) -> bool {
+        let file = match MappedFile::open(path) {
+            Ok(file) => file,
+            Err(e) => {
+                log::error!("Failed to open {}: {e}", path.display());
+                return false;
+            }
+        };
+        self.close_file_viewer();
+
+        let obj = FileViewer {
+            file,
+            path: path.to_path_buf(),
+            first_line: line,
+            page_lines: 1,
+            open: true,
+            return_focus: self.focused,
+            font_size: 14.0,
+        };
+        // Reuse the node, so the file it had is unmapped.
+        let node = Some(Rc::new(Ui::FileViewer(Box::new(RefCell::new(obj)))));
+        let panel = match self.file_viewer {
+            Some(panel) => {
+                self.nodes.borrow_mut()[panel.0] = node;
+                panel
+            }
+            None => {
+                let idx = self.nodes.borrow().len();
+                self.nodes.borrow_mut().push(node);
+                self.file_viewer = Some(UiNodeId(idx));
+                UiNodeId(idx)
+            }
+        };
+        self.focused = Some(panel);
+        true
+    }
+
+    /// Close the file viewer, giving the focus back to the text it took it from.
+    /// Returns false if the viewer wasn't open.
+    pub fn close_file_viewer(&mut self) -> bool {
+        let Some(panel) = self.open_file_viewer() else {
+            return false;
+        };
+        let Ui::FileViewer(v) = panel.as_ref() else {
+            return false;
+        };
+
+        let mut v = v.borrow_mut();
+        v.open = false;
+        if self.focused == self.file_viewer {
+            self.focused = v.return_focus;
+        }
+        true
+    }
+
+    fn open_file_viewer(&self) -> Option<Rc<Ui>> {
+        let panel = self.node(self.file_viewer?);
+        match panel.as_ref() {
+            Ui::FileViewer(v) if v.borrow().open => Some(panel),
+            _ => None,
+        }
+    }
+
+    /// Handle a key while the file viewer is focused. It takes every key but the
+    /// modifiers, so nothing is typed into the text behind it. Returns true if the
+    /// viewer took the key.
+    fn file_viewer_keystroke(&mut self, event: &KeyInput) -> bool {
+        if self.focused != self.file_viewer {
+            return false;
+        }
+        let Some(panel) = self.open_file_viewer() else {
+            return false;
+        };
+        let Ui::FileViewer(v) = panel.as_ref() else {
+            return false;
+        };
+
+        match &event.logical_key {
+            Key::Named(NamedKey::Control | NamedKey::Shift | NamedKey::Alt) => false,
+            _ => {
+                v.borrow_mut().keystroke(event);
+                true
+            }
+        }
+    }
+
+    /// Open 'path' in the focused text, or in the file viewer if it is too large to
+    /// edit. Returns false if it couldn't be read.
+    pub fn open_path(&mut self, path: &Path) -> bool {
+        if self.too_large_to_edit(path) {
+            return self.open_viewer(path, 0);
+        }
+
+        let Some(target) = self.keyboard_target() else {
+            return false;
+        };
+        match self.node(target).as_ref() {
+            Ui::Text(td) => td.borrow_mut().open_path(path),
+            _ => false,
+        }
+    }
+
+    /// What is open in every pane, to pick up from when the editor is next started.
+    /// Untitled buffers are left out, there is nothing to open them from. So are
+    /// modified ones: their changes aren't kept, and the file on disk would come
+    /// back looking as if nothing was lost.
+    pub fn session(&self) -> Session {
+        let mut panes = vec![];
+        self.collect_text_nodes(self.node_root, &mut panes);
+
+        // The same file can be open in more than one pane, or by different paths.
+        let absolute = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let mut session = Session::default();
+        for id in panes {
+            let node = self.node(id);
+            let Ui::Text(td) = node.as_ref() else {
+                continue;
+            };
+            for editor in td.borrow().buffers().iter() {
+                let Some(path) = editor.path().filter(|_| !editor.is_modified()) else {
+                    continue;
+                };
+                let path = absolute(path);
+                if session.buffers.iter().any(|b| b.path == path) {
+                    continue;
+                }
+                session.buffers.push(SessionBuffer {
+                    path,
+                    cursor: editor.cursor_position(),
+                    scroll: editor.text_start_idx(),
+                });
+            }
+        }
+
+        let active = self
+            .keyboard_target()
+            .and_then(|id| match self.node(id).as_ref() {
+                Ui::Text(td) => td.borrow().editor().path().map(absolute),
+                _ => None,
+            });
+        session.active = session
+            .buffers
+            .iter()
+            .position(|b| Some(&b.path) == active.as_ref())
+            .unwrap_or(0);
+        session
+    }
+
+    /// Open the files of 'session' in the focused text. Files that have grown too
+    /// large to edit are left closed.
+    pub fn restore_session(&mut self, session: &Session) {
+        let Some(target) = self.keyboard_target() else {
+            return;
+        };
+        let buffers: Vec<_> = session
+            .buffers
+            .iter()
+            .filter(|buffer| !self.too_large_to_edit(&buffer.path))
+            .cloned()
+            .collect();
+        let active = session
+            .buffers
+            .get(session.active)
+            .map(|b| b.path.as_path());
+
+        if let Ui::Text(td) = self.node(target).as_ref() {
+            td.borrow_mut().restore_session(&buffers, active);
+        }
+    }
+
+    /// Is the file at 'path' too large to load for editing? Files that can't be
+    /// looked at are left to fail when they are opened.
+    fn too_large_to_edit(&self, path: &Path) -> bool {
+        std::fs::metadata(path)
+            .is_ok_and(|m| self.settings.file_size(m.len()) == FileSize::TooLarge)
+    }
+
+    /// Handle a key while the help is focused. Typing filters it. Returns true if
+    /// the help used the key.
+    fn help_keystroke(&mut self, event: &KeyInput) -> bool {
+        if event.state != ElementState::Pressed || self.focused != self.help {
+            return false;
+        }
+        let Some(panel) = self.open_help() else {
+            return false;
+        };
+        let Ui::Help(h) = panel.as_ref() else {
+            return false;
+        };
+
+        let chord = self.chord(event);
+
+        let mut h = h.borrow_mut();
+        let mut filter = h.list.filter().to_string();
+        match &event.logical_key {
+            Key::Named(NamedKey::Control | NamedKey::Shift | NamedKey::Alt) => return false,
+            _ if self.keymap.lookup(&chord) == Some(Command::Help) => {
+                drop(h);
+                self.close_help();
+                return true;
+            }
+            _ if h.list.keystroke(event) => return true,
+            Key::Named(NamedKey::Backspace) => {
+                filter.pop();
+            }
+            Key::Named(NamedKey::Space) => filter.push(' '),
+            Key::Character(c) => filter.push_str(c),
+            _ => {}
+        }
+        h.list.set_filter(&filter);
+
+        true
+    }
+
+    /// Handle a key while a list node is focused. The keys that move the selection
+    /// move it, and typing filters the list. Returns true if the list used the key.
+    fn virtual_list_keystroke(&mut self, event: &KeyInput) -> bool {
+        let Some(focused) = self.focused else {
+            return false;
+        };
+        if event.state != ElementState::Pressed {
+            return false;
+        }
+        let node = self.node(focused);
+        let Ui::VirtualList(l) = node.as_ref() else {
+            return false;
+        };
+
+        let mut l = l.borrow_mut();
+        let mut filter = l.filter().to_string();
+        match &event.logical_key {
+            _ if l.keystroke(event) => return true,
+            Key::Named(NamedKey::Backspace) => {
+                filter.pop();
+            }
+            Key::Named(NamedKey::Space) => filter.push(' '),
+            Key::Character(c) if !self.modifiers.ctrl => filter.push_str(c),
+            _ => return false,
+        }
+        l.set_filter(&filter);
+
+        true
+    }
+
+    /// Jump to the selected problem, in the pane and buffer it is in.
+    fn open_selected_problem(&mut self, glyph_rasterizer: &mut impl text_editor::GlyphRasterizer) {
+        let Some(panel) = self.problems.map(|id| self.node(id)) else {
+            return;
+        };
+        let Ui::Problems(p) = panel.as_ref() else {
+            return;
+        };
+        let Some(target) = p.borrow().selected_target() else {
+            return;
+        };
+
+        if let Ui::Text(td) = self.node(target.text).as_ref() {
+            let mut td = td.borrow_mut();
+            if target.buffer >= td.buffers.len() {
+                return;
+            }
+            td.buffers.switch_to(target.buffer);
+            let editor = td.editor_mut();
+            editor.move_cursor_to(target.byte_idx.min(editor.len()));
+            editor.scroll_to_cursor(glyph_rasterizer);
+            td.report_activity();
+        }
+        self.move_focus(target.text);
     }
 
     pub fn fixed_size_bbox(
@@ -463,7 +5034,7 @@ impl Scene {
         let idx = self.nodes.borrow().len();
         self.nodes
             .borrow_mut()
-            .push(Rc::new(Ui::FixedSizedBox(obj)));
+            .push(Some(Rc::new(Ui::FixedSizedBox(obj))));
         UiNodeId(idx)
     }
 
@@ -475,7 +5046,7 @@ impl Scene {
         let idx = self.nodes.borrow().len();
         self.nodes
             .borrow_mut()
-            .push(Rc::new(Ui::TexturedRectangle(obj)));
+            .push(Some(Rc::new(Ui::TexturedRectangle(obj))));
         UiNodeId(idx)
     }
 
@@ -491,60 +5062,353 @@ impl Scene {
         let idx = self.nodes.borrow().len();
         self.nodes
             .borrow_mut()
-            .push(Rc::new(Ui::TexturedRectangle(obj)));
+            .push(Some(Rc::new(Ui::TexturedRectangle(obj))));
+        UiNodeId(idx)
+    }
+
+    /// An icon drawn in the theme's widget foreground color, or in 'tint'.
+    pub fn icon(&self, icon: Icon, tint: Option<Color>) -> UiNodeId {
+        let obj = IconView { icon, tint };
+        let idx = self.nodes.borrow().len();
+        self.nodes.borrow_mut().push(Some(Rc::new(Ui::Icon(obj))));
         UiNodeId(idx)
     }
 
     pub fn rectangle(&self, color: Color) -> UiNodeId {
         let obj = Rectangle { color };
         let idx = self.nodes.borrow().len();
-        self.nodes.borrow_mut().push(Rc::new(Ui::Rectangle(obj)));
+        self.nodes
+            .borrow_mut()
+            .push(Some(Rc::new(Ui::Rectangle(obj))));
         UiNodeId(idx)
     }
 
-    pub fn text_details(
-        &self,
-        text: String,
-        font_size: f32,
-        text_color: Color,
-        background_color: Color,
-    ) -> UiNodeId {
+    pub fn text_details(&self, text: String, path: Option<PathBuf>, font_size: f32) -> UiNodeId {
         // TODO: way that we don't need to hardcode starting window sizes?
+        let mut editor = TextEditor::new(&text, 1360.0, 720.0, font_size);
+        if let Some(path) = path {
+            editor.set_path(path);
+        }
+        self.text_node(editor, font_size)
+    }
+
+    /// A text node showing 'editor', set up the way the settings say.
+    pub fn text_node(&self, mut editor: TextEditor, font_size: f32) -> UiNodeId {
+        let large_file_bytes = self.settings.large_file_bytes();
+        let large = editor.len() as u64 > large_file_bytes;
+        editor.set_wrap_policy(wrap_policy(self.settings.wrap, large));
+        editor.set_tab_width(self.settings.tab_width);
+        editor.set_insert_spaces(self.settings.insert_spaces);
+        editor.follow_detected_indentation();
+        if large {
+            editor.disable_syntax();
+        }
+
         let obj = Text {
-            editor: TextEditor::new(&text, 1360.0, 720.0, font_size),
+            buffers: Buffers::new(editor),
             font_size,
-            text_color,
-            background_color,
+            wrap: self.settings.wrap,
+            tab_width: self.settings.tab_width,
+            insert_spaces: self.settings.insert_spaces,
+            line_spacing: self.settings.line_height,
+            large_file_bytes,
             last_cursor_blink: Instant::now(),
             last_action: Instant::now(),
+            cursor_blink: self.settings.cursor_blink,
+            cursor_tween: None,
+            scroll_tween: Tween::new(0.0),
+            scrolled_lines: 0,
+            bounds: None,
+            search: None,
+            unicode_entry: None,
+            goto_line: None,
+            preedit: None,
+            cursor_area: None,
+            buffer_info: false,
+            loading: vec![],
+            opened_behind: None,
+            closing_unsaved: None,
+            message: None,
+        };
+        let idx = self.nodes.borrow().len();
+        self.nodes
+            .borrow_mut()
+            .push(Some(Rc::new(Ui::Text(Box::new(RefCell::new(obj))))));
+        UiNodeId(idx)
+    }
+
+    pub fn tab_bar(&self, child: UiNodeId, font_size: f32) -> UiNodeId {
+        let obj = TabBar {
+            child,
+            font_size,
+            tabs: RefCell::new(vec![]),
+            indentation_item: RefCell::new(None),
+            indentation_choices: RefCell::new(vec![]),
         };
         let idx = self.nodes.borrow().len();
+        self.nodes.borrow_mut().push(Some(Rc::new(Ui::TabBar(obj))));
+        UiNodeId(idx)
+    }
+
+    pub fn virtual_list(&self, items: Vec<String>, font_size: f32) -> UiNodeId {
+        let mut obj = VirtualList::new(font_size);
+        obj.set_items(items);
+        let idx = self.nodes.borrow().len();
         self.nodes
             .borrow_mut()
-            .push(Rc::new(Ui::Text(RefCell::new(obj))));
+            .push(Some(Rc::new(Ui::VirtualList(Box::new(RefCell::new(obj))))));
         UiNodeId(idx)
     }
 
     pub fn hbox(&self, elements: Vec<UiNodeId>) -> UiNodeId {
-        let obj = Hbox { elements };
+        let obj = Hbox {
+            elements: RefCell::new(elements),
+        };
         let idx = self.nodes.borrow().len();
-        self.nodes.borrow_mut().push(Rc::new(Ui::Hbox(obj)));
+        self.nodes.borrow_mut().push(Some(Rc::new(Ui::Hbox(obj))));
         UiNodeId(idx)
     }
 
     pub fn vbox(&self, elements: Vec<UiNodeId>) -> UiNodeId {
-        let obj = Vbox { elements };
+        let obj = Vbox {
+            elements: RefCell::new(elements),
+        };
         let idx = self.nodes.borrow().len();
-        self.nodes.borrow_mut().push(Rc::new(Ui::Vbox(obj)));
+        self.nodes.borrow_mut().push(Some(Rc::new(Ui::Vbox(obj))));
         UiNodeId(idx)
     }
 
-    pub fn update_cursor_pos(&mut self, cx: f32, cy: f32) {
+    pub fn update_cursor_pos(
+        &mut self,
+        cx: f32,
+        cy: f32,
+        glyph_rasterizer: &mut impl text_editor::GlyphRasterizer,
+    ) {
         self.cursor_pos = (cx, cy);
+        self.update_tab_hover();
+
+        if !self.dragging {
+            return;
+        }
+
+        if let Some(focused) = self.focused {
+            if let Ui::Text(td) = self.node(focused).as_ref() {
+                let mut td = td.borrow_mut();
+                if let Some(start) = self.block_drag {
+                    td.select_block(start, self.cursor_pos, glyph_rasterizer);
+                    td.report_activity();
+                } else if let Some(pos) = td.position_at(self.cursor_pos, glyph_rasterizer) {
+                    td.editor_mut().select_to(pos);
+                    td.report_activity();
+                }
+            }
+        }
+    }
+
+    /// Note which tab the pointer is on. Resting on the same tab keeps the time
+    /// it arrived, so its preview shows after the delay.
+    fn update_tab_hover(&mut self) {
+        let hovered = self
+            .nodes
+            .borrow()
+            .iter()
+            .flatten()
+            .find_map(|node| match node.as_ref() {
+                Ui::TabBar(t) => t.tab_at(self.cursor_pos).map(|tab| (t.child, tab)),
+                _ => None,
+            });
+
+        let same = self
+            .tab_hover
+            .is_some_and(|h| Some((h.text, h.tab)) == hovered);
+        if !same {
+            self.tab_hover = hovered.map(|(text, tab)| TabHover {
+                text,
+                tab,
+                since: self.animation_clock.now(),
+            });
+        }
+    }
+
+    /// Handle a mouse button. Pressing the left button on a text node focuses it and
+    /// moves the cursor there, and holding it down drags out a selection. A double
+    /// click selects the word under the pointer and a triple click the whole line.
+    pub fn mouse_input(
+        &mut self,
+        state: ElementState,
+        button: MouseButton,
+        click_count: usize,
+        glyph_rasterizer: &mut impl text_editor::GlyphRasterizer,
+    ) {
+        if button != MouseButton::Left {
+            return;
+        }
+
+        if state == ElementState::Released {
+            self.dragging = false;
+            self.block_drag = None;
+            return;
+        }
+
+        // Clicking anywhere closes the indentation menu, and on a choice also picks it.
+        if let Some(text) = self.indentation_menu.take() {
+            let choice =
+                self.nodes
+                    .borrow()
+                    .iter()
+                    .flatten()
+                    .find_map(|node| match node.as_ref() {
+                        Ui::TabBar(t) if t.child == text => {
+                            t.indentation_choice_at(self.cursor_pos)
+                        }
+                        _ => None,
+                    });
+            if let (Some((insert_spaces, tab_width)), Ui::Text(td)) =
+                (choice, self.node(text).as_ref())
+            {
+                let mut td = td.borrow_mut();
+                td.editor_mut().set_insert_spaces(insert_spaces);
+                td.editor_mut().set_tab_width(tab_width);
+            }
+            return;
+        }
+        let on_indentation =
+            self.nodes
+                .borrow()
+                .iter()
+                .flatten()
+                .find_map(|node| match node.as_ref() {
+                    Ui::TabBar(t) if t.on_indentation_item(self.cursor_pos) => Some(t.child),
+                    _ => None,
+                });
+        if on_indentation.is_some() {
+            self.indentation_menu = on_indentation;
+            return;
+        }
+
+        if let Some(panel) = self.open_find_in_files_panel() {
+            if let Ui::FindInFiles(f) = panel.as_ref() {
+                if f.borrow().bounds.is_some_and(|b| b.inside(self.cursor_pos)) {
+                    if self.focused != self.find_in_files {
+                        f.borrow_mut().return_focus = self.focused;
+                        self.focused = self.find_in_files;
+                    }
+                    let on_result = f.borrow_mut().results.click(self.cursor_pos);
+                    if on_result && click_count == 2 {
+                        self.open_selected_hit(glyph_rasterizer);
+                    }
+                    return;
+                }
+            }
+        }
+
+        if let Some(panel) = self.open_problems_panel() {
+            if let Ui::Problems(p) = panel.as_ref() {
+                if p.borrow().bounds.is_some_and(|b| b.inside(self.cursor_pos)) {
+                    if self.focused != self.problems {
+                        p.borrow_mut().return_focus = self.focused;
+                        self.focused = self.problems;
+                    }
+                    let on_row = p.borrow_mut().list.click(self.cursor_pos);
+                    if on_row && click_count == 2 {
+                        self.open_selected_problem(glyph_rasterizer);
+                    }
+                    return;
+                }
+            }
+        }
+
+        let list = self.find_node(|node| match node {
+            Ui::VirtualList(l) => l
+                .borrow()
+                .bounds()
+                .is_some_and(|b| b.inside(self.cursor_pos)),
+            _ => false,
+        });
+        if let Some(list) = list {
+            if let Ui::VirtualList(l) = self.node(list).as_ref() {
+                l.borrow_mut().click(self.cursor_pos);
+            }
+            self.focused = Some(list);
+            return;
+        }
+
+        let Some(clicked) = self.find_node(|node| match node {
+            Ui::Text(td) => td
+                .borrow()
+                .bounds
+                .is_some_and(|b| b.inside(self.cursor_pos)),
+            _ => false,
+        }) else {
+            return;
+        };
+        self.move_focus(clicked);
+
+        if let Ui::Text(td) = self.node(clicked).as_ref() {
+            let mut td = td.borrow_mut();
+            td.editor_mut().extend_selection = self.modifiers.shift;
+            match click_count {
+                1 if self.modifiers.alt => {
+                    td.select_block(self.cursor_pos, self.cursor_pos, glyph_rasterizer);
+                    self.block_drag = Some(self.cursor_pos);
+                    self.dragging = true;
+                }
+                1 => {
+                    if let Some(pos) = td.position_at(self.cursor_pos, glyph_rasterizer) {
+                        td.editor_mut().move_cursor_to(pos);
+                        self.dragging = true;
+                    }
+                }
+                2 => {
+                    if let Some(pos) = td.position_at(self.cursor_pos, glyph_rasterizer) {
+                        let word = td.editor().word_range_at(pos);
+                        td.editor_mut().select_range(word);
+                    }
+                }
+                _ => {
+                    if let Some(line) = td.line_at(self.cursor_pos, glyph_rasterizer) {
+                        td.editor_mut()
+                            .select_range(line.range.start..line.next_start());
+                    }
+                }
+            }
+
+            td.report_activity();
+        }
+    }
+
+    /// Handle the escape key. Returns false if there was nothing for it to close.
+    pub fn escape(&mut self) -> bool {
+        if self.indentation_menu.take().is_some() {
+            return true;
+        }
+        if let Some(focused) = self.focused {
+            if let Ui::Text(td) = self.node(focused).as_ref() {
+                let mut td = td.borrow_mut();
+                if td.cancel_unicode_entry()
+                    || td.cancel_goto_line()
+                    || td.close_search()
+                    || td.close_buffer_info()
+                    || td.editor_mut().clear_extra_cursors()
+                {
+                    return true;
+                }
+            }
+        }
+
+        self.close_help()
+            || self.close_file_viewer()
+            || self.close_find_in_files()
+            || self.close_problems()
     }
 
     fn node(&self, id: UiNodeId) -> Rc<Ui> {
-        self.nodes.borrow()[id.0].clone()
+        self.get_node(id).expect("node was removed")
+    }
+
+    /// The node with 'id', or None if it was removed.
+    fn get_node(&self, id: UiNodeId) -> Option<Rc<Ui>> {
+        self.nodes.borrow().get(id.0).cloned().flatten()
     }
 }
 
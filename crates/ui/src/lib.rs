@@ -1,21 +1,56 @@
+pub mod animation;
+pub mod buffers;
 pub mod camera_uniform;
+pub mod clipboard;
+pub mod debug_snapshot;
+pub mod file_dialog;
 pub mod image_pipeline;
+pub mod input;
+pub mod keymap;
+pub mod latency;
 pub mod layout;
+pub mod pipeline;
+pub mod project_search;
 pub mod quad_pipeline;
+pub mod recording;
+pub mod render_graph;
+pub mod resources;
+pub mod session;
+pub mod settings;
+#[cfg(debug_assertions)]
+mod shader_reload;
+pub mod text_input;
 pub mod texture;
 pub mod texture_atlas;
+pub mod theme;
+pub mod virtual_list;
 
 use camera_uniform::CameraUniform;
+use debug_snapshot::DebugSnapshot;
 use image_pipeline::ImagePipeline;
-use layout::{Color, Scene};
+use input::KeyInput;
+use keymap::Keymap;
+use latency::LatencyTracer;
+use layout::Scene;
 use quad_pipeline::QuadPipeline;
-use std::{cell::RefCell, io::Read, rc::Rc};
-use texture_atlas::TextureAtlas;
+use recording::{InputEvent, InputMode, Recorder, Recording, Replay};
+use render_graph::{RenderGraph, RenderTargets, ScenePass};
+use session::Session;
+use settings::{FileSize, Settings};
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+use text_editor::TextEditor;
+use texture_atlas::{GlyphAtlas, TextureAtlas};
+use theme::Theme;
 use wgpu::Surface;
 use winit::{
-    dpi::PhysicalSize,
-    event::{ElementState, Event, KeyEvent, WindowEvent},
-    event_loop::EventLoop,
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{ElementState, Event, Ime, KeyEvent, MouseButton, WindowEvent},
+    event_loop::{ControlFlow, EventLoop, EventLoopWindowTarget},
     keyboard::{KeyCode, PhysicalKey},
     window::{Window, WindowBuilder},
 };
@@ -23,7 +58,7 @@ use winit::{
 struct State<'window> {
     window: &'window Window,
     surface: wgpu::Surface<'window>,
-    device: wgpu::Device,
+    device: Rc<wgpu::Device>,
     queue: Rc<wgpu::Queue>,
     config: wgpu::SurfaceConfiguration,
 
@@ -32,12 +67,77 @@ struct State<'window> {
 
     quad_pipeline: QuadPipeline,
     image_pipeline: ImagePipeline,
+    render_targets: RenderTargets,
 
     scene: Scene,
+
+    /// When the left mouse button was last pressed, and how many presses in a row
+    /// came quickly enough to count as one multi-click.
+    last_click: Option<Instant>,
+    click_count: usize,
+
+    /// Set if the user picked a theme, which then doesn't follow the system appearance.
+    theme_pinned: bool,
+
+    /// The newest window size that the surface hasn't been configured for yet.
+    pending_size: Option<PhysicalSize<u32>>,
+    last_configure: Instant,
+
+    /// Times key presses until they are on screen, if the settings ask for it.
+    latency: LatencyTracer,
+
+    /// Save a debug snapshot after drawing the next frame.
+    snapshot_requested: bool,
+
+    /// How long a frame lasts on the monitor the window is on, and when the last
+    /// one was drawn. Frames are only drawn once one has passed.
+    frame_interval: Duration,
+    last_frame: Instant,
+
+    /// Is the window hidden, e.g. minimized or behind other windows? Nothing is
+    /// drawn while it is.
+    occluded: bool,
+
+    /// The cursor area last given to the input method.
+    ime_cursor_area: Option<layout::BoundingBox>,
+
+    /// Rebuilds the pipelines when their shaders are edited.
+    #[cfg(debug_assertions)]
+    shader_watcher: shader_reload::ShaderWatcher,
+
+    /// Saves the input of this session, if asked to on the command line.
+    recorder: Option<Recorder>,
+    /// Input of an earlier session being played back. Input from the window is
+    /// ignored until it's done.
+    replay: Option<Replay>,
+}
+
+/// Presses closer together than this count as a double or triple click.
+const MULTI_CLICK_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Dragging a window corner sends a flood of resize events. The surface is
+/// reconfigured at most this often while that happens.
+const RESIZE_INTERVAL: Duration = Duration::from_millis(33);
+
+/// Assumed when the monitor doesn't say how often it refreshes.
+const DEFAULT_REFRESH_RATE_MILLIHERTZ: u32 = 60_000;
+
+/// How long a frame lasts on the monitor 'window' is on.
+fn frame_interval(window: &Window) -> Duration {
+    let millihertz = window
+        .current_monitor()
+        .and_then(|monitor| monitor.refresh_rate_millihertz())
+        .filter(|&millihertz| millihertz > 0)
+        .unwrap_or(DEFAULT_REFRESH_RATE_MILLIHERTZ);
+    Duration::from_secs_f64(1000.0 / millihertz as f64)
 }
 
 impl<'window> State<'window> {
-    fn new(window: &'window Window, file_to_open: Option<String>) -> State<'window> {
+    fn new(
+        window: &'window Window,
+        file_to_open: Option<String>,
+        input_mode: Option<InputMode>,
+    ) -> State<'window> {
         let mut size = window.inner_size();
         size.width = size.width.max(1);
         size.height = size.height.max(1);
@@ -62,6 +162,7 @@ impl<'window> State<'window> {
             None,
         ))
         .expect("Failed to create device");
+        let device = Rc::new(device);
         let queue = Rc::new(queue);
 
         let config = wgpu::SurfaceConfiguration {
@@ -83,32 +184,122 @@ impl<'window> State<'window> {
             0,
         )));
 
-        let atlas = TextureAtlas::new(&device, queue.clone(), 1024);
+        let atlas_size = TextureAtlas::initial_size(&device.limits(), window.scale_factor());
+        let atlas = TextureAtlas::new(device.clone(), queue.clone(), atlas_size);
+
+        let quad_pipeline = QuadPipeline::new(&device, &camera_uniform.borrow(), &[]);
+        let image_pipeline = ImagePipeline::new(&device, &camera_uniform.borrow(), &atlas);
+        let render_targets = RenderTargets::new(size.width, size.height, config.format);
 
-        let quad_pipeline = QuadPipeline::new(&device, camera_uniform.clone());
-        let image_pipeline = ImagePipeline::new(&device, camera_uniform.clone(), &atlas);
+        // Dead keys and input methods send what they compose as Ime events.
+        window.set_ime_allowed(true);
 
         let mut scene = Scene::default();
 
-        let file_contents = if let Some(file_name) = file_to_open {
-            let mut file = std::fs::File::open(file_name).unwrap();
-            let mut buf = String::new();
-            file.read_to_string(&mut buf).unwrap();
-            buf
-        } else {
-            String::from("")
+        // A theme file pins the theme. Otherwise it follows the system's appearance.
+        let mut theme_pinned = false;
+        if let Some(theme_path) = Theme::default_path().filter(|p| p.exists()) {
+            match Theme::load(&theme_path) {
+                Ok(theme) => {
+                    scene.set_theme(theme);
+                    theme_pinned = true;
+                }
+                Err(e) => log::error!("Failed to load theme {}: {e}", theme_path.display()),
+            }
+        }
+        if let (false, Some(appearance)) = (theme_pinned, window.theme()) {
+            scene.set_theme(Theme::for_appearance(appearance));
+        }
+        if let Some(keymap_path) = Keymap::default_path().filter(|p| p.exists()) {
+            match Keymap::load(&keymap_path) {
+                Ok(keymap) => scene.set_keymap(keymap),
+                Err(e) => log::error!("Failed to load keymap {}: {e}", keymap_path.display()),
+            }
+        }
+        if let Some(settings_path) = Settings::default_path().filter(|p| p.exists()) {
+            match Settings::load(&settings_path) {
+                Ok(settings) => scene.set_settings(settings),
+                Err(e) => log::error!("Failed to load settings {}: {e}", settings_path.display()),
+            }
+        }
+
+        // Started without a file, the files open last time come back. Not for
+        // recordings, whose replays start from the file they name.
+        let restore_session =
+            file_to_open.is_none() && input_mode.is_none() && scene.settings().restore_session;
+
+        // A replay starts from the file its recording did.
+        let start = Instant::now();
+        let mut file_to_open = file_to_open;
+        let (recorder, replay) = match input_mode {
+            Some(InputMode::Record(path)) => {
+                let size = InputEvent::Resized {
+                    width: size.width,
+                    height: size.height,
+                };
+                let recorder = Recorder::new(&path, file_to_open.clone(), start)
+                    .and_then(|mut recorder| recorder.record(start, &size).map(|()| recorder));
+                match recorder {
+                    Ok(recorder) => (Some(recorder), None),
+                    Err(e) => {
+                        log::error!("Failed to record input to {}: {e}", path.display());
+                        (None, None)
+                    }
+                }
+            }
+            Some(InputMode::Replay(path)) => match Recording::load(&path) {
+                Ok(recording) => {
+                    if file_to_open.is_some() && file_to_open != recording.file {
+                        log::warn!("Replaying input that was recorded with another file open");
+                    }
+                    file_to_open = file_to_open.or(recording.file.clone());
+                    (None, Some(Replay::new(recording, start)))
+                }
+                Err(e) => {
+                    log::error!("Failed to load input to replay {}: {e}", path.display());
+                    (None, None)
+                }
+            },
+            None => (None, None),
         };
 
-        let td = scene.text_details(
-            file_contents,
-            16.0,
-            Color::new(255, 255, 255, 255),
-            Color::new(5, 5, 5, 255),
-        );
+        // A file too large to edit is only viewed, and a large one is loaded in the
+        // background. Both start out next to an empty buffer.
+        let file_size = file_to_open
+            .as_ref()
+            .and_then(|file_name| std::fs::metadata(file_name).ok())
+            .map(|m| scene.settings().file_size(m.len()));
+        let open_later = file_to_open
+            .take_if(|_| matches!(file_size, Some(FileSize::Large | FileSize::TooLarge)));
+
+        // A file that can't be read starts out empty, and is created when saved.
+        let td = match file_to_open {
+            Some(file_name) => match TextEditor::from_file(&file_name, 1360.0, 720.0, 16.0) {
+                Ok(editor) => scene.text_node(editor, 16.0),
+                Err(e) => {
+                    log::error!("Failed to open {file_name}: {e}");
+                    scene.text_details(String::new(), Some(file_name.into()), 16.0)
+                }
+            },
+            None => scene.text_details(String::new(), None, 16.0),
+        };
         scene.set_focus(td);
 
-        let root = scene.hbox(vec![td]);
+        let tabs = scene.tab_bar(td, 14.0);
+        let root = scene.hbox(vec![tabs]);
         scene.set_root(root);
+        if let Some(file_name) = open_later {
+            scene.open_path(Path::new(&file_name));
+        }
+        if let Some(session_path) = Session::default_path().filter(|_| restore_session) {
+            match Session::load(&session_path) {
+                Ok(session) => scene.restore_session(&session),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => log::error!("Failed to load session {}: {e}", session_path.display()),
+            }
+        }
+
+        let latency = LatencyTracer::new(scene.settings().latency_trace);
 
         Self {
             window,
@@ -122,12 +313,53 @@ impl<'window> State<'window> {
 
             quad_pipeline,
             image_pipeline,
+            render_targets,
 
             scene,
+
+            last_click: None,
+            click_count: 0,
+
+            theme_pinned,
+
+            pending_size: None,
+            last_configure: Instant::now(),
+
+            latency,
+            snapshot_requested: false,
+
+            frame_interval: frame_interval(window),
+            last_frame: Instant::now(),
+            occluded: false,
+            ime_cursor_area: None,
+
+            #[cfg(debug_assertions)]
+            shader_watcher: shader_reload::ShaderWatcher::default(),
+
+            recorder,
+            replay,
         }
     }
 
-    fn resize(&mut self, new_size: &PhysicalSize<u32>) {
+    /// Remember the new window size. The surface follows once RESIZE_INTERVAL has
+    /// passed since it was last configured, so the size the window ends up with is
+    /// applied shortly after the resizing stops.
+    fn resize(&mut self, new_size: &PhysicalSize<u32>, now: Instant) {
+        self.pending_size = Some(*new_size);
+        self.apply_pending_resize(false, now);
+    }
+
+    /// Configure the surface for the pending window size, if there is one and either
+    /// 'force' is set or it's been long enough since the last time.
+    fn apply_pending_resize(&mut self, force: bool, now: Instant) {
+        if !force && now.saturating_duration_since(self.last_configure) < RESIZE_INTERVAL {
+            return;
+        }
+        let Some(new_size) = self.pending_size.take() else {
+            return;
+        };
+        self.last_configure = now;
+
         let width = new_size.width.max(1);
         let height = new_size.height.max(1);
 
@@ -139,16 +371,39 @@ impl<'window> State<'window> {
             .update_size(&self.queue, width as f32, height as f32);
 
         self.surface.configure(&self.device, &self.config);
+        self.render_targets.resize(width, height);
         self.window.request_redraw();
     }
 
-    fn update(&mut self) {
-        let instances = self.scene.layout(
-            &mut self.atlas,
-            (self.config.width as f32, self.config.height as f32),
-            &self.queue,
-            self.window,
-        );
+    fn update(&mut self, now: Instant) {
+        #[cfg(debug_assertions)]
+        self.reload_changed_shaders();
+
+        self.scene.tick(now, &mut self.atlas);
+
+        let view_size = (self.config.width as f32, self.config.height as f32);
+        let atlas_generation = self.atlas.generation();
+        let mut instances = self.scene.layout(&mut self.atlas, view_size);
+
+        // Glyphs laid out before the atlas grew point at the wrong spot in the new
+        // texture. Everything fits now, so a second pass gets them all right.
+        if self.atlas.generation() != atlas_generation {
+            instances = self.scene.layout(&mut self.atlas, view_size);
+        }
+
+        // Input methods show their candidates next to the cursor, wherever it moved.
+        let ime_cursor_area = self.scene.ime_cursor_area();
+        if let Some(area) = ime_cursor_area.filter(|_| ime_cursor_area != self.ime_cursor_area) {
+            self.window.set_ime_cursor_area(
+                PhysicalPosition::new(area.min.0, area.min.1),
+                PhysicalSize::new(area.width(), area.height()),
+            );
+        }
+        self.ime_cursor_area = ime_cursor_area;
+
+        let view_box = layout::BoundingBox::new(0.0, 0.0, view_size.0, view_size.1);
+        instances.extend(self.latency.sparkline(view_box, self.scene.theme()));
+        self.latency.laid_out(Instant::now());
 
         let quad_instances = self.quad_pipeline.instances();
         let image_instances = self.image_pipeline.instances();
@@ -163,87 +418,385 @@ impl<'window> State<'window> {
             }
         }
 
-        self.quad_pipeline.update(&self.queue);
-        self.image_pipeline.update(&self.queue);
+        self.quad_pipeline.update(&self.device, &self.queue);
+        self.image_pipeline.update(&self.device, &self.queue);
+        self.image_pipeline.update_atlas(&self.device, &self.atlas);
+    }
+
+    /// Rebuild the pipelines of shaders saved since the last frame. A shader that
+    /// doesn't compile leaves its pipeline as it was and shows the error.
+    #[cfg(debug_assertions)]
+    fn reload_changed_shaders(&mut self) {
+        use shader_reload::Shader;
+
+        for (shader, source) in self.shader_watcher.changed() {
+            let camera_uniform = &self.camera_uniform.borrow();
+            let reloaded = match shader {
+                Shader::Quad => {
+                    self.quad_pipeline
+                        .reload_shader(&self.device, camera_uniform, &[], &source)
+                }
+                Shader::Image => {
+                    self.image_pipeline
+                        .reload_shader(&self.device, camera_uniform, &source)
+                }
+            };
+
+            match reloaded {
+                Ok(()) => {
+                    log::info!("Reloaded {}", shader.file_name());
+                    self.scene.set_notice(None);
+                }
+                Err(e) => {
+                    log::error!("Failed to reload {}: {e}", shader.file_name());
+                    let first_line = e.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+                    let notice = format!("{} failed to compile: {first_line}", shader.file_name());
+                    self.scene.set_notice(Some(notice));
+                }
+            }
+        }
     }
 
     fn draw(&mut self) {
-        let frame = self
-            .surface
-            .get_current_texture()
-            .expect("Failed to acquire next swap chain texture");
+        self.last_frame = Instant::now();
+        let frame = match self.surface.get_current_texture() {
+            Ok(frame) => frame,
+            // The window size changed under the surface. Catch up and skip this frame.
+            Err(wgpu::SurfaceError::Outdated | wgpu::SurfaceError::Lost) => {
+                self.pending_size.get_or_insert(self.window.inner_size());
+                self.apply_pending_resize(true, Instant::now());
+                return;
+            }
+            Err(e) => panic!("Failed to acquire next swap chain texture: {e}"),
+        };
         let view = frame
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
         let mut encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        self.record_scene(&mut encoder, &view);
+
+        self.queue.submit(Some(encoder.finish()));
+        frame.present();
+        self.latency.presented(Instant::now());
+
+        if self.snapshot_requested {
+            self.snapshot_requested = false;
+            self.save_debug_snapshot();
+        }
+    }
+
+    /// Record the passes drawing the laid out scene into 'view'.
+    fn record_scene(&mut self, encoder: &mut wgpu::CommandEncoder, view: &wgpu::TextureView) {
         let camera_uniform = &self.camera_uniform.borrow();
-        {
-            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: None,
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.0,
-                            g: 0.0,
-                            b: 0.0,
-                            a: 1.0,
-                        }),
-                        store: wgpu::StoreOp::Store,
+
+        let scene_pass = ScenePass {
+            quad_pipeline: &self.quad_pipeline,
+            image_pipeline: &self.image_pipeline,
+            camera_uniform,
+            clear_color: self.scene.theme().background.to_wgpu(),
+        };
+
+        let mut graph = RenderGraph::default();
+        graph.add_pass(&scene_pass);
+        graph.execute(&self.device, encoder, &mut self.render_targets, view);
+    }
+
+    /// The scene as last laid out, drawn again into a texture that can be read
+    /// back, as a PNG. The surface itself may not allow copying out of it.
+    fn screenshot(&mut self) -> image::ImageResult<Vec<u8>> {
+        let (width, height) = (self.config.width, self.config.height);
+        let size = wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        };
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("screenshot"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        // Rows of a texture copy have to be padded to the alignment.
+        let bytes_per_row = (width * 4).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("screenshot"),
+            size: (bytes_per_row * height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        self.record_scene(&mut encoder, &view);
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(bytes_per_row),
+                    rows_per_image: None,
+                },
+            },
+            size,
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |_| {});
+        self.device.poll(wgpu::Maintain::Wait);
+
+        // The surface format is BGRA.
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        for row in slice.get_mapped_range().chunks(bytes_per_row as usize) {
+            for bgra in row[..(width * 4) as usize].chunks(4) {
+                pixels.extend([bgra[2], bgra[1], bgra[0], bgra[3]]);
+            }
+        }
+        buffer.unmap();
+
+        let image = image::RgbaImage::from_raw(width, height, pixels).unwrap();
+        let mut png = std::io::Cursor::new(vec![]);
+        image.write_to(&mut png, image::ImageFormat::Png)?;
+        Ok(png.into_inner())
+    }
+
+    /// Save a screenshot, the state of the buffers and scene, the config and
+    /// performance counters to a zip to attach to bug reports.
+    fn save_debug_snapshot(&mut self) {
+        let mut snapshot = DebugSnapshot::default();
+        match self.screenshot() {
+            Ok(png) => snapshot.add("screenshot.png", png),
+            Err(e) => log::error!("Failed to take a screenshot for the debug snapshot: {e}"),
+        }
+        snapshot.add("state.txt", self.scene.debug_report());
+        snapshot.add("config.txt", self.scene.config_report());
+
+        let atlas = self.atlas.stats();
+        let perf = format!(
+            "Window: {}x{}, scale factor {}\nAtlas: {}x{}, {} glyphs, {} evicted\n{}\n",
+            self.config.width,
+            self.config.height,
+            self.window.scale_factor(),
+            atlas.size,
+            atlas.size,
+            atlas.glyphs,
+            atlas.evictions,
+            self.latency.report(),
+        );
+        snapshot.add("perf.txt", perf);
+
+        let path = DebugSnapshot::default_path();
+        match snapshot.save(&path) {
+            Ok(()) => log::info!("Saved debug snapshot to {}", path.display()),
+            Err(e) => log::error!("Failed to save debug snapshot {}: {e}", path.display()),
+        }
+    }
+
+    /// Save what is open, to be restored on the next start, if the settings ask for
+    /// it. A replay's files aren't the user's, so they are never saved.
+    fn save_session(&self) {
+        if !self.scene.settings().restore_session || self.replay.is_some() {
+            return;
+        }
+        let Some(path) = Session::default_path() else {
+            return;
+        };
+        if let Err(e) = self.scene.session().save(&path) {
+            log::error!("Failed to save session {}: {e}", path.display());
+        }
+    }
+
+    /// What 'event' tells the scene, if anything.
+    fn translate(&mut self, event: &WindowEvent, now: Instant) -> Option<InputEvent> {
+        let input = match event {
+            WindowEvent::Resized(size) => InputEvent::Resized {
+                width: size.width,
+                height: size.height,
+            },
+            WindowEvent::Focused(focused) => InputEvent::Focused { focused: *focused },
+            WindowEvent::Occluded(occluded) => InputEvent::Occluded {
+                occluded: *occluded,
+            },
+            WindowEvent::Ime(Ime::Preedit(text, _)) => InputEvent::Preedit { text: text.clone() },
+            WindowEvent::Ime(Ime::Commit(text)) => InputEvent::Commit { text: text.clone() },
+            WindowEvent::Ime(Ime::Disabled) => InputEvent::Preedit {
+                text: String::new(),
+            },
+            WindowEvent::ModifiersChanged(modifiers) => InputEvent::Modifiers {
+                modifiers: modifiers.state().into(),
+            },
+            WindowEvent::ThemeChanged(theme) => InputEvent::ThemeChanged { theme: *theme },
+            WindowEvent::RedrawRequested => InputEvent::Redraw,
+            WindowEvent::MouseWheel { delta, .. } => InputEvent::Scroll { delta: *delta },
+            WindowEvent::CursorMoved { position, .. } => InputEvent::CursorMoved {
+                x: position.x as f32,
+                y: position.y as f32,
+            },
+            WindowEvent::MouseInput { state, button, .. } => {
+                if *state == ElementState::Pressed && *button == MouseButton::Left {
+                    let quick = self
+                        .last_click
+                        .is_some_and(|last| now - last < MULTI_CLICK_INTERVAL);
+
+                    // Clicks cycle through single, double and triple.
+                    self.click_count = if quick { self.click_count % 3 + 1 } else { 1 };
+                    self.last_click = Some(now);
+                }
+
+                InputEvent::MouseInput {
+                    state: *state,
+                    button: *button,
+                    clicks: self.click_count,
+                }
+            }
+            WindowEvent::KeyboardInput {
+                event:
+                    KeyEvent {
+                        physical_key: PhysicalKey::Code(KeyCode::Escape),
+                        state: ElementState::Pressed,
+                        ..
                     },
-                })],
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
-            });
-
-            self.quad_pipeline.draw(&mut rpass, camera_uniform);
-            self.image_pipeline.draw(&mut rpass, camera_uniform);
+                ..
+            } => InputEvent::Escape,
+            WindowEvent::KeyboardInput { event, .. } => InputEvent::Key {
+                key: KeyInput::from(event),
+            },
+            _ => return None,
+        };
+        Some(input)
+    }
+
+    /// Pass 'input' on to the scene, as if it came at 'now'.
+    fn handle_input(&mut self, input: InputEvent, now: Instant, elwt: &EventLoopWindowTarget<()>) {
+        if let Some(recorder) = &mut self.recorder {
+            if let Err(e) = recorder.record(now, &input) {
+                log::error!("Stopped recording input: {e}");
+                self.recorder = None;
+            }
         }
 
-        self.queue.submit(Some(encoder.finish()));
-        frame.present();
+        match input {
+            InputEvent::Resized { width, height } => {
+                let size = PhysicalSize::new(width, height);
+                if self.replay.is_some() {
+                    let _ = self.window.request_inner_size(size);
+                }
+                self.resize(&size, now);
+            }
+            InputEvent::Focused { focused } => self.scene.set_window_focused(focused),
+            InputEvent::Occluded { occluded } => {
+                self.occluded = occluded;
+                self.scene.set_window_occluded(occluded);
+            }
+            InputEvent::Preedit { text } => self.scene.set_preedit(&text),
+            InputEvent::Commit { text } => self.scene.commit_text(&text),
+            InputEvent::Modifiers { modifiers } => self.scene.set_modifiers(modifiers),
+            InputEvent::ThemeChanged { theme } => {
+                if !self.theme_pinned {
+                    self.scene.fade_to_theme(Theme::for_appearance(theme))
+                }
+            }
+            InputEvent::Redraw => {
+                self.apply_pending_resize(false, now);
+                self.update(now);
+                self.draw();
+            }
+            InputEvent::Scroll { delta } => self.scene.scroll(delta, &mut self.atlas),
+            InputEvent::CursorMoved { x, y } => self.scene.update_cursor_pos(x, y, &mut self.atlas),
+            InputEvent::MouseInput {
+                state,
+                button,
+                clicks,
+            } => self
+                .scene
+                .mouse_input(state, button, clicks, &mut self.atlas),
+            InputEvent::Escape => {
+                // Escape closes whatever is open first, e.g. the search box.
+                let closed_something = self.scene.escape();
+                if !closed_something && self.scene.confirm_quit() {
+                    elwt.exit()
+                }
+            }
+            InputEvent::Key { key } => {
+                if key.state == ElementState::Pressed {
+                    self.latency.key_received(Instant::now());
+                }
+                self.scene.send_keystroke(&key, &mut self.atlas);
+                self.latency.mutated(Instant::now());
+                self.snapshot_requested |= self.scene.take_snapshot_request();
+            }
+        }
+    }
+
+    /// Hand the scene the replayed input that is due, and wait for the next.
+    fn replay_due_input(&mut self, elwt: &EventLoopWindowTarget<()>) {
+        let Some(replay) = &mut self.replay else {
+            return;
+        };
+        for (time, input) in replay.due(Instant::now()) {
+            self.handle_input(input, time, elwt);
+        }
+
+        match self.replay.as_ref().and_then(Replay::next_time) {
+            Some(next) => elwt.set_control_flow(ControlFlow::WaitUntil(next)),
+            None => {
+                log::info!("Finished replaying input");
+                self.replay = None;
+                elwt.set_control_flow(ControlFlow::Wait);
+            }
+        }
     }
 
     fn run(&mut self, event_loop: EventLoop<()>) {
         event_loop
             .run(move |event, elwt| match event {
-                Event::AboutToWait => self.window.request_redraw(),
+                Event::AboutToWait if self.replay.is_some() => self.replay_due_input(elwt),
+                Event::AboutToWait => {
+                    // Draw once per refresh of the monitor, and not at all while hidden.
+                    let next_frame = self.last_frame + self.frame_interval;
+                    if self.occluded {
+                        elwt.set_control_flow(ControlFlow::Wait);
+                    } else if Instant::now() >= next_frame {
+                        elwt.set_control_flow(ControlFlow::Wait);
+                        self.window.request_redraw();
+                    } else {
+                        elwt.set_control_flow(ControlFlow::WaitUntil(next_frame));
+                    }
+                }
+                Event::LoopExiting => {
+                    self.save_session();
+                }
                 Event::WindowEvent {
                     ref event,
                     window_id,
                 } if window_id == self.window.id() => match event {
-                    WindowEvent::Resized(new_size) => self.resize(new_size),
-                    WindowEvent::RedrawRequested => {
-                        self.update();
-                        self.draw();
+                    WindowEvent::CloseRequested => {
+                        if self.scene.confirm_quit() {
+                            elwt.exit()
+                        }
                     }
-                    WindowEvent::MouseWheel { delta, .. } => {
-                        self.scene.scroll(*delta, &mut self.atlas);
+                    // The window may have moved to a monitor with another refresh rate.
+                    WindowEvent::Moved(_) | WindowEvent::ScaleFactorChanged { .. } => {
+                        self.frame_interval = frame_interval(self.window);
                     }
-                    WindowEvent::CursorMoved {
-                        device_id: _,
-                        position,
-                    } => self
-                        .scene
-                        .update_cursor_pos(position.x as f32, position.y as f32),
-                    WindowEvent::CloseRequested
-                    | WindowEvent::KeyboardInput {
-                        event:
-                            KeyEvent {
-                                physical_key: PhysicalKey::Code(KeyCode::Escape),
-                                state: ElementState::Pressed,
-                                ..
-                            },
-                        ..
-                    } => elwt.exit(),
-                    WindowEvent::KeyboardInput { event, .. } => {
-                        self.scene.send_keystroke(event, &mut self.atlas)
+                    // Input from the window would get in the way of a replay.
+                    _ if self.replay.is_some() => {}
+                    event => {
+                        let now = Instant::now();
+                        if let Some(input) = self.translate(event, now) {
+                            self.handle_input(input, now, elwt);
+                        }
                     }
-                    _ => {}
                 },
                 _ => {}
             })
@@ -251,13 +804,22 @@ impl<'window> State<'window> {
     }
 }
 
-pub fn run(file_to_open: Option<String>) {
+/// The directory the editor's config files live in: $XDG_CONFIG_HOME/editor, or
+/// ~/.config/editor.
+pub fn config_dir() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("editor"))
+}
+
+pub fn run(file_to_open: Option<String>, input_mode: Option<InputMode>) {
     let event_loop = EventLoop::new().unwrap();
     let window = WindowBuilder::new()
         .with_inner_size(PhysicalSize::new(1360, 720))
         .with_title("WGPU")
         .build(&event_loop)
         .unwrap();
-    let mut state = State::new(&window, file_to_open);
+    let mut state = State::new(&window, file_to_open, input_mode);
     state.run(event_loop);
 }
@@ -0,0 +1,191 @@
+use crate::camera_uniform::CameraUniform;
+use std::{borrow::Cow, marker::PhantomData};
+use wgpu::util::DeviceExt;
+
+/// Data that is uploaded to a vertex buffer, one per vertex or one per instance.
+pub trait VertexLayout: bytemuck::Pod {
+    fn desc() -> wgpu::VertexBufferLayout<'static>;
+}
+
+/// The shape every instance of a pipeline is drawn as.
+pub trait Mesh: VertexLayout {
+    const VERTICES: &'static [Self];
+    const INDICES: &'static [u16];
+}
+
+/// One drawn copy of a pipeline's mesh, e.g. a colored rect or a glyph. Says how the
+/// pipeline that draws it is built.
+pub trait Instance: VertexLayout {
+    type Mesh: Mesh;
+
+    /// The WGSL source the pipeline starts out with. It has 'vs_main' and 'fs_main'
+    /// entry points, and the camera uniform in bind group 0.
+    const SHADER: &'static str;
+    const BLEND: Option<wgpu::BlendState>;
+
+    /// How many instances fit in the buffer before it has to grow.
+    const INITIAL_CAPACITY: usize = 1024;
+}
+
+/// Draws a list of instances of the same mesh with a single draw call. Bind groups
+/// other than the camera, e.g. a texture, are owned by whoever wraps the pipeline and
+/// passed in when it's built and drawn.
+pub struct InstancedPipeline<I: Instance> {
+    pipeline: wgpu::RenderPipeline,
+
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    instance_buffer: wgpu::Buffer,
+    /// How many instances 'instance_buffer' has room for.
+    capacity: usize,
+    instances: Vec<I>,
+
+    mesh: PhantomData<I::Mesh>,
+}
+
+impl<I: Instance> InstancedPipeline<I> {
+    /// 'bind_group_layouts' are the layouts of the bind groups after the camera's.
+    pub fn new(
+        device: &wgpu::Device,
+        camera_uniform: &CameraUniform,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+    ) -> Self {
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Vertex Buffer"),
+            contents: bytemuck::cast_slice(I::Mesh::VERTICES),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Index Buffer"),
+            contents: bytemuck::cast_slice(I::Mesh::INDICES),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        let pipeline = Self::create_pipeline(device, camera_uniform, bind_group_layouts, I::SHADER);
+
+        Self {
+            pipeline,
+
+            vertex_buffer,
+            index_buffer,
+            instance_buffer: Self::instance_buffer(device, I::INITIAL_CAPACITY),
+            capacity: I::INITIAL_CAPACITY,
+            instances: vec![],
+
+            mesh: PhantomData,
+        }
+    }
+
+    fn instance_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Instance Buffer"),
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            size: (std::mem::size_of::<I>() * capacity) as u64,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_pipeline(
+        device: &wgpu::Device,
+        camera_uniform: &CameraUniform,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+        source: &str,
+    ) -> wgpu::RenderPipeline {
+        let layouts: Vec<&wgpu::BindGroupLayout> =
+            std::iter::once(camera_uniform.bind_group_layout())
+                .chain(bind_group_layouts.iter().copied())
+                .collect();
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: None,
+            bind_group_layouts: &layouts,
+            push_constant_ranges: &[],
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(source)),
+        });
+
+        device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: None,
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[I::Mesh::desc(), I::desc()],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    blend: I::BLEND,
+                    format: wgpu::TextureFormat::Bgra8UnormSrgb,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        })
+    }
+
+    /// Rebuild the pipeline with the shader in 'source'. Keeps the old one if it
+    /// doesn't compile.
+    #[cfg(debug_assertions)]
+    pub fn reload_shader(
+        &mut self,
+        device: &wgpu::Device,
+        camera_uniform: &CameraUniform,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+        source: &str,
+    ) -> Result<(), String> {
+        self.pipeline = crate::shader_reload::validated(device, || {
+            Self::create_pipeline(device, camera_uniform, bind_group_layouts, source)
+        })?;
+        Ok(())
+    }
+
+    pub fn instances(&mut self) -> &mut Vec<I> {
+        &mut self.instances
+    }
+
+    /// Upload the instances, growing the buffer first if they don't fit.
+    pub fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if self.instances.len() > self.capacity {
+            self.capacity = self.instances.len().next_power_of_two();
+            self.instance_buffer = Self::instance_buffer(device, self.capacity);
+        }
+
+        queue.write_buffer(
+            &self.instance_buffer,
+            0,
+            bytemuck::cast_slice(&self.instances),
+        );
+    }
+
+    /// 'bind_groups' go after the camera's, in the order their layouts were given.
+    pub fn draw<'a>(
+        &'a self,
+        rpass: &mut wgpu::RenderPass<'a>,
+        camera_uniform: &'a CameraUniform,
+        bind_groups: &[&'a wgpu::BindGroup],
+    ) {
+        rpass.set_pipeline(&self.pipeline);
+
+        rpass.set_bind_group(camera_uniform.index(), camera_uniform.bind_group(), &[]);
+        for (i, bind_group) in bind_groups.iter().enumerate() {
+            rpass.set_bind_group(camera_uniform.index() + 1 + i as u32, bind_group, &[]);
+        }
+
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        rpass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        rpass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        rpass.draw_indexed(
+            0..I::Mesh::INDICES.len() as u32,
+            0,
+            0..self.instances.len() as u32,
+        );
+    }
+}
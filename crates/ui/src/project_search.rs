@@ -0,0 +1,129 @@
+use regex::Regex;
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, TryRecvError},
+        Arc,
+    },
+    thread,
+};
+
+/// Searches stop after this many hits so a very common query can't use up all memory.
+const MAX_HITS: usize = 10_000;
+
+/// A line of a file that matched the search.
+#[derive(Debug, Clone)]
+pub struct SearchHit {
+    pub path: PathBuf,
+
+    /// Zero based line number.
+    pub line: usize,
+
+    /// The contents of the line, without the trailing newline.
+    pub text: String,
+
+    /// Byte ranges in 'text' of each match on the line.
+    pub matches: Vec<Range<usize>>,
+}
+
+/// Searches every file under a directory on a background thread, skipping files
+/// ignored by .gitignore. Hits arrive as they are found and are collected by poll().
+pub struct ProjectSearch {
+    receiver: Receiver<SearchHit>,
+    hits: Vec<SearchHit>,
+    done: bool,
+
+    /// Set when the search is dropped. The thread checks it before each file, so
+    /// it stops even when no more hits are found.
+    cancelled: Arc<AtomicBool>,
+}
+
+impl ProjectSearch {
+    /// Start searching 'root' for lines matching 'pattern'. Dropping the search
+    /// stops the background thread before it reads another file.
+    pub fn start(root: &Path, pattern: Regex) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let root = root.to_path_buf();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let stop = cancelled.clone();
+
+        thread::spawn(move || {
+            let mut hit_count = 0;
+            for entry in ignore::Walk::new(&root).flatten() {
+                if stop.load(Ordering::Relaxed) {
+                    return;
+                }
+                if !entry.file_type().is_some_and(|t| t.is_file()) {
+                    continue;
+                }
+
+                // Binary files and files that aren't UTF-8 fail here and are skipped.
+                let Ok(contents) = std::fs::read_to_string(entry.path()) else {
+                    continue;
+                };
+
+                for (line, text) in contents.lines().enumerate() {
+                    let matches: Vec<_> = pattern.find_iter(text).map(|m| m.range()).collect();
+                    if matches.is_empty() {
+                        continue;
+                    }
+
+                    let hit = SearchHit {
+                        path: entry.path().to_path_buf(),
+                        line,
+                        text: text.to_string(),
+                        matches,
+                    };
+
+                    // The receiver is gone, nobody wants the results anymore.
+                    if sender.send(hit).is_err() {
+                        return;
+                    }
+
+                    hit_count += 1;
+                    if hit_count >= MAX_HITS {
+                        return;
+                    }
+                }
+            }
+        });
+
+        Self {
+            receiver,
+            hits: vec![],
+            done: false,
+            cancelled,
+        }
+    }
+
+    /// Collect the hits found since the last call.
+    pub fn poll(&mut self) {
+        loop {
+            match self.receiver.try_recv() {
+                Ok(hit) => self.hits.push(hit),
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => {
+                    self.done = true;
+                    return;
+                }
+            }
+        }
+    }
+
+    pub fn hits(&self) -> &[SearchHit] {
+        &self.hits
+    }
+
+    /// Has the whole directory been searched?
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+}
+
+impl Drop for ProjectSearch {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
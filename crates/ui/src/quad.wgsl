@@ -5,6 +5,10 @@ struct VertexInput {
 struct VertexOutput {
     @builtin(position) clip_position: vec4f,
     @location(0) color: vec4f,
+    // Where in the rect the fragment is, in pixels from its top left.
+    @location(1) local: vec2f,
+    @location(2) @interpolate(flat) size: vec2f,
+    @location(3) @interpolate(flat) border: f32,
 }
 
 struct CameraUniform {
@@ -15,6 +19,7 @@ struct InstanceInput {
     @location(5) position: vec2f,
     @location(6) scale: vec2f,
     @location(7) color: vec4f,
+    @location(8) border: f32,
 }
 
 @group(0) @binding(0)
@@ -24,11 +29,21 @@ var<uniform> camera: CameraUniform;
 fn vs_main(model: VertexInput, instance: InstanceInput) -> VertexOutput {
     var out: VertexOutput;
     out.color = instance.color;
+    out.local = model.position * instance.scale;
+    out.size = instance.scale;
+    out.border = instance.border;
     out.clip_position = camera.projection * vec4f(model.position * instance.scale + instance.position, 0.0, 1.0);
     return out;
 }
 
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4f {
+    // Leave out everything further from the edges than the border is thick.
+    if in.border > 0.0 {
+        let edge_distance = min(in.local, in.size - in.local);
+        if min(edge_distance.x, edge_distance.y) > in.border {
+            discard;
+        }
+    }
     return in.color;
 }
\ No newline at end of file
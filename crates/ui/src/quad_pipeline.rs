@@ -1,6 +1,7 @@
-use crate::camera_uniform::CameraUniform;
-use std::{borrow::Cow, cell::RefCell, rc::Rc};
-use wgpu::util::DeviceExt;
+use crate::{
+    layout::Color,
+    pipeline::{Instance, InstancedPipeline, Mesh, VertexLayout},
+};
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
@@ -8,16 +9,43 @@ pub struct QuadInstance {
     pub position: [f32; 2],
     pub size: [f32; 2],
     pub color: [f32; 4],
+
+    /// 0 fills the rect. Anything else draws only a border that many pixels thick
+    /// along the inside of its edges, e.g. for an outline around a pane.
+    pub border: f32,
 }
 
 impl QuadInstance {
-    const ATTRIBS: [wgpu::VertexAttribute; 3] = wgpu::vertex_attr_array![
+    /// A rect filled with 'color'.
+    pub fn filled(position: [f32; 2], size: [f32; 2], color: &Color) -> Self {
+        Self {
+            position,
+            size,
+            color: color.to_f32_arr(),
+            border: 0.0,
+        }
+    }
+
+    /// Only the edges of a rect, 'border' pixels thick on the inside.
+    pub fn outline(position: [f32; 2], size: [f32; 2], color: &Color, border: f32) -> Self {
+        Self {
+            position,
+            size,
+            color: color.to_f32_arr(),
+            border,
+        }
+    }
+
+    const ATTRIBS: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
         5 => Float32x2,
         6 => Float32x2,
         7 => Float32x4,
+        8 => Float32,
     ];
+}
 
-    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+impl VertexLayout for QuadInstance {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
         wgpu::VertexBufferLayout {
             array_stride: std::mem::size_of::<QuadInstance>() as wgpu::BufferAddress,
             step_mode: wgpu::VertexStepMode::Instance,
@@ -26,6 +54,13 @@ impl QuadInstance {
     }
 }
 
+impl Instance for QuadInstance {
+    type Mesh = QuadVertex;
+
+    const SHADER: &'static str = include_str!("quad.wgsl");
+    const BLEND: Option<wgpu::BlendState> = None;
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct QuadVertex {
@@ -33,21 +68,25 @@ pub struct QuadVertex {
 }
 
 impl QuadVertex {
+    const ATTRIBS: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![
+        0 => Float32x2,
+    ];
+}
+
+impl Mesh for QuadVertex {
     #[rustfmt::skip]
-    pub const VERTICES: &'static [QuadVertex] = &[
+    const VERTICES: &'static [QuadVertex] = &[
         QuadVertex { position: [0.0, 1.0] },
         QuadVertex { position: [0.0, 0.0] },
         QuadVertex { position: [1.0, 1.0] },
         QuadVertex { position: [1.0, 0.0] },
     ];
 
-    pub const INDICES: &'static [u16] = &[0, 1, 2, 2, 3, 1];
-
-    const ATTRIBS: [wgpu::VertexAttribute; 1] = wgpu::vertex_attr_array![
-        0 => Float32x2,
-    ];
+    const INDICES: &'static [u16] = &[0, 1, 2, 2, 3, 1];
+}
 
-    pub fn desc() -> wgpu::VertexBufferLayout<'static> {
+impl VertexLayout for QuadVertex {
+    fn desc() -> wgpu::VertexBufferLayout<'static> {
         wgpu::VertexBufferLayout {
             array_stride: std::mem::size_of::<QuadVertex>() as wgpu::BufferAddress,
             step_mode: wgpu::VertexStepMode::Vertex,
@@ -56,106 +95,5 @@ impl QuadVertex {
     }
 }
 
-pub struct QuadPipeline {
-    pipeline: wgpu::RenderPipeline,
-
-    vertex_buffer: wgpu::Buffer,
-    index_buffer: wgpu::Buffer,
-    instance_buffer: wgpu::Buffer,
-    instances: Vec<QuadInstance>,
-}
-
-impl QuadPipeline {
-    pub fn new(device: &wgpu::Device, camera_uniform: Rc<RefCell<CameraUniform>>) -> Self {
-        let instance_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some("Instance Buffer"),
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-            size: (std::mem::size_of::<QuadInstance>() * 1024) as u64,
-            mapped_at_creation: false,
-        });
-
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Vertex Buffer"),
-            contents: bytemuck::cast_slice(QuadVertex::VERTICES),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
-
-        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Index Buffer"),
-            contents: bytemuck::cast_slice(QuadVertex::INDICES),
-            usage: wgpu::BufferUsages::INDEX,
-        });
-
-        let instances = vec![QuadInstance {
-            position: [0.0, 0.0],
-            size: [300.0, 300.0],
-            color: [1.0, 0.0, 0.0, 1.0],
-        }];
-
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: None,
-            bind_group_layouts: &[camera_uniform.borrow().bind_group_layout()],
-            push_constant_ranges: &[],
-        });
-
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: None,
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("quad.wgsl"))),
-        });
-
-        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: None,
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[QuadVertex::desc(), QuadInstance::desc()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::TextureFormat::Bgra8UnormSrgb.into())],
-            }),
-            primitive: wgpu::PrimitiveState::default(),
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState::default(),
-            multiview: None,
-        });
-
-        Self {
-            pipeline,
-
-            vertex_buffer,
-            instance_buffer,
-            index_buffer,
-            instances,
-        }
-    }
-
-    pub fn update(&mut self, queue: &wgpu::Queue) {
-        queue.write_buffer(
-            &self.instance_buffer,
-            0,
-            bytemuck::cast_slice(&self.instances),
-        );
-    }
-
-    pub fn draw<'a>(&'a self, rpass: &mut wgpu::RenderPass<'a>, camera_uniform: &'a CameraUniform) {
-        rpass.set_pipeline(&self.pipeline);
-
-        rpass.set_bind_group(camera_uniform.index(), camera_uniform.bind_group(), &[]);
-
-        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        rpass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-        rpass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-        rpass.draw_indexed(
-            0..QuadVertex::INDICES.len() as u32,
-            0,
-            0..self.instances.len() as u32,
-        );
-    }
-
-    pub fn instances(&mut self) -> &mut Vec<QuadInstance> {
-        &mut self.instances
-    }
-}
+/// Draws solid colored rects, and the borders of rects.
+pub type QuadPipeline = InstancedPipeline<QuadInstance>;
@@ -0,0 +1,171 @@
+use crate::input::{KeyInput, Modifiers};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+use winit::{
+    event::{ElementState, MouseButton, MouseScrollDelta},
+    window::Theme,
+};
+
+/// Input from the window, after it was translated into what the scene is told.
+/// Replaying the same events at the same times gets the scene into the same state.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum InputEvent {
+    Resized {
+        width: u32,
+        height: u32,
+    },
+    Focused {
+        focused: bool,
+    },
+    Occluded {
+        occluded: bool,
+    },
+    Preedit {
+        text: String,
+    },
+    Commit {
+        text: String,
+    },
+    Modifiers {
+        modifiers: Modifiers,
+    },
+    ThemeChanged {
+        theme: Theme,
+    },
+    Scroll {
+        delta: MouseScrollDelta,
+    },
+    CursorMoved {
+        x: f32,
+        y: f32,
+    },
+    /// 'clicks' is how many presses in a row counted as one multi-click.
+    MouseInput {
+        state: ElementState,
+        button: MouseButton,
+        clicks: usize,
+    },
+    Escape,
+    Key {
+        key: KeyInput,
+    },
+    /// A frame was laid out and drawn.
+    Redraw,
+}
+
+/// An event and when it came, counted from the start of the recording.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimedEvent {
+    pub micros: u64,
+    #[serde(flatten)]
+    pub event: InputEvent,
+}
+
+/// Everything needed to replay a session: the file it started with and the input
+/// that came after. Saved as TOML.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Recording {
+    pub file: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<TimedEvent>,
+}
+
+impl Recording {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        toml::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_toml()?)
+    }
+
+    fn to_toml(&self) -> io::Result<String> {
+        toml::to_string(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// What to do with the input of a session, picked on the command line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InputMode {
+    Record(PathBuf),
+    Replay(PathBuf),
+}
+
+/// Writes the input of a session to a file as it comes, so that a crash loses
+/// none of it. Each event is appended as one more entry of the 'events' array.
+pub struct Recorder {
+    out: File,
+    start: Instant,
+}
+
+impl Recorder {
+    pub fn new(path: &Path, file: Option<String>, start: Instant) -> io::Result<Self> {
+        let mut out = File::create(path)?;
+        out.write_all(
+            Recording {
+                file,
+                events: vec![],
+            }
+            .to_toml()?
+            .as_bytes(),
+        )?;
+        Ok(Self { out, start })
+    }
+
+    pub fn record(&mut self, now: Instant, event: &InputEvent) -> io::Result<()> {
+        let event = TimedEvent {
+            micros: now.duration_since(self.start).as_micros() as u64,
+            event: event.clone(),
+        };
+        let entry = Recording {
+            file: None,
+            events: vec![event],
+        };
+        self.out.write_all(entry.to_toml()?.as_bytes())
+    }
+}
+
+/// Hands out the events of a recording once as much time has passed since the
+/// replay started as had when they were recorded.
+pub struct Replay {
+    start: Instant,
+    events: std::vec::IntoIter<TimedEvent>,
+    next: Option<TimedEvent>,
+}
+
+impl Replay {
+    pub fn new(recording: Recording, start: Instant) -> Self {
+        let mut events = recording.events.into_iter();
+        let next = events.next();
+        Self {
+            start,
+            events,
+            next,
+        }
+    }
+
+    /// When the next event is due, or None once all of them were handed out.
+    pub fn next_time(&self) -> Option<Instant> {
+        let next = self.next.as_ref()?;
+        Some(self.start + Duration::from_micros(next.micros))
+    }
+
+    /// The events due at 'now', with the time each was recorded at moved to this
+    /// replay. That time, not 'now', is what the scene should be told, so that
+    /// animations end up in the same state however late the events are handled.
+    pub fn due(&mut self, now: Instant) -> Vec<(Instant, InputEvent)> {
+        let mut due = vec![];
+        while let Some(time) = self.next_time().filter(|&time| time <= now) {
+            let next = std::mem::replace(&mut self.next, self.events.next()).unwrap();
+            due.push((time, next.event));
+        }
+        due
+    }
+}
@@ -0,0 +1,171 @@
+use crate::{
+    camera_uniform::CameraUniform, image_pipeline::ImagePipeline, quad_pipeline::QuadPipeline,
+    texture::Texture,
+};
+use std::collections::{HashMap, HashSet};
+
+/// The texture a pass renders into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Target {
+    /// The swapchain texture that gets presented at the end of the frame.
+    Surface,
+    /// A named offscreen texture the size of the surface. Later passes can sample it.
+    Offscreen(&'static str),
+}
+
+/// Declares what a pass reads and writes so the graph can allocate targets
+/// and check that passes run in a valid order.
+#[derive(Debug, Clone)]
+pub struct PassDesc {
+    pub name: &'static str,
+    /// Offscreen targets this pass samples. Each must be written by an earlier pass.
+    pub inputs: Vec<&'static str>,
+    pub output: Target,
+    /// Color to clear the output with, or None to draw over its current contents.
+    pub clear: Option<wgpu::Color>,
+}
+
+/// A single render pass in the frame graph.
+pub trait RenderNode {
+    fn desc(&self) -> PassDesc;
+
+    /// Records the draw calls for this pass. Offscreen inputs can be looked up in 'targets'.
+    fn record<'p>(&'p self, rpass: &mut wgpu::RenderPass<'p>, targets: &'p RenderTargets);
+}
+
+/// Offscreen textures shared between passes. These live across frames and are
+/// reallocated whenever the surface changes size.
+pub struct RenderTargets {
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+    textures: HashMap<&'static str, Texture>,
+}
+
+impl RenderTargets {
+    pub fn new(width: u32, height: u32, format: wgpu::TextureFormat) -> Self {
+        Self {
+            width,
+            height,
+            format,
+            textures: HashMap::new(),
+        }
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if self.width != width || self.height != height {
+            self.width = width;
+            self.height = height;
+            self.textures.clear();
+        }
+    }
+
+    /// Get an offscreen target written by an earlier pass this frame.
+    pub fn get(&self, name: &str) -> Option<&Texture> {
+        self.textures.get(name)
+    }
+
+    fn ensure(&mut self, device: &wgpu::Device, name: &'static str) {
+        let (width, height, format) = (self.width, self.height, self.format);
+        self.textures
+            .entry(name)
+            .or_insert_with(|| Texture::render_target(device, width, height, format, name));
+    }
+}
+
+/// An ordered list of passes making up one frame. Passes run in the order they
+/// were added; their declared inputs are checked against the outputs of earlier passes.
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+    passes: Vec<&'a dyn RenderNode>,
+}
+
+impl<'a> RenderGraph<'a> {
+    pub fn add_pass(&mut self, node: &'a dyn RenderNode) {
+        self.passes.push(node);
+    }
+
+    pub fn execute(
+        &self,
+        device: &wgpu::Device,
+        encoder: &mut wgpu::CommandEncoder,
+        targets: &mut RenderTargets,
+        surface_view: &wgpu::TextureView,
+    ) {
+        let descs: Vec<PassDesc> = self.passes.iter().map(|pass| pass.desc()).collect();
+
+        // Allocate everything up front so the passes below only need shared access.
+        let mut written = HashSet::new();
+        for desc in &descs {
+            for input in &desc.inputs {
+                assert!(
+                    written.contains(input),
+                    "render pass '{}' reads '{}' before any pass writes it",
+                    desc.name,
+                    input
+                );
+            }
+
+            if let Target::Offscreen(name) = desc.output {
+                targets.ensure(device, name);
+                written.insert(name);
+            }
+        }
+
+        let targets = &*targets;
+        for (pass, desc) in self.passes.iter().zip(&descs) {
+            let view = match desc.output {
+                Target::Surface => surface_view,
+                Target::Offscreen(name) => &targets.get(name).unwrap().view,
+            };
+
+            let load = match desc.clear {
+                Some(color) => wgpu::LoadOp::Clear(color),
+                None => wgpu::LoadOp::Load,
+            };
+
+            let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(desc.name),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load,
+                        store: wgpu::StoreOp::Store,
+                    },
+                })],
+                depth_stencil_attachment: None,
+                timestamp_writes: None,
+                occlusion_query_set: None,
+            });
+
+            pass.record(&mut rpass, targets);
+        }
+    }
+}
+
+/// Draws the laid out scene: all quads, then all textured quads on top.
+pub struct ScenePass<'a> {
+    pub quad_pipeline: &'a QuadPipeline,
+    pub image_pipeline: &'a ImagePipeline,
+    pub camera_uniform: &'a CameraUniform,
+
+    /// The surface is cleared to this before drawing.
+    pub clear_color: wgpu::Color,
+}
+
+impl RenderNode for ScenePass<'_> {
+    fn desc(&self) -> PassDesc {
+        PassDesc {
+            name: "scene",
+            inputs: vec![],
+            output: Target::Surface,
+            clear: Some(self.clear_color),
+        }
+    }
+
+    fn record<'p>(&'p self, rpass: &mut wgpu::RenderPass<'p>, _targets: &'p RenderTargets) {
+        self.quad_pipeline.draw(rpass, self.camera_uniform, &[]);
+        self.image_pipeline.draw(rpass, self.camera_uniform);
+    }
+}
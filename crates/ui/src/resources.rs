@@ -0,0 +1,132 @@
+use image::RgbaImage;
+use tiny_skia::{FillRule, LineCap, Paint, Path, PathBuilder, Pixmap, Rect, Stroke, Transform};
+
+/// The small icons used by the UI chrome. They are drawn from paths built into the
+/// binary, so they can be rasterized crisply at whatever size they are shown.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Icon {
+    Close,
+    Folder,
+    File,
+    Warning,
+    Error,
+}
+
+/// Icon paths are written on a grid this many units wide and tall.
+const GRID: f32 = 16.0;
+
+enum Style {
+    Fill(FillRule),
+    Stroke(f32),
+}
+
+impl Icon {
+    /// Rasterize the icon into a 'size' by 'size' image. It is white with the shape in
+    /// the alpha channel, so it can be tinted like a glyph.
+    pub fn rasterize(self, size: u32) -> RgbaImage {
+        let size = size.max(1);
+        let mut pixmap = Pixmap::new(size, size).unwrap();
+
+        let mut paint = Paint::default();
+        paint.set_color_rgba8(255, 255, 255, 255);
+        paint.anti_alias = true;
+
+        let scale = size as f32 / GRID;
+        let transform = Transform::from_scale(scale, scale);
+        for (path, style) in self.shapes() {
+            match style {
+                Style::Fill(rule) => pixmap.fill_path(&path, &paint, rule, transform, None),
+                Style::Stroke(width) => {
+                    let stroke = Stroke {
+                        width,
+                        line_cap: LineCap::Round,
+                        ..Stroke::default()
+                    };
+                    pixmap.stroke_path(&path, &paint, &stroke, transform, None)
+                }
+            }
+        }
+
+        // White premultiplied by alpha is just the alpha.
+        let pixels = pixmap
+            .pixels()
+            .iter()
+            .flat_map(|p| [255, 255, 255, p.alpha()])
+            .collect();
+        RgbaImage::from_raw(size, size, pixels).unwrap()
+    }
+
+    fn shapes(self) -> Vec<(Path, Style)> {
+        match self {
+            Icon::Close => vec![
+                (line(&[(4.0, 4.0), (12.0, 12.0)]), Style::Stroke(1.5)),
+                (line(&[(12.0, 4.0), (4.0, 12.0)]), Style::Stroke(1.5)),
+            ],
+            Icon::Folder => vec![(
+                polygon(&[
+                    (1.5, 3.0),
+                    (6.0, 3.0),
+                    (7.5, 4.5),
+                    (14.5, 4.5),
+                    (14.5, 13.0),
+                    (1.5, 13.0),
+                ]),
+                Style::Fill(FillRule::Winding),
+            )],
+            Icon::File => vec![
+                (
+                    polygon(&[
+                        (3.5, 1.5),
+                        (9.5, 1.5),
+                        (12.5, 4.5),
+                        (12.5, 14.5),
+                        (3.5, 14.5),
+                    ]),
+                    Style::Stroke(1.2),
+                ),
+                (
+                    line(&[(9.5, 1.5), (9.5, 4.5), (12.5, 4.5)]),
+                    Style::Stroke(1.2),
+                ),
+            ],
+            Icon::Warning => {
+                // A triangle with the exclamation mark cut out of it.
+                let mut builder = PathBuilder::new();
+                builder.move_to(8.0, 1.5);
+                builder.line_to(15.0, 14.0);
+                builder.line_to(1.0, 14.0);
+                builder.close();
+                builder.push_rect(Rect::from_xywh(7.25, 5.5, 1.5, 5.0).unwrap());
+                builder.push_rect(Rect::from_xywh(7.25, 11.25, 1.5, 1.5).unwrap());
+                vec![(builder.finish().unwrap(), Style::Fill(FillRule::EvenOdd))]
+            }
+            Icon::Error => {
+                // A circle with the exclamation mark cut out of it.
+                let mut builder = PathBuilder::new();
+                builder.push_circle(8.0, 8.0, 7.0);
+                builder.push_rect(Rect::from_xywh(7.25, 3.5, 1.5, 6.0).unwrap());
+                builder.push_rect(Rect::from_xywh(7.25, 10.75, 1.5, 1.5).unwrap());
+                vec![(builder.finish().unwrap(), Style::Fill(FillRule::EvenOdd))]
+            }
+        }
+    }
+}
+
+fn line(points: &[(f32, f32)]) -> Path {
+    let mut builder = PathBuilder::new();
+    builder.move_to(points[0].0, points[0].1);
+    for &(x, y) in &points[1..] {
+        builder.line_to(x, y);
+    }
+    builder.finish().unwrap()
+}
+
+fn polygon(points: &[(f32, f32)]) -> Path {
+    let mut builder = PathBuilder::new();
+    builder.move_to(points[0].0, points[0].1);
+    for &(x, y) in &points[1..] {
+        builder.line_to(x, y);
+    }
+    builder.close();
+    builder.finish().unwrap()
+}
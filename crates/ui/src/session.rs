@@ -0,0 +1,46 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+/// A file that was open, and where it was being looked at.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SessionBuffer {
+    pub path: PathBuf,
+
+    /// Byte indices of the cursor and of the start of the view. The file may have
+    /// changed since, so they are clamped to it when restored.
+    pub cursor: usize,
+    pub scroll: usize,
+}
+
+/// The files open when the editor last closed, so starting it without a file
+/// picks up where it left off. Saved as TOML when the restore_session setting is on.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Session {
+    /// Index into 'buffers' of the one that was shown.
+    pub active: usize,
+    pub buffers: Vec<SessionBuffer>,
+}
+
+impl Session {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let text = fs::read_to_string(path)?;
+        toml::from_str(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let text =
+            toml::to_string(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, text)
+    }
+
+    /// Where the session is kept: session.toml in the editor's config directory.
+    pub fn default_path() -> Option<PathBuf> {
+        Some(crate::config_dir()?.join("session.toml"))
+    }
+}
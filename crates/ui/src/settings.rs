@@ -0,0 +1,141 @@
+use crate::latency::LatencyTrace;
+use serde::Deserialize;
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+use text_editor::layout::{CharWrap, NoWrap, WordWrap, WrapPolicy, DEFAULT_TAB_WIDTH};
+
+const MB: u64 = 1024 * 1024;
+
+/// Editor options, loaded from a TOML file. Options missing from the file keep
+/// their default.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    /// Make the text end with exactly one newline when saving.
+    pub ensure_trailing_newline: bool,
+
+    /// Convert the line endings of pasted text to the ones the buffer uses.
+    pub normalize_line_endings_on_paste: bool,
+
+    /// Time key presses on their way to the screen: "off", "log" or "overlay".
+    pub latency_trace: LatencyTrace,
+
+    /// How long the pointer has to rest on a tab before its preview shows.
+    pub tab_preview_delay_ms: u64,
+
+    /// Where long lines are broken: "char", "word" or "none".
+    pub wrap: Wrap,
+
+    /// How many spaces apart tab stops are.
+    pub tab_width: usize,
+
+    /// Indent with spaces rather than tabs. Can be toggled per buffer.
+    pub insert_spaces: bool,
+
+    /// How far apart lines of text are, as a multiple of the height the font
+    /// gives them.
+    pub line_height: f32,
+
+    /// Files bigger than this many megabytes open read-only in a viewer instead
+    /// of being loaded for editing.
+    pub max_file_size_mb: u64,
+
+    /// Files bigger than this many megabytes are edited without syntax
+    /// highlighting, and the status bar says so.
+    pub large_file_size_mb: u64,
+
+    /// Save the open files on exit, and open them again when the editor is started
+    /// without a file.
+    pub restore_session: bool,
+
+    /// Blink the cursor after a second without input. Off keeps it solid.
+    pub cursor_blink: bool,
+
+    /// Outline every fixed size box, to see where it is while working on a layout.
+    pub debug_outlines: bool,
+}
+
+/// How a file is opened, going by its size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileSize {
+    Normal,
+    /// Edited without the features that get slow on big files.
+    Large,
+    /// Only viewed, never loaded.
+    TooLarge,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            ensure_trailing_newline: false,
+            normalize_line_endings_on_paste: true,
+            latency_trace: LatencyTrace::Off,
+            tab_preview_delay_ms: 500,
+            wrap: Wrap::Char,
+            tab_width: DEFAULT_TAB_WIDTH,
+            insert_spaces: true,
+            line_height: 1.0,
+            max_file_size_mb: 256,
+            large_file_size_mb: 16,
+            restore_session: false,
+            cursor_blink: true,
+            debug_outlines: false,
+        }
+    }
+}
+
+impl Settings {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        toml::from_str(&contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// How a file of 'bytes' is opened.
+    pub fn file_size(&self, bytes: u64) -> FileSize {
+        if bytes > self.max_file_size_mb.saturating_mul(MB) {
+            FileSize::TooLarge
+        } else if bytes > self.large_file_bytes() {
+            FileSize::Large
+        } else {
+            FileSize::Normal
+        }
+    }
+
+    /// The size in bytes past which files are edited without highlighting.
+    pub fn large_file_bytes(&self) -> u64 {
+        self.large_file_size_mb.saturating_mul(MB)
+    }
+
+    /// Where the user's settings live: settings.toml in the editor's config directory.
+    pub fn default_path() -> Option<PathBuf> {
+        Some(crate::config_dir()?.join("settings.toml"))
+    }
+}
+
+/// How long lines are broken into visual lines, set with wrap in the settings file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Wrap {
+    /// At whatever character reaches the right edge.
+    #[default]
+    Char,
+
+    /// After whitespace or a hyphen, breaking words only when they don't fit on a line.
+    Word,
+
+    /// Never. Lines run past the right edge.
+    None,
+}
+
+impl Wrap {
+    pub fn policy(self) -> Box<dyn WrapPolicy> {
+        match self {
+            Wrap::Char => Box::new(CharWrap),
+            Wrap::Word => Box::new(WordWrap),
+            Wrap::None => Box::new(NoWrap),
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use std::{
+    fs,
+    path::PathBuf,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// How often the shader files are checked for changes.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// The shaders that can be reloaded, one per pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shader {
+    Quad,
+    Image,
+}
+
+impl Shader {
+    const ALL: [Shader; 2] = [Shader::Quad, Shader::Image];
+
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Shader::Quad => "quad.wgsl",
+            Shader::Image => "image.wgsl",
+        }
+    }
+
+    /// Where the source the shader was built from lives, in the source tree.
+    fn path(self) -> PathBuf {
+        [env!("CARGO_MANIFEST_DIR"), "src", self.file_name()]
+            .iter()
+            .collect()
+    }
+}
+
+/// Notices when the shader sources in the source tree are saved, so pipelines can
+/// be rebuilt without restarting. Only used in debug builds, release builds use
+/// the shaders they were compiled with.
+pub struct ShaderWatcher {
+    modified: Vec<(Shader, Option<SystemTime>)>,
+    last_poll: Instant,
+}
+
+impl Default for ShaderWatcher {
+    fn default() -> Self {
+        Self {
+            modified: Shader::ALL
+                .iter()
+                .map(|&shader| (shader, modified(shader)))
+                .collect(),
+            last_poll: Instant::now(),
+        }
+    }
+}
+
+impl ShaderWatcher {
+    /// The shaders saved since the last call, with their new source.
+    pub fn changed(&mut self) -> Vec<(Shader, String)> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return vec![];
+        }
+        self.last_poll = Instant::now();
+
+        let mut changed = vec![];
+        for (shader, last_modified) in &mut self.modified {
+            let now_modified = modified(*shader);
+            if now_modified == *last_modified {
+                continue;
+            }
+            *last_modified = now_modified;
+
+            match fs::read_to_string(shader.path()) {
+                Ok(source) => changed.push((*shader, source)),
+                Err(e) => log::error!("Failed to read {}: {e}", shader.path().display()),
+            }
+        }
+        changed
+    }
+}
+
+fn modified(shader: Shader) -> Option<SystemTime> {
+    fs::metadata(shader.path()).and_then(|m| m.modified()).ok()
+}
+
+/// Build GPU objects with 'build', returning the validation error instead of
+/// panicking if the shader doesn't compile.
+pub fn validated<T>(device: &wgpu::Device, build: impl FnOnce() -> T) -> Result<T, String> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let built = build();
+    match pollster::block_on(device.pop_error_scope()) {
+        Some(error) => Err(error.to_string()),
+        None => Ok(built),
+    }
+}
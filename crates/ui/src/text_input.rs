@@ -0,0 +1,188 @@
+use crate::{
+    image_pipeline,
+    input::{KeyInput, Modifiers},
+    layout::{BoundingBox, Drawables},
+    quad_pipeline::QuadInstance,
+    texture_atlas::GlyphAtlas,
+    theme::Theme,
+};
+use text_editor::LineInput;
+use winit::{
+    event::ElementState,
+    keyboard::{Key, NamedKey},
+};
+
+/// What a key did to a text input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputAction {
+    /// The text changed.
+    Edited,
+    /// The key was used but the text is the same, e.g. the cursor moved or a
+    /// character was refused.
+    Handled,
+    /// Enter was pressed.
+    Submitted,
+    /// Escape was pressed.
+    Cancelled,
+    /// The key isn't one the input uses.
+    Ignored,
+}
+
+/// A box to type a single line in, like the search box or the goto line prompt.
+/// Text that doesn't fit scrolls sideways to keep the cursor in view.
+#[derive(Debug, Default)]
+pub struct TextInput {
+    line: LineInput,
+
+    /// Shown dimmed while the input is empty and not focused.
+    placeholder: Option<&'static str>,
+
+    /// How far the text is scrolled left, in pixels, as of the last layout.
+    scroll: f32,
+}
+
+impl TextInput {
+    pub fn new(line: LineInput) -> Self {
+        Self {
+            line,
+            placeholder: None,
+            scroll: 0.0,
+        }
+    }
+
+    pub fn with_placeholder(mut self, placeholder: &'static str) -> Self {
+        self.placeholder = Some(placeholder);
+        self
+    }
+
+    pub fn line_mut(&mut self) -> &mut LineInput {
+        &mut self.line
+    }
+
+    pub fn text(&self) -> String {
+        self.line.text()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.line.is_empty()
+    }
+
+    /// Send a key to the input. Enter submits and Escape cancels, the rest edit
+    /// the text or move the cursor.
+    pub fn keystroke(&mut self, event: &KeyInput, modifiers: Modifiers) -> InputAction {
+        if event.state != ElementState::Pressed {
+            return InputAction::Ignored;
+        }
+
+        let edited = match &event.logical_key {
+            Key::Named(NamedKey::Enter) => return InputAction::Submitted,
+            Key::Named(NamedKey::Escape) => return InputAction::Cancelled,
+            Key::Named(NamedKey::Backspace) => self.line.backspace(),
+            Key::Named(NamedKey::Delete) => self.line.delete(),
+            Key::Named(NamedKey::ArrowLeft) => {
+                self.line.move_left();
+                false
+            }
+            Key::Named(NamedKey::ArrowRight) => {
+                self.line.move_right();
+                false
+            }
+            Key::Named(NamedKey::Home) => {
+                self.line.move_to_start();
+                false
+            }
+            Key::Named(NamedKey::End) => {
+                self.line.move_to_end();
+                false
+            }
+            Key::Named(NamedKey::Space) => self.line.insert(" "),
+            Key::Character(c) if !modifiers.ctrl && !modifiers.alt => self.line.insert(c),
+            _ => return InputAction::Ignored,
+        };
+
+        if edited {
+            InputAction::Edited
+        } else {
+            InputAction::Handled
+        }
+    }
+
+    /// Draw the text in 'bounds', on the baseline of a line starting at its top.
+    /// The cursor is only drawn if 'focused'.
+    pub fn layout(
+        &mut self,
+        atlas: &mut impl GlyphAtlas,
+        bounds: BoundingBox,
+        font_size: f32,
+        theme: &Theme,
+        focused: bool,
+        drawables: &mut Vec<Drawables>,
+    ) {
+        let baseline = (
+            bounds.min.0,
+            bounds.min.1 + atlas.line_metrics(font_size).baseline(),
+        );
+
+        if self.line.is_empty() && !focused {
+            if let Some(placeholder) = self.placeholder {
+                let (glyphs, _) = image_pipeline::layout_label(
+                    baseline,
+                    bounds.max.0,
+                    atlas,
+                    font_size,
+                    &theme.placeholder,
+                    placeholder,
+                );
+                drawables.extend(glyphs);
+            }
+            self.scroll = 0.0;
+            return;
+        }
+
+        // Where each character starts, with the text scrolled all the way left.
+        let (glyphs, x) =
+            image_pipeline::label_glyphs(atlas, font_size, &self.line.text(), f32::INFINITY);
+        let cursor_x = glyphs
+            .iter()
+            .find(|(i, _, _)| *i >= self.line.cursor())
+            .map_or(x, |(_, x, _)| *x);
+
+        // Scroll just far enough for the cursor to be in view, and no further than
+        // it takes to show the end of the text.
+        let cursor_width = (font_size / 10.0).floor().max(1.0);
+        let width = (bounds.width() - cursor_width).max(0.0);
+        self.scroll = self
+            .scroll
+            .min(cursor_x)
+            .max(cursor_x - width)
+            .min((x - width).max(0.0));
+
+        for (_, x, glyph) in glyphs {
+            let x = bounds.min.0 + x - self.scroll;
+            if x < bounds.min.0 {
+                continue;
+            }
+            if x + glyph.metrics.advance.0 > bounds.max.0 {
+                break;
+            }
+            drawables.push(image_pipeline::layout_glyph(
+                atlas,
+                &glyph,
+                (x, baseline.1),
+                &theme.foreground,
+            ));
+        }
+
+        if focused {
+            let (cursor_top, cursor_height) = atlas.line_metrics(font_size).cursor();
+            drawables.push(Drawables::Rect(QuadInstance::filled(
+                [
+                    bounds.min.0 + cursor_x - self.scroll,
+                    baseline.1 - cursor_top,
+                ],
+                [cursor_width, cursor_height],
+                &theme.foreground,
+            )));
+        }
+    }
+}
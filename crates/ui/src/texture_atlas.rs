@@ -1,10 +1,10 @@
-use crate::texture::Texture;
-use etagere::{AllocId, Allocation, AtlasAllocator};
+use crate::{resources::Icon, texture::Texture};
+use etagere::{AllocId, Allocation, BucketedAtlasAllocator};
 use freetype::face::LoadFlag;
 use image::{DynamicImage, ImageError, RgbaImage};
 use lru::LruCache;
-use std::rc::Rc;
-use text_editor::{GlyphMetrics, GlyphRasterizer};
+use std::{collections::HashMap, rc::Rc};
+use text_editor::{GlyphMetrics, GlyphRasterizer, LineMetrics};
 
 #[derive(Debug)]
 pub enum AtlasError {
@@ -44,41 +44,140 @@ impl FontGlyph {
 #[derive(Debug, PartialEq, Eq, Hash)]
 /// A key for a glyph being inserted into the atlas. We store the character
 /// the glyph is of, as well as the font size because glyphs of different
-/// font sizes must be re-rasterized instead of simply scaled up.
-pub struct GlyphMapKey {
-    c: char,
-    font_size: u32,
+/// font sizes must be re-rasterized instead of simply scaled up. Icons are
+/// kept by their pixel size for the same reason.
+pub enum GlyphMapKey {
+    Char { c: char, font_size: u32 },
+    Icon { icon: Icon, size: u32 },
 }
 
-/// A dynamically packed bundle of images. If the atlas is full, the least recently used
-/// glyphs will be evicted until there is room to allocate a new glyph.
+/// Counters describing how well the atlas is keeping up.
+#[derive(Debug, Clone, Copy)]
+pub struct AtlasStats {
+    pub size: u16,
+    pub glyphs: usize,
+
+    /// How many glyphs were evicted to make room for others since the atlas was created.
+    pub evictions: u64,
+}
+
+/// A dynamically packed bundle of images. If the atlas is full, it grows up to the
+/// largest texture the device supports. After that, the least recently used glyphs
+/// will be evicted until there is room to allocate a new glyph.
 pub struct TextureAtlas {
+    device: Rc<wgpu::Device>,
     queue: Rc<wgpu::Queue>,
 
     regular_face: freetype::Face,
     emoji_face: freetype::Face,
 
     /// Keeps track of the dynamic allocations we request.
-    allocator: AtlasAllocator,
+    allocator: BucketedAtlasAllocator,
     /// The current atlas texture state
     texture: Texture,
     /// The size of the atlas
     size: u16,
+    /// The atlas won't grow past this size
+    max_size: u16,
+    /// Bumped whenever 'texture' is replaced by a bigger one
+    generation: u64,
     /// Keeps track of how recently the chars have been used
     cache: LruCache<GlyphMapKey, FontGlyph>,
+    evictions: u64,
+    /// The regular font's line metrics by font size, read once per size
+    line_metrics: HashMap<u32, LineMetrics>,
+}
+
+/// Where layout gets glyphs from. TextureAtlas rasterizes them into a GPU texture,
+/// but anything that hands out glyph metrics and atlas positions will do, e.g. to
+/// lay out a scene without a GPU.
+pub trait GlyphAtlas: GlyphRasterizer {
+    /// The width and height of the atlas texture.
+    fn size(&self) -> u16;
+
+    /// The glyph of 'c' at 'font_size', or None if no font has it.
+    fn glyph(&mut self, c: char, font_size: f32) -> Option<FontGlyph>;
+
+    /// 'icon' rasterized 'size' pixels wide and tall.
+    fn icon(&mut self, icon: Icon, size: u32) -> Option<AllocationInfo>;
+
+    fn stats(&self) -> AtlasStats;
 }
 
 impl GlyphRasterizer for TextureAtlas {
     fn get_glyph(&mut self, c: char, font_size: f32) -> GlyphMetrics {
         self.map_get_or_insert_glyph(c, font_size).unwrap().metrics
     }
+
+    fn line_metrics(&mut self, font_size: f32) -> LineMetrics {
+        let face = &self.regular_face;
+        *self
+            .line_metrics
+            .entry(font_size as u32)
+            .or_insert_with(|| {
+                let metrics = face
+                    .set_char_size(font_size as isize * 64, 0, 0, 0)
+                    .ok()
+                    .and_then(|_| face.size_metrics());
+                let Some(metrics) = metrics else {
+                    return LineMetrics::fallback(font_size);
+                };
+
+                // Sizes are in 26.6 fixed point, and the descender is negative.
+                let ascent = metrics.ascender as f32 / 64.0;
+                let descent = -metrics.descender as f32 / 64.0;
+                let height = metrics.height as f32 / 64.0;
+                LineMetrics {
+                    ascent,
+                    descent,
+                    line_gap: (height - ascent - descent).max(0.0),
+                    spacing: 1.0,
+                }
+            })
+    }
+}
+
+impl GlyphAtlas for TextureAtlas {
+    fn size(&self) -> u16 {
+        self.size
+    }
+
+    fn glyph(&mut self, c: char, font_size: f32) -> Option<FontGlyph> {
+        self.map_get_or_insert_glyph(c, font_size)
+    }
+
+    fn icon(&mut self, icon: Icon, size: u32) -> Option<AllocationInfo> {
+        let key = GlyphMapKey::Icon { icon, size };
+        if let Some(glyph) = self.cache.get(&key) {
+            return Some(glyph.allocation_info);
+        }
+
+        let image = icon.rasterize(size);
+        let allocation = self.load_from_image(&self.queue.clone(), &image).ok()?;
+        let metrics = GlyphMetrics {
+            advance: (size as f32, 0.0),
+            size: (size as f32, size as f32),
+            pos: (0.0, size as f32),
+        };
+        let glyph = FontGlyph::new(metrics, allocation);
+        self.cache.put(key, glyph);
+        Some(glyph.allocation_info)
+    }
+
+    fn stats(&self) -> AtlasStats {
+        AtlasStats {
+            size: self.size,
+            glyphs: self.cache.len(),
+            evictions: self.evictions,
+        }
+    }
 }
 
 impl TextureAtlas {
     /// Create a new texture atlas. This will also initialize the freetype library, a regular
     /// and an emoji font face, and set up the atlas allocator and cache.
     /// TODO: separate the font related setup?
-    pub fn new(device: &wgpu::Device, queue: Rc<wgpu::Queue>, size: u16) -> Self {
+    pub fn new(device: Rc<wgpu::Device>, queue: Rc<wgpu::Queue>, size: u16) -> Self {
         let library = freetype::Library::init().unwrap();
 
         let regular_face = library.new_face("res/RobotoMono-Regular.ttf", 0).unwrap();
@@ -91,21 +190,41 @@ impl TextureAtlas {
             library.new_face("res/NotoColorEmoji.ttf", 0).unwrap()
         };
 
-        let texture = Texture::from_size(device, &queue, size);
+        let max_size = Self::max_size(&device.limits());
+        let size = size.min(max_size);
+        let texture = Texture::from_size(&device, &queue, size);
 
         Self {
+            device,
             queue,
 
             regular_face,
             emoji_face,
 
-            allocator: AtlasAllocator::new(etagere::size2(size as i32, size as i32)),
+            allocator: BucketedAtlasAllocator::new(etagere::size2(size as i32, size as i32)),
             texture,
             size,
+            max_size,
+            generation: 0,
             cache: LruCache::unbounded(),
+            evictions: 0,
+            line_metrics: HashMap::new(),
         }
     }
 
+    /// A good size to start the atlas at. Glyphs take up more room on HiDPI screens,
+    /// so the atlas starts bigger as the scale factor goes up.
+    pub fn initial_size(limits: &wgpu::Limits, scale_factor: f64) -> u16 {
+        let size = (1024.0 * scale_factor.max(1.0)).ceil() as u32;
+        (size.next_power_of_two() as u16).min(Self::max_size(limits))
+    }
+
+    fn max_size(limits: &wgpu::Limits) -> u16 {
+        // The largest power of two that fits both the device limit and a u16.
+        let max = limits.max_texture_dimension_2d.min(u16::MAX as u32);
+        1 << max.ilog2()
+    }
+
     /// Using the provided image and character, loads the image into the texture atlas and
     /// saves the character in the glyph cache.
     fn load_char_from_image(
@@ -117,7 +236,7 @@ impl TextureAtlas {
     ) -> Result<Allocation, AtlasError> {
         let texture_allocation = self.load_from_image(&self.queue.clone(), img)?;
         self.cache.put(
-            GlyphMapKey {
+            GlyphMapKey::Char {
                 c,
                 font_size: font_size as u32,
             },
@@ -156,6 +275,12 @@ impl TextureAtlas {
         self.size
     }
 
+    /// Changes whenever the atlas texture is replaced, meaning anything bound to the
+    /// old texture has to be bound again.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
     /// Get the atlas's entire GPU texture
     pub fn texture(&self) -> &Texture {
         &self.texture
@@ -194,7 +319,7 @@ impl TextureAtlas {
     /// If the glyph is not in the atlas, we load the glyph using freetype, rasterize
     /// the glyph, save it in the atlas, and then return the resulting glyph metrics.
     pub fn map_get_or_insert_glyph(&mut self, c: char, font_size: f32) -> Option<FontGlyph> {
-        let glyph_key = GlyphMapKey {
+        let glyph_key = GlyphMapKey::Char {
             c,
             font_size: font_size as u32,
         };
@@ -258,9 +383,10 @@ impl TextureAtlas {
             };
 
             let image = if is_emoji {
-                let line_height = font_size * 1.2;
-                let new_width = ((glyph_width * line_height) / glyph_height).ceil();
-                let new_height = line_height;
+                // Emoji fill the line from the ascent to the descent of the regular font.
+                let line_metrics = self.line_metrics(font_size);
+                let new_height = (line_metrics.ascent + line_metrics.descent).ceil();
+                let new_width = ((glyph_width * new_height) / glyph_height).ceil();
 
                 glyph_width = new_width;
                 glyph_height = new_height;
@@ -269,7 +395,7 @@ impl TextureAtlas {
                 advance_y = 0.0;
 
                 bitmap_left = 0.0;
-                bitmap_top = font_size;
+                bitmap_top = line_metrics.ascent;
 
                 let image = DynamicImage::from(image);
                 let image = image.resize(
@@ -345,14 +471,46 @@ impl TextureAtlas {
 
                     return Ok(allocation);
                 }
+                None if self.size < self.max_size => self.grow(),
                 None => {
                     // Evict the least recently used glyph.
                     let entry = self.cache.pop_lru();
                     if let Some((_, value)) = entry {
                         self.allocator.deallocate(value.allocation_info.id);
+                        self.evictions += 1;
                     }
                 }
             }
         }
     }
+
+    /// Double the size of the atlas, keeping everything already in it in place.
+    fn grow(&mut self) {
+        let old_size = self.size;
+        let new_size = old_size.saturating_mul(2).min(self.max_size);
+        let texture = Texture::from_size(&self.device, &self.queue, new_size);
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("atlas grow"),
+            });
+        encoder.copy_texture_to_texture(
+            self.texture.texture.as_image_copy(),
+            texture.texture.as_image_copy(),
+            wgpu::Extent3d {
+                width: old_size as u32,
+                height: old_size as u32,
+                depth_or_array_layers: 1,
+            },
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        self.allocator
+            .grow(etagere::size2(new_size as i32, new_size as i32));
+        self.texture = texture;
+        self.size = new_size;
+        self.generation += 1;
+        log::info!("Grew the glyph atlas from {old_size} to {new_size}");
+    }
 }
@@ -0,0 +1,248 @@
+use crate::layout::Color;
+use serde::{de, Deserialize, Deserializer};
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+use text_editor::highlight::TokenKind;
+
+/// Every color the editor draws with. Loaded from a TOML file where colors are
+/// written as "#rrggbb" or "#rrggbbaa". Colors missing from the file keep their
+/// default.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    /// Behind the text.
+    pub background: Color,
+    /// The text itself.
+    pub foreground: Color,
+    pub cursor: Color,
+
+    /// Behind selected text, and the selected row of lists.
+    pub selection: Color,
+
+    /// Behind text matching the search query.
+    pub search_match: Color,
+
+    /// Behind the bracket at the cursor and the one matching it.
+    pub bracket_match: Color,
+
+    /// The strip beside the text for line numbers.
+    pub gutter_background: Color,
+    pub gutter_foreground: Color,
+
+    /// Panels and bars around the text, like the tab bar and find in files.
+    pub widget_background: Color,
+    pub widget_foreground: Color,
+
+    /// The active tab.
+    pub widget_active: Color,
+
+    /// Boxes drawn over the text, like the search box.
+    pub popup_background: Color,
+
+    /// Marks and counts of problems in the text, by severity.
+    pub error: Color,
+    pub warning: Color,
+
+    /// Dimmed text standing in for an empty field, and the hints in an empty editor.
+    pub placeholder: Color,
+
+    pub syntax: SyntaxTheme,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self {
+            background: Color::new(5, 5, 5, 255),
+            foreground: Color::new(255, 255, 255, 255),
+            cursor: Color::new(255, 255, 255, 255),
+            selection: Color::new(38, 79, 120, 255),
+            search_match: Color::new(90, 75, 20, 255),
+            bracket_match: Color::new(70, 70, 70, 255),
+            gutter_background: Color::new(5, 5, 5, 255),
+            gutter_foreground: Color::new(110, 110, 110, 255),
+            widget_background: Color::new(20, 20, 20, 255),
+            widget_foreground: Color::new(200, 200, 200, 255),
+            widget_active: Color::new(45, 45, 45, 255),
+            popup_background: Color::new(30, 30, 30, 255),
+            error: Color::new(240, 85, 85, 255),
+            warning: Color::new(230, 180, 60, 255),
+            placeholder: Color::new(120, 120, 120, 255),
+            syntax: SyntaxTheme::default(),
+        }
+    }
+}
+
+impl Theme {
+    /// The built in theme for a light system appearance. Default is the dark one.
+    pub fn light() -> Self {
+        Self {
+            background: Color::new(250, 250, 250, 255),
+            foreground: Color::new(30, 30, 30, 255),
+            cursor: Color::new(30, 30, 30, 255),
+            selection: Color::new(173, 214, 255, 255),
+            search_match: Color::new(255, 223, 93, 255),
+            bracket_match: Color::new(205, 205, 205, 255),
+            gutter_background: Color::new(250, 250, 250, 255),
+            gutter_foreground: Color::new(150, 150, 150, 255),
+            widget_background: Color::new(235, 235, 235, 255),
+            widget_foreground: Color::new(60, 60, 60, 255),
+            widget_active: Color::new(215, 215, 215, 255),
+            popup_background: Color::new(225, 225, 225, 255),
+            error: Color::new(205, 40, 40, 255),
+            warning: Color::new(180, 120, 0, 255),
+            placeholder: Color::new(140, 140, 140, 255),
+            syntax: SyntaxTheme::light(),
+        }
+    }
+
+    /// The built in theme matching the system's appearance.
+    pub fn for_appearance(appearance: winit::window::Theme) -> Self {
+        match appearance {
+            winit::window::Theme::Dark => Self::default(),
+            winit::window::Theme::Light => Self::light(),
+        }
+    }
+
+    /// The theme 't' of the way from 'self' to 'other', for fading between them.
+    pub fn lerp(&self, other: &Theme, t: f32) -> Self {
+        Self {
+            background: self.background.lerp(other.background, t),
+            foreground: self.foreground.lerp(other.foreground, t),
+            cursor: self.cursor.lerp(other.cursor, t),
+            selection: self.selection.lerp(other.selection, t),
+            search_match: self.search_match.lerp(other.search_match, t),
+            bracket_match: self.bracket_match.lerp(other.bracket_match, t),
+            gutter_background: self.gutter_background.lerp(other.gutter_background, t),
+            gutter_foreground: self.gutter_foreground.lerp(other.gutter_foreground, t),
+            widget_background: self.widget_background.lerp(other.widget_background, t),
+            widget_foreground: self.widget_foreground.lerp(other.widget_foreground, t),
+            widget_active: self.widget_active.lerp(other.widget_active, t),
+            popup_background: self.popup_background.lerp(other.popup_background, t),
+            error: self.error.lerp(other.error, t),
+            warning: self.warning.lerp(other.warning, t),
+            placeholder: self.placeholder.lerp(other.placeholder, t),
+            syntax: self.syntax.lerp(&other.syntax, t),
+        }
+    }
+
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let contents = std::fs::read_to_string(path)?;
+        toml::from_str(&contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Where the user's theme lives: theme.toml in the editor's config directory.
+    pub fn default_path() -> Option<PathBuf> {
+        Some(crate::config_dir()?.join("theme.toml"))
+    }
+}
+
+/// The colors of syntax highlighted text, in the [syntax] table of a theme.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SyntaxTheme {
+    pub keyword: Color,
+    pub function: Color,
+    #[serde(rename = "type")]
+    pub type_: Color,
+    pub string: Color,
+    pub escape: Color,
+    pub number: Color,
+    pub constant: Color,
+    pub comment: Color,
+    pub attribute: Color,
+    pub property: Color,
+    pub label: Color,
+}
+
+impl Default for SyntaxTheme {
+    fn default() -> Self {
+        Self {
+            keyword: Color::new(86, 156, 214, 255),
+            function: Color::new(220, 220, 170, 255),
+            type_: Color::new(78, 201, 176, 255),
+            string: Color::new(206, 145, 120, 255),
+            escape: Color::new(215, 186, 125, 255),
+            number: Color::new(181, 206, 168, 255),
+            constant: Color::new(79, 193, 255, 255),
+            comment: Color::new(106, 153, 85, 255),
+            attribute: Color::new(197, 134, 192, 255),
+            property: Color::new(156, 220, 254, 255),
+            label: Color::new(197, 134, 192, 255),
+        }
+    }
+}
+
+impl SyntaxTheme {
+    pub fn light() -> Self {
+        Self {
+            keyword: Color::new(0, 0, 255, 255),
+            function: Color::new(121, 94, 38, 255),
+            type_: Color::new(38, 127, 153, 255),
+            string: Color::new(163, 21, 21, 255),
+            escape: Color::new(238, 0, 0, 255),
+            number: Color::new(9, 134, 88, 255),
+            constant: Color::new(0, 112, 193, 255),
+            comment: Color::new(0, 128, 0, 255),
+            attribute: Color::new(175, 0, 219, 255),
+            property: Color::new(0, 16, 128, 255),
+            label: Color::new(175, 0, 219, 255),
+        }
+    }
+
+    fn lerp(&self, other: &SyntaxTheme, t: f32) -> Self {
+        Self {
+            keyword: self.keyword.lerp(other.keyword, t),
+            function: self.function.lerp(other.function, t),
+            type_: self.type_.lerp(other.type_, t),
+            string: self.string.lerp(other.string, t),
+            escape: self.escape.lerp(other.escape, t),
+            number: self.number.lerp(other.number, t),
+            constant: self.constant.lerp(other.constant, t),
+            comment: self.comment.lerp(other.comment, t),
+            attribute: self.attribute.lerp(other.attribute, t),
+            property: self.property.lerp(other.property, t),
+            label: self.label.lerp(other.label, t),
+        }
+    }
+
+    /// The color text of the given kind is drawn in. None keeps the foreground color.
+    pub fn color(&self, kind: TokenKind) -> Option<Color> {
+        let color = match kind {
+            TokenKind::Keyword => self.keyword,
+            TokenKind::Function => self.function,
+            TokenKind::Type => self.type_,
+            TokenKind::String => self.string,
+            TokenKind::Escape => self.escape,
+            TokenKind::Number => self.number,
+            TokenKind::Constant => self.constant,
+            TokenKind::Comment => self.comment,
+            TokenKind::Attribute => self.attribute,
+            TokenKind::Property => self.property,
+            TokenKind::Label => self.label,
+            TokenKind::Variable | TokenKind::Operator | TokenKind::Punctuation => return None,
+        };
+        Some(color)
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+        parse_hex_color(&hex).ok_or_else(|| {
+            de::Error::custom(format!("'{hex}' is not a #rrggbb or #rrggbbaa color"))
+        })
+    }
+}
+
+fn parse_hex_color(hex: &str) -> Option<Color> {
+    let digits = hex.strip_prefix('#')?;
+    if !matches!(digits.len(), 6 | 8) || !digits.is_ascii() {
+        return None;
+    }
+
+    let channel = |i: usize| u8::from_str_radix(digits.get(i..i + 2)?, 16).ok();
+    let alpha = if digits.len() == 8 { channel(6)? } else { 255 };
+    Some(Color::new(channel(0)?, channel(2)?, channel(4)?, alpha))
+}
@@ -0,0 +1,384 @@
+use crate::{
+    image_pipeline,
+    input::KeyInput,
+    layout::{BoundingBox, Drawables},
+    quad_pipeline::QuadInstance,
+    texture_atlas::GlyphAtlas,
+    theme::Theme,
+};
+use std::{
+    sync::{
+        mpsc::{self, Receiver, TryRecvError},
+        Arc,
+    },
+    thread,
+};
+use winit::{
+    event::ElementState,
+    keyboard::{Key, NamedKey},
+};
+
+/// How many items the filter thread checks before sending what it found so far.
+const FILTER_CHUNK: usize = 1000;
+
+/// A scrolling list that can hold many thousands of rows. Only the rows on screen
+/// are laid out, and filtering runs on a worker thread so typing never stalls.
+pub struct VirtualList {
+    items: Items,
+
+    /// Only items containing all characters of the filter, in order, are shown.
+    filter: String,
+
+    /// Indices into 'items' of the rows that are shown.
+    matches: Vec<usize>,
+
+    /// Is 'matches' complete for the current filter?
+    filtered: bool,
+
+    /// Receives chunks of matching item indices from the filter thread.
+    filter_job: Option<Receiver<Vec<usize>>>,
+
+    /// Index into 'matches' of the selected row.
+    selected: usize,
+    first_visible: usize,
+
+    /// How many rows fit on screen, as of the last layout. Used for paging.
+    page_rows: usize,
+
+    bounds: Option<BoundingBox>,
+    font_size: f32,
+}
+
+impl VirtualList {
+    pub fn new(font_size: f32) -> Self {
+        Self {
+            items: Items::default(),
+            filter: String::new(),
+            matches: vec![],
+            filtered: true,
+            filter_job: None,
+            selected: 0,
+            first_visible: 0,
+            page_rows: 1,
+            bounds: None,
+            font_size,
+        }
+    }
+
+    /// Replace every item, resetting the selection.
+    pub fn set_items(&mut self, items: Vec<String>) {
+        self.items = Items::new(items);
+        self.selected = 0;
+        self.first_visible = 0;
+        self.refilter(false);
+    }
+
+    /// Replace every item, keeping the selection and scroll where they are.
+    pub fn update_items(&mut self, items: Vec<String>) {
+        self.items = Items::new(items);
+        self.refilter(false);
+        self.select(self.selected);
+    }
+
+    /// Add items to the end of the list, keeping the selection.
+    pub fn extend(&mut self, items: impl IntoIterator<Item = String>) {
+        let start = self.items.len();
+        self.items.push_chunk(items.into_iter().collect());
+
+        if self.filter.is_empty() {
+            self.matches.extend(start..self.items.len());
+        } else {
+            self.refilter(false);
+        }
+    }
+
+    /// Drop the items from 'len' on. The selection stays where it is, so items
+    /// added in their place can be selected.
+    pub fn truncate(&mut self, len: usize) {
+        if len >= self.items.len() {
+            return;
+        }
+        self.items.truncate(len);
+
+        if self.filter.is_empty() {
+            self.matches.truncate(len);
+        } else {
+            self.refilter(false);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.set_items(vec![]);
+    }
+
+    pub fn items(&self) -> impl Iterator<Item = &String> {
+        self.items.iter()
+    }
+
+    pub fn filter(&self) -> &str {
+        &self.filter
+    }
+
+    /// Show only the items matching 'filter'. Matching happens on a worker thread,
+    /// rows show up as poll() collects them.
+    pub fn set_filter(&mut self, filter: &str) {
+        if filter == self.filter {
+            return;
+        }
+
+        // Narrowing a finished filter only needs to look at what matched before.
+        let narrowing = self.filtered && filter.starts_with(self.filter.as_str());
+        self.filter = filter.to_string();
+        self.selected = 0;
+        self.first_visible = 0;
+        self.refilter(narrowing);
+    }
+
+    /// Start filtering again. If 'narrowing', only the current matches are checked.
+    fn refilter(&mut self, narrowing: bool) {
+        if self.filter.is_empty() {
+            self.filter_job = None;
+            self.matches = (0..self.items.len()).collect();
+            self.filtered = true;
+            return;
+        }
+
+        let candidates = if narrowing {
+            std::mem::take(&mut self.matches)
+        } else {
+            self.matches.clear();
+            (0..self.items.len()).collect()
+        };
+
+        let (sender, receiver) = mpsc::channel();
+        let items = self.items.clone();
+        let filter = self.filter.to_lowercase();
+        thread::spawn(move || {
+            for chunk in candidates.chunks(FILTER_CHUNK) {
+                let found = chunk
+                    .iter()
+                    .copied()
+                    .filter(|&i| matches_filter(items.get(i), &filter))
+                    .collect();
+
+                // The filter changed, nobody wants these results anymore.
+                if sender.send(found).is_err() {
+                    return;
+                }
+            }
+        });
+
+        self.filtered = false;
+        self.filter_job = Some(receiver);
+    }
+
+    /// Collect rows found by the filter thread since the last call.
+    pub fn poll(&mut self) {
+        let Some(receiver) = &self.filter_job else {
+            return;
+        };
+
+        loop {
+            match receiver.try_recv() {
+                Ok(found) => self.matches.extend(found),
+                Err(TryRecvError::Empty) => return,
+                Err(TryRecvError::Disconnected) => {
+                    self.filter_job = None;
+                    self.filtered = true;
+                    return;
+                }
+            }
+        }
+    }
+
+    /// How many rows are shown with the current filter.
+    pub fn len(&self) -> usize {
+        self.matches.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.matches.is_empty()
+    }
+
+    /// Is the filter still running?
+    pub fn is_filtering(&self) -> bool {
+        !self.filtered
+    }
+
+    /// Index into items() of the selected row.
+    pub fn selected(&self) -> Option<usize> {
+        self.matches.get(self.selected).copied()
+    }
+
+    fn select(&mut self, row: usize) {
+        self.selected = row.min(self.matches.len().saturating_sub(1));
+    }
+
+    /// Handle the keys that move the selection: arrows, Page Up/Down, and
+    /// Home/End. Returns false for any other key.
+    pub fn keystroke(&mut self, event: &KeyInput) -> bool {
+        if event.state != ElementState::Pressed {
+            return false;
+        }
+
+        match &event.logical_key {
+            Key::Named(NamedKey::ArrowUp) => self.select(self.selected.saturating_sub(1)),
+            Key::Named(NamedKey::ArrowDown) => self.select(self.selected + 1),
+            Key::Named(NamedKey::PageUp) => {
+                self.select(self.selected.saturating_sub(self.page_rows))
+            }
+            Key::Named(NamedKey::PageDown) => self.select(self.selected + self.page_rows),
+            Key::Named(NamedKey::Home) => self.select(0),
+            Key::Named(NamedKey::End) => self.select(usize::MAX),
+            _ => return false,
+        }
+
+        true
+    }
+
+    fn row_height(&self) -> f32 {
+        (self.font_size * 1.5).floor()
+    }
+
+    /// Select the row under 'pos'. Returns false if there is no row there.
+    pub fn click(&mut self, pos: (f32, f32)) -> bool {
+        let Some(bounds) = self.bounds.filter(|b| b.inside(pos)) else {
+            return false;
+        };
+
+        let row = self.first_visible + ((pos.1 - bounds.min.1) / self.row_height()) as usize;
+        if row >= self.matches.len() {
+            return false;
+        }
+
+        self.selected = row;
+        true
+    }
+
+    pub fn bounds(&self) -> Option<BoundingBox> {
+        self.bounds
+    }
+
+    pub fn layout(
+        &mut self,
+        atlas: &mut impl GlyphAtlas,
+        view_size: BoundingBox,
+        theme: &Theme,
+        drawables: &mut Vec<Drawables>,
+    ) {
+        self.poll();
+        self.bounds = Some(view_size);
+
+        drawables.push(Drawables::Rect(QuadInstance::filled(
+            [view_size.min.0, view_size.min.1],
+            [view_size.width(), view_size.height()],
+            &theme.widget_background,
+        )));
+
+        let row_height = self.row_height();
+        let padding = self.font_size / 2.0;
+        let baseline_offset = (row_height + self.font_size * 0.7) / 2.0;
+
+        // Keep the selected row on screen.
+        self.page_rows = ((view_size.height() / row_height).floor() as usize).max(1);
+        if self.selected < self.first_visible {
+            self.first_visible = self.selected;
+        } else if self.selected >= self.first_visible + self.page_rows {
+            self.first_visible = self.selected + 1 - self.page_rows;
+        }
+
+        let rows = self.matches.iter().enumerate();
+        for (row, &item) in rows.skip(self.first_visible).take(self.page_rows) {
+            let y = view_size.min.1 + row_height * (row - self.first_visible) as f32;
+
+            if row == self.selected {
+                drawables.push(Drawables::Rect(QuadInstance::filled(
+                    [view_size.min.0, y],
+                    [view_size.width(), row_height],
+                    &theme.selection,
+                )));
+            }
+
+            let (glyphs, _) = image_pipeline::layout_label(
+                (view_size.min.0 + padding, y + baseline_offset),
+                view_size.max.0 - padding,
+                atlas,
+                self.font_size,
+                &theme.widget_foreground,
+                self.items.get(item),
+            );
+            drawables.extend(glyphs);
+        }
+    }
+}
+
+/// The items of a list, in chunks shared with the filter thread. Adding items adds
+/// a chunk, so the items already there are never copied, even while a filter
+/// thread holds on to them.
+#[derive(Clone, Default)]
+struct Items {
+    chunks: Vec<Arc<[String]>>,
+
+    /// The index of the first item of each chunk.
+    starts: Vec<usize>,
+    len: usize,
+}
+
+impl Items {
+    fn new(items: Vec<String>) -> Self {
+        let mut shared = Self::default();
+        shared.push_chunk(items);
+        shared
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The item at 'idx'. Panics if there is none, like indexing a Vec.
+    fn get(&self, idx: usize) -> &str {
+        let chunk = self.starts.partition_point(|&start| start <= idx) - 1;
+        &self.chunks[chunk][idx - self.starts[chunk]]
+    }
+
+    fn iter(&self) -> impl Iterator<Item = &String> {
+        self.chunks.iter().flat_map(|chunk| chunk.iter())
+    }
+
+    fn push_chunk(&mut self, items: Vec<String>) {
+        if items.is_empty() {
+            return;
+        }
+        self.starts.push(self.len);
+        self.len += items.len();
+        self.chunks.push(items.into());
+    }
+
+    /// Drop the items from 'len' on. Only the items kept from the chunk 'len'
+    /// falls in are copied.
+    fn truncate(&mut self, len: usize) {
+        while self.starts.last().is_some_and(|&start| start >= len) {
+            self.starts.pop();
+            self.chunks.pop();
+        }
+        if let (Some(chunk), Some(&start)) = (self.chunks.last_mut(), self.starts.last()) {
+            if start + chunk.len() > len {
+                let kept = Arc::from(&chunk[..len - start]);
+                *chunk = kept;
+            }
+        }
+        self.len = self.len.min(len);
+    }
+}
+
+/// Does 'item' contain every character of the lowercase 'filter', in order?
+fn matches_filter(item: &str, filter: &str) -> bool {
+    let mut filter = filter.chars().peekable();
+    for c in item.chars().flat_map(char::to_lowercase) {
+        if filter.peek() == Some(&c) {
+            filter.next();
+        }
+    }
+
+    filter.peek().is_none()
+}
@@ -0,0 +1,340 @@
+//! A headless scene for tests. Glyphs come from MockAtlas instead of freetype and a
+//! GPU texture, input is scripted with InputEvents, and tests assert on the
+//! Drawables the scene lays out.
+
+// Each test file that includes this uses only part of it.
+#![allow(dead_code)]
+
+use etagere::AtlasAllocator;
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use text_editor::{GlyphMetrics, GlyphRasterizer};
+use ui::{
+    input::KeyInput,
+    layout::{Color, Drawables, Scene, UiNodeId},
+    quad_pipeline::QuadInstance,
+    resources::Icon,
+    texture_atlas::{AllocationInfo, AtlasStats, FontGlyph, GlyphAtlas},
+    theme::Theme,
+};
+use winit::{
+    event::{ElementState, MouseButton, MouseScrollDelta},
+    keyboard::{Key, NamedKey},
+};
+
+pub const FONT_SIZE: f32 = 16.0;
+pub const WINDOW_SIZE: (f32, f32) = (800.0, 600.0);
+
+/// An empty directory of its own for a test to write files in.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let nanos = std::time::UNIX_EPOCH.elapsed().unwrap().as_nanos();
+    let dir =
+        std::env::temp_dir().join(format!("editor-test-{name}-{}-{nanos}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Every glyph is a box of the same size, so positions are easy to predict. Emoji
+/// take two of these, the way they do in real fonts.
+pub fn advance(font_size: f32) -> f32 {
+    (font_size * 0.6).round()
+}
+
+/// Hands out fixed size glyphs. Each character gets its own spot in the atlas, so
+/// the character a glyph drawable shows can be looked up from its atlas offset.
+pub struct MockAtlas {
+    allocator: AtlasAllocator,
+    glyphs: HashMap<(char, u32), FontGlyph>,
+    chars: HashMap<(u32, u32), char>,
+    icons: HashMap<Icon, AllocationInfo>,
+}
+
+impl MockAtlas {
+    const SIZE: u16 = 4096;
+
+    pub fn new() -> Self {
+        Self {
+            allocator: AtlasAllocator::new(etagere::size2(Self::SIZE as i32, Self::SIZE as i32)),
+            glyphs: HashMap::new(),
+            chars: HashMap::new(),
+            icons: HashMap::new(),
+        }
+    }
+
+    /// The character drawn by a glyph drawable, if it came from this atlas.
+    pub fn char_of(&self, atlas_offset: [f32; 2]) -> Option<char> {
+        let size = Self::SIZE as f32;
+        let x = (atlas_offset[0] * size).round() as u32;
+        let y = (atlas_offset[1] * size).round() as u32;
+        self.chars.get(&(x, y)).copied()
+    }
+
+    /// The icon drawn by an image drawable, if it came from this atlas.
+    pub fn icon_of(&self, atlas_offset: [f32; 2]) -> Option<Icon> {
+        let size = Self::SIZE as f32;
+        let x = (atlas_offset[0] * size).round();
+        let y = (atlas_offset[1] * size).round();
+        self.icons
+            .iter()
+            .find(|(_, info)| info.x == x && info.y == y)
+            .map(|(icon, _)| *icon)
+    }
+}
+
+impl GlyphRasterizer for MockAtlas {
+    fn get_glyph(&mut self, c: char, font_size: f32) -> GlyphMetrics {
+        self.glyph(c, font_size).unwrap().metrics
+    }
+}
+
+impl GlyphAtlas for MockAtlas {
+    fn size(&self) -> u16 {
+        Self::SIZE
+    }
+
+    fn glyph(&mut self, c: char, font_size: f32) -> Option<FontGlyph> {
+        if let Some(glyph) = self.glyphs.get(&(c, font_size as u32)) {
+            return Some(*glyph);
+        }
+
+        let allocation = self.allocator.allocate(etagere::size2(8, 8))?;
+        let width = if c as u32 >= 0x1F000 { 2.0 } else { 1.0 };
+        let metrics = GlyphMetrics {
+            advance: (advance(font_size) * width, 0.0),
+            size: (font_size * 0.5, font_size * 0.7),
+            pos: (0.0, font_size * 0.7),
+        };
+
+        let glyph = FontGlyph::new(metrics, allocation);
+        let origin = allocation.rectangle.min;
+        self.chars.insert((origin.x as u32, origin.y as u32), c);
+        self.glyphs.insert((c, font_size as u32), glyph);
+        Some(glyph)
+    }
+
+    fn icon(&mut self, icon: Icon, _size: u32) -> Option<AllocationInfo> {
+        if let Some(allocation_info) = self.icons.get(&icon) {
+            return Some(*allocation_info);
+        }
+
+        let allocation = self.allocator.allocate(etagere::size2(8, 8))?;
+        let metrics = GlyphMetrics {
+            advance: (0.0, 0.0),
+            size: (0.0, 0.0),
+            pos: (0.0, 0.0),
+        };
+        let allocation_info = FontGlyph::new(metrics, allocation).allocation_info;
+        self.icons.insert(icon, allocation_info);
+        Some(allocation_info)
+    }
+
+    fn stats(&self) -> AtlasStats {
+        AtlasStats {
+            size: Self::SIZE,
+            glyphs: self.glyphs.len(),
+            evictions: 0,
+        }
+    }
+}
+
+/// One step of scripted input.
+#[derive(Debug, Clone)]
+pub enum InputEvent {
+    /// Press and release a key.
+    Key(Key),
+    Press(Key),
+    Release(Key),
+    /// Press and release a key for each character.
+    Type(&'static str),
+    /// Press and release the left button at a position. The number is the click
+    /// count, 2 for a double click.
+    Click(f32, f32, usize),
+    /// Scroll the mouse wheel by lines, positive scrolls up.
+    Scroll(f32),
+}
+
+pub fn named(key: NamedKey) -> Key {
+    Key::Named(key)
+}
+
+/// A scene laid out like the editor's window: a tab bar holding one text node.
+pub struct Harness {
+    pub scene: Scene,
+    pub atlas: MockAtlas,
+
+    /// The text node, focused to begin with.
+    pub text: UiNodeId,
+
+    /// What the last frame drew.
+    pub drawables: Vec<Drawables>,
+
+    /// The frame time fed to the scene. Advanced by hand so animations are predictable.
+    clock: Instant,
+}
+
+impl Harness {
+    pub fn new(contents: &str) -> Self {
+        Self::with_path(contents, None)
+    }
+
+    /// A harness whose buffer pretends to be the file at 'path', e.g. for its syntax.
+    /// Nothing is read from or written to the path.
+    pub fn with_path(contents: &str, path: Option<&str>) -> Self {
+        let mut scene = Scene::default();
+        let text = scene.text_details(contents.to_string(), path.map(Into::into), FONT_SIZE);
+        scene.set_focus(text);
+        let tabs = scene.tab_bar(text, 14.0);
+        let root = scene.hbox(vec![tabs]);
+        scene.set_root(root);
+        scene.set_cursor_blink(false);
+
+        let mut harness = Self {
+            scene,
+            atlas: MockAtlas::new(),
+            text,
+            drawables: vec![],
+            clock: Instant::now(),
+        };
+
+        // Input handling looks at where things were last drawn.
+        harness.frame();
+        harness
+    }
+
+    pub fn theme(&self) -> &Theme {
+        self.scene.theme()
+    }
+
+    /// Send every event, laying out a frame after each one like the window would.
+    pub fn run(&mut self, events: &[InputEvent]) {
+        for event in events {
+            match event {
+                InputEvent::Key(key) => {
+                    self.key(KeyInput::pressed(key.clone()));
+                    self.key(KeyInput::released(key.clone()));
+                }
+                InputEvent::Press(key) => self.key(KeyInput::pressed(key.clone())),
+                InputEvent::Release(key) => self.key(KeyInput::released(key.clone())),
+                InputEvent::Type(text) => {
+                    for c in text.chars() {
+                        let key = Key::Character(c.to_string().into());
+                        self.key(KeyInput::pressed(key.clone()));
+                        self.key(KeyInput::released(key));
+                    }
+                }
+                InputEvent::Click(x, y, count) => {
+                    self.scene.update_cursor_pos(*x, *y, &mut self.atlas);
+                    for state in [ElementState::Pressed, ElementState::Released] {
+                        self.scene
+                            .mouse_input(state, MouseButton::Left, *count, &mut self.atlas);
+                    }
+                }
+                InputEvent::Scroll(lines) => self
+                    .scene
+                    .scroll(MouseScrollDelta::LineDelta(0.0, *lines), &mut self.atlas),
+            }
+
+            self.frame();
+        }
+    }
+
+    fn key(&mut self, input: KeyInput) {
+        self.scene.send_keystroke(&input, &mut self.atlas);
+    }
+
+    /// Lay out a frame after letting every animation finish.
+    pub fn frame(&mut self) {
+        // The first layout starts animations towards their new targets.
+        self.scene.layout(&mut self.atlas, WINDOW_SIZE);
+        self.clock += Duration::from_secs(1);
+        self.scene.tick(self.clock, &mut self.atlas);
+        self.drawables = self.scene.layout(&mut self.atlas, WINDOW_SIZE);
+    }
+
+    /// Lay out a frame 'elapsed' after the last one, with animations part way.
+    pub fn frame_after(&mut self, elapsed: Duration) {
+        self.clock += elapsed;
+        self.scene.tick(self.clock, &mut self.atlas);
+        self.drawables = self.scene.layout(&mut self.atlas, WINDOW_SIZE);
+    }
+
+    /// The rectangles the last frame drew in 'color'.
+    pub fn rects(&self, color: Color) -> Vec<QuadInstance> {
+        let color = color.to_f32_arr();
+        self.drawables
+            .iter()
+            .filter_map(|d| match d {
+                Drawables::Rect(quad) if quad.color == color => Some(*quad),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The cursor, if the last frame drew it.
+    pub fn cursor(&self) -> Option<QuadInstance> {
+        let cursor_width = (FONT_SIZE / 10.0).floor();
+        self.rects(self.theme().cursor)
+            .into_iter()
+            .find(|quad| quad.size[0] == cursor_width)
+    }
+
+    /// The icons the last frame drew.
+    pub fn icons(&self) -> Vec<Icon> {
+        self.drawables
+            .iter()
+            .filter_map(|d| match d {
+                Drawables::TexturedRect(image) => self.atlas.icon_of(image.atlas_offset),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The text the last frame drew, one string per row from top to bottom.
+    pub fn rows(&self) -> Vec<String> {
+        let mut rows: Vec<(f32, String)> = vec![];
+        for ((y, _), c) in self.glyphs() {
+            match rows.last_mut() {
+                Some((row_y, row)) if *row_y == y => row.push(c),
+                _ => rows.push((y, c.to_string())),
+            }
+        }
+
+        rows.into_iter()
+            .map(|(_, row)| row.trim_end_matches('\n').to_string())
+            .collect()
+    }
+
+    /// The middle of the first glyph of 'text', if the last frame drew it on one row.
+    pub fn position_of(&self, text: &str) -> Option<(f32, f32)> {
+        let glyphs = self.glyphs();
+        let len = text.chars().count();
+        glyphs.windows(len).find_map(|window| {
+            let ((y, x), _) = window[0];
+            let same_row = window.iter().all(|((row_y, _), _)| *row_y == y);
+            let matches = window.iter().map(|(_, c)| *c).eq(text.chars());
+            let middle = advance(FONT_SIZE) / 2.0;
+            (same_row && matches).then_some((x + middle, y + middle))
+        })
+    }
+
+    /// Every glyph the last frame drew and where, ordered by row and then from left
+    /// to right. Positions are (y, x) of the glyph's top left.
+    fn glyphs(&self) -> Vec<((f32, f32), char)> {
+        let mut glyphs: Vec<((f32, f32), char)> = self
+            .drawables
+            .iter()
+            .filter_map(|d| match d {
+                Drawables::TexturedRect(image) => self
+                    .atlas
+                    .char_of(image.atlas_offset)
+                    .map(|c| ((image.position[1], image.position[0]), c)),
+                _ => None,
+            })
+            .collect();
+        glyphs.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        glyphs
+    }
+}
@@ -0,0 +1,198 @@
+use text_editor::{Indentation, TextEditor};
+
+fn text(editor: &TextEditor) -> String {
+    editor.slice(0..editor.len()).to_string()
+}
+
+const FAMILY: &str = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+const E_ACUTE: &str = "e\u{0301}";
+const FLAG: &str = "\u{1F1F3}\u{1F1F4}";
+const THUMBS_UP: &str = "\u{1F44D}\u{1F3FD}";
+
+#[test]
+fn arrows_step_over_whole_grapheme_clusters() {
+    let content = format!("a{FAMILY}{E_ACUTE}{FLAG}{FLAG}{THUMBS_UP}\u{1100}\u{1161}\u{11A8}z\n");
+    let mut editor = TextEditor::new(&content, 800.0, 600.0, 16.0);
+
+    let mut stops = vec![editor.cursor_position()];
+    for _ in 0..8 {
+        editor.right();
+        stops.push(editor.cursor_position());
+    }
+
+    let mut expected = vec![0];
+    for cluster in [
+        "a",
+        FAMILY,
+        E_ACUTE,
+        FLAG,
+        FLAG,
+        THUMBS_UP,
+        "\u{1100}\u{1161}\u{11A8}",
+        "z",
+    ] {
+        expected.push(expected.last().unwrap() + cluster.len());
+    }
+    assert_eq!(stops, expected);
+
+    for &stop in expected.iter().rev().skip(1) {
+        editor.left();
+        assert_eq!(editor.cursor_position(), stop);
+    }
+}
+
+#[test]
+fn clusters_are_found_across_the_chunks_of_the_rope() {
+    // Thousands of flags, so pairs of regional indicators straddle chunk ends and
+    // pairing them needs the ones before.
+    let content = format!("{}x\n", FLAG.repeat(3000));
+    let mut editor = TextEditor::new(&content, 800.0, 600.0, 16.0);
+
+    for i in 1..=3000 {
+        editor.right();
+        assert_eq!(editor.cursor_position(), i * FLAG.len());
+    }
+    for i in (0..3000).rev() {
+        editor.left();
+        assert_eq!(editor.cursor_position(), i * FLAG.len());
+    }
+}
+
+#[test]
+fn backspace_and_delete_remove_whole_grapheme_clusters() {
+    let content = format!("{E_ACUTE}{FAMILY}{FLAG}x\n");
+    let mut editor = TextEditor::new(&content, 800.0, 600.0, 16.0);
+    editor.move_cursor_to(E_ACUTE.len() + FAMILY.len());
+
+    editor.backspace();
+    assert_eq!(text(&editor), format!("{E_ACUTE}{FLAG}x\n"));
+    assert_eq!(editor.cursor_position(), E_ACUTE.len());
+
+    editor.delete();
+    assert_eq!(text(&editor), format!("{E_ACUTE}x\n"));
+
+    editor.backspace();
+    assert_eq!(text(&editor), "x\n");
+    assert_eq!(editor.cursor_position(), 0);
+}
+
+#[test]
+fn new_cursors_never_land_inside_a_cluster() {
+    // The column of the main cursor falls between the e and its accent.
+    let content = format!("abc\n{E_ACUTE}f\n");
+    let mut editor = TextEditor::new(&content, 800.0, 600.0, 16.0);
+    editor.move_cursor_to(1);
+    editor.add_cursor_below();
+    assert_eq!(editor.extra_cursors().collect::<Vec<_>>(), vec![4]);
+}
+
+#[test]
+fn delete_removes_whole_characters_up_to_the_end_of_the_text() {
+    let mut editor = TextEditor::new("\u{6F22}\u{5B57}\u{1F600}a", 800.0, 600.0, 16.0);
+    editor.delete();
+    assert_eq!(text(&editor), "\u{5B57}\u{1F600}a");
+    editor.delete();
+    assert_eq!(text(&editor), "\u{1F600}a");
+    editor.delete();
+    assert_eq!(text(&editor), "a");
+    editor.delete();
+    assert_eq!(text(&editor), "");
+    editor.delete();
+    assert_eq!(text(&editor), "");
+
+    editor.undo();
+    assert_eq!(text(&editor), "\u{6F22}\u{5B57}\u{1F600}a");
+}
+
+#[test]
+fn indenting_uses_tabs_or_spaces_per_buffer() {
+    let mut editor = TextEditor::new("fn f() {", 800.0, 600.0, 16.0);
+    editor.move_cursor_to(editor.len());
+    editor.set_insert_spaces(false);
+    editor.insert_newline();
+    editor.indent();
+    assert_eq!(text(&editor), "fn f() {\n\t\t");
+
+    editor.set_insert_spaces(true);
+    editor.set_tab_width(2);
+    editor.indent();
+    assert_eq!(text(&editor), "fn f() {\n\t\t  ");
+}
+
+#[test]
+fn indentation_is_detected_from_the_text() {
+    let detect = |text: &str| TextEditor::new(text, 800.0, 600.0, 16.0).detect_indentation();
+    assert_eq!(
+        detect("a {\n\tb {\n\t\tc\n\t}\n}\n"),
+        Some(Indentation::Tabs)
+    );
+    assert_eq!(
+        detect("a:\n    b:\n        c\n    d\n/*\n *\n */\n"),
+        Some(Indentation::Spaces(4))
+    );
+    assert_eq!(detect("a\n  b\n    c\n"), Some(Indentation::Spaces(2)));
+    assert_eq!(detect("a\nb\n"), None);
+}
+
+#[test]
+fn indentation_converts_between_tabs_and_spaces_in_one_undo_step() {
+    let mut editor = TextEditor::new("a\n\t  b\n \tc\n      d\n", 800.0, 600.0, 16.0);
+    editor.indentation_to_spaces();
+    assert_eq!(text(&editor), "a\n      b\n    c\n      d\n");
+
+    editor.indentation_to_tabs();
+    assert_eq!(text(&editor), "a\n\t  b\n\tc\n\t  d\n");
+    assert!(!editor.insert_spaces());
+
+    editor.undo();
+    editor.undo();
+    assert_eq!(text(&editor), "a\n\t  b\n \tc\n      d\n");
+}
+
+#[test]
+fn line_ending_counts_follow_edits_that_join_and_split_crlf() {
+    let mut editor = TextEditor::new("a\r\nb\nc", 800.0, 600.0, 16.0);
+    let counts = |editor: &TextEditor| {
+        let endings = editor.line_endings();
+        (endings.lf, endings.crlf, endings.trailing)
+    };
+    assert_eq!(counts(&editor), (1, 1, 0));
+
+    // Typing between the '\r' and the '\n' splits the CRLF.
+    editor.move_cursor_to(2);
+    editor.insert_text("x");
+    assert_eq!(counts(&editor), (2, 0, 0));
+
+    // Deleting it joins them again.
+    editor.backspace();
+    assert_eq!(counts(&editor), (1, 1, 0));
+
+    editor.move_cursor_to(4);
+    editor.insert_text("\r");
+    editor.move_cursor_to(editor.len());
+    editor.insert_text("\r\n\n");
+    assert_eq!(counts(&editor), (1, 3, 2));
+    assert_eq!(counts(&editor), {
+        let fresh = TextEditor::new(&text(&editor), 800.0, 600.0, 16.0);
+        counts(&fresh)
+    });
+}
+
+#[test]
+fn the_trailing_newline_fixed_on_save_is_undone_with_the_last_edit() {
+    let mut editor = TextEditor::new("a\n", 800.0, 600.0, 16.0);
+    editor.move_cursor_to(2);
+    editor.insert_text("b");
+
+    editor.ensure_trailing_newline_on_save();
+    assert_eq!(text(&editor), "a\nb\n");
+    editor.undo();
+    assert_eq!(text(&editor), "a\n");
+
+    // With nothing to join, it is a step of its own.
+    let mut editor = TextEditor::new("a", 800.0, 600.0, 16.0);
+    editor.ensure_trailing_newline_on_save();
+    assert_eq!(text(&editor), "a\n");
+    editor.undo();
+    assert_eq!(text(&editor), "a");
+}
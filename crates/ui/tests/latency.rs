@@ -0,0 +1,80 @@
+use std::time::{Duration, Instant};
+use ui::{
+    latency::{LatencySample, LatencyTrace, LatencyTracer},
+    layout::BoundingBox,
+    theme::Theme,
+};
+
+fn ms(n: u64) -> Duration {
+    Duration::from_millis(n)
+}
+
+#[test]
+fn each_stage_is_timed_from_the_end_of_the_last() {
+    let mut tracer = LatencyTracer::new(LatencyTrace::Log);
+    let start = Instant::now();
+
+    tracer.key_received(start);
+    tracer.key_received(start + ms(1));
+    tracer.mutated(start + ms(2));
+    tracer.laid_out(start + ms(10));
+    tracer.presented(start + ms(14));
+
+    let samples: Vec<_> = tracer.recent().copied().collect();
+    assert_eq!(
+        samples,
+        [
+            LatencySample {
+                mutate: ms(2),
+                layout: ms(8),
+                present: ms(4),
+            },
+            LatencySample {
+                mutate: ms(1),
+                layout: ms(8),
+                present: ms(4),
+            },
+        ]
+    );
+    assert!(tracer.report().contains("total 13.00/14.00/14.00"));
+}
+
+#[test]
+fn keys_applied_after_layout_wait_for_the_next_frame() {
+    let mut tracer = LatencyTracer::new(LatencyTrace::Log);
+    let start = Instant::now();
+
+    tracer.laid_out(start);
+    tracer.key_received(start + ms(1));
+    tracer.mutated(start + ms(2));
+    tracer.presented(start + ms(3));
+    assert_eq!(tracer.recent().count(), 0);
+
+    tracer.laid_out(start + ms(20));
+    tracer.presented(start + ms(21));
+    assert_eq!(tracer.recent().next().unwrap().total(), ms(20));
+}
+
+#[test]
+fn only_the_overlay_mode_draws_a_sparkline() {
+    let view = BoundingBox::new(0.0, 0.0, 800.0, 600.0);
+    let start = Instant::now();
+
+    for mode in [LatencyTrace::Off, LatencyTrace::Log, LatencyTrace::Overlay] {
+        let mut tracer = LatencyTracer::new(mode);
+        for i in 0..3 {
+            let t = start + ms(i * 100);
+            tracer.key_received(t);
+            tracer.mutated(t);
+            tracer.laid_out(t);
+            tracer.presented(t + ms(5));
+        }
+
+        let drawn = tracer.sparkline(view, &Theme::default()).len();
+        match mode {
+            // The background and a bar per key press.
+            LatencyTrace::Overlay => assert_eq!(drawn, 4),
+            _ => assert_eq!(drawn, 0),
+        }
+    }
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use std::time::{Duration, Instant};
+use ui::{
+    input::{KeyInput, Modifiers},
+    recording::{InputEvent, Recorder, Recording, Replay},
+};
+use winit::{
+    dpi::PhysicalPosition,
+    event::{ElementState, MouseButton, MouseScrollDelta},
+    keyboard::{Key, NamedKey},
+};
+
+#[test]
+fn recorded_input_is_saved_and_replayed_at_its_times() {
+    let dir = common::temp_dir("recording");
+    let path = dir.join("recording.toml");
+    let start = Instant::now();
+    let ms = Duration::from_millis;
+
+    let events = [
+        InputEvent::Resized {
+            width: 800,
+            height: 600,
+        },
+        InputEvent::Modifiers {
+            modifiers: Modifiers {
+                ctrl: true,
+                ..Modifiers::default()
+            },
+        },
+        InputEvent::Key {
+            key: KeyInput::pressed(Key::Character("z".into())),
+        },
+        InputEvent::Key {
+            key: KeyInput::pressed(Key::Named(NamedKey::ArrowLeft)),
+        },
+        InputEvent::Scroll {
+            delta: MouseScrollDelta::PixelDelta(PhysicalPosition::new(0.0, -12.5)),
+        },
+        InputEvent::MouseInput {
+            state: ElementState::Pressed,
+            button: MouseButton::Left,
+            clicks: 2,
+        },
+        InputEvent::Redraw,
+    ];
+    // Events are in the file as soon as they are recorded, without saving.
+    let mut recorder = Recorder::new(&path, Some("notes.txt".into()), start).unwrap();
+    for (i, event) in events.iter().enumerate() {
+        recorder.record(start + ms(10 * i as u64), event).unwrap();
+    }
+
+    let recording = Recording::load(&path).unwrap();
+    assert_eq!(recording.file.as_deref(), Some("notes.txt"));
+
+    // Only what was recorded by then is due, at the time it was recorded.
+    let replay_start = Instant::now();
+    let mut replay = Replay::new(recording, replay_start);
+    let due = replay.due(replay_start + ms(25));
+    assert_eq!(
+        due,
+        [
+            (replay_start, events[0].clone()),
+            (replay_start + ms(10), events[1].clone()),
+            (replay_start + ms(20), events[2].clone()),
+        ]
+    );
+    assert_eq!(replay.next_time(), Some(replay_start + ms(30)));
+
+    let due = replay.due(replay_start + ms(1000));
+    let rest: Vec<InputEvent> = due.into_iter().map(|(_, event)| event).collect();
+    assert_eq!(rest, events[3..]);
+    assert_eq!(replay.next_time(), None);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
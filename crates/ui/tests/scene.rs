@@ -0,0 +1,1616 @@
+mod common;
+
+use common::{advance, named, temp_dir, Harness, InputEvent::*, FONT_SIZE};
+use std::time::Duration;
+use text_editor::LineMetrics;
+use ui::{
+    clipboard::Clipboard,
+    input::Modifiers,
+    keymap::{Command, Keymap},
+    layout::{Color, Drawables, RemoteCaret, SplitDirection},
+    resources::Icon,
+    session::Session,
+    settings::{Settings, Wrap},
+    theme::Theme,
+};
+use winit::{
+    event::{ElementState, MouseButton, MouseScrollDelta},
+    keyboard::NamedKey,
+};
+
+#[test]
+fn typing_moves_the_cursor_right() {
+    let mut harness = Harness::new("");
+    let before = harness.cursor().unwrap();
+
+    harness.run(&[Type("ab")]);
+    let after = harness.cursor().unwrap();
+
+    assert_eq!(
+        after.position[0],
+        before.position[0] + 2.0 * advance(FONT_SIZE)
+    );
+    assert_eq!(after.position[1], before.position[1]);
+}
+
+#[test]
+fn enter_moves_the_cursor_to_the_next_line() {
+    let mut harness = Harness::new("");
+    let before = harness.cursor().unwrap();
+
+    harness.run(&[Type("ab"), Key(named(NamedKey::Enter))]);
+    let after = harness.cursor().unwrap();
+
+    assert_eq!(after.position[0], before.position[0]);
+    assert_eq!(after.position[1], before.position[1] + FONT_SIZE * 1.2);
+}
+
+#[test]
+fn a_new_text_draws_its_cursor_in_place_without_gliding_there() {
+    let mut harness = Harness::new("hello");
+    let settled = harness.cursor().unwrap();
+
+    let text = harness
+        .scene
+        .text_details("hello".to_string(), None, FONT_SIZE);
+    let tabs = harness.scene.tab_bar(text, 14.0);
+    let root = harness.scene.hbox(vec![tabs]);
+    harness.scene.set_root(root);
+    harness.scene.set_focus(text);
+    harness.scene.set_cursor_blink(false);
+    harness.frame_after(Duration::ZERO);
+    assert_eq!(harness.cursor().unwrap().position, settled.position);
+}
+
+#[test]
+fn the_cursor_blink_setting_keeps_the_cursor_solid() {
+    let mut harness = Harness::new("hello");
+    harness.scene.set_settings(Settings::default());
+
+    // After a second without input the cursor blinks, and is hidden right after
+    // it was last drawn.
+    std::thread::sleep(Duration::from_millis(1100));
+    harness.frame();
+    assert!(harness.cursor().is_none());
+
+    harness.scene.set_settings(Settings {
+        cursor_blink: false,
+        ..Settings::default()
+    });
+    harness.frame();
+    assert!(harness.cursor().is_some());
+}
+
+#[test]
+fn the_line_height_setting_spreads_lines_out() {
+    let mut harness = Harness::new("");
+    harness.scene.set_settings(Settings {
+        line_height: 1.5,
+        ..Settings::default()
+    });
+    harness.frame();
+    let first = harness.cursor().unwrap();
+
+    harness.run(&[Type("one"), Key(named(NamedKey::Enter)), Type("two")]);
+    let second = harness.cursor().unwrap();
+    assert_eq!(
+        second.position[1],
+        first.position[1] + FONT_SIZE * 1.2 * 1.5
+    );
+
+    // Clicks land on the line drawn under them.
+    let (x, y) = harness.position_of("one").unwrap();
+    harness.run(&[Click(x, y, 1)]);
+    assert_eq!(harness.cursor().unwrap().position[1], first.position[1]);
+}
+
+#[test]
+fn typed_text_is_drawn() {
+    let mut harness = Harness::new("");
+    harness.run(&[Type("hello"), Key(named(NamedKey::Enter)), Type("world")]);
+
+    let rows = harness.rows();
+    let hello = rows.iter().position(|row| row == "hello").unwrap();
+    assert_eq!(rows[hello + 1], "world");
+}
+
+#[test]
+fn shift_arrows_draw_a_selection() {
+    let mut harness = Harness::new("hello");
+    harness.run(&[
+        Key(named(NamedKey::End)),
+        Press(named(NamedKey::Shift)),
+        Key(named(NamedKey::ArrowLeft)),
+        Key(named(NamedKey::ArrowLeft)),
+        Release(named(NamedKey::Shift)),
+    ]);
+
+    let selection = harness.rects(harness.theme().selection);
+    assert_eq!(selection.len(), 2);
+    assert!(selection
+        .iter()
+        .all(|quad| quad.size[0] == advance(FONT_SIZE)));
+
+    // The selection covers the last two characters, ending at the cursor.
+    let cursor = harness.cursor().unwrap();
+    let end = selection
+        .iter()
+        .map(|q| q.position[0] + q.size[0])
+        .fold(0.0, f32::max);
+    assert_eq!(end, cursor.position[0] + 2.0 * advance(FONT_SIZE));
+}
+
+#[test]
+fn clicking_places_the_cursor() {
+    let mut harness = Harness::new("hello world");
+    let start = harness.cursor().unwrap();
+
+    let x = start.position[0] + 6.0 * advance(FONT_SIZE) + 1.0;
+    let y = start.position[1] + start.size[1] / 2.0;
+    harness.run(&[Click(x, y, 1)]);
+
+    let cursor = harness.cursor().unwrap();
+    assert_eq!(
+        cursor.position[0],
+        start.position[0] + 6.0 * advance(FONT_SIZE)
+    );
+    assert_eq!(cursor.position[1], start.position[1]);
+}
+
+#[test]
+fn double_click_selects_a_word() {
+    let mut harness = Harness::new("hello world");
+    let start = harness.cursor().unwrap();
+
+    let x = start.position[0] + 8.0 * advance(FONT_SIZE);
+    let y = start.position[1] + start.size[1] / 2.0;
+    harness.run(&[Click(x, y, 1), Click(x, y, 2)]);
+
+    let selection = harness.rects(harness.theme().selection);
+    assert_eq!(selection.len(), "world".len());
+    assert_eq!(
+        selection[0].position[0],
+        start.position[0] + 6.0 * advance(FONT_SIZE)
+    );
+}
+
+#[test]
+fn scrolling_moves_the_text_up() {
+    let contents: Vec<String> = (0..200).map(|i| format!("line {i}")).collect();
+    let mut harness = Harness::new(&contents.join("\n"));
+
+    let first_line = |harness: &Harness| {
+        let rows = harness.rows();
+        rows.into_iter()
+            .find(|row| row.starts_with("line"))
+            .unwrap()
+    };
+    assert_eq!(first_line(&harness), "line 0");
+
+    harness.run(&[Scroll(-1.0)]);
+    assert_eq!(first_line(&harness), "line 3");
+
+    harness.run(&[Scroll(1.0)]);
+    assert_eq!(first_line(&harness), "line 0");
+
+    // The lines of a wheel scroll go by a few at a time.
+    harness
+        .scene
+        .scroll(MouseScrollDelta::LineDelta(0.0, -1.0), &mut harness.atlas);
+    harness.frame_after(Duration::from_millis(20));
+    assert_eq!(first_line(&harness), "line 1");
+    harness.frame_after(Duration::from_millis(100));
+    assert_eq!(first_line(&harness), "line 3");
+}
+
+#[test]
+fn keymap_file_rebinds_keys() {
+    let dir = temp_dir("keymap");
+    let path = dir.join("keymap.toml");
+    std::fs::write(&path, "\"ctrl+j\" = \"newline\"\n\"enter\" = \"unbound\"\n").unwrap();
+    let keymap = Keymap::load(&path).unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+
+    let mut harness = Harness::new("");
+    harness.scene.set_keymap(keymap);
+    let start = harness.cursor().unwrap();
+
+    harness.run(&[Key(named(NamedKey::Enter))]);
+    assert_eq!(harness.cursor().unwrap().position, start.position);
+
+    harness.run(&[
+        Press(named(NamedKey::Control)),
+        Type("j"),
+        Release(named(NamedKey::Control)),
+    ]);
+    let cursor = harness.cursor().unwrap();
+    assert_eq!(cursor.position[0], start.position[0]);
+    assert_eq!(cursor.position[1], start.position[1] + FONT_SIZE * 1.2);
+}
+
+#[test]
+fn fading_to_a_theme_ends_on_its_colors() {
+    let mut harness = Harness::new("hello");
+    assert!(harness.rects(Theme::light().background).is_empty());
+
+    harness.scene.fade_to_theme(Theme::light());
+    harness.frame();
+
+    assert!(!harness.rects(Theme::light().background).is_empty());
+    assert!(harness.rects(Theme::default().background).is_empty());
+}
+
+#[test]
+fn composed_text_shows_at_the_cursor_until_it_is_committed() {
+    let mut harness = Harness::new("ab\n");
+    harness.run(&[
+        Key(named(NamedKey::ArrowRight)),
+        Key(named(NamedKey::ArrowRight)),
+        Key(winit::keyboard::Key::Dead(Some('´'))),
+    ]);
+    assert!(harness.rows().iter().any(|row| row == "ab´"));
+
+    harness.run(&[Press(named(NamedKey::Shift)), Type("É")]);
+    assert!(harness.rows().iter().any(|row| row == "abÉ"));
+
+    harness.scene.set_preedit("にほ");
+    harness.frame();
+    assert!(harness.rows().iter().any(|row| row == "abÉにほ"));
+
+    harness.scene.commit_text("日本");
+    harness.frame();
+    assert!(harness.rows().iter().any(|row| row == "abÉ日本"));
+}
+
+#[test]
+fn composed_text_pushes_the_rest_of_the_line_and_the_cursor_right() {
+    let mut harness = Harness::new("ab");
+    let start = harness.cursor().unwrap();
+    harness.run(&[Key(named(NamedKey::ArrowRight))]);
+    harness.scene.set_preedit("xy");
+    harness.frame();
+
+    assert!(harness.rows().iter().any(|row| row == "axyb"));
+    let cursor = harness.cursor().unwrap();
+    assert_eq!(
+        cursor.position[0],
+        start.position[0] + 3.0 * advance(FONT_SIZE)
+    );
+
+    // The input method's candidates go next to what is being composed.
+    let area = harness.scene.ime_cursor_area().unwrap();
+    assert_eq!(area.min.0, start.position[0] + advance(FONT_SIZE));
+    assert_eq!(area.width(), 2.0 * advance(FONT_SIZE));
+}
+
+#[test]
+fn theme_fades_wait_while_the_window_is_hidden() {
+    let mut harness = Harness::new("hello");
+    harness.scene.set_window_occluded(true);
+    harness.scene.fade_to_theme(Theme::light());
+    harness.frame();
+    assert!(harness.rects(Theme::light().background).is_empty());
+
+    // Hiding and focusing are separate, so focus alone doesn't resume it.
+    harness.scene.set_window_focused(true);
+    harness.frame();
+    assert!(harness.rects(Theme::light().background).is_empty());
+
+    harness.scene.set_window_occluded(false);
+    harness.frame();
+    assert!(!harness.rects(Theme::light().background).is_empty());
+}
+
+#[test]
+fn emacs_preset_moves_by_lines_and_words() {
+    let dir = temp_dir("emacs-keymap");
+    let path = dir.join("keymap.toml");
+    std::fs::write(&path, "preset = \"emacs\"\n").unwrap();
+    let keymap = Keymap::load(&path).unwrap();
+    std::fs::remove_dir_all(dir).unwrap();
+
+    let mut harness = Harness::new("hello brave world");
+    harness.scene.set_keymap(keymap);
+    let start = harness.cursor().unwrap();
+
+    harness.run(&[
+        Press(named(NamedKey::Control)),
+        Type("e"),
+        Release(named(NamedKey::Control)),
+    ]);
+    let end = harness.cursor().unwrap();
+    assert_eq!(
+        end.position[0],
+        start.position[0] + 17.0 * advance(FONT_SIZE)
+    );
+
+    harness.run(&[
+        Press(named(NamedKey::Alt)),
+        Type("bb"),
+        Release(named(NamedKey::Alt)),
+        Press(named(NamedKey::Control)),
+        Type("k"),
+        Release(named(NamedKey::Control)),
+    ]);
+    assert!(harness.rows().iter().any(|row| row == "hello "));
+}
+
+#[test]
+fn auto_close_can_be_toggled_per_buffer() {
+    let mut harness = Harness::new("");
+    harness.run(&[Type("(")]);
+    assert!(harness.rows().iter().any(|row| row == "()"));
+
+    harness.run(&[
+        Key(named(NamedKey::Backspace)),
+        Press(named(NamedKey::Control)),
+        Press(named(NamedKey::Shift)),
+        Type("P"),
+        Release(named(NamedKey::Shift)),
+        Release(named(NamedKey::Control)),
+        Type("("),
+    ]);
+    assert!(harness.rows().iter().any(|row| row == "("));
+}
+
+/// Click at 'x' glyph advances into the first text row, and return how many
+/// advances from the start of the row the cursor ended up.
+fn click_at_advance(harness: &mut Harness, x: f32, row: usize) -> f32 {
+    let start = harness.cursor().unwrap();
+    let line_height = FONT_SIZE * 1.2;
+    let y = start.position[1] + start.size[1] / 2.0 + row as f32 * line_height;
+    harness.run(&[Click(start.position[0] + x * advance(FONT_SIZE), y, 1)]);
+
+    let cursor = harness.cursor().unwrap();
+    (cursor.position[0] - start.position[0]) / advance(FONT_SIZE)
+}
+
+#[test]
+fn clicks_go_to_the_nearest_glyph_edge() {
+    // Left half of the second glyph goes before it, right half after.
+    let mut harness = Harness::new("hello");
+    assert_eq!(click_at_advance(&mut harness, 1.4, 0), 1.0);
+
+    let mut harness = Harness::new("hello");
+    assert_eq!(click_at_advance(&mut harness, 1.6, 0), 2.0);
+}
+
+#[test]
+fn clicks_on_emoji_and_tabs_use_their_whole_glyph() {
+    // The emoji is two advances wide, so its middle is at 2.
+    let mut harness = Harness::new("a😀b");
+    assert_eq!(click_at_advance(&mut harness, 1.8, 0), 1.0);
+
+    let mut harness = Harness::new("a😀b");
+    assert_eq!(click_at_advance(&mut harness, 2.2, 0), 3.0);
+    harness.run(&[Type("x")]);
+    assert!(harness.rows().iter().any(|row| row == "a😀xb"));
+
+    // The tab reaches the tab stop after four advances and draws no glyph.
+    let mut harness = Harness::new("a\tb");
+    assert_eq!(click_at_advance(&mut harness, 2.4, 0), 1.0);
+
+    let mut harness = Harness::new("a\tb");
+    assert_eq!(click_at_advance(&mut harness, 2.6, 0), 4.0);
+    harness.run(&[Type("x")]);
+    assert!(harness.rows().iter().any(|row| row == "axb"));
+}
+
+#[test]
+fn clicks_past_the_end_of_a_line_go_to_its_end() {
+    let mut harness = Harness::new("hi\nthere");
+    assert_eq!(click_at_advance(&mut harness, 20.0, 0), 2.0);
+    harness.run(&[Type("!")]);
+    let rows = harness.rows();
+    let hi = rows.iter().position(|row| row == "hi!").unwrap();
+    assert_eq!(rows[hi + 1], "there");
+}
+
+#[test]
+fn brackets_next_to_the_cursor_are_highlighted_with_their_match() {
+    let mut harness = Harness::new("(a[b]c) d");
+    let start = harness.cursor().unwrap();
+
+    let brackets = harness.rects(harness.theme().bracket_match);
+    let xs: Vec<f32> = brackets.iter().map(|quad| quad.position[0]).collect();
+    assert_eq!(
+        xs,
+        [
+            start.position[0],
+            start.position[0] + 6.0 * advance(FONT_SIZE)
+        ]
+    );
+
+    // Nested brackets match each other, not the outer ones.
+    harness.run(&[
+        Key(named(NamedKey::ArrowRight)),
+        Key(named(NamedKey::ArrowRight)),
+    ]);
+    let brackets = harness.rects(harness.theme().bracket_match);
+    let xs: Vec<f32> = brackets.iter().map(|quad| quad.position[0]).collect();
+    assert_eq!(
+        xs,
+        [
+            start.position[0] + 2.0 * advance(FONT_SIZE),
+            start.position[0] + 4.0 * advance(FONT_SIZE)
+        ]
+    );
+
+    harness.run(&[Key(named(NamedKey::End))]);
+    assert!(harness.rects(harness.theme().bracket_match).is_empty());
+}
+
+#[test]
+fn mixed_line_endings_are_shown_and_normalized() {
+    let mut harness = Harness::new("a\r\nb\nc\n");
+    // The status bar draws its items on one row.
+    let has_row = |harness: &Harness, text: &str| {
+        let text = format!("{text}Spaces: 4");
+        harness.rows().contains(&text)
+    };
+    assert!(has_row(&harness, "Mixed line endings (2 LF, 1 CRLF)"));
+    assert_eq!(harness.icons(), [Icon::Warning]);
+
+    harness.run(&[
+        Press(named(NamedKey::Control)),
+        Press(named(NamedKey::Shift)),
+        Type("L"),
+        Release(named(NamedKey::Shift)),
+        Release(named(NamedKey::Control)),
+    ]);
+    assert!(has_row(&harness, "LF"));
+    assert!(harness.icons().is_empty());
+
+    harness.run(&[
+        Press(named(NamedKey::Control)),
+        Type("z"),
+        Release(named(NamedKey::Control)),
+    ]);
+    assert!(has_row(&harness, "Mixed line endings (2 LF, 1 CRLF)"));
+}
+
+#[test]
+fn indentation_is_detected_and_picked_from_the_status_bar() {
+    let mut harness = Harness::new("fn f() {\n  if x {\n    y\n  }\n}\n");
+    let item = harness.position_of("Spaces: 2").unwrap();
+
+    harness.run(&[Click(item.0, item.1, 1)]);
+    let choice = harness.position_of("Tab width: 8").unwrap();
+    assert!(choice.1 < item.1);
+
+    harness.run(&[Click(choice.0, choice.1, 1)]);
+    assert!(harness.position_of("Tab width: 8").is_some());
+    assert!(harness.position_of("Spaces: 2").is_none());
+
+    // A tab goes in, reaching the eighth column.
+    let start = harness.cursor().unwrap().position[0];
+    harness.run(&[Key(named(NamedKey::Tab))]);
+    let cursor = harness.cursor().unwrap().position[0];
+    assert_eq!(cursor - start, 8.0 * advance(FONT_SIZE));
+    assert!(harness.rows().iter().any(|row| row == "fn f() {"));
+}
+
+#[test]
+fn crlf_line_endings_are_kept_for_new_lines_and_can_be_converted() {
+    let mut harness = Harness::new("a\r\nb\r\n");
+    let rows = harness.rows();
+    assert!(rows.contains(&"a".to_string()) && rows.contains(&"b".to_string()));
+    assert!(rows.contains(&"CRLFSpaces: 4".to_string()));
+
+    harness.run(&[Key(named(NamedKey::Enter))]);
+    assert!(harness.rows().contains(&"CRLFSpaces: 4".to_string()));
+
+    harness.run(&[
+        Press(named(NamedKey::Control)),
+        Press(named(NamedKey::Alt)),
+        Type("r"),
+        Release(named(NamedKey::Alt)),
+        Release(named(NamedKey::Control)),
+    ]);
+    assert!(harness.rows().contains(&"LFSpaces: 4".to_string()));
+}
+
+#[test]
+fn trailing_newline_mismatch_is_shown_when_the_setting_is_on() {
+    let mut harness = Harness::new("a\n\n\n");
+    // The status bar draws its items on one row.
+    let has_row =
+        |harness: &Harness, text: &str| harness.rows().iter().any(|row| row.ends_with(text));
+    assert!(!has_row(&harness, "3 newlines at end of file"));
+
+    harness.scene.set_settings(Settings {
+        ensure_trailing_newline: true,
+        ..Settings::default()
+    });
+    harness.frame();
+    assert!(has_row(&harness, "3 newlines at end of file"));
+
+    harness.run(&[
+        Press(named(NamedKey::Control)),
+        Press(named(NamedKey::Shift)),
+        Type("N"),
+        Release(named(NamedKey::Shift)),
+        Release(named(NamedKey::Control)),
+    ]);
+    assert!(!has_row(&harness, "3 newlines at end of file"));
+    assert!(!has_row(&harness, "No newline at end of file"));
+}
+
+#[test]
+fn buffer_info_overlay_shows_text_and_history_sizes() {
+    let mut harness = Harness::new("ab\ncd");
+    harness.run(&[Type("x")]);
+    let info = [
+        Press(named(NamedKey::Control)),
+        Press(named(NamedKey::Shift)),
+        Type("I"),
+        Release(named(NamedKey::Shift)),
+        Release(named(NamedKey::Control)),
+    ];
+
+    harness.run(&info);
+    let rows = harness.rows();
+    assert!(rows.iter().any(|row| row == "Text: 6 bytes, 2 lines"));
+    assert!(rows
+        .iter()
+        .any(|row| row.starts_with("History: 1 undo, 0 redo")));
+    assert!(rows.iter().any(|row| row.starts_with("Atlas: 4096x4096")));
+
+    harness.run(&info);
+    assert!(!harness.rows().iter().any(|row| row.starts_with("Text:")));
+
+    harness.run(&info);
+    assert!(harness.scene.escape());
+    harness.frame();
+    assert!(!harness.rows().iter().any(|row| row.starts_with("Text:")));
+}
+
+#[test]
+fn icon_nodes_are_drawn_centered_at_their_size() {
+    let mut harness = Harness::new("");
+    let icon = harness.scene.icon(Icon::Folder, None);
+    let sized = harness
+        .scene
+        .fixed_size_bbox(40.0, 20.0, icon, Color::new(0, 0, 0, 0));
+    harness.scene.set_root(sized);
+    harness.frame();
+
+    assert_eq!(harness.icons(), [Icon::Folder]);
+    let Some(Drawables::TexturedRect(image)) = harness.drawables.last() else {
+        panic!("no icon drawn");
+    };
+    assert_eq!(image.size, [20.0, 20.0]);
+    // The box is centered in the window, and the icon in the box.
+    assert_eq!(image.position, [390.0, 290.0]);
+
+    // The debug setting outlines the box just outside its edges.
+    let outline_color = harness.theme().widget_foreground;
+    assert!(harness.rects(outline_color).is_empty());
+    harness.scene.set_settings(Settings {
+        debug_outlines: true,
+        ..Settings::default()
+    });
+    harness.frame();
+    let outlines = harness.rects(outline_color);
+    assert_eq!(outlines.len(), 1);
+    assert_eq!(outlines[0].position, [379.0, 289.0]);
+    assert_eq!(outlines[0].size, [42.0, 22.0]);
+    assert_eq!(outlines[0].border, 1.0);
+}
+
+#[test]
+fn icons_rasterize_to_a_white_mask_at_any_size() {
+    for size in [12, 16, 32] {
+        let image = Icon::Warning.rasterize(size);
+        assert_eq!(image.dimensions(), (size, size));
+        assert!(image.pixels().all(|p| p.0[..3] == [255, 255, 255]));
+        assert!(image.pixels().any(|p| p.0[3] == 255));
+        assert!(image.pixels().any(|p| p.0[3] == 0));
+    }
+}
+
+#[test]
+fn ctrl_d_adds_cursors_at_next_occurrences_and_edits_at_all_of_them() {
+    let mut harness = Harness::new("foo bar foo baz foo");
+    let ctrl_d = [
+        Press(named(NamedKey::Control)),
+        Type("d"),
+        Release(named(NamedKey::Control)),
+    ];
+
+    // The first press selects the word, the next ones add its next occurrences.
+    harness.run(&ctrl_d);
+    harness.run(&ctrl_d);
+    harness.run(&[Type("xy")]);
+    assert!(harness.rows().iter().any(|row| row == "xy bar xy baz foo"));
+
+    let cursor_width = (FONT_SIZE / 10.0).floor();
+    let cursors = harness
+        .rects(harness.theme().cursor)
+        .into_iter()
+        .filter(|quad| quad.size[0] == cursor_width)
+        .count();
+    assert_eq!(cursors, 2);
+
+    harness.run(&[Key(named(NamedKey::Backspace))]);
+    assert!(harness.rows().iter().any(|row| row == "x bar x baz foo"));
+
+    // Escape goes back to one cursor.
+    assert!(harness.scene.escape());
+    harness.run(&[Type("!")]);
+    assert!(harness.rows().iter().any(|row| row == "x bar x! baz foo"));
+}
+
+#[test]
+fn closing_a_buffer_with_unsaved_changes_warns_first() {
+    let mut harness = Harness::new("");
+    let ctrl_w = [
+        Press(named(NamedKey::Control)),
+        Type("w"),
+        Release(named(NamedKey::Control)),
+    ];
+    let warns = |harness: &Harness| {
+        harness
+            .rows()
+            .iter()
+            .any(|row| row.ends_with("close again to discard them"))
+    };
+    let shows_text = |harness: &Harness| harness.rows().iter().any(|row| row == "abc");
+
+    harness.run(&[Type("abc")]);
+    harness.run(&ctrl_w);
+    assert!(warns(&harness));
+    assert!(shows_text(&harness));
+
+    // Any other key takes the warning back.
+    harness.run(&[Key(named(NamedKey::ArrowLeft))]);
+    assert!(!warns(&harness));
+    harness.run(&ctrl_w);
+    assert!(shows_text(&harness));
+
+    harness.run(&ctrl_w);
+    assert!(!warns(&harness));
+    assert!(!shows_text(&harness));
+}
+
+#[test]
+fn failed_saves_are_shown_in_the_status_bar() {
+    let dir = temp_dir("save-error");
+    let path = dir.join("gone.txt");
+    std::fs::write(&path, "abc").unwrap();
+
+    let mut harness = Harness::new("");
+    assert!(harness.scene.open_path(&path));
+    harness.run(&[Type("x")]);
+    std::fs::remove_dir_all(&dir).unwrap();
+    harness.run(&[
+        Press(named(NamedKey::Control)),
+        Type("s"),
+        Release(named(NamedKey::Control)),
+    ]);
+    let failed = |harness: &Harness| {
+        harness
+            .rows()
+            .iter()
+            .any(|row| row.contains("Couldn't save gone.txt"))
+    };
+    assert!(failed(&harness));
+
+    harness.run(&[Key(named(NamedKey::ArrowLeft))]);
+    assert!(!failed(&harness));
+}
+
+#[test]
+fn reopening_with_another_encoding_asks_before_dropping_changes() {
+    let dir = temp_dir("reopen-encoding");
+    let path = dir.join("file.txt");
+    std::fs::write(&path, "abc").unwrap();
+
+    let mut harness = Harness::new("");
+    assert!(harness.scene.open_path(&path));
+    harness.run(&[Type("x")]);
+    let ctrl_alt_e = [
+        Press(named(NamedKey::Control)),
+        Press(named(NamedKey::Alt)),
+        Type("e"),
+        Release(named(NamedKey::Alt)),
+        Release(named(NamedKey::Control)),
+    ];
+    let has = |harness: &Harness, text: &str| harness.rows().iter().any(|row| row.contains(text));
+
+    harness.run(&ctrl_alt_e);
+    assert!(has(&harness, "reopen again to discard them"));
+    assert!(has(&harness, "xabc"));
+
+    harness.run(&ctrl_alt_e);
+    assert!(has(&harness, "Reopened as UTF-8 with BOM"));
+    assert!(!has(&harness, "xabc"));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn quitting_with_unsaved_changes_warns_first() {
+    let mut harness = Harness::new("");
+    let warns = |harness: &Harness| {
+        harness
+            .rows()
+            .iter()
+            .any(|row| row.ends_with("close again to discard them"))
+    };
+    assert!(harness.scene.confirm_quit());
+
+    // Escape with nothing to close quits, unless that would lose changes.
+    harness.run(&[Type("abc")]);
+    assert!(!harness.scene.escape());
+    assert!(!harness.scene.confirm_quit());
+    harness.frame();
+    assert!(warns(&harness));
+
+    // Any other key takes the warning back.
+    harness.run(&[Key(named(NamedKey::ArrowLeft))]);
+    assert!(!warns(&harness));
+    assert!(!harness.scene.confirm_quit());
+
+    assert!(!harness.scene.escape());
+    assert!(harness.scene.confirm_quit());
+}
+
+#[test]
+fn ctrl_alt_down_adds_cursors_in_the_same_column() {
+    let mut harness = Harness::new("abc\nd\nghi");
+    harness.run(&[
+        Key(named(NamedKey::ArrowRight)),
+        Key(named(NamedKey::ArrowRight)),
+    ]);
+    harness.run(&[
+        Press(named(NamedKey::Control)),
+        Press(named(NamedKey::Alt)),
+        Key(named(NamedKey::ArrowDown)),
+        Key(named(NamedKey::ArrowDown)),
+        Release(named(NamedKey::Alt)),
+        Release(named(NamedKey::Control)),
+        Type("|"),
+    ]);
+
+    // The short middle line gets its cursor at its end.
+    let rows = harness.rows();
+    assert!(rows.iter().any(|row| row == "ab|c"));
+    assert!(rows.iter().any(|row| row == "d|"));
+    assert!(rows.iter().any(|row| row == "gh|i"));
+}
+
+#[test]
+fn ctrl_shift_z_redoes_with_modifiers_from_the_window() {
+    let mut harness = Harness::new("");
+    harness.run(&[Type("ab")]);
+    let ctrl = Modifiers {
+        ctrl: true,
+        ..Modifiers::default()
+    };
+
+    // No key events for the modifiers themselves, like when they were pressed
+    // before the window got the focus.
+    harness.scene.set_modifiers(ctrl);
+    harness.run(&[Type("z")]);
+    assert!(!harness.rows().iter().any(|row| row.starts_with("ab")));
+
+    harness.scene.set_modifiers(Modifiers {
+        shift: true,
+        ..ctrl
+    });
+    harness.run(&[Type("Z")]);
+    harness.scene.set_modifiers(Modifiers::default());
+    harness.run(&[Type("c")]);
+    assert!(harness.rows().iter().any(|row| row == "abc"));
+}
+
+#[test]
+fn alt_arrows_move_lines_as_one_undo_step_each() {
+    let mut harness = Harness::new("one\ntwo\nthree");
+    let lines = |harness: &Harness| {
+        let rows = harness.rows();
+        ["one", "two", "three"].map(|line| rows.iter().position(|row| row == line))
+    };
+
+    harness.run(&[
+        Key(named(NamedKey::ArrowRight)),
+        Press(named(NamedKey::Alt)),
+        Key(named(NamedKey::ArrowDown)),
+        Key(named(NamedKey::ArrowDown)),
+        Release(named(NamedKey::Alt)),
+    ]);
+    let [one, two, three] = lines(&harness);
+    assert!(two < three && three < one);
+
+    // The cursor moved along with its line.
+    harness.run(&[Type("|")]);
+    assert!(harness.rows().iter().any(|row| row == "o|ne"));
+
+    harness.run(&[
+        Press(named(NamedKey::Control)),
+        Type("z"),
+        Type("z"),
+        Release(named(NamedKey::Control)),
+    ]);
+    let [one, two, three] = lines(&harness);
+    assert!(two < one && one < three);
+}
+
+#[test]
+fn ctrl_shift_d_duplicates_the_line_or_selection() {
+    let mut harness = Harness::new("abc\nxyz");
+    let ctrl_shift_d = [
+        Press(named(NamedKey::Control)),
+        Press(named(NamedKey::Shift)),
+        Type("d"),
+        Release(named(NamedKey::Shift)),
+        Release(named(NamedKey::Control)),
+    ];
+
+    harness.run(&[Key(named(NamedKey::ArrowRight))]);
+    harness.run(&ctrl_shift_d);
+    harness.run(&[Type("|")]);
+    let rows = harness.rows();
+    assert!(rows.iter().any(|row| row == "abc"));
+    assert!(rows.iter().any(|row| row == "a|bc"));
+
+    // The copy of a selection is selected, so typing replaces it.
+    harness.run(&[
+        Press(named(NamedKey::Shift)),
+        Key(named(NamedKey::ArrowRight)),
+        Key(named(NamedKey::ArrowRight)),
+        Release(named(NamedKey::Shift)),
+    ]);
+    harness.run(&ctrl_shift_d);
+    harness.run(&[Type("!")]);
+    assert!(harness.rows().iter().any(|row| row == "a|bc!"));
+}
+
+#[test]
+fn resting_on_a_tab_previews_its_buffer_after_the_delay() {
+    let mut harness = Harness::new("first line\nsecond line");
+    let popups = |harness: &Harness| harness.rects(harness.theme().popup_background).len();
+    let hover = |harness: &mut Harness, x: f32, y: f32| {
+        harness.scene.update_cursor_pos(x, y, &mut harness.atlas);
+        harness.frame();
+    };
+    assert_eq!(popups(&harness), 0);
+
+    // Every frame advances the clock by a second, past the default delay.
+    hover(&mut harness, 5.0, 5.0);
+    assert_eq!(popups(&harness), 1);
+    // The tab's own label, and the path at the top of the preview.
+    let titles = harness
+        .rows()
+        .iter()
+        .filter(|row| row.contains("untitled"))
+        .count();
+    assert_eq!(titles, 2);
+
+    hover(&mut harness, 5.0, 300.0);
+    assert_eq!(popups(&harness), 0);
+
+    harness.scene.set_settings(Settings {
+        tab_preview_delay_ms: 5000,
+        ..Settings::default()
+    });
+    hover(&mut harness, 5.0, 5.0);
+    assert_eq!(popups(&harness), 0);
+}
+
+#[test]
+fn problems_panel_lists_syntax_errors_and_jumps_to_them() {
+    let mut harness = Harness::with_path("fn main() {\n    let x = 1\n}\n", Some("main.rs"));
+    let ctrl_shift_m = [
+        Press(named(NamedKey::Control)),
+        Press(named(NamedKey::Shift)),
+        Type("m"),
+        Release(named(NamedKey::Shift)),
+        Release(named(NamedKey::Control)),
+    ];
+
+    // The line is marked before the panel is ever opened.
+    assert_eq!(harness.icons(), [Icon::Warning]);
+
+    harness.run(&ctrl_shift_m);
+    let rows = harness.rows();
+    assert!(rows.iter().any(|row| row == "Problems0 errors1 warning"));
+    assert!(rows.iter().any(|row| row == "main.rs (1)"));
+    assert!(rows
+        .iter()
+        .any(|row| row == "    2:14 warning: Missing `;`"));
+
+    // Enter on the file jumps to its first problem and focuses the text again.
+    harness.run(&[Key(named(NamedKey::Enter)), Type(";")]);
+    assert!(harness.rows().iter().any(|row| row == "    let x = 1;"));
+    assert!(harness
+        .rows()
+        .iter()
+        .any(|row| row == "Problems0 errors0 warnings"));
+    // Only the panel's own icons are left, the line's mark is gone.
+    assert_eq!(harness.icons(), [Icon::Error, Icon::Warning]);
+
+    harness.run(&ctrl_shift_m);
+    assert!(!harness.rows().iter().any(|row| row.starts_with("Problems")));
+}
+
+#[test]
+fn the_focused_pane_is_outlined_once_there_are_two() {
+    let mut harness = Harness::new("left");
+    let outlines = |harness: &Harness| -> Vec<_> {
+        harness
+            .rects(harness.theme().cursor)
+            .into_iter()
+            .filter(|quad| quad.border > 0.0)
+            .collect()
+    };
+    assert!(outlines(&harness).is_empty());
+
+    harness.scene.split(SplitDirection::Horizontal);
+    harness.frame();
+    let right = outlines(&harness);
+    assert_eq!(right.len(), 1);
+
+    harness.scene.focus_next_pane(false);
+    harness.frame();
+    let left = outlines(&harness);
+    assert_eq!(left.len(), 1);
+    assert!(left[0].position[0] < right[0].position[0]);
+}
+
+#[test]
+fn closing_a_pane_removes_its_nodes() {
+    let mut harness = Harness::new("left");
+    let ctrl_shift = |key: &'static str| {
+        [
+            Press(named(NamedKey::Control)),
+            Press(named(NamedKey::Shift)),
+            Type(key),
+            Release(named(NamedKey::Shift)),
+            Release(named(NamedKey::Control)),
+        ]
+    };
+    let before = harness.scene.stats();
+
+    harness.scene.split(SplitDirection::Horizontal);
+    harness.run(&[Type("right")]);
+    let split = harness.scene.stats();
+    assert_eq!((split.nodes, split.texts), (before.nodes + 2, 2));
+
+    // What was typed isn't saved, so the first try only warns.
+    harness.run(&ctrl_shift("w"));
+    assert_eq!(harness.scene.stats(), split);
+
+    // The new pane's tab bar and text are dropped, and so is the box that held both
+    // panes. The old pane gets the focus.
+    harness.run(&ctrl_shift("w"));
+    let closed = harness.scene.stats();
+    assert_eq!((closed.nodes, closed.removed, closed.texts), (2, 3, 1));
+    assert!(!harness.rows().iter().any(|row| row.contains("right")));
+    harness.run(&[Type("!")]);
+    assert!(harness.rows().iter().any(|row| row == "!left"));
+
+    // The last pane stays.
+    harness.run(&ctrl_shift("w"));
+    assert_eq!(harness.scene.stats(), closed);
+
+    harness.run(&ctrl_shift("i"));
+    let rows = harness.rows();
+    assert!(rows
+        .iter()
+        .any(|row| row.ends_with("Nodes: 2 live, 3 removed")));
+    assert!(rows.iter().any(|row| row.ends_with("Texts: 1, 1 buffers")));
+}
+
+#[test]
+fn alt_drag_selects_a_block() {
+    let mut harness = Harness::new("abcd\nefgh\nijkl");
+    let start = harness.cursor().unwrap();
+    let line_height = FONT_SIZE * 1.2;
+    let point = |column: f32, row: f32| {
+        (
+            start.position[0] + column * advance(FONT_SIZE),
+            start.position[1] + start.size[1] / 2.0 + row * line_height,
+        )
+    };
+
+    harness.run(&[Press(named(NamedKey::Alt))]);
+    let (x, y) = point(1.0, 0.0);
+    harness.scene.update_cursor_pos(x, y, &mut harness.atlas);
+    harness.scene.mouse_input(
+        ElementState::Pressed,
+        MouseButton::Left,
+        1,
+        &mut harness.atlas,
+    );
+    let (x, y) = point(3.0, 2.0);
+    harness.scene.update_cursor_pos(x, y, &mut harness.atlas);
+    harness.scene.mouse_input(
+        ElementState::Released,
+        MouseButton::Left,
+        1,
+        &mut harness.atlas,
+    );
+    harness.run(&[Release(named(NamedKey::Alt))]);
+
+    let selected = harness.rects(harness.theme().selection);
+    assert_eq!(selected.len(), 6);
+
+    harness.run(&[Type("-")]);
+    let rows = harness.rows();
+    assert!(rows.iter().any(|row| row == "a-d"));
+    assert!(rows.iter().any(|row| row == "e-h"));
+    assert!(rows.iter().any(|row| row == "i-l"));
+}
+
+#[test]
+fn word_wrap_setting_breaks_lines_between_words() {
+    let mut harness = Harness::new(&"abcdefg ".repeat(40));
+    let only_whole_words = |harness: &Harness| {
+        harness
+            .rows()
+            .iter()
+            .filter(|row| row.contains("bcd"))
+            .all(|row| row.split_whitespace().all(|word| word == "abcdefg"))
+    };
+    assert!(!only_whole_words(&harness));
+
+    harness.scene.set_settings(Settings {
+        wrap: Wrap::Word,
+        ..Settings::default()
+    });
+    harness.frame();
+    assert!(
+        harness
+            .rows()
+            .iter()
+            .filter(|row| row.contains("bcd"))
+            .count()
+            > 1
+    );
+    assert!(only_whole_words(&harness));
+}
+
+#[test]
+fn copy_and_paste_work_without_a_system_clipboard() {
+    let mut harness = Harness::new("hello\n");
+    harness.scene.set_clipboard(Clipboard::internal());
+    harness.run(&[
+        Press(named(NamedKey::Shift)),
+        Press(named(NamedKey::End)),
+        Release(named(NamedKey::Shift)),
+        Press(named(NamedKey::Control)),
+        Type("c"),
+        Release(named(NamedKey::Control)),
+        Press(named(NamedKey::End)),
+        Press(named(NamedKey::Control)),
+        Type("v"),
+        Release(named(NamedKey::Control)),
+    ]);
+    assert!(harness.rows().iter().any(|row| row == "hellohello"));
+    assert!(harness
+        .rows()
+        .iter()
+        .any(|row| row.ends_with("Copying within the editor only")));
+}
+
+#[test]
+fn remote_carets_are_drawn_in_their_color_with_a_name_tag() {
+    let mut harness = Harness::new("one\ntwo\nthree\n");
+    let color = Color::new(200, 40, 120, 255);
+    harness.scene.set_remote_carets(
+        harness.text,
+        0,
+        vec![RemoteCaret {
+            name: "ada".to_string(),
+            color,
+            cursor: 6,
+            selection: Some(4..6),
+        }],
+    );
+    harness.frame();
+
+    let caret = harness.cursor().unwrap();
+    let rects = harness.rects(color);
+    assert_eq!(rects.len(), 2, "a caret and a tag behind the name");
+    assert!(rects[0].position[0] > caret.position[0]);
+    assert!(rects[0].position[1] > caret.position[1]);
+    assert!(harness.rows().iter().any(|row| row == "ada"));
+
+    // They belong to the buffer they were set for, not the pane.
+    let dir = temp_dir("remote-carets");
+    let other = dir.join("other.txt");
+    std::fs::write(&other, "other\n").unwrap();
+    assert!(harness.scene.open_path(&other));
+    harness.frame();
+    assert!(harness.rects(color).is_empty());
+
+    harness.run(&[
+        Press(named(NamedKey::Control)),
+        Key(named(NamedKey::Tab)),
+        Release(named(NamedKey::Control)),
+    ]);
+    assert_eq!(harness.rects(color).len(), 2);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn an_empty_untitled_pane_shows_what_to_do_until_typed_in() {
+    let mut harness = Harness::new("");
+    harness.frame();
+    let rows = harness.rows();
+    assert!(rows.iter().any(|row| row == "No file open"));
+    assert!(rows
+        .iter()
+        .any(|row| row.starts_with("ctrl+o") && row.ends_with("Open file")));
+
+    harness.run(&[Type("a")]);
+    assert!(!harness.rows().iter().any(|row| row == "No file open"));
+}
+
+#[test]
+fn empty_search_fields_without_the_keys_show_a_placeholder() {
+    let mut harness = Harness::new("hello");
+    let ctrl_h = [
+        Press(named(NamedKey::Control)),
+        Type("h"),
+        Release(named(NamedKey::Control)),
+    ];
+    harness.run(&ctrl_h);
+    let rows = harness.rows();
+    assert!(rows.iter().any(|row| row == "Replace: Type a replacement…"));
+    assert!(!rows.iter().any(|row| row.contains("Type to search…")));
+
+    harness.run(&[Key(named(NamedKey::Tab))]);
+    let rows = harness.rows();
+    assert!(rows
+        .iter()
+        .any(|row| row.starts_with("Find: Type to search…")));
+    assert!(rows.iter().any(|row| row.trim_end() == "Replace:"));
+
+    // The focused replacement shows its cursor right after the label instead.
+    let font_size = (FONT_SIZE * 0.9).floor();
+    let (x, y) = harness.position_of("Replace:").unwrap();
+    let (x, y) = (x - advance(FONT_SIZE) / 2.0, y - advance(FONT_SIZE) / 2.0);
+    let (cursor_top, cursor_height) = LineMetrics::fallback(font_size).cursor();
+    let cursor = harness
+        .rects(harness.theme().foreground)
+        .into_iter()
+        .find(|quad| quad.size == [(font_size / 10.0).floor(), cursor_height])
+        .unwrap();
+    assert_eq!(cursor.position[0], x + advance(font_size) * 9.0);
+    assert_eq!(cursor.position[1] + cursor_top, y + font_size * 0.7);
+}
+
+#[test]
+fn long_search_queries_scroll_to_keep_the_cursor_in_the_box() {
+    let mut harness = Harness::new("hello");
+    harness.run(&[
+        Press(named(NamedKey::Control)),
+        Type("f"),
+        Release(named(NamedKey::Control)),
+        Type("abcdefghijklmnopqrstuvwxyz0123456789"),
+    ]);
+    let query_row = |harness: &Harness| {
+        harness
+            .rows()
+            .into_iter()
+            .find(|row| row.starts_with("Find: "))
+            .unwrap()
+    };
+
+    // The start of the query has scrolled out of the box, the end shows.
+    let row = query_row(&harness);
+    assert!(!row.contains("abc"), "{row}");
+    assert!(row.contains("789"), "{row}");
+
+    harness.run(&[Key(named(NamedKey::Home))]);
+    let row = query_row(&harness);
+    assert!(row.contains("abc"), "{row}");
+    assert!(!row.contains("789"), "{row}");
+}
+
+#[test]
+fn goto_line_only_takes_digits() {
+    let mut harness = Harness::new("one\ntwo\nthree\n");
+    harness.run(&[
+        Press(named(NamedKey::Control)),
+        Type("g"),
+        Release(named(NamedKey::Control)),
+        Type("x3"),
+    ]);
+    assert!(harness
+        .rows()
+        .iter()
+        .any(|row| row.trim_end() == "Go to line (1-4): 3"));
+
+    let before = harness.cursor().unwrap();
+    harness.run(&[Key(named(NamedKey::Enter))]);
+    assert!(!harness
+        .rows()
+        .iter()
+        .any(|row| row.starts_with("Go to line")));
+    let after = harness.cursor().unwrap();
+    assert_eq!(
+        after.position[1],
+        before.position[1] + 2.0 * FONT_SIZE * 1.2
+    );
+}
+
+#[test]
+fn notices_show_in_the_status_bar_until_cleared() {
+    let mut harness = Harness::new("");
+    let shows = |harness: &Harness| {
+        harness
+            .rows()
+            .iter()
+            .any(|row| row.ends_with("quad.wgsl failed to compile"))
+    };
+
+    harness
+        .scene
+        .set_notice(Some("quad.wgsl failed to compile".to_string()));
+    harness.frame();
+    assert!(shows(&harness));
+
+    harness.scene.set_notice(None);
+    harness.frame();
+    assert!(!shows(&harness));
+}
+
+#[test]
+fn a_focused_list_node_takes_arrows_typing_and_clicks() {
+    let mut harness = Harness::new("");
+    let items = ["apple", "banana", "cherry"].map(String::from).to_vec();
+    let list = harness.scene.virtual_list(items, FONT_SIZE);
+    let root = harness.scene.hbox(vec![list]);
+    harness.scene.set_root(root);
+    harness.scene.set_focus(list);
+    harness.frame();
+
+    let row_height = (FONT_SIZE * 1.5).floor();
+    let selected_y = |harness: &Harness| {
+        let selection = harness.rects(harness.theme().selection);
+        assert_eq!(selection.len(), 1);
+        selection[0].position[1]
+    };
+    let top = selected_y(&harness);
+
+    harness.run(&[
+        Key(named(NamedKey::ArrowDown)),
+        Key(named(NamedKey::ArrowDown)),
+    ]);
+    assert_eq!(selected_y(&harness), top + 2.0 * row_height);
+
+    harness.run(&[Click(10.0, top + row_height * 1.5, 1)]);
+    assert_eq!(selected_y(&harness), top + row_height);
+
+    // Filtering happens on another thread.
+    harness.run(&[Type("an")]);
+    for _ in 0..100 {
+        if harness.rows() == ["banana"] {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        harness.frame();
+    }
+    assert_eq!(harness.rows(), ["banana"]);
+}
+
+#[test]
+fn f1_lists_the_keymap_and_typing_filters_it() {
+    let mut harness = Harness::new("");
+    let mut keymap = Keymap::default();
+    keymap.bind("ctrl+shift+v".parse().unwrap(), Command::Paste);
+    harness.scene.set_keymap(keymap);
+
+    harness.run(&[Key(named(NamedKey::F1))]);
+    let rows = harness.rows();
+    assert!(rows.contains(&"Keyboard shortcuts: |".to_string()));
+    assert!(rows.iter().any(|row| row.starts_with("Clipboard")
+        && row.ends_with("Paste                   ctrl+shift+v, ctrl+v")));
+
+    // Filtering happens on another thread.
+    harness.run(&[Type("redo")]);
+    for _ in 0..100 {
+        if harness
+            .rows()
+            .iter()
+            .any(|row| row.ends_with("Redo                    ctrl+shift+z, ctrl+y"))
+        {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        harness.frame();
+    }
+    let rows = harness.rows();
+    assert!(rows
+        .iter()
+        .any(|row| row.ends_with("Redo                    ctrl+shift+z, ctrl+y")));
+    assert!(!rows.iter().any(|row| row.contains("Paste")));
+
+    // F1 again closes it and the keys go back to the text.
+    harness.run(&[Key(named(NamedKey::F1)), Type("x")]);
+    assert!(!harness
+        .rows()
+        .iter()
+        .any(|row| row.starts_with("Keyboard shortcuts")));
+    assert!(harness.rows().contains(&"x".to_string()));
+}
+
+#[test]
+fn debug_report_has_sizes_and_commands_but_no_text() {
+    let mut harness = Harness::with_path("secret\r\nwords\r\n", Some("/home/someone/notes.md"));
+    harness.run(&[
+        Key(named(NamedKey::ArrowRight)),
+        Press(named(NamedKey::Control)),
+        Type("c"),
+        Release(named(NamedKey::Control)),
+    ]);
+
+    let report = harness.scene.debug_report();
+    assert!(report.contains(
+        "pane 1 buffer 1, active: .md file, UTF-8, 15 bytes, 3 lines, 1 chunks, 0 LF, 2 CRLF"
+    ));
+    assert!(report.ends_with("Recent commands, oldest first\n  Move right\n  Copy\n"));
+    for private in ["secret", "words", "someone", "notes"] {
+        assert!(!report.contains(private), "{private} in {report}");
+    }
+
+    assert!(harness.scene.config_report().contains("ctrl+c = Copy"));
+}
+
+#[test]
+fn large_files_lose_highlighting_and_too_large_ones_open_read_only() {
+    let dir = temp_dir("large");
+    let large = dir.join("large.rs");
+    let too_large = dir.join("too-large.txt");
+    std::fs::write(&large, "fn main() {}\n").unwrap();
+    let lines: String = (0..100).map(|i| format!("line {i}\n")).collect();
+    std::fs::write(&too_large, lines).unwrap();
+
+    let mut harness = Harness::new("");
+    let has_row = |harness: &Harness, text: &str| harness.rows().iter().any(|r| r.contains(text));
+
+    // Any file with something in it is over a limit of 0 MB.
+    harness.scene.set_settings(Settings {
+        large_file_size_mb: 0,
+        ..Settings::default()
+    });
+    assert!(harness.scene.open_path(&large));
+    // Large files are read on another thread.
+    for _ in 0..100 {
+        harness.frame();
+        if has_row(&harness, "Large file, highlighting is off") {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert!(has_row(&harness, "Large file, highlighting is off"));
+    assert!(!has_row(&harness, "Loading"));
+
+    harness.scene.set_settings(Settings {
+        max_file_size_mb: 0,
+        ..Settings::default()
+    });
+    assert!(harness.scene.open_path(&too_large));
+    harness.frame();
+    // The temp directory's long name pushes the rest of the header out of view.
+    assert!(has_row(&harness, "too-large.txt (0 MB"));
+    assert!(has_row(&harness, "  1  line 0"));
+
+    harness.run(&[Key(named(NamedKey::End))]);
+    assert!(has_row(&harness, "100  line 99"));
+    assert!(!has_row(&harness, "  1  line 0"));
+
+    // Typing doesn't change it, and Escape gives the keys back to the text.
+    harness.run(&[Type("x")]);
+    assert!(harness.scene.escape());
+    harness.frame();
+    assert!(!has_row(&harness, "line 99"));
+    harness.run(&[Type("y")]);
+    assert!(harness.rows().iter().any(|row| row.starts_with("yfn main")));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn large_files_dont_take_over_the_buffer_being_typed_in() {
+    let dir = temp_dir("opened-behind");
+    let large = dir.join("big.log");
+    std::fs::write(&large, "loaded\n").unwrap();
+
+    let mut harness = Harness::new("");
+    let has_row = |harness: &Harness, text: &str| harness.rows().iter().any(|r| r.contains(text));
+    harness.scene.set_settings(Settings {
+        large_file_size_mb: 0,
+        ..Settings::default()
+    });
+    assert!(harness.scene.open_path(&large));
+    harness.run(&[Type("typing")]);
+    for _ in 0..100 {
+        harness.frame();
+        if has_row(&harness, "Opened big.log in the next buffer") {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert!(has_row(&harness, "Opened big.log in the next buffer"));
+    assert!(harness.rows().iter().any(|row| row.starts_with("typing")));
+
+    harness.run(&[
+        Press(named(NamedKey::Control)),
+        Key(named(NamedKey::Tab)),
+        Release(named(NamedKey::Control)),
+    ]);
+    assert!(harness.rows().iter().any(|row| row.starts_with("loaded")));
+    assert!(!has_row(&harness, "Opened big.log"));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn sessions_reopen_files_with_their_cursors() {
+    let dir = temp_dir("session");
+    let first = dir.join("first.txt");
+    let second = dir.join("second.txt");
+    std::fs::write(&first, "first file").unwrap();
+    std::fs::write(&second, "second file").unwrap();
+
+    let mut harness = Harness::new("");
+    assert!(harness.scene.open_path(&first));
+    assert!(harness.scene.open_path(&second));
+    harness.run(&[
+        Key(named(NamedKey::ArrowRight)),
+        Key(named(NamedKey::ArrowRight)),
+    ]);
+
+    let session = harness.scene.session();
+    let paths: Vec<_> = session.buffers.iter().map(|b| b.path.clone()).collect();
+    assert_eq!(
+        paths,
+        [
+            first.canonicalize().unwrap(),
+            second.canonicalize().unwrap()
+        ]
+    );
+    assert_eq!(session.active, 1);
+    assert_eq!(session.buffers[1].cursor, 2);
+
+    let path = dir.join("session.toml");
+    session.save(&path).unwrap();
+    let loaded = Session::load(&path).unwrap();
+    assert_eq!(loaded, session);
+
+    // The empty buffer a new window starts with makes way for the files.
+    let mut restored = Harness::new("");
+    restored.scene.restore_session(&loaded);
+    restored.frame();
+    assert_eq!(restored.scene.stats().buffers, 2);
+    assert_eq!(restored.scene.session(), session);
+    assert!(restored.rows().iter().any(|row| row == "second file"));
+
+    // Unsaved changes can't be restored, so a modified file isn't either.
+    restored.run(&[Type("x")]);
+    let paths: Vec<_> = restored
+        .scene
+        .session()
+        .buffers
+        .into_iter()
+        .map(|b| b.path)
+        .collect();
+    assert_eq!(paths, [first.canonicalize().unwrap()]);
+
+    // A file that changed since keeps its cursor inside it, and its view starts
+    // at the start of a line.
+    std::fs::write(&second, "2nd\nfile").unwrap();
+    let mut shrunk = Harness::new("");
+    let mut session = session;
+    session.buffers[1].cursor = 100;
+    session.buffers[1].scroll = 6;
+    shrunk.scene.restore_session(&session);
+    assert_eq!(shrunk.scene.session().buffers[1].cursor, 8);
+    assert_eq!(shrunk.scene.session().buffers[1].scroll, 4);
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn replacing_in_files_previews_every_match_and_replaces_only_the_checked_ones() {
+    let dir = temp_dir("replace-in-files");
+    let first = dir.join("first.txt");
+    let second = dir.join("second.txt");
+    std::fs::write(&first, "foo foo\nbar\n").unwrap();
+    std::fs::write(&second, "foo\n").unwrap();
+
+    let mut harness = Harness::new("");
+    harness.scene.set_project_root(&dir);
+    harness.scene.open_find_in_files();
+    harness.run(&[Type("foo"), Key(named(NamedKey::Enter))]);
+    for _ in 0..500 {
+        if harness
+            .rows()
+            .iter()
+            .any(|row| row.ends_with("(2 results)"))
+        {
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        harness.frame();
+    }
+
+    // The replace key turns the results into a preview, with every match checked.
+    harness.run(&[
+        Press(named(NamedKey::Control)),
+        Type("h"),
+        Release(named(NamedKey::Control)),
+        Type("baz"),
+    ]);
+    let rows: Vec<_> = harness
+        .rows()
+        .iter()
+        .map(|row| row.trim().to_string())
+        .collect();
+    assert!(rows
+        .iter()
+        .any(|row| row.starts_with("Replace with: baz(3 of 3")));
+    for preview in [
+        "[x] first.txt (2 matches)",
+        "[x] 1: baz foo",
+        "[x] 1: foo baz",
+        "[x] second.txt (1 match)",
+        "[x] 1: baz",
+    ] {
+        assert!(
+            rows.iter().any(|row| row == preview),
+            "{preview} in {rows:?}"
+        );
+    }
+
+    let (x, y) = harness.position_of("foo baz").unwrap();
+    harness.run(&[
+        Click(x, y, 1),
+        Press(named(NamedKey::Control)),
+        Key(named(NamedKey::Space)),
+        Release(named(NamedKey::Control)),
+    ]);
+    let rows: Vec<_> = harness
+        .rows()
+        .iter()
+        .map(|row| row.trim().to_string())
+        .collect();
+    assert!(rows.iter().any(|row| row == "[-] first.txt (2 matches)"));
+    assert!(rows.iter().any(|row| row == "[ ] 1: foo foo"));
+
+    harness.run(&[
+        Press(named(NamedKey::Control)),
+        Key(named(NamedKey::Enter)),
+        Release(named(NamedKey::Control)),
+    ]);
+    assert!(harness
+        .rows()
+        .iter()
+        .any(|row| row.ends_with("(replaced 2 matches in 2 files)")));
+    assert_eq!(harness.scene.stats().buffers, 3);
+
+    // Each file changed in its buffer as one undo step, and nothing was saved.
+    assert!(harness.scene.escape());
+    assert!(harness.scene.open_path(&first));
+    harness.frame();
+    assert!(harness.rows().iter().any(|row| row == "baz foo"));
+    harness.run(&[
+        Press(named(NamedKey::Control)),
+        Type("z"),
+        Release(named(NamedKey::Control)),
+    ]);
+    assert!(harness.rows().iter().any(|row| row == "foo foo"));
+
+    assert!(harness.scene.open_path(&second));
+    harness.frame();
+    assert!(harness.rows().iter().any(|row| row == "baz"));
+    assert_eq!(std::fs::read_to_string(&first).unwrap(), "foo foo\nbar\n");
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,36 @@
+use std::io::{Cursor, Read};
+use ui::debug_snapshot::DebugSnapshot;
+use zip::{CompressionMethod, ZipArchive};
+
+/// Every file in 'zip', in the order they were added.
+fn unzip(zip: Vec<u8>) -> Vec<(String, Vec<u8>)> {
+    let mut archive = ZipArchive::new(Cursor::new(zip)).unwrap();
+    (0..archive.len())
+        .map(|i| {
+            let mut file = archive.by_index(i).unwrap();
+            assert_eq!(file.compression(), CompressionMethod::Deflated);
+            let mut contents = vec![];
+            file.read_to_end(&mut contents).unwrap();
+            (file.name().to_string(), contents)
+        })
+        .collect()
+}
+
+#[test]
+fn snapshot_zips_its_files() {
+    let mut snapshot = DebugSnapshot::default();
+    snapshot.add("state.txt", "some state\n".repeat(100));
+    snapshot.add("empty.txt", "");
+
+    let files = unzip(snapshot.to_zip().unwrap());
+    assert_eq!(
+        files,
+        vec![
+            (
+                "state.txt".to_string(),
+                "some state\n".repeat(100).into_bytes()
+            ),
+            ("empty.txt".to_string(), vec![]),
+        ]
+    );
+}